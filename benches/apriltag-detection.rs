@@ -7,6 +7,7 @@ use opencv::imgcodecs;
 use opencv::imgcodecs::IMREAD_GRAYSCALE;
 use opencv::prelude::*;
 use xDIMScreen_locator::tag::apriltag::{self, ImageU8View};
+use xDIMScreen_locator::tag::{Preprocessing, detect_in_roi};
 
 fn load_image_from_resource(file_name: &str) -> Result<Mat, Box<dyn std::error::Error>> {
     let path = Path::new(&env::current_dir()?)
@@ -37,5 +38,41 @@ fn benchmark_apriltag_detection(c: &mut Criterion) {
     }
 }
 
-criterion_group!(benches, benchmark_apriltag_detection);
+/// Compare downscaling the image before detection against decimating it for the quad detection only, at the
+/// same effective resolution of the quad detection.
+fn benchmark_detect_scale(c: &mut Criterion) {
+    let mut tag_family = apriltag::ApriltagFamilyType::new(apriltag::ApriltagFamily::Tag36h11);
+    let decimated = apriltag::ApriltagDetector::new_multithreading(4)
+        .add_family(&mut tag_family)
+        .quad_decimate(2.0);
+    let mut tag_family = apriltag::ApriltagFamilyType::new(apriltag::ApriltagFamily::Tag36h11);
+    let full = apriltag::ApriltagDetector::new_multithreading(4)
+        .add_family(&mut tag_family)
+        .quad_decimate(1.0);
+    let scaled = Preprocessing {
+        detect_scale: Some(0.5),
+        ..Default::default()
+    };
+
+    for suffix in ["1440x900", "2880x1800"] {
+        let mut image =
+            load_image_from_resource(&format!("apriltag-screen-{}.png", suffix)).unwrap();
+        let mut image_april = ImageU8View::from(&mut image);
+
+        let bench_name = format!("apriltag detection screen {} quad decimate 2", suffix);
+        c.bench_function(&bench_name, |b| {
+            b.iter(|| black_box(decimated.detect(image_april.inner_mut())));
+        });
+        let bench_name = format!("apriltag detection screen {} detect scale 0.5", suffix);
+        c.bench_function(&bench_name, |b| {
+            b.iter(|| black_box(detect_in_roi(&full, &mut image_april, None, &scaled)));
+        });
+    }
+}
+
+criterion_group!(
+    benches,
+    benchmark_apriltag_detection,
+    benchmark_detect_scale
+);
 criterion_main!(benches);
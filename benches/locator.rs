@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::hint::black_box;
+use std::path::Path;
+use std::time::Duration;
+
+use criterion::Criterion;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use map_macro::hash_map;
+use xDIMScreen_locator::camera::{CameraProperty, FrameTimestamp};
+use xDIMScreen_locator::tag::apriltag::{ApriltagDetection, ApriltagFamily};
+use xDIMScreen_locator::tag::locator::{TAG_CORNERS, TaggedObjectLocator};
+use xDIMScreen_locator::tag::tagged_object::{TagIndex, TaggedObject};
+
+extern crate nalgebra as na;
+
+fn load_object_from_resource(
+    file_name: &str,
+    object_name: &str,
+    id_map: HashMap<String, TagIndex>,
+) -> Result<TaggedObject, Box<dyn std::error::Error>> {
+    let path = Path::new(&std::env::current_dir()?)
+        .join("resources")
+        .join("tagobj")
+        .join(file_name);
+    let tagobj_json: serde_json::Value = serde_json::from_reader(File::open(path)?)?;
+    TaggedObject::new_from_json(object_name, &tagobj_json, &id_map)
+}
+
+/// The three objects that the locator binary registers.
+fn main_objects() -> Vec<TaggedObject> {
+    let tag = |id| TagIndex::new(ApriltagFamily::Tag36h11, id);
+    let fractal_ids = [
+        10, 11, 15, 19, 23, 12, 13, 14, 16, 17, 18, 20, 21, 22, 24, 25, 26,
+    ];
+    vec![
+        load_object_from_resource(
+            "handheld-screen.tagobj",
+            "handheld screen",
+            hash_map! {
+                "UL".to_string() => tag(0),
+                "UR".to_string() => tag(1),
+                "DL".to_string() => tag(2),
+                "DR".to_string() => tag(3),
+            },
+        )
+        .unwrap(),
+        load_object_from_resource(
+            "wand.tagobj",
+            "wand",
+            hash_map! {
+                "U".to_string() => tag(120),
+                "R".to_string() => tag(121),
+                "B".to_string() => tag(122),
+                "L".to_string() => tag(123),
+                "F".to_string() => tag(124),
+            },
+        )
+        .unwrap(),
+        load_object_from_resource(
+            "fractal-tag.tagobj",
+            "fractal tag",
+            fractal_ids
+                .iter()
+                .enumerate()
+                .map(|(name, id)| (name.to_string(), tag(*id)))
+                .collect(),
+        )
+        .unwrap(),
+    ]
+}
+
+/// Project the tags of `object` located at `location` that face the camera into detections.
+fn project_object(
+    object: &TaggedObject,
+    location: &na::Isometry3<f64>,
+    camera: &CameraProperty,
+) -> Vec<ApriltagDetection> {
+    object
+        .tags
+        .iter()
+        .filter_map(|(index, tag_location)| {
+            let points = TAG_CORNERS[..4]
+                .iter()
+                .map(|corner| location.transform_point(&tag_location.transform_point(corner)))
+                .collect::<Vec<_>>();
+            let corners: [na::Vector2<f64>; 4] =
+                camera.project_points(&points).unwrap().try_into().unwrap();
+            // a tag facing away from the camera is projected with its corners in the reverse order
+            let signed_area = (0..4)
+                .map(|i| corners[i].perp(&corners[(i + 1) % 4]))
+                .sum::<f64>();
+            if signed_area >= 0.0 {
+                return None;
+            }
+            ApriltagDetection::new(index.family, index.id, corners, 100.0)
+        })
+        .collect()
+}
+
+fn benchmark_locate_objects(c: &mut Criterion) {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let objects = main_objects();
+    let locations = [
+        na::Isometry3::new(na::vector![-200.0, 0.0, 600.0], na::vector![0.1, 0.3, 0.0]),
+        na::Isometry3::new(na::vector![0.0, 50.0, 600.0], na::vector![0.4, 0.5, 0.1]),
+        na::Isometry3::new(na::vector![200.0, 0.0, 600.0], na::vector![-0.2, 0.2, 0.3]),
+    ];
+    let detections = objects
+        .iter()
+        .zip(&locations)
+        .flat_map(|(object, location)| project_object(object, location, &camera))
+        .collect::<Vec<_>>();
+
+    for object_count in [1, objects.len()] {
+        let mut locator = TaggedObjectLocator::new(camera.clone());
+        for object in &objects[..object_count] {
+            locator.add(object).unwrap();
+        }
+        let mut timestamp = FrameTimestamp::now();
+        let bench_name = format!("locate {} objects", object_count);
+        c.bench_function(&bench_name, |b| {
+            b.iter(|| {
+                timestamp = timestamp + Duration::from_millis(33);
+                black_box(
+                    locator
+                        .locate_objects_owned(timestamp, &detections)
+                        .unwrap(),
+                )
+            });
+        });
+    }
+}
+
+criterion_group!(benches, benchmark_locate_objects);
+criterion_main!(benches);
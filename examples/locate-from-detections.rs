@@ -0,0 +1,106 @@
+//! Locate objects from tag detections made by another program, without opening a camera.
+//!
+//! Usage: `cargo run --example locate-from-detections [detections.json]`, where the JSON file describes the
+//! camera, the objects to locate, and the detected tags. See `examples/detections.json` for an example.
+use serde::Deserialize;
+use std::fs::File;
+use std::path::Path;
+
+use xDIMScreen_locator::camera::{CameraProperty, FrameTimestamp};
+use xDIMScreen_locator::tag::apriltag::{ApriltagDetection, ApriltagFamily};
+use xDIMScreen_locator::tag::locator::TaggedObjectLocator;
+use xDIMScreen_locator::tag::tagged_object::TaggedObject;
+
+extern crate nalgebra as na;
+
+#[derive(Deserialize)]
+struct Input {
+    camera: Camera,
+    objects: Vec<Object>,
+    detections: Vec<Detection>,
+}
+
+/// An uncalibrated camera. The FOVs are in degrees, and at least one of them must be given.
+#[derive(Deserialize)]
+struct Camera {
+    resolution: (u32, u32),
+    fov_x: Option<f64>,
+    fov_y: Option<f64>,
+}
+
+/// An object made of a single tag.
+#[derive(Deserialize)]
+struct Object {
+    name: String,
+    family: String,
+    id: i32,
+    size: f64,
+}
+
+/// A detected tag, with its corners in the same order as `ApriltagDetection::corners`.
+#[derive(Deserialize)]
+struct Detection {
+    family: String,
+    id: i32,
+    corners: [[f64; 2]; 4],
+    #[serde(default = "default_decision_margin")]
+    decision_margin: f32,
+}
+
+fn default_decision_margin() -> f32 {
+    100.0
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "examples/detections.json".to_string());
+    let input: Input = serde_json::from_reader(File::open(Path::new(&path))?)?;
+
+    let camera = CameraProperty::new(
+        input.camera.resolution,
+        (
+            input.camera.fov_x.map(f64::to_radians),
+            input.camera.fov_y.map(f64::to_radians),
+        ),
+        None,
+    )?;
+    let objects = input
+        .objects
+        .iter()
+        .map(|object| {
+            let family = ApriltagFamily::try_from(object.family.as_str())?;
+            Ok(TaggedObject::new_simple(
+                object.name.clone(),
+                family,
+                object.id,
+                object.size,
+            ))
+        })
+        .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+    let mut locator = TaggedObjectLocator::new(camera);
+    for object in &objects {
+        locator.add(object)?;
+    }
+
+    let detections = input
+        .detections
+        .iter()
+        .map(|detection| {
+            let family = ApriltagFamily::try_from(detection.family.as_str())?;
+            let corners = detection.corners.map(|[x, y]| na::Vector2::new(x, y));
+            ApriltagDetection::new(family, detection.id, corners, detection.decision_margin)
+                .ok_or_else(|| {
+                    format!(
+                        "The corners of tag {}:{} do not form a convex quadrilateral!",
+                        detection.family, detection.id
+                    )
+                    .into()
+                })
+        })
+        .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+
+    let located = locator.locate_objects_owned(FrameTimestamp::now(), &detections)?;
+    println!("{}", located);
+    Ok(())
+}
@@ -1,15 +1,17 @@
 use std::{
     env,
     fs::{self, File},
-    io::{Read, Write},
-    net::TcpStream,
-    path::Path,
+    io::Write,
+    path::{Path, PathBuf},
     time::SystemTime,
 };
 
 use clap::Parser;
 
-use xDIMScreen_locator::net::packet::ObjectLocationPacket;
+use xDIMScreen_locator::net::client::{ClientSecurity, PoseClient};
+use xDIMScreen_locator::net::packet::WireFormat;
+#[cfg(feature = "tls")]
+use xDIMScreen_locator::net::tls::load_client_config;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
 struct Sample {
@@ -18,15 +20,13 @@ struct Sample {
     num_objects: usize,
 }
 
-fn read_line(stream: &mut impl Read) -> Result<String, std::io::Error> {
-    let mut buf = [0u8; 1024];
-    let mut len = 0;
-    stream.read(&mut buf[len..len + 1])?;
-    while len < 1024 && buf[len] != b'\n' {
-        len += 1;
-        stream.read(&mut buf[len..len + 1])?;
+impl Sample {
+    /// The delay between sending and receiving the packet, in miliseconds.
+    ///
+    /// This may be negative if the server's and client's wall clocks are out of sync.
+    fn delay(&self) -> f64 {
+        self.timestamp_recv as f64 - self.timestamp_sent as f64
     }
-    Ok(String::from_utf8_lossy(&buf[0..len]).into_owned())
 }
 
 #[derive(Parser, Debug)]
@@ -48,6 +48,20 @@ struct Args {
     #[arg(short, long, default_value_t = 30002)]
     port: u16,
 
+    /// The format in which the locator server sends the packets.
+    #[arg(short, long, value_enum, default_value_t = WireFormat::Json)]
+    format: WireFormat,
+
+    /// A file containing the token required by the locator server, if any.
+    #[arg(long)]
+    token_file: Option<PathBuf>,
+
+    /// The PEM file of the certificate to trust when the locator server uses TLS, e.g. the server's
+    /// self-signed certificate. The connection is not encrypted if unset.
+    #[cfg(feature = "tls")]
+    #[arg(long)]
+    tls_ca: Option<PathBuf>,
+
     /// Name of the file to write to.
     #[arg(default_value_t = String::from("samples.csv"))]
     benchmark_file_name: String,
@@ -69,13 +83,36 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let mut samples = vec![Sample::default(); args.nsamples];
 
-    let mut stream = TcpStream::connect(format!("{}:{}", args.host, args.port))?;
-    log::info!("Connected to address {}:{}", args.host, args.port);
+    let security = ClientSecurity {
+        token: match &args.token_file {
+            Some(path) => Some(fs::read_to_string(path)?.trim().to_string()),
+            None => None,
+        },
+        #[cfg(feature = "tls")]
+        tls: match &args.tls_ca {
+            Some(ca) => Some((load_client_config(ca)?, args.host.clone().try_into()?)),
+            None => None,
+        },
+    };
+    let mut client = PoseClient::connect_with(
+        format!("{}:{}", args.host, args.port),
+        args.format,
+        &security,
+    )?;
+    log::info!(
+        "Connected to address {}:{} (unit: {})",
+        args.host,
+        args.port,
+        client.unit()
+    );
 
     let mut index = 0;
     while index < args.nsamples {
-        let line = read_line(&mut stream)?;
-        let packet: ObjectLocationPacket = serde_json::from_str(&line)?;
+        let packet = client.recv()?;
+        if packet.lost {
+            // lost objects are not located in this frame
+            continue;
+        }
         let now = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)?
             .as_millis();
@@ -111,10 +148,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     log::info!("Finished saving to CSV file.");
 
     // statistics
-    let total_send_recv_delay = samples
-        .iter()
-        .map(|sample| sample.timestamp_recv - sample.timestamp_sent)
-        .sum::<u128>() as f64;
+    let total_send_recv_delay = samples.iter().map(Sample::delay).sum::<f64>();
     let mean_send_recv_delay = total_send_recv_delay / (args.nsamples as f64);
     log::info!(
         "Average delay between timestamp is: {:.2} ms",
@@ -122,7 +156,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     );
     let sum_square_send_recv_delay = samples
         .iter()
-        .map(|sample| (sample.timestamp_recv - sample.timestamp_sent) as f64 - mean_send_recv_delay)
+        .map(|sample| sample.delay() - mean_send_recv_delay)
         .map(|x| x * x)
         .sum::<f64>();
     let std_send_recv_delay = f64::sqrt(sum_square_send_recv_delay / (args.nsamples as f64 - 1.0));
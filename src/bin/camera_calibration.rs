@@ -1,11 +1,15 @@
 #![cfg_attr(any(), rustfmt::skip)]
 
-use clap::Parser;
+use std::time::{Duration, Instant};
+
+use clap::{Parser, ValueEnum};
 use opencv::core::*;
 use opencv::{
     calib3d, highgui, imgproc,
+    objdetect::{self, BoardTraitConst, CharucoDetectorTraitConst},
     videoio::{self, VideoCaptureTrait},
 };
+use xDIMScreen_locator::camera::CameraProperty;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -17,13 +21,15 @@ use opencv::{
 To use the camera calibration program, please:
 
 1. Prepare the camera you want to calibrate and connect it to your computer.
-2. Prepare a calibration board with chessboard pattern. Make sure the board is flat and does not bend, or it might impact the calibration result.
+2. Prepare a calibration board with a chessboard, ChArUco, or symmetric circle grid pattern. Make sure the board is flat and does not bend, or it might impact the calibration result. A ChArUco board does not need to be fully in view, so it can also cover the edges of the image.
 
 After launching the program, you should see a window on your screen. Then, move the calibration board so that the camera captures all corners on the board, and press ENTER or SPACE on your keyboard to take a picture. Repeat this procedure as many times as needed.
 
 When enough pictures are taken, you can press ESC on your keyboard. This should destroy the window and print a message on the command line, which includes the camera's calibrated parameters.
 
-To obtain an accurate calibration result, it is recommended to have at least 20 images, with the green mask covering as much area on the screen as possible."#
+To obtain an accurate calibration result, it is recommended to have at least 20 images. The screen is divided into a grid of regions, each tinted red when no picture covers it, yellow when it is under-sampled, and green when it is covered by enough pictures. The board's tilts are tracked the same way on the top-left of the screen. Keep taking pictures until every region and every tilt turns green.
+
+To verify the intrinsics that the locator is started with, run this program with `--verify` and the same field of view (or the calibrated camera matrix) and distortion coefficients. Instead of calibrating, the program then estimates the board's pose in each frame and shows its reprojection error. A large error means the intrinsics are wrong."#
 )]
 struct Args {
     /// The device index of the camera to calibrate. Laptop's builtin camera is usually at index 0.
@@ -38,21 +44,37 @@ struct Args {
     #[arg(long, default_value_t = 1080)]
     res_y: u32,
 
+    /// The pattern on the calibration board.
+    #[arg(long, value_enum, default_value_t = Pattern::Chessboard)]
+    pattern: Pattern,
+
     /// The chessboard corners's width (number of points on X direction). This is NOT the number of grids.
+    /// For a circle grid, this is the number of circles on X direction.
     #[arg(long)]
     board_x: i32,
 
     /// The chessboard corners's height (number of points on Y direction). This is NOT the number of grids.
+    /// For a circle grid, this is the number of circles on Y direction.
     #[arg(long)]
     board_y: i32,
 
     /// The size of each square on the chessboard. You can choose an arbitrary
     /// unit of length, but the returned camera matrix will be in the same
     /// unit as your unit of choice. Usually we use millimeter as the unit
-    /// of length for this field.
+    /// of length for this field. For a circle grid, this is the distance
+    /// between the centers of adjacent circles.
     #[arg(short, long)]
     square_size: f32,
 
+    /// The size of each ArUco marker on a ChArUco board, in the same unit as `square_size`. Required for the
+    /// ChArUco pattern.
+    #[arg(long)]
+    marker_size: Option<f32>,
+
+    /// The dictionary of the ArUco markers on a ChArUco board.
+    #[arg(long, value_enum, default_value_t = ArucoDictionary::Dict4x4)]
+    aruco_dict: ArucoDictionary,
+
     /// When set, fix the tangential distortion coefficients to zero.
     #[arg(long)]
     zero_tangent_dist: bool,
@@ -68,6 +90,649 @@ struct Args {
     /// When set, fix the K3 distortion coefficient to zero.
     #[arg(long)]
     fix_k3: bool,
+
+    /// The minimum number of images needed for the calibration. Pressing ESC with fewer images resumes
+    /// capturing, unless `--force` is set.
+    #[arg(long, default_value_t = 10)]
+    min_images: usize,
+
+    /// The maximum accepted reprojection error of the calibration. Unit: pixels. A calibration with a larger
+    /// error is rejected, unless `--force` is set.
+    #[arg(long, default_value_t = 1.0)]
+    max_reproj_error: f64,
+
+    /// When set, accept the calibration even if it fails the quality checks.
+    #[arg(long)]
+    force: bool,
+
+    /// When set, verify the given intrinsics instead of calibrating the camera, by continuously estimating
+    /// the board's pose and its reprojection error.
+    #[arg(long)]
+    verify: bool,
+
+    /// The camera's field of view on x direction to verify. Unit: degrees. Either this, `--fov-y`, or
+    /// `--camera-mat` is required with `--verify`.
+    #[arg(long, requires = "verify")]
+    fov_x: Option<f64>,
+
+    /// The camera's field of view on y direction to verify. Unit: degrees. Either this, `--fov-x`, or
+    /// `--camera-mat` is required with `--verify`.
+    #[arg(long, requires = "verify")]
+    fov_y: Option<f64>,
+
+    /// The camera matrix to verify, as `fx,fy,cx,cy` in pixels. Overrides the fields of view.
+    #[arg(long, requires = "verify", value_delimiter = ',')]
+    camera_mat: Option<Vec<f64>>,
+
+    /// The lens distortion coefficients to verify, separated by commas, e.g. `k1,k2,p1,p2,k3`. No
+    /// distortion if unset.
+    #[arg(long, requires = "verify", value_delimiter = ',', allow_negative_numbers = true)]
+    distortion: Option<Vec<f64>>,
+}
+
+/// The pattern on the calibration board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Pattern {
+    /// A chessboard, which must be fully in view.
+    Chessboard,
+    /// A chessboard with ArUco markers in its white squares, which can be partially in view.
+    Charuco,
+    /// A symmetric grid of circles, which must be fully in view.
+    Circles,
+}
+
+/// The dictionary of the ArUco markers on a ChArUco board, named by the number of bits of each marker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ArucoDictionary {
+    #[value(name = "4x4")]
+    Dict4x4,
+    #[value(name = "5x5")]
+    Dict5x5,
+    #[value(name = "6x6")]
+    Dict6x6,
+    #[value(name = "7x7")]
+    Dict7x7,
+}
+
+impl ArucoDictionary {
+    /// The predefined dictionary with the largest number of markers of this size that OpenCV's board
+    /// generators share ids with, so that boards generated with a smaller dictionary are also detected.
+    fn predefined(self) -> objdetect::PredefinedDictionaryType {
+        match self {
+            ArucoDictionary::Dict4x4 => objdetect::PredefinedDictionaryType::DICT_4X4_250,
+            ArucoDictionary::Dict5x5 => objdetect::PredefinedDictionaryType::DICT_5X5_250,
+            ArucoDictionary::Dict6x6 => objdetect::PredefinedDictionaryType::DICT_6X6_250,
+            ArucoDictionary::Dict7x7 => objdetect::PredefinedDictionaryType::DICT_7X7_250,
+        }
+    }
+}
+
+/// The minimum number of ChArUco corners needed in a picture. Fewer corners constrain the pose of the board
+/// too weakly to improve the calibration.
+const MIN_CHARUCO_CORNERS: usize = 6;
+
+/// Detects the points of the calibration board in each frame.
+struct BoardDetector {
+    pattern: Pattern,
+    pattern_size: Size2i,
+    square_size: f32,
+    charuco: Option<(objdetect::CharucoBoard, objdetect::CharucoDetector)>,
+}
+
+impl BoardDetector {
+    fn new(args: &Args) -> Result<Self, Box<dyn std::error::Error>> {
+        let charuco = if args.pattern == Pattern::Charuco {
+            let marker_size = args
+                .marker_size
+                .ok_or("The marker size is required for the ChArUco pattern!")?;
+            let dictionary = objdetect::get_predefined_dictionary(args.aruco_dict.predefined())?;
+            // the board's size is in squares, which are one more than the inner corners on each direction
+            let board = objdetect::CharucoBoard::new_def(
+                Size2i::new(args.board_x + 1, args.board_y + 1),
+                args.square_size,
+                marker_size,
+                &dictionary,
+            )?;
+            let detector = objdetect::CharucoDetector::new_def(&board)?;
+            Some((board, detector))
+        } else {
+            None
+        };
+        Ok(Self {
+            pattern: args.pattern,
+            pattern_size: Size2i::new(args.board_x, args.board_y),
+            square_size: args.square_size,
+            charuco,
+        })
+    }
+
+    /// The number of points needed in a picture to use it for the calibration.
+    fn min_points(&self) -> usize {
+        match self.pattern {
+            Pattern::Chessboard | Pattern::Circles => {
+                (self.pattern_size.width * self.pattern_size.height) as usize
+            }
+            Pattern::Charuco => MIN_CHARUCO_CORNERS,
+        }
+    }
+
+    /// The points of a fully visible chessboard or circle grid on the board's plane, row by row.
+    fn grid_object_points(&self) -> Vector<Point3f> {
+        let mut object_points = Vector::<Point3f>::with_capacity(self.min_points());
+        for j in 0..self.pattern_size.height {
+            for i in 0..self.pattern_size.width {
+                object_points.push(Point3f::new(
+                    i as f32 * self.square_size,
+                    j as f32 * self.square_size,
+                    0.0,
+                ));
+            }
+        }
+        object_points
+    }
+
+    /// Detect the board's points in `gray`, and draw them on `frame`.
+    ///
+    /// Returns the detected points on the image and their corresponding points on the board's plane. A
+    /// chessboard or a circle grid is either fully detected or not detected at all, while a ChArUco board
+    /// can be partially detected.
+    fn detect(
+        &self,
+        gray: &Mat,
+        frame: &mut Mat,
+    ) -> opencv::Result<(Vector<Point2f>, Vector<Point3f>)> {
+        let mut image_points = Vector::<Point2f>::new();
+        match (self.pattern, &self.charuco) {
+            (Pattern::Charuco, Some((board, detector))) => {
+                let mut charuco_corners = Vector::<Point2f>::new();
+                let mut charuco_ids = Vector::<i32>::new();
+                detector.detect_board_def(gray, &mut charuco_corners, &mut charuco_ids)?;
+                if charuco_corners.is_empty() {
+                    return Ok((image_points, Vector::new()));
+                }
+                objdetect::draw_detected_corners_charuco(
+                    frame,
+                    &charuco_corners,
+                    &charuco_ids,
+                    Scalar::new(255.0, 0.0, 0.0, 0.0),
+                )?;
+                let mut object_points = Vector::<Point3f>::new();
+                board.match_image_points(
+                    &charuco_corners,
+                    &charuco_ids,
+                    &mut object_points,
+                    &mut image_points,
+                )?;
+                Ok((image_points, object_points))
+            }
+            (Pattern::Circles, _) => {
+                let found = calib3d::find_circles_grid_def(gray, self.pattern_size, &mut image_points)?;
+                if !found {
+                    image_points.clear();
+                    return Ok((image_points, Vector::new()));
+                }
+                calib3d::draw_chessboard_corners(frame, self.pattern_size, &image_points, found)?;
+                Ok((image_points, self.grid_object_points()))
+            }
+            _ => {
+                let found = calib3d::find_chessboard_corners(
+                    gray,
+                    self.pattern_size,
+                    &mut image_points,
+                    calib3d::CALIB_CB_ADAPTIVE_THRESH
+                        | calib3d::CALIB_CB_NORMALIZE_IMAGE
+                        | calib3d::CALIB_CB_FAST_CHECK,
+                )?;
+                if !found {
+                    image_points.clear();
+                    return Ok((image_points, Vector::new()));
+                }
+                imgproc::corner_sub_pix(
+                    gray,
+                    &mut image_points,
+                    Size2i::new(11, 11),
+                    Size2i::new(-1, -1),
+                    TermCriteria::new(TermCriteria_EPS | TermCriteria_COUNT, 40, 0.001)?,
+                )?;
+                calib3d::draw_chessboard_corners(frame, self.pattern_size, &image_points, found)?;
+                Ok((image_points, self.grid_object_points()))
+            }
+        }
+    }
+}
+
+/// The image points nearest to the four corners of the board, in order around the board, given the image
+/// points and their corresponding points on the board's plane.
+///
+/// For a fully detected board, these are the board's outermost points. For a partially detected ChArUco
+/// board, these are the outermost points of the detected part.
+fn outer_quad(image_points: &Vector<Point2f>, object_points: &Vector<Point3f>) -> [Point2f; 4] {
+    let extreme = |key: &dyn Fn(&Point3f) -> f32| {
+        object_points
+            .iter()
+            .zip(image_points.iter())
+            .max_by(|(a, _), (b, _)| key(a).total_cmp(&key(b)))
+            .map(|(_, image_point)| image_point)
+            .unwrap_or_default()
+    };
+    [
+        extreme(&|p| -p.x - p.y),
+        extreme(&|p| p.x - p.y),
+        extreme(&|p| p.x + p.y),
+        extreme(&|p| -p.x + p.y),
+    ]
+}
+
+/// The number of images with the largest reprojection errors listed when the calibration is rejected.
+const WORST_VIEW_COUNT: usize = 5;
+
+/// The indices and reprojection errors of the `count` views with the largest errors, in descending order.
+fn worst_views(per_view_errors: &[f64], count: usize) -> Vec<(usize, f64)> {
+    let mut views = per_view_errors.iter().copied().enumerate().collect::<Vec<_>>();
+    views.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    views.truncate(count);
+    views
+}
+
+/// The number of columns and rows of the grid tracking which regions of the image the captured boards cover.
+const COVERAGE_GRID: (usize, usize) = (3, 3);
+
+/// The names of the regions of the coverage grid, indexed by row and column.
+const REGION_NAMES: [[&str; COVERAGE_GRID.0]; COVERAGE_GRID.1] = [
+    ["top-left", "top", "top-right"],
+    ["left", "center", "right"],
+    ["bottom-left", "bottom", "bottom-right"],
+];
+
+/// The number of captured boards covering a region needed for the region to be sufficiently sampled.
+const MIN_REGION_CAPTURES: u32 = 3;
+
+/// The number of captured boards with a tilt needed for the tilt to be sufficiently sampled.
+const MIN_TILT_CAPTURES: u32 = 2;
+
+/// The relative length difference between the opposite edges of a board, above which the board is
+/// considered tilted.
+const TILT_THRESHOLD: f32 = 0.1;
+
+/// The orientation of a captured board relative to the camera.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tilt {
+    Frontal,
+    LeftAway,
+    RightAway,
+    TopAway,
+    BottomAway,
+}
+
+impl Tilt {
+    const ALL: [Tilt; 5] = [
+        Tilt::Frontal,
+        Tilt::LeftAway,
+        Tilt::RightAway,
+        Tilt::TopAway,
+        Tilt::BottomAway,
+    ];
+
+    /// Classify the tilt of a board from its four outer corners, in order around the board.
+    ///
+    /// The edge farther from the camera looks shorter than its opposite edge. The board is tilted towards
+    /// the image side of the shortest edge, if the edge is sufficiently shorter than its opposite.
+    fn classify(quad: &[Point2f; 4]) -> Self {
+        let length = |a: Point2f, b: Point2f| ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt();
+        let center_x = quad.iter().map(|p| p.x).sum::<f32>() / 4.0;
+        let center_y = quad.iter().map(|p| p.y).sum::<f32>() / 4.0;
+        let mut most_tilted: Option<(f32, (usize, usize))> = None;
+        for (edge, opposite) in [((0, 1), (2, 3)), ((1, 2), (3, 0))] {
+            let edge_length = length(quad[edge.0], quad[edge.1]);
+            let opposite_length = length(quad[opposite.0], quad[opposite.1]);
+            let (far_edge, ratio) = if edge_length < opposite_length {
+                (edge, 1.0 - edge_length / opposite_length)
+            } else {
+                (opposite, 1.0 - opposite_length / edge_length)
+            };
+            if ratio > TILT_THRESHOLD && most_tilted.is_none_or(|(max_ratio, _)| ratio > max_ratio) {
+                most_tilted = Some((ratio, far_edge));
+            }
+        }
+        match most_tilted {
+            None => Tilt::Frontal,
+            Some((_, (a, b))) => {
+                let dx = (quad[a].x + quad[b].x) / 2.0 - center_x;
+                let dy = (quad[a].y + quad[b].y) / 2.0 - center_y;
+                if dx.abs() > dy.abs() {
+                    if dx < 0.0 { Tilt::LeftAway } else { Tilt::RightAway }
+                } else if dy < 0.0 {
+                    Tilt::TopAway
+                } else {
+                    Tilt::BottomAway
+                }
+            }
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Tilt::Frontal => "facing the camera",
+            Tilt::LeftAway => "left edge away",
+            Tilt::RightAway => "right edge away",
+            Tilt::TopAway => "top edge away",
+            Tilt::BottomAway => "bottom edge away",
+        }
+    }
+}
+
+/// The color of a region or tilt captured `count` times, where `min_count` captures are sufficient.
+fn coverage_color(count: u32, min_count: u32) -> Scalar {
+    if count >= min_count {
+        Scalar::new(0.0, 255.0, 0.0, 0.0)
+    } else if count > 0 {
+        Scalar::new(0.0, 255.0, 255.0, 0.0)
+    } else {
+        Scalar::new(0.0, 0.0, 255.0, 0.0)
+    }
+}
+
+/// How many captured boards cover each region of the image, and how many have each tilt.
+#[derive(Debug, Default)]
+struct Coverage {
+    regions: [[u32; COVERAGE_GRID.0]; COVERAGE_GRID.1],
+    tilts: [u32; Tilt::ALL.len()],
+}
+
+impl Coverage {
+    /// Record a captured board with detected `corners` and outer corners `quad`.
+    fn add(&mut self, corners: &Vector<Point2f>, quad: &[Point2f; 4], image_size: Size) {
+        let mut covered = [[false; COVERAGE_GRID.0]; COVERAGE_GRID.1];
+        for corner in corners {
+            let col = (corner.x / image_size.width as f32 * COVERAGE_GRID.0 as f32).max(0.0) as usize;
+            let row = (corner.y / image_size.height as f32 * COVERAGE_GRID.1 as f32).max(0.0) as usize;
+            covered[row.min(COVERAGE_GRID.1 - 1)][col.min(COVERAGE_GRID.0 - 1)] = true;
+        }
+        for (row, covered_row) in covered.iter().enumerate() {
+            for (col, covered) in covered_row.iter().enumerate() {
+                if *covered {
+                    self.regions[row][col] += 1;
+                }
+            }
+        }
+        self.tilts[Tilt::classify(quad) as usize] += 1;
+    }
+
+    fn region_rect(row: usize, col: usize, image_size: Size) -> Rect {
+        let x0 = image_size.width * col as i32 / COVERAGE_GRID.0 as i32;
+        let y0 = image_size.height * row as i32 / COVERAGE_GRID.1 as i32;
+        let x1 = image_size.width * (col as i32 + 1) / COVERAGE_GRID.0 as i32;
+        let y1 = image_size.height * (row as i32 + 1) / COVERAGE_GRID.1 as i32;
+        Rect::new(x0, y0, x1 - x0, y1 - y0)
+    }
+
+    /// A mask tinting each region of the image by how well it is covered.
+    fn mask(&self, image_size: Size) -> opencv::Result<Mat> {
+        let mut mask = Mat::zeros(image_size.height, image_size.width, CV_8UC3)?.to_mat()?;
+        for (row, counts) in self.regions.iter().enumerate() {
+            for (col, count) in counts.iter().enumerate() {
+                imgproc::rectangle(
+                    &mut mask,
+                    Self::region_rect(row, col, image_size),
+                    coverage_color(*count, MIN_REGION_CAPTURES),
+                    imgproc::FILLED,
+                    imgproc::LINE_8,
+                    0,
+                )?;
+            }
+        }
+        Ok(mask)
+    }
+
+    /// Draw the grid of regions with their capture counts, and the capture count of each tilt.
+    fn annotate(&self, frame: &mut Mat) -> opencv::Result<()> {
+        let image_size = frame.size()?;
+        for (row, counts) in self.regions.iter().enumerate() {
+            for (col, count) in counts.iter().enumerate() {
+                let rect = Self::region_rect(row, col, image_size);
+                let color = coverage_color(*count, MIN_REGION_CAPTURES);
+                imgproc::rectangle(frame, rect, color, 1, imgproc::LINE_8, 0)?;
+                imgproc::put_text(
+                    frame,
+                    &format!("{}/{}", count, MIN_REGION_CAPTURES),
+                    Point::new(rect.x + rect.width - 80, rect.y + rect.height - 10),
+                    imgproc::FONT_HERSHEY_SIMPLEX,
+                    0.8,
+                    color,
+                    2,
+                    imgproc::LINE_AA,
+                    false,
+                )?;
+            }
+        }
+        for (i, tilt) in Tilt::ALL.iter().enumerate() {
+            let count = self.tilts[*tilt as usize];
+            imgproc::put_text(
+                frame,
+                &format!("{}: {}/{}", tilt.name(), count, MIN_TILT_CAPTURES),
+                Point::new(10, 30 + 30 * i as i32),
+                imgproc::FONT_HERSHEY_SIMPLEX,
+                0.8,
+                coverage_color(count, MIN_TILT_CAPTURES),
+                2,
+                imgproc::LINE_AA,
+                false,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The names of the regions and tilts that are not sufficiently sampled.
+    fn under_sampled(&self) -> Vec<String> {
+        let regions = self.regions.iter().zip(REGION_NAMES.iter()).flat_map(|(counts, names)| {
+            counts
+                .iter()
+                .zip(names.iter())
+                .filter(|(count, _)| **count < MIN_REGION_CAPTURES)
+                .map(|(_, name)| format!("the {} region", name))
+        });
+        let tilts = Tilt::ALL
+            .iter()
+            .filter(|tilt| self.tilts[**tilt as usize] < MIN_TILT_CAPTURES)
+            .map(|tilt| format!("the board with its {}", tilt.name()));
+        regions.chain(tilts).collect()
+    }
+}
+
+/// A rating of a reprojection error, in pixels.
+fn error_rating(reprojection_error: f64) -> &'static str {
+    if reprojection_error < 0.2 {
+        "VERY GOOD"
+    } else if reprojection_error < 0.5 {
+        "GOOD"
+    } else if reprojection_error < 1.0 {
+        "FINE"
+    } else if reprojection_error < 2.0 {
+        "BAD"
+    } else {
+        "VERY BAD"
+    }
+}
+
+/// Read a frame from the camera, and check that it has the requested size.
+fn read_frame(
+    cam: &mut videoio::VideoCapture,
+    image_size: Size,
+) -> Result<Mat, Box<dyn std::error::Error>> {
+    let mut frame = Mat::default();
+    cam.read(&mut frame)?;
+    let frame_size = frame.size()?;
+    if frame_size != image_size {
+        return Err(format!(
+            "Frame size mismatch! Expected {}x{}, got {}x{}.",
+            image_size.width, image_size.height, frame_size.width, frame_size.height,
+        )
+        .into());
+    }
+    Ok(frame)
+}
+
+fn to_gray(frame: &Mat) -> opencv::Result<Mat> {
+    let mut gray = Mat::default();
+    imgproc::cvt_color(
+        frame,
+        &mut gray,
+        imgproc::COLOR_BGR2GRAY,
+        0,
+        opencv::core::AlgorithmHint::ALGO_HINT_ACCURATE,
+    )?;
+    Ok(gray)
+}
+
+/// The camera's intrinsics to verify.
+fn verified_camera(args: &Args) -> Result<CameraProperty, Box<dyn std::error::Error>> {
+    CameraProperty::from_intrinsics(
+        (args.res_x, args.res_y),
+        (args.fov_x, args.fov_y),
+        args.camera_mat.as_deref(),
+        args.distortion.as_deref(),
+    )
+}
+
+/// The pose of the board relative to the camera, as a rotation vector and a translation vector in the
+/// board's unit of length, and the RMS reprojection error of the board's points in pixels.
+fn board_pose(
+    image_points: &Vector<Point2f>,
+    object_points: &Vector<Point3f>,
+    camera: &CameraProperty,
+) -> opencv::Result<(Mat, Mat, f64)> {
+    let mut rvec = Mat::default();
+    let mut tvec = Mat::default();
+    // the board's points are all on its plane
+    calib3d::solve_pnp(
+        object_points,
+        image_points,
+        camera.camera_mat(),
+        camera.distortion(),
+        &mut rvec,
+        &mut tvec,
+        false,
+        calib3d::SOLVEPNP_IPPE,
+    )?;
+    let mut projected = Vector::<Point2f>::new();
+    calib3d::project_points_def(
+        object_points,
+        &rvec,
+        &tvec,
+        camera.camera_mat(),
+        camera.distortion(),
+        &mut projected,
+    )?;
+    let squared_error = image_points
+        .iter()
+        .zip(projected.iter())
+        .map(|(detected, projected)| {
+            ((detected.x - projected.x).powi(2) + (detected.y - projected.y).powi(2)) as f64
+        })
+        .sum::<f64>();
+    Ok((rvec, tvec, (squared_error / image_points.len() as f64).sqrt()))
+}
+
+/// The interval between two reports of the board's pose on the command line in the verification mode.
+const VERIFY_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Continuously estimate the pose of the board with the intrinsics given in `args`, and show the pose and
+/// its reprojection error until ESC is pressed. The mean error over each `VERIFY_REPORT_INTERVAL` is also
+/// printed.
+fn verify(
+    args: &Args,
+    cam: &mut videoio::VideoCapture,
+    board_detector: &BoardDetector,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let camera = verified_camera(args)?;
+    println!("Verifying the camera matrix {}", camera.camera_mat_na()?);
+    println!("Distortion coefficients: {:?}", camera.distortion().data_typed::<f64>()?);
+    highgui::named_window("verification", highgui::WINDOW_KEEPRATIO)?;
+    let image_size = Size::new(args.res_x as i32, args.res_y as i32);
+    let mut errors = Vec::new();
+    let mut last_report = Instant::now();
+    loop {
+        let mut frame = read_frame(cam, image_size)?;
+        let gray = to_gray(&frame)?;
+        let (corners, object_corners) = board_detector.detect(&gray, &mut frame)?;
+        let mut lines = Vec::new();
+        if corners.len() >= board_detector.min_points() {
+            let (rvec, tvec, error) = board_pose(&corners, &object_corners, &camera)?;
+            calib3d::draw_frame_axes(
+                &mut frame,
+                camera.camera_mat(),
+                camera.distortion(),
+                &rvec,
+                &tvec,
+                board_detector.square_size * 3.0,
+                3,
+            )?;
+            let color = if error <= args.max_reproj_error {
+                Scalar::new(0.0, 255.0, 0.0, 0.0)
+            } else {
+                Scalar::new(0.0, 0.0, 255.0, 0.0)
+            };
+            let (r, t) = (rvec.data_typed::<f64>()?, tvec.data_typed::<f64>()?);
+            lines.push((
+                format!("reprojection error: {:.3} px ({})", error, error_rating(error)),
+                color,
+            ));
+            lines.push((
+                format!("translation: ({:.1}, {:.1}, {:.1})", t[0], t[1], t[2]),
+                color,
+            ));
+            lines.push((
+                format!("rotation: ({:.3}, {:.3}, {:.3})", r[0], r[1], r[2]),
+                color,
+            ));
+            errors.push(error);
+        } else {
+            lines.push((
+                "board not found".to_string(),
+                Scalar::new(0.0, 0.0, 255.0, 0.0),
+            ));
+        }
+        for (i, (line, color)) in lines.iter().enumerate() {
+            imgproc::put_text(
+                &mut frame,
+                line,
+                Point::new(10, 30 + 30 * i as i32),
+                imgproc::FONT_HERSHEY_SIMPLEX,
+                0.8,
+                *color,
+                2,
+                imgproc::LINE_AA,
+                false,
+            )?;
+        }
+        highgui::imshow("verification", &frame)?;
+        if highgui::wait_key(10)? == 27 {
+            break;
+        }
+
+        if last_report.elapsed() >= VERIFY_REPORT_INTERVAL {
+            if errors.is_empty() {
+                println!("Board not found.");
+            } else {
+                let mean_error = errors.iter().sum::<f64>() / errors.len() as f64;
+                println!(
+                    "Mean reprojection error over {} frames: {:.3} px ({}). {}",
+                    errors.len(),
+                    mean_error,
+                    error_rating(mean_error),
+                    lines[1..]
+                        .iter()
+                        .map(|(line, _)| line.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                );
+            }
+            errors.clear();
+            last_report = Instant::now();
+        }
+    }
+    highgui::destroy_all_windows()?;
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -79,62 +744,28 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     cam.set(videoio::CAP_PROP_FRAME_HEIGHT, args.res_y as f64)?;
     println!("Camera {} started.", args.cam_id);
 
-    let chessboard_pattern_size = Size2i::new(args.board_x, args.board_y);
+    let board_detector = BoardDetector::new(&args)?;
+    if args.verify {
+        return verify(&args, &mut cam, &board_detector);
+    }
     let mut object_points = Vector::<Vector<Point3f>>::new();
     let mut image_points = Vector::<Vector<Point2f>>::new();
 
-    let mut mask = Mat::zeros(args.res_y as i32, args.res_x as i32, CV_8UC3)?.to_mat()?;
+    let image_size = Size::new(args.res_x as i32, args.res_y as i32);
+    let mut coverage = Coverage::default();
+    let mut mask = coverage.mask(image_size)?;
     let mut taken_picture = false; // whether the user took picture on the last frame
 
     let (camera_mat, dist_coeff) = loop {
-        let mut frame = Mat::default();
-        cam.read(&mut frame)?;
-        let frame_size = frame.size()?;
-        if frame_size.width != args.res_x as i32 || frame_size.height != args.res_y as i32 {
-            return Err(format!(
-                "Frame size mismatch! Expected {}x{}, got {}x{}.",
-                args.res_x, args.res_y, frame_size.width, frame_size.height,
-            )
-            .into());
-        }
+        let mut frame = read_frame(&mut cam, image_size)?;
+        let gray = to_gray(&frame)?;
 
-        let mut gray = Mat::default();
-        imgproc::cvt_color(
-            &frame,
-            &mut gray,
-            imgproc::COLOR_BGR2GRAY,
-            0,
-            opencv::core::AlgorithmHint::ALGO_HINT_ACCURATE,
-        )?;
-
-        // find chessboard corner
-        let mut corners = Vector::<Point2f>::new();
-        let found_chessboard = calib3d::find_chessboard_corners(
-            &gray,
-            chessboard_pattern_size,
-            &mut corners,
-            calib3d::CALIB_CB_ADAPTIVE_THRESH
-                | calib3d::CALIB_CB_NORMALIZE_IMAGE
-                | calib3d::CALIB_CB_FAST_CHECK,
-        )?;
-        if found_chessboard {
-            imgproc::corner_sub_pix(
-                &gray,
-                &mut corners,
-                Size2i::new(11, 11),
-                Size2i::new(-1, -1),
-                TermCriteria::new(TermCriteria_EPS | TermCriteria_COUNT, 40, 0.001)?,
-            )?;
-            calib3d::draw_chessboard_corners(
-                &mut frame,
-                chessboard_pattern_size,
-                &corners,
-                found_chessboard,
-            )?;
-        }
+        // find the board's points
+        let (corners, object_corners) = board_detector.detect(&gray, &mut frame)?;
 
         // show image and wait for input
-        let masked_frame = ((&frame) + (&mask) * 0.2).into_result()?;
+        let mut masked_frame = ((&frame) + (&mask) * 0.2).into_result()?.to_mat()?;
+        coverage.annotate(&mut masked_frame)?;
         if taken_picture {
             highgui::imshow("calibration", &(masked_frame * 2.0).into_result()?)?; // lighten the image for one frame to simulate shutter effect
         } else {
@@ -146,11 +777,20 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             if object_points.len() == 0 {
                 return Err("No images taken. Terminate.".into());
             }
+            if object_points.len() < args.min_images && !args.force {
+                println!(
+                    "Only {} images taken, but at least {} are needed. Please take more pictures, or use --force to calibrate anyway.",
+                    object_points.len(),
+                    args.min_images,
+                );
+                continue;
+            }
             let mut camera_mat = Mat::default();
             let mut dist_coeff = Mat::default();
             let mut rvecs = Vector::<Mat>::new();
             let mut tvecs = Vector::<Mat>::new();
-            let reprojection_error = calib3d::calibrate_camera(
+            let mut per_view_errors = Mat::default();
+            let reprojection_error = calib3d::calibrate_camera_extended(
                 &object_points,
                 &image_points,
                 Size2i::new(args.res_x as i32, args.res_y as i32),
@@ -158,6 +798,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 &mut dist_coeff,
                 &mut rvecs,
                 &mut tvecs,
+                &mut no_array(),
+                &mut no_array(),
+                &mut per_view_errors,
                 (if args.zero_tangent_dist { calib3d::CALIB_ZERO_TANGENT_DIST } else { 0 })
                     | (if args.fix_k1 { calib3d::CALIB_FIX_K1 } else { 0 })
                     | (if args.fix_k2 { calib3d::CALIB_FIX_K2 } else { 0 })
@@ -165,80 +808,57 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 TermCriteria::new(TermCriteria_EPS | TermCriteria_COUNT, 30, f64::EPSILON)?,
             )?;
             println!("Calibration completed.");
-            println!("Camera mat: {:?}", camera_mat);
-            println!("Distortion coefficients: {:?}", dist_coeff);
             println!(
                 "Reprojection error: {} ({})",
                 reprojection_error,
-                if reprojection_error < 0.2 {
-                    "VERY GOOD"
-                } else if reprojection_error < 0.5 {
-                    "GOOD"
-                } else if reprojection_error < 1.0 {
-                    "FINE"
-                } else if reprojection_error < 2.0 {
-                    "BAD"
-                } else {
-                    "VERY BAD"
-                }
+                error_rating(reprojection_error)
             );
+            if reprojection_error > args.max_reproj_error {
+                println!(
+                    "The reprojection error exceeds the maximum of {}. Images with the largest errors:",
+                    args.max_reproj_error,
+                );
+                let per_view_errors = per_view_errors.data_typed::<f64>()?;
+                for (index, error) in worst_views(per_view_errors, WORST_VIEW_COUNT) {
+                    println!("  Image {}: {}", index + 1, error);
+                }
+                if !args.force {
+                    return Err(
+                        "Calibration rejected. Retake the images above, or use --force to accept it anyway."
+                            .into(),
+                    );
+                }
+            }
+            println!("Camera mat: {:?}", camera_mat);
+            println!("Distortion coefficients: {:?}", dist_coeff);
             break (camera_mat, dist_coeff); // return the camera matrix and distortion coefficients
         } else if (key == 10 || key == 13 || key == 32) && !taken_picture {
             // Enter or Space pressed. Take a picture and store it in the array.
-            if corners.len() != (args.board_x * args.board_y) as usize {
+            if corners.len() < board_detector.min_points() {
                 println!(
-                    "This image does not contain the correct number of corners. Corners needed: {}x{}={}. Corners detected: {}.",
-                    args.board_x,
-                    args.board_y,
-                    args.board_x * args.board_y,
+                    "This image does not contain enough corners. Corners needed: {}. Corners detected: {}.",
+                    board_detector.min_points(),
                     corners.len(),
                 );
                 continue;
             }
 
-            // draw the chessboard area on the color mask
-            let mut points = Vector::<Point>::with_capacity(4);
-            points.push(corners.get(0)?.to().unwrap());
-            points.push(corners.get(args.board_x as usize - 1)?.to().unwrap());
-            points.push(
-                corners
-                    .get((args.board_x * args.board_y - 1) as usize)?
-                    .to()
-                    .unwrap(),
-            );
-            points.push(
-                corners
-                    .get((args.board_x * (args.board_y - 1)) as usize)?
-                    .to()
-                    .unwrap(),
-            );
-            imgproc::fill_poly(
-                &mut mask,
-                &points,
-                Scalar::new(0.0, 255.0, 0.0, 0.0),
-                imgproc::LINE_AA,
-                0,
-                Point::default(),
-            )?;
+            // record the covered regions and the board's tilt, and update the color mask
+            let quad = outer_quad(&corners, &object_corners);
+            coverage.add(&corners, &quad, image_size);
+            mask = coverage.mask(image_size)?;
 
             // add corners to object_points and image_points
-            let mut object_corners =
-                Vector::<Point3f>::with_capacity((args.board_x * args.board_y) as usize);
-            for j in 0..args.board_y {
-                for i in 0..args.board_x {
-                    object_corners.push(Point3f::new(
-                        i as f32 * args.square_size,
-                        j as f32 * args.square_size,
-                        0.0,
-                    ));
-                }
-            }
             object_points.push(object_corners);
             image_points.push(corners);
             println!(
                 "Image captured. Total number of images: {}.",
                 object_points.len()
             );
+            let under_sampled = coverage.under_sampled();
+            if !under_sampled.is_empty() {
+                println!("Please also take pictures of: {}.", under_sampled.join(", "));
+            }
         }
         // update variable `taken_picture`
         taken_picture = key == 10 || key == 13 || key == 32;
@@ -246,7 +866,6 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     highgui::destroy_all_windows()?;
 
     // display the undistorted image based on the calibrated parameters
-    let image_size = Size::new(args.res_x as i32, args.res_y as i32);
     let new_cam_matrix = calib3d::get_optimal_new_camera_matrix(
         &camera_mat,
         &dist_coeff,
@@ -0,0 +1,230 @@
+//! Locate objects offline from a log of recorded detections, e.g. to evaluate changes to the locator
+//! against a fixed dataset.
+//!
+//! The input is a JSONL file with a `DetectionsPacket` on each line, such as the stream of a JSON server
+//! started with `--send-detections`. Other lines (e.g. the handshake and the object packets in that stream)
+//! are skipped. The located objects of each frame are written to the output as JSONL, one
+//! `ObjectLocationPacket` per line, in the same format as the JSON server sends them.
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use clap::Parser;
+
+use xDIMScreen_locator::camera::{CameraProperty, FrameTimestamp};
+use xDIMScreen_locator::net::frame_packets;
+use xDIMScreen_locator::net::packet::{AxisRemap, DetectionsPacket, RotationFormat, WireFormat};
+use xDIMScreen_locator::tag::apriltag::ApriltagDetection;
+use xDIMScreen_locator::tag::locator::TaggedObjectLocator;
+use xDIMScreen_locator::tag::tagged_object::{ValidationMode, load_tagobj_dir_with_mode};
+
+extern crate nalgebra as na;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "xDIMScreen log processor",
+    version,
+    about = "Locate objects from a log of recorded detections."
+)]
+struct Args {
+    /// The JSONL file of recorded `DetectionsPacket`s, one per line.
+    input: PathBuf,
+
+    /// The JSONL file to which the located objects are written.
+    #[arg(short, long, default_value = "poses.jsonl")]
+    output: PathBuf,
+
+    /// The directory from which all version 2 tagobj files are loaded as the objects to locate.
+    #[arg(long)]
+    tagobj_dir: PathBuf,
+
+    /// Fail with the JSON path of the invalid field when a tag entry in the tagobj files is invalid, instead
+    /// of skipping the tag with a warning.
+    #[arg(long)]
+    strict_tagobj: bool,
+
+    /// The X component of the resolution of the camera that the detections are made on.
+    #[arg(long, default_value_t = 1920)]
+    res_x: u32,
+
+    /// The Y component of the resolution of the camera that the detections are made on.
+    #[arg(long, default_value_t = 1080)]
+    res_y: u32,
+
+    /// The camera's field of view on x direction. Unit: degrees. Either this, `--fov-y`, or `--camera-mat` is
+    /// required.
+    #[arg(long)]
+    fov_x: Option<f64>,
+
+    /// The camera's field of view on y direction. Unit: degrees. Either this, `--fov-x`, or `--camera-mat` is
+    /// required.
+    #[arg(long)]
+    fov_y: Option<f64>,
+
+    /// The camera matrix, as `fx,fy,cx,cy` in pixels. Overrides the fields of view.
+    #[arg(long, value_delimiter = ',')]
+    camera_mat: Option<Vec<f64>>,
+
+    /// The camera's lens distortion coefficients, separated by commas, e.g. `k1,k2,p1,p2,k3`. No distortion
+    /// if unset.
+    #[arg(long, value_delimiter = ',', allow_negative_numbers = true)]
+    distortion: Option<Vec<f64>>,
+
+    /// The representation of the objects' rotations in the output.
+    #[arg(long, value_enum, default_value_t = RotationFormat::Quaternion)]
+    rotation_format: RotationFormat,
+}
+
+/// The camera that the detections are made on.
+fn camera(args: &Args) -> Result<CameraProperty, Box<dyn std::error::Error>> {
+    CameraProperty::from_intrinsics(
+        (args.res_x, args.res_y),
+        (args.fov_x, args.fov_y),
+        args.camera_mat.as_deref(),
+        args.distortion.as_deref(),
+    )
+}
+
+/// Statistics of the processed frames, printed after processing.
+#[derive(Debug, Default)]
+struct Summary {
+    frames: usize,
+    /// The number of detections whose corners do not form a convex quadrilateral, which are skipped.
+    invalid_detections: usize,
+    /// The number of located objects summed over all frames.
+    located: usize,
+    /// The number of frames in which at least one object is located.
+    frames_with_objects: usize,
+    /// The reprojection error of each detected tag of each located object, summed over all frames.
+    reprojection_error_sum: f64,
+    reprojected_tags: usize,
+}
+
+impl Summary {
+    fn print(&self) {
+        println!("Processed {} frames", self.frames);
+        if self.frames == 0 {
+            return;
+        }
+        println!(
+            "Located {:.2} objects per frame, with objects in {} frames ({:.1}%)",
+            self.located as f64 / self.frames as f64,
+            self.frames_with_objects,
+            self.frames_with_objects as f64 / self.frames as f64 * 100.0
+        );
+        if self.reprojected_tags > 0 {
+            println!(
+                "Mean reprojection error: {:.3} pixels over {} tags",
+                self.reprojection_error_sum / self.reprojected_tags as f64,
+                self.reprojected_tags
+            );
+        }
+        if self.invalid_detections > 0 {
+            println!(
+                "Skipped {} detections whose corners do not form a convex quadrilateral",
+                self.invalid_detections
+            );
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::Builder::new()
+        .filter_level(log::LevelFilter::Info)
+        .parse_default_env()
+        .try_init()?;
+    let args = Args::parse();
+
+    let objects = load_tagobj_dir_with_mode(
+        &args.tagobj_dir,
+        if args.strict_tagobj {
+            ValidationMode::Strict
+        } else {
+            ValidationMode::Lenient
+        },
+    )?;
+    if objects.is_empty() {
+        return Err(format!("No tagobj file is found in {}!", args.tagobj_dir.display()).into());
+    }
+    let mut locator = TaggedObjectLocator::new(camera(&args)?);
+    for object in &objects {
+        locator.add(object)?;
+    }
+
+    let input = BufReader::new(File::open(&args.input)?);
+    let mut output = BufWriter::new(File::create(&args.output)?);
+    let mut summary = Summary::default();
+    // the frames' monotonic times are reconstructed from their recorded wall-clock times
+    let start = Instant::now();
+    let mut first_time = None;
+    for (line_number, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value: serde_json::Value = serde_json::from_str(&line)
+            .map_err(|e| format!("Invalid JSON at line {}: {}", line_number + 1, e))?;
+        // the handshake's `detections` is a flag rather than a list of detections
+        if !value
+            .get("detections")
+            .is_some_and(serde_json::Value::is_array)
+        {
+            continue;
+        }
+        let packet: DetectionsPacket = serde_json::from_value(value)
+            .map_err(|e| format!("Invalid detections at line {}: {}", line_number + 1, e))?;
+
+        let first = *first_time.get_or_insert(packet.time);
+        let elapsed = Duration::from_millis(packet.time.saturating_sub(first) as u64);
+        let timestamp = FrameTimestamp {
+            instant: start + elapsed,
+            system_time: UNIX_EPOCH + Duration::from_millis(packet.time as u64),
+        };
+        let detections = packet
+            .detections
+            .iter()
+            .filter_map(|snapshot| {
+                let corners = snapshot.corners.map(|[x, y]| na::Vector2::new(x, y));
+                let detection = ApriltagDetection::new(
+                    snapshot.family,
+                    snapshot.id,
+                    corners,
+                    snapshot.decision_margin,
+                );
+                if detection.is_none() {
+                    summary.invalid_detections += 1;
+                }
+                detection
+            })
+            .collect::<Vec<_>>();
+
+        let located = locator.locate_objects_owned(timestamp, &detections)?;
+        for packet in frame_packets(&located, args.rotation_format, AxisRemap::identity())? {
+            WireFormat::Json.write(&mut output, &packet)?;
+        }
+        summary.frames += 1;
+        summary.located += located.name_map().len();
+        if !located.name_map().is_empty() {
+            summary.frames_with_objects += 1;
+        }
+        for (name, location) in located.name_map() {
+            if located.extrapolated().contains(name) {
+                continue;
+            }
+            let Some(errors) = locator.tag_reprojection_error(name, location, &detections)? else {
+                continue;
+            };
+            summary.reprojection_error_sum += errors.iter().map(|error| error.error).sum::<f64>();
+            summary.reprojected_tags += errors.len();
+        }
+    }
+    output.flush()?;
+
+    summary.print();
+    println!(
+        "The located objects are written to {}",
+        args.output.display()
+    );
+    Ok(())
+}
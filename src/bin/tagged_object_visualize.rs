@@ -65,7 +65,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         println!();
         println!("Tag id: {}", id);
         println!("Transformation matrix:");
-        println!("{:?}", tag.0);
+        println!("{:?}", tag);
     }
 
     // Visualize the object
@@ -141,7 +141,7 @@ impl VisualizeChart {
                     chart
                         .draw_series(LineSeries::new(
                             TAG_CORNERS.iter().map(|point| {
-                                let transformed = location.0.transform_point(point);
+                                let transformed = location.transform_point(point);
                                 (transformed.x, transformed.y, transformed.z)
                             }),
                             &BLACK,
@@ -149,7 +149,7 @@ impl VisualizeChart {
                         .unwrap();
                     chart
                         .draw_series(TAG_CORNERS.iter().take(4).map(|point| {
-                            let transformed = location.0.transform_point(point);
+                            let transformed = location.transform_point(point);
                             Circle::new((transformed.x, transformed.y, transformed.z), 6.0, &BLACK)
                         }))
                         .unwrap();
@@ -166,7 +166,7 @@ impl VisualizeChart {
                     chart
                         .draw_series(LineSeries::new(
                             X_AXIS.iter().map(|point| {
-                                let transformed = location.0.transform_point(point);
+                                let transformed = location.transform_point(point);
                                 (transformed.x, transformed.y, transformed.z)
                             }),
                             &RED,
@@ -175,7 +175,7 @@ impl VisualizeChart {
                     chart
                         .draw_series(LineSeries::new(
                             Y_AXIS.iter().map(|point| {
-                                let transformed = location.0.transform_point(point);
+                                let transformed = location.transform_point(point);
                                 (transformed.x, transformed.y, transformed.z)
                             }),
                             &GREEN,
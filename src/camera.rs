@@ -1,22 +1,357 @@
+use std::ops::{Add, Sub};
 use std::sync::{
-    Arc, RwLock,
-    atomic::{AtomicBool, Ordering},
+    Arc, Condvar, Mutex,
+    atomic::{AtomicBool, AtomicUsize, Ordering},
 };
-use std::thread::Thread;
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
 
 use opencv::{
-    core::{CV_64F, MatExpr, Vec4d},
+    calib3d,
+    core::{self, CV_64F, MatExpr, Vec4d},
     prelude::*,
     videoio,
 };
 
+use crate::metrics::Metrics;
+use crate::recording::FrameRecorder;
+
 extern crate nalgebra as na;
 
+/// The interval at which the camera thread logs the number of dropped frames, and the frame recorder the
+/// number of dropped recorded frames.
+pub const DROPPED_FRAME_LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The maximum age of a capture timestamp reported by the camera driver. Older timestamps (or timestamps in
+/// the future) are considered bogus, and the frame is stamped when it is read instead.
+pub const MAX_CAPTURE_TIMESTAMP_AGE: Duration = Duration::from_secs(1);
+
+/// The interval at which a paused camera thread checks the termination signal.
+pub const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The maximum difference between the aspect ratios of the delivered frames and the camera's resolution for
+/// `CameraProperty::fitted_to_frames` to scale the camera matrix instead of failing.
+pub const MAX_ASPECT_RATIO_MISMATCH: f64 = 0.01;
+
+/// The number of frames `read_frame_resolution` reads before giving up, since some drivers deliver a few
+/// empty frames while the camera starts.
+pub const FIRST_FRAME_ATTEMPTS: usize = 30;
+
+/// The default variance of each detected corner's x and y coordinates, in pixels squared.
+pub const DEFAULT_CORNER_VARIANCE: (f64, f64) = (2.0, 2.0);
+
+/// The time at which a camera frame is captured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameTimestamp {
+    /// Monotonic time of the frame. Used for ordering frames and measuring durations between them, since
+    /// it never jumps backwards.
+    pub instant: Instant,
+    /// Wall-clock time of the frame. Only used for stamping the packets sent to clients.
+    pub system_time: SystemTime,
+}
+
+impl FrameTimestamp {
+    pub fn now() -> Self {
+        Self {
+            instant: Instant::now(),
+            system_time: SystemTime::now(),
+        }
+    }
+}
+
+impl Add<Duration> for FrameTimestamp {
+    type Output = Self;
+
+    fn add(self, rhs: Duration) -> Self::Output {
+        Self {
+            instant: self.instant + rhs,
+            system_time: self.system_time + rhs,
+        }
+    }
+}
+
+impl Sub<Duration> for FrameTimestamp {
+    type Output = Self;
+
+    fn sub(self, rhs: Duration) -> Self::Output {
+        Self {
+            instant: self.instant - rhs,
+            system_time: self.system_time - rhs,
+        }
+    }
+}
+
+/// Counts the camera frames that are overwritten before the frame consumer (e.g. the locator thread)
+/// reads them.
+///
+/// A growing count means the consumer runs slower than the camera at the current resolution.
+#[derive(Debug, Default)]
+pub struct DroppedFrameCounter {
+    /// Whether the latest frame has not been read by the consumer yet.
+    pending: AtomicBool,
+    dropped: AtomicUsize,
+}
+
+impl DroppedFrameCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Called when a new frame is sent to the consumer, e.g. by `LatestFrameSender::send`.
+    pub fn frame_produced(&self) {
+        if self.pending.swap(true, Ordering::AcqRel) {
+            // the previous frame is overwritten without being read
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Called when the consumer receives a new frame, e.g. by `LatestFrameReceiver::recv`.
+    pub fn frame_consumed(&self) {
+        self.pending.store(false, Ordering::Release);
+    }
+
+    /// The total number of dropped frames.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// The state shared by the two ends of a latest frame channel.
+#[derive(Default)]
+struct LatestFrameSlot {
+    /// The latest frame, if it has not been received yet.
+    frame: Option<(Mat, FrameTimestamp)>,
+    /// Whether the sender is dropped.
+    closed: bool,
+}
+
+struct LatestFrameShared {
+    slot: Mutex<LatestFrameSlot>,
+    available: Condvar,
+    dropped_frames: Arc<DroppedFrameCounter>,
+}
+
+/// Create a single-slot channel through which the camera thread hands its frames to a consumer thread (e.g.
+/// the locator thread).
+///
+/// Sending a frame replaces the frame that has not been received yet, so the consumer always receives the
+/// newest frame, and never receives a frame twice. The replaced frames are counted by `dropped_frames`.
+pub fn latest_frame_channel(
+    dropped_frames: Arc<DroppedFrameCounter>,
+) -> (LatestFrameSender, LatestFrameReceiver) {
+    let shared = Arc::new(LatestFrameShared {
+        slot: Mutex::new(LatestFrameSlot::default()),
+        available: Condvar::new(),
+        dropped_frames,
+    });
+    (
+        LatestFrameSender(shared.clone()),
+        LatestFrameReceiver(shared),
+    )
+}
+
+/// The sending end of a latest frame channel. The channel is closed when the sender is dropped.
+pub struct LatestFrameSender(Arc<LatestFrameShared>);
+
+impl LatestFrameSender {
+    /// Send a frame, replacing the frame that has not been received yet, if any.
+    ///
+    /// Returns `true` if a frame was replaced without being received.
+    pub fn send(&self, frame: Mat, timestamp: FrameTimestamp) -> bool {
+        let mut slot = self.0.slot.lock().unwrap();
+        // counted while holding the lock, so that the counter agrees with the slot
+        self.0.dropped_frames.frame_produced();
+        let replaced = slot.frame.replace((frame, timestamp)).is_some();
+        drop(slot);
+        self.0.available.notify_one();
+        replaced
+    }
+}
+
+impl Drop for LatestFrameSender {
+    fn drop(&mut self) {
+        self.0.slot.lock().unwrap().closed = true;
+        self.0.available.notify_all();
+    }
+}
+
+/// The receiving end of a latest frame channel.
+pub struct LatestFrameReceiver(Arc<LatestFrameShared>);
+
+impl LatestFrameReceiver {
+    /// Wait for a frame that has not been received yet.
+    ///
+    /// Returns `None` once the sender is dropped and the last frame it sent has been received.
+    pub fn recv(&self) -> Option<(Mat, FrameTimestamp)> {
+        let mut slot = self
+            .0
+            .available
+            .wait_while(self.0.slot.lock().unwrap(), |slot| {
+                slot.frame.is_none() && !slot.closed
+            })
+            .unwrap();
+        let frame = slot.frame.take();
+        if frame.is_some() {
+            self.0.dropped_frames.frame_consumed();
+        }
+        frame
+    }
+}
+
+/// Pauses the camera thread, and steps it frame by frame while paused.
+///
+/// A paused camera thread stops capturing frames, so the whole pipeline waits on the last frame. The frame
+/// consumers (e.g. the locator thread) stay parked meanwhile. Not paused by default.
+#[derive(Debug, Default)]
+pub struct PauseControl {
+    /// Whether the camera is paused, and the number of frames requested to be captured while paused.
+    state: Mutex<(bool, usize)>,
+    changed: Condvar,
+}
+
+impl PauseControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().0
+    }
+
+    /// Pause or resume the camera. Discards the frames requested by `step`.
+    pub fn set_paused(&self, paused: bool) {
+        *self.state.lock().unwrap() = (paused, 0);
+        self.changed.notify_all();
+    }
+
+    /// Pause the camera if it is running, or resume it if it is paused. Returns whether it is now paused.
+    pub fn toggle(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        *state = (!state.0, 0);
+        self.changed.notify_all();
+        state.0
+    }
+
+    /// Capture exactly one more frame if the camera is paused, and return `true`. If the camera is running,
+    /// pause it instead and return `false`.
+    pub fn step(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if !state.0 {
+            state.0 = true;
+            return false;
+        }
+        state.1 += 1;
+        self.changed.notify_all();
+        true
+    }
+
+    /// Called by the camera thread before capturing each frame. Returns `true` at once if the camera is
+    /// running. Otherwise, waits for up to `timeout` until the camera is resumed or a frame is stepped, and
+    /// returns whether a frame should be captured.
+    pub fn wait_for_capture(&self, timeout: Duration) -> bool {
+        let state = self.state.lock().unwrap();
+        let (mut state, _) = self
+            .changed
+            .wait_timeout_while(state, timeout, |(paused, steps)| *paused && *steps == 0)
+            .unwrap();
+        if !state.0 {
+            return true;
+        }
+        if state.1 > 0 {
+            state.1 -= 1;
+            return true;
+        }
+        false
+    }
+}
+
+/// A flip or rotation applied to each captured frame before detection, e.g. to turn the frames of a camera
+/// mounted upside down upright. See `CameraProperty::transformed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum FrameTransform {
+    #[default]
+    None,
+    /// Mirror the frame horizontally.
+    #[value(name = "flip-h")]
+    FlipH,
+    /// Mirror the frame vertically.
+    #[value(name = "flip-v")]
+    FlipV,
+    /// Rotate the frame by 180 degrees.
+    #[value(name = "rot180")]
+    Rot180,
+    /// Rotate the frame by 90 degrees clockwise.
+    #[value(name = "rot90cw")]
+    Rot90Cw,
+    /// Rotate the frame by 90 degrees counter-clockwise.
+    #[value(name = "rot90ccw")]
+    Rot90Ccw,
+}
+
+impl FrameTransform {
+    /// How the transform moves the image's x and y axes, which is also how it moves the x and y axes of the
+    /// camera's frame. Each row has a single entry of 1 or -1.
+    pub fn axes(&self) -> na::Matrix2<f64> {
+        match self {
+            FrameTransform::None => na::Matrix2::identity(),
+            FrameTransform::FlipH => na::Matrix2::new(-1.0, 0.0, 0.0, 1.0),
+            FrameTransform::FlipV => na::Matrix2::new(1.0, 0.0, 0.0, -1.0),
+            FrameTransform::Rot180 => -na::Matrix2::identity(),
+            FrameTransform::Rot90Cw => na::Matrix2::new(0.0, -1.0, 1.0, 0.0),
+            FrameTransform::Rot90Ccw => na::Matrix2::new(0.0, 1.0, -1.0, 0.0),
+        }
+    }
+
+    /// The resolution of a transformed frame whose original resolution is `resolution`.
+    pub fn resolution(&self, resolution: (u32, u32)) -> (u32, u32) {
+        match self {
+            FrameTransform::Rot90Cw | FrameTransform::Rot90Ccw => (resolution.1, resolution.0),
+            _ => resolution,
+        }
+    }
+
+    /// Map the pixel `px` on an original frame of `resolution` to the transformed frame.
+    pub fn transform_pixel(
+        &self,
+        px: na::Vector2<f64>,
+        resolution: (u32, u32),
+    ) -> na::Vector2<f64> {
+        let axes = self.axes();
+        let last_pixel = na::vector![resolution.0 as f64 - 1.0, resolution.1 as f64 - 1.0];
+        // each flipped axis counts from the last pixel of the axis it comes from
+        let offset = axes.map(|entry| entry.min(0.0).abs()) * last_pixel;
+        axes * px + offset
+    }
+
+    /// Apply the transform to a captured frame.
+    pub fn apply(&self, frame: Mat) -> opencv::Result<Mat> {
+        let mut transformed = Mat::default();
+        match self {
+            FrameTransform::None => return Ok(frame),
+            FrameTransform::FlipH => core::flip(&frame, &mut transformed, 1)?,
+            FrameTransform::FlipV => core::flip(&frame, &mut transformed, 0)?,
+            FrameTransform::Rot180 => core::rotate(&frame, &mut transformed, core::ROTATE_180)?,
+            FrameTransform::Rot90Cw => {
+                core::rotate(&frame, &mut transformed, core::ROTATE_90_CLOCKWISE)?
+            }
+            FrameTransform::Rot90Ccw => {
+                core::rotate(&frame, &mut transformed, core::ROTATE_90_COUNTERCLOCKWISE)?
+            }
+        }
+        Ok(transformed)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CameraProperty {
     pub resolution: (u32, u32),
     pub fov: (Option<f64>, Option<f64>),
+    /// The variance of each detected corner's x and y coordinates on the image, in pixels squared.
+    ///
+    /// This depends on the camera's noise and lens, and is used to estimate the uncertainty of the
+    /// located objects.
+    pub corner_variance: (f64, f64),
+    /// The nominal frame rate of the camera, in frames per second, if known.
+    pub fps: Option<f64>,
     pub(crate) camera_mat: Mat,
     pub(crate) distortion: Mat,
 }
@@ -87,15 +422,231 @@ impl CameraProperty {
         Ok(Self {
             resolution,
             fov,
+            corner_variance: DEFAULT_CORNER_VARIANCE,
+            fps: None,
             camera_mat,
             distortion,
         })
     }
 
+    /// Create a camera from intrinsics given on the command line: the fields of view in degrees, or the
+    /// camera matrix as `[fx, fy, cx, cy]` in pixels, which overrides them. The lens distortion
+    /// coefficients are e.g. `[k1, k2, p1, p2, k3]`, and there is no distortion if they are `None`.
+    pub fn from_intrinsics(
+        resolution: (u32, u32),
+        fov_degrees: (Option<f64>, Option<f64>),
+        camera_mat: Option<&[f64]>,
+        distortion: Option<&[f64]>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let camera_mat_and_distortion = match camera_mat {
+            Some(camera_mat) => {
+                let [fx, fy, cx, cy] = camera_mat
+                    .try_into()
+                    .map_err(|_| "The camera matrix must be given as fx,fy,cx,cy!")?;
+                let camera_mat =
+                    Mat::from_slice_2d(&[[fx, 0.0, cx], [0.0, fy, cy], [0.0, 0.0, 1.0]])?;
+                Some((camera_mat, Mat::zeros(1, 5, CV_64F)?.to_mat()?))
+            }
+            None => None,
+        };
+        let camera = Self::new(
+            resolution,
+            (
+                fov_degrees.0.map(f64::to_radians),
+                fov_degrees.1.map(f64::to_radians),
+            ),
+            camera_mat_and_distortion,
+        )?;
+        match distortion {
+            Some(distortion) => camera.with_distortion(distortion),
+            None => Ok(camera),
+        }
+    }
+
+    /// Set the variance of each detected corner's x and y coordinates, in pixels squared.
+    pub fn with_corner_variance(mut self, corner_variance: (f64, f64)) -> Self {
+        self.corner_variance = corner_variance;
+        self
+    }
+
+    /// Set the nominal frame rate of the camera, in frames per second. Non-positive rates (reported by drivers
+    /// that do not know the frame rate) are ignored.
+    pub fn with_fps(mut self, fps: f64) -> Self {
+        self.fps = (fps > 0.0 && fps.is_finite()).then_some(fps);
+        self
+    }
+
+    /// The nominal time between two consecutive frames, if the frame rate is known.
+    pub fn frame_period(&self) -> Option<Duration> {
+        self.fps.map(|fps| Duration::from_secs_f64(1.0 / fps))
+    }
+
+    /// This camera running at another `resolution`, e.g. a camera calibrated at 1920x1080 running at
+    /// 960x540.
+    ///
+    /// The camera matrix is scaled from this camera's resolution to the new one, keeping pixel centers at
+    /// integer coordinates. The distortion coefficients are dimensionless, so they are kept unchanged.
+    pub fn scaled_to(&self, resolution: (u32, u32)) -> Result<Self, Box<dyn std::error::Error>> {
+        let scale = (
+            resolution.0 as f64 / self.resolution.0 as f64,
+            resolution.1 as f64 / self.resolution.1 as f64,
+        );
+        let mut camera_mat = self.camera_mat_na()?;
+        for (row, scale) in [(0, scale.0), (1, scale.1)] {
+            // `x' = (x + 0.5) * scale - 0.5` for each pixel coordinate `x`
+            camera_mat[(row, 0)] *= scale;
+            camera_mat[(row, 1)] *= scale;
+            camera_mat[(row, 2)] = (camera_mat[(row, 2)] + 0.5) * scale - 0.5;
+        }
+        let camera_mat_data = camera_mat.transpose();
+        Ok(Self {
+            resolution,
+            camera_mat: Mat::new_rows_cols_with_data(3, 3, camera_mat_data.as_slice())?
+                .try_clone()?,
+            ..self.clone()
+        })
+    }
+
+    /// This camera fitted to the `frame_resolution` actually delivered by the driver, which may ignore the
+    /// requested resolution that the camera matrix is built for.
+    ///
+    /// If the aspect ratios match within `MAX_ASPECT_RATIO_MISMATCH`, the driver most likely scales the whole
+    /// sensor image, so the camera matrix is scaled with `scaled_to` and a warning is logged. Otherwise the
+    /// frames are likely cropped from the sensor, which the camera matrix can't be fitted to, so an error is
+    /// returned.
+    pub fn fitted_to_frames(
+        &self,
+        frame_resolution: (u32, u32),
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        if frame_resolution == self.resolution {
+            return Ok(self.clone());
+        }
+        let aspect_ratio = |resolution: (u32, u32)| resolution.0 as f64 / resolution.1 as f64;
+        if (aspect_ratio(frame_resolution) / aspect_ratio(self.resolution) - 1.0).abs()
+            > MAX_ASPECT_RATIO_MISMATCH
+        {
+            return Err(format!(
+                "The camera delivers {}x{} frames instead of the requested {}x{}, with a different aspect \
+                 ratio. Request {}x{} frames, with the intrinsics for that resolution.",
+                frame_resolution.0,
+                frame_resolution.1,
+                self.resolution.0,
+                self.resolution.1,
+                frame_resolution.0,
+                frame_resolution.1
+            )
+            .into());
+        }
+        log::warn!(
+            "The camera delivers {}x{} frames instead of the requested {}x{}. The camera matrix is scaled \
+             accordingly, which is only correct if the driver scales the whole sensor image.",
+            frame_resolution.0,
+            frame_resolution.1,
+            self.resolution.0,
+            self.resolution.1
+        );
+        self.scaled_to(frame_resolution)
+    }
+
+    /// This camera with its frames transformed by `transform`, e.g. rotated upright for a camera mounted upside
+    /// down.
+    ///
+    /// The transformed frames are seen by a virtual camera whose x and y axes are moved like the image's
+    /// (see `FrameTransform::axes`), so the objects are located relative to the transformed frames. The
+    /// camera matrix, the resolution and the field of view are transformed accordingly, as are the
+    /// tangential and thin prism distortion coefficients. The tilt coefficients (`tx`, `ty`) can't be
+    /// transformed, so they must be zero unless `transform` is `FrameTransform::None`.
+    pub fn transformed(
+        &self,
+        transform: FrameTransform,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        if transform == FrameTransform::None {
+            return Ok(self.clone());
+        }
+        let axes = transform.axes();
+        let camera_mat = self.camera_mat_na()?;
+        let mut transformed_mat = na::Matrix3::identity();
+        transformed_mat
+            .fixed_view_mut::<2, 2>(0, 0)
+            .copy_from(&(axes * camera_mat.fixed_view::<2, 2>(0, 0) * axes.transpose()));
+        let center = na::vector![camera_mat[(0, 2)], camera_mat[(1, 2)]];
+        transformed_mat
+            .fixed_view_mut::<2, 1>(0, 2)
+            .copy_from(&transform.transform_pixel(center, self.resolution));
+        let camera_mat_data = transformed_mat.transpose();
+
+        let mut distortion = self.distortion.data_typed::<f64>()?.to_vec();
+        if distortion.len() > 12 && distortion[12..].iter().any(|coeff| *coeff != 0.0) {
+            return Err("The tilt distortion coefficients can't be transformed!".into());
+        }
+        // the tangential coefficients `(p2, p1)` and the thin prism coefficients `(s1, s3)` and `(s2, s4)`
+        // each act on the x and y axes like a vector
+        let mut pairs = vec![(3, 2)];
+        if distortion.len() >= 12 {
+            pairs.extend([(8, 10), (9, 11)]);
+        }
+        for (x, y) in pairs {
+            let transformed = axes * na::vector![distortion[x], distortion[y]];
+            distortion[x] = transformed.x;
+            distortion[y] = transformed.y;
+        }
+
+        let fov = match transform {
+            FrameTransform::Rot90Cw | FrameTransform::Rot90Ccw => (self.fov.1, self.fov.0),
+            _ => self.fov,
+        };
+        Ok(Self {
+            resolution: transform.resolution(self.resolution),
+            fov,
+            camera_mat: Mat::new_rows_cols_with_data(3, 3, camera_mat_data.as_slice())?
+                .try_clone()?,
+            distortion: Mat::from_slice(&distortion)?.try_clone()?,
+            ..self.clone()
+        })
+    }
+
+    /// Set the lens distortion coefficients, in OpenCV's order `(k1, k2, p1, p2[, k3[, k4, k5, k6[, s1, s2,
+    /// s3, s4[, tx, ty]]]])`.
+    ///
+    /// Returns an error if the number of coefficients is not 4, 5, 8, 12, or 14.
+    pub fn with_distortion(mut self, coeffs: &[f64]) -> Result<Self, Box<dyn std::error::Error>> {
+        if ![4, 5, 8, 12, 14].contains(&coeffs.len()) {
+            return Err(format!(
+                "Expected 4, 5, 8, 12, or 14 distortion coefficients, but got {}!",
+                coeffs.len()
+            )
+            .into());
+        }
+        self.distortion = Mat::from_slice(coeffs)?.try_clone()?;
+        Ok(self)
+    }
+
+    /// Override the principal point `(cx, cy)` of the camera matrix, in pixels. Each `None` component is
+    /// kept unchanged.
+    ///
+    /// The FOV-based constructor places the principal point at the image center, which is off for cropped
+    /// sensors or off-center optics. The focal lengths are kept unchanged.
+    pub fn with_principal_point(
+        mut self,
+        principal_point: (Option<f64>, Option<f64>),
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Some(cx) = principal_point.0 {
+            *self.camera_mat.at_2d_mut::<f64>(0, 2)? = cx;
+        }
+        if let Some(cy) = principal_point.1 {
+            *self.camera_mat.at_2d_mut::<f64>(1, 2)? = cy;
+        }
+        Ok(self)
+    }
+
     pub fn camera_mat(&self) -> &Mat {
         &self.camera_mat
     }
 
+    pub fn distortion(&self) -> &Mat {
+        &self.distortion
+    }
+
     pub fn camera_mat_na(&self) -> Result<na::Matrix3<f64>, Box<dyn std::error::Error>> {
         unsafe {
             Ok(na::Matrix3::new(
@@ -111,31 +662,640 @@ impl CameraProperty {
             ))
         }
     }
+
+    /// The horizontal and vertical field of view of the camera in radians, derived from the camera matrix
+    /// and the resolution.
+    ///
+    /// Unlike `fov`, this is also known for calibrated cameras. Each FOV spans the centers of the first and
+    /// last pixels, as the FOV given to `new` does, and the principal point does not need to be centered.
+    pub fn computed_fov(&self) -> (f64, f64) {
+        let camera_mat = self
+            .camera_mat_na()
+            .expect("The camera matrix is not a 3x3 matrix!");
+        let fov = |focal: f64, center: f64, resolution: u32| {
+            f64::atan(center / focal) + f64::atan((resolution as f64 - 1.0 - center) / focal)
+        };
+        (
+            fov(camera_mat[(0, 0)], camera_mat[(0, 2)], self.resolution.0),
+            fov(camera_mat[(1, 1)], camera_mat[(1, 2)], self.resolution.1),
+        )
+    }
+
+    /// Un-project the pixel `px` on the image into the direction of the ray through it, as a unit vector
+    /// in the camera's frame.
+    ///
+    /// This is the inverse of projecting a point with the camera matrix and the lens distortion, so the
+    /// lens distortion is removed before the inverse camera matrix is applied.
+    pub fn pixel_to_ray(
+        &self,
+        px: na::Vector2<f64>,
+    ) -> Result<na::Vector3<f64>, Box<dyn std::error::Error>> {
+        let distorted = core::Vector::<core::Point2d>::from_iter([core::Point2d::new(px.x, px.y)]);
+        let mut undistorted = core::Vector::<core::Point2d>::new();
+        // without a new projection matrix, the undistorted points are in normalized camera coordinates
+        calib3d::undistort_points_def(
+            &distorted,
+            &mut undistorted,
+            &self.camera_mat,
+            &self.distortion,
+        )?;
+        let point = undistorted.get(0)?;
+        Ok(na::vector![point.x, point.y, 1.0].normalize())
+    }
+
+    /// Project `points` in the camera's frame onto the image with the camera matrix and the lens distortion.
+    ///
+    /// This is the inverse of `pixel_to_ray`. The points must be in front of the camera.
+    pub fn project_points(
+        &self,
+        points: &[na::Point3<f64>],
+    ) -> Result<Vec<na::Vector2<f64>>, Box<dyn std::error::Error>> {
+        let object_points = core::Vector::<core::Point3d>::from_iter(
+            points.iter().map(|p| core::Point3d::new(p.x, p.y, p.z)),
+        );
+        let mut image_points = core::Vector::<core::Point2d>::new();
+        calib3d::project_points_def(
+            &object_points,
+            &core::Vector::<f64>::from_iter([0.0, 0.0, 0.0]),
+            &core::Vector::<f64>::from_iter([0.0, 0.0, 0.0]),
+            &self.camera_mat,
+            &self.distortion,
+            &mut image_points,
+        )?;
+        Ok(image_points.iter().map(|p| na::vector![p.x, p.y]).collect())
+    }
+
+    /// Project a single point in the camera's frame onto the image. See `project_points`.
+    pub fn project_point(
+        &self,
+        point: &na::Point3<f64>,
+    ) -> Result<na::Vector2<f64>, Box<dyn std::error::Error>> {
+        Ok(self.project_points(std::slice::from_ref(point))?[0])
+    }
+}
+
+/// The age of a frame captured at `capture_ms`, given the current time `now_ms` on the same clock.
+///
+/// Returns `None` if the capture timestamp is missing (0) or not within `MAX_CAPTURE_TIMESTAMP_AGE`.
+fn capture_age(capture_ms: f64, now_ms: f64) -> Option<Duration> {
+    if capture_ms <= 0.0 {
+        return None;
+    }
+    let age = Duration::try_from_secs_f64((now_ms - capture_ms) / 1000.0).ok()?;
+    (age <= MAX_CAPTURE_TIMESTAMP_AGE).then_some(age)
+}
+
+/// The current time of the monotonic clock used by V4L2 buffer timestamps, in milliseconds.
+#[cfg(target_os = "linux")]
+fn monotonic_now_ms() -> Option<f64> {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    if unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) } != 0 {
+        return None;
+    }
+    Some(now.tv_sec as f64 * 1000.0 + now.tv_nsec as f64 / 1_000_000.0)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn monotonic_now_ms() -> Option<f64> {
+    None
+}
+
+/// Get the time at which the frame that was just read from `cam` is captured.
+///
+/// Only the V4L2 backend (Linux) reports the driver's capture timestamp, through `CAP_PROP_POS_MSEC` on
+/// the monotonic clock. Other backends (e.g. MSMF, DirectShow, AVFoundation, GStreamer) report either 0
+/// or the position in the stream, so the frame is stamped with the time after it is read instead, which
+/// includes the decoding and transfer latency.
+fn capture_timestamp(cam: &videoio::VideoCapture, is_v4l2: bool) -> FrameTimestamp {
+    let now = FrameTimestamp::now();
+    if !is_v4l2 {
+        return now;
+    }
+    let age = cam
+        .get(videoio::CAP_PROP_POS_MSEC)
+        .ok()
+        .zip(monotonic_now_ms())
+        .and_then(|(capture_ms, now_ms)| capture_age(capture_ms, now_ms));
+    match age {
+        Some(age) => now - age,
+        None => now,
+    }
+}
+
+/// Read frames from `cam` until a non-empty one arrives, and return its resolution.
+///
+/// Returns an error if all of the first `FIRST_FRAME_ATTEMPTS` frames are empty.
+pub fn read_frame_resolution(
+    cam: &mut videoio::VideoCapture,
+) -> Result<(u32, u32), Box<dyn std::error::Error>> {
+    let mut frame = Mat::default();
+    for _ in 0..FIRST_FRAME_ATTEMPTS {
+        cam.read(&mut frame)?;
+        let size = frame.size()?;
+        if size.width > 0 && size.height > 0 {
+            return Ok((size.width as u32, size.height as u32));
+        }
+    }
+    Err(format!(
+        "The camera delivered no frame in {} attempts!",
+        FIRST_FRAME_ATTEMPTS
+    )
+    .into())
 }
 
 pub fn camera_thread_main(
     termination_signal: Arc<AtomicBool>,
     mut cam: videoio::VideoCapture,
-    shared_frame: Arc<RwLock<(Mat, SystemTime)>>,
-    parked_threads: Vec<&Thread>,
+    frame_transform: FrameTransform,
+    frames: LatestFrameSender,
+    dropped_frames: Arc<DroppedFrameCounter>,
+    pause: Arc<PauseControl>,
+    metrics: Arc<Metrics>,
+    mut recorder: Option<FrameRecorder>,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let is_v4l2 = cam.get_backend_name()? == "V4L2";
+    if is_v4l2 {
+        log::info!("Frames are stamped with the capture timestamps of the V4L2 driver");
+    }
+    let mut last_log = Instant::now();
+    let mut last_logged_dropped = 0;
     while !termination_signal.load(Ordering::Relaxed) {
+        if !pause.wait_for_capture(PAUSE_POLL_INTERVAL) {
+            continue;
+        }
         let mut frame = Mat::default();
         cam.read(&mut frame)?;
         if frame.size()?.width <= 0 {
             continue;
         }
-        let mut shared_frame_write = shared_frame.write().unwrap();
-        *shared_frame_write = (frame, SystemTime::now());
-        drop(shared_frame_write);
-        for thread in &parked_threads {
-            thread.unpark();
+        let timestamp = capture_timestamp(&cam, is_v4l2);
+        let frame = frame_transform.apply(frame)?;
+        if let Some(recorder) = &mut recorder {
+            recorder.record(&frame, timestamp);
+        }
+        frames.send(frame, timestamp);
+        metrics.frame_captured();
+        if last_log.elapsed() >= DROPPED_FRAME_LOG_INTERVAL {
+            let dropped = dropped_frames.dropped();
+            if dropped > last_logged_dropped {
+                log::info!(
+                    "Dropped {} frames in the last {:.1} seconds ({} in total)",
+                    dropped - last_logged_dropped,
+                    last_log.elapsed().as_secs_f64(),
+                    dropped
+                );
+            }
+            last_log = Instant::now();
+            last_logged_dropped = dropped;
         }
     }
+    // The consumer stops after receiving the last frame, once `frames` is dropped
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Make sure all threads are unparked
-    for thread in &parked_threads {
-        thread.unpark();
+    #[test]
+    fn test_scaled_to() {
+        // a synthetic lens with a focal length of 4mm on a 6.4mm x 3.6mm sensor, off-center by a few pixels
+        let camera_at = |resolution: (u32, u32)| {
+            let pixels_per_mm = resolution.0 as f64 / 6.4;
+            let focal = 4.0 * pixels_per_mm;
+            let center = (
+                resolution.0 as f64 / 2.0 - 0.5 + 0.02 * pixels_per_mm,
+                resolution.1 as f64 / 2.0 - 0.5 - 0.01 * pixels_per_mm,
+            );
+            let camera_mat = Mat::from_slice_2d(&[
+                [focal, 0.0, center.0],
+                [0.0, focal, center.1],
+                [0.0, 0.0, 1.0],
+            ])
+            .unwrap();
+            let distortion = Mat::from_slice(&[0.1, -0.2, 0.001, 0.002, 0.05])
+                .unwrap()
+                .try_clone()
+                .unwrap();
+            CameraProperty::new(resolution, (None, None), Some((camera_mat, distortion))).unwrap()
+        };
+        let calibrated = camera_at((1920, 1080));
+        for resolution in [(960, 540), (1280, 720), (3840, 2160)] {
+            let scaled = calibrated.scaled_to(resolution).unwrap();
+            assert_eq!(scaled.resolution, resolution);
+            let expected = camera_at(resolution).camera_mat_na().unwrap();
+            assert!((scaled.camera_mat_na().unwrap() - expected).amax() < 1e-9);
+            assert_eq!(
+                scaled.distortion().data_typed::<f64>().unwrap(),
+                calibrated.distortion().data_typed::<f64>().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_frame_transform() {
+        let project = |camera: &CameraProperty, points: &[na::Vector3<f64>]| {
+            let object_points = core::Vector::<core::Point3d>::from_iter(
+                points.iter().map(|p| core::Point3d::new(p.x, p.y, p.z)),
+            );
+            let mut image_points = core::Vector::<core::Point2d>::new();
+            calib3d::project_points_def(
+                &object_points,
+                &core::Vector::<f64>::from_iter([0.0, 0.0, 0.0]),
+                &core::Vector::<f64>::from_iter([0.0, 0.0, 0.0]),
+                camera.camera_mat(),
+                camera.distortion(),
+                &mut image_points,
+            )
+            .unwrap();
+            image_points
+                .iter()
+                .map(|p| na::vector![p.x, p.y])
+                .collect::<Vec<_>>()
+        };
+        let camera_mat =
+            Mat::from_slice_2d(&[[1400.0, 0.0, 1000.0], [0.0, 1350.0, 520.0], [0.0, 0.0, 1.0]])
+                .unwrap();
+        let camera = CameraProperty::new(
+            (1920, 1080),
+            (Some(f64::to_radians(70.0)), None),
+            Some((camera_mat, Mat::default())),
+        )
+        .unwrap();
+        let points = [
+            na::vector![0.0, 0.0, 1.0],
+            na::vector![0.3, -0.2, 1.0],
+            na::vector![-0.5, 0.1, 2.0],
+            na::vector![0.2, 0.4, 0.8],
+        ];
+        let transforms = [
+            FrameTransform::None,
+            FrameTransform::FlipH,
+            FrameTransform::FlipV,
+            FrameTransform::Rot180,
+            FrameTransform::Rot90Cw,
+            FrameTransform::Rot90Ccw,
+        ];
+        for distortion in [
+            &[0.1, -0.2, 0.001, 0.002, 0.05][..],
+            &[
+                0.1, -0.2, 0.001, 0.002, 0.05, 0.01, -0.02, 0.003, 0.001, -0.002, 0.003, 0.004,
+            ],
+        ] {
+            let camera = camera.clone().with_distortion(distortion).unwrap();
+            let original = project(&camera, &points);
+            for transform in transforms {
+                let transformed = camera.transformed(transform).unwrap();
+                assert_eq!(
+                    transformed.resolution,
+                    transform.resolution(camera.resolution)
+                );
+                // the virtual camera sees each point with its x and y moved like the image's axes
+                let mut axes = na::Matrix3::identity();
+                axes.fixed_view_mut::<2, 2>(0, 0)
+                    .copy_from(&transform.axes());
+                let moved = points.map(|p| axes * p);
+                for (expected, projected) in original.iter().zip(project(&transformed, &moved)) {
+                    let expected = transform.transform_pixel(*expected, camera.resolution);
+                    assert!((projected - expected).norm() < 1e-6);
+                }
+            }
+        }
+        let tilted = camera.with_distortion(&[0.01; 14]).unwrap();
+        assert!(tilted.transformed(FrameTransform::None).is_ok());
+        assert!(tilted.transformed(FrameTransform::FlipH).is_err());
+
+        // the frame is moved the same way as its pixels
+        let mut frame =
+            Mat::new_rows_cols_with_default(3, 5, core::CV_8UC1, core::Scalar::all(0.0)).unwrap();
+        *frame.at_2d_mut::<u8>(1, 3).unwrap() = 255;
+        for transform in transforms {
+            let transformed = transform.apply(frame.try_clone().unwrap()).unwrap();
+            let resolution = transform.resolution((5, 3));
+            assert_eq!(
+                (transformed.cols() as u32, transformed.rows() as u32),
+                resolution
+            );
+            let pixel = transform.transform_pixel(na::vector![3.0, 1.0], (5, 3));
+            let mut max_loc = core::Point::default();
+            core::min_max_loc(
+                &transformed,
+                None,
+                None,
+                None,
+                Some(&mut max_loc),
+                &core::no_array(),
+            )
+            .unwrap();
+            assert_eq!(na::vector![max_loc.x as f64, max_loc.y as f64], pixel);
+        }
+    }
+
+    #[test]
+    fn test_computed_fov() {
+        for fov in [
+            (Some(f64::to_radians(70.0)), Some(f64::to_radians(45.0))),
+            (Some(f64::to_radians(90.0)), None),
+            (None, Some(f64::to_radians(50.0))),
+        ] {
+            let camera = CameraProperty::new((1920, 1080), fov, None).unwrap();
+            let computed = camera.computed_fov();
+            if let Some(fov_x) = fov.0 {
+                assert!((computed.0 - fov_x).abs() < 1e-9);
+            }
+            if let Some(fov_y) = fov.1 {
+                assert!((computed.1 - fov_y).abs() < 1e-9);
+            }
+            // the FOV is also known from a camera matrix alone
+            let calibrated = CameraProperty::new(
+                camera.resolution,
+                (None, None),
+                Some((
+                    camera.camera_mat().try_clone().unwrap(),
+                    camera.distortion().try_clone().unwrap(),
+                )),
+            )
+            .unwrap();
+            let recomputed = calibrated.computed_fov();
+            assert!((recomputed.0 - computed.0).abs() < 1e-12);
+            assert!((recomputed.1 - computed.1).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_pause_control() {
+        let pause = PauseControl::new();
+        assert!(!pause.is_paused());
+        assert!(pause.wait_for_capture(Duration::ZERO));
+
+        // stepping a running camera pauses it without capturing
+        assert!(!pause.step());
+        assert!(pause.is_paused());
+        assert!(!pause.wait_for_capture(Duration::from_millis(10)));
+
+        // each step captures exactly one frame
+        assert!(pause.step());
+        assert!(pause.step());
+        assert!(pause.wait_for_capture(Duration::ZERO));
+        assert!(pause.wait_for_capture(Duration::ZERO));
+        assert!(!pause.wait_for_capture(Duration::ZERO));
+
+        // a step from another thread wakes the waiting camera thread
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                std::thread::sleep(Duration::from_millis(10));
+                pause.step();
+            });
+            assert!(pause.wait_for_capture(Duration::from_secs(10)));
+        });
+
+        assert!(!pause.toggle());
+        assert!(pause.wait_for_capture(Duration::ZERO));
+        pause.set_paused(true);
+        assert!(pause.is_paused());
+    }
+
+    #[test]
+    fn test_with_distortion() {
+        let camera = CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None)
+            .unwrap()
+            .with_distortion(&[0.1, -0.2, 0.001, 0.002, 0.05])
+            .unwrap();
+        assert_eq!(camera.distortion().total(), 5);
+        assert_eq!(*camera.distortion().at::<f64>(1).unwrap(), -0.2);
+        assert_eq!(*camera.distortion().at::<f64>(4).unwrap(), 0.05);
+
+        let camera =
+            CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+        assert!(camera.with_distortion(&[0.1, 0.2, 0.3]).is_err());
+    }
+
+    #[test]
+    fn test_with_principal_point() {
+        let camera =
+            CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+        let centered = camera.camera_mat_na().unwrap();
+        let camera = camera.with_principal_point((Some(1000.0), None)).unwrap();
+        let camera_mat = camera.camera_mat_na().unwrap();
+        assert_eq!(camera_mat[(0, 2)], 1000.0);
+        assert_eq!(camera_mat[(1, 2)], centered[(1, 2)]);
+        assert_eq!(
+            camera_mat.fixed_view::<2, 2>(0, 0),
+            centered.fixed_view::<2, 2>(0, 0)
+        );
+
+        // the optical axis is projected onto the principal point, and every other ray is shifted with it
+        let camera = camera.with_principal_point((None, Some(500.0))).unwrap();
+        let object_points = core::Vector::<core::Point3d>::from_iter([
+            core::Point3d::new(0.0, 0.0, 1.0),
+            core::Point3d::new(0.3, -0.2, 1.0),
+        ]);
+        let mut image_points = core::Vector::<core::Point2d>::new();
+        calib3d::project_points_def(
+            &object_points,
+            &core::Vector::<f64>::from_iter([0.0, 0.0, 0.0]),
+            &core::Vector::<f64>::from_iter([0.0, 0.0, 0.0]),
+            camera.camera_mat(),
+            camera.distortion(),
+            &mut image_points,
+        )
+        .unwrap();
+        let axis = image_points.get(0).unwrap();
+        assert!((axis.x - 1000.0).abs() < 1e-9);
+        assert!((axis.y - 500.0).abs() < 1e-9);
+        let off_axis = image_points.get(1).unwrap();
+        let centered_off_axis = centered * na::vector![0.3, -0.2, 1.0];
+        assert!((off_axis.x - centered_off_axis.x - (1000.0 - centered[(0, 2)])).abs() < 1e-9);
+        assert!((off_axis.y - centered_off_axis.y - (500.0 - centered[(1, 2)])).abs() < 1e-9);
+        let ray = camera.pixel_to_ray(na::vector![1000.0, 500.0]).unwrap();
+        assert!((ray - na::Vector3::z()).norm() < 1e-9);
+    }
+
+    #[test]
+    fn test_fitted_to_frames() {
+        let camera =
+            CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+        let fitted = camera.fitted_to_frames((1920, 1080)).unwrap();
+        assert_eq!(fitted.resolution, (1920, 1080));
+        assert_eq!(
+            fitted.camera_mat_na().unwrap(),
+            camera.camera_mat_na().unwrap()
+        );
+
+        // a driver scaling the sensor image keeps the field of view
+        let fitted = camera.fitted_to_frames((1280, 720)).unwrap();
+        assert_eq!(fitted.resolution, (1280, 720));
+        let (fov_x, fov_y) = fitted.computed_fov();
+        assert!((fov_y - f64::to_radians(50.0)).abs() < 1e-3);
+        assert!((fov_x - camera.computed_fov().0).abs() < 1e-3);
+
+        // a driver cropping the sensor image can't be fitted
+        let error = camera.fitted_to_frames((640, 480)).unwrap_err();
+        assert!(error.to_string().contains("640x480"));
+        assert!(error.to_string().contains("1920x1080"));
+    }
+
+    #[test]
+    fn test_frame_period() {
+        let camera =
+            CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+        assert_eq!(camera.frame_period(), None);
+        let camera = camera.with_fps(15.0);
+        assert_eq!(camera.fps, Some(15.0));
+        assert!((camera.frame_period().unwrap().as_secs_f64() - 1.0 / 15.0).abs() < 1e-9);
+        // drivers report 0 when the frame rate is unknown
+        assert_eq!(camera.with_fps(0.0).fps, None);
+    }
+
+    #[test]
+    fn test_from_intrinsics() {
+        let camera =
+            CameraProperty::from_intrinsics((1920, 1080), (None, Some(50.0)), None, None).unwrap();
+        let expected =
+            CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+        assert_eq!(
+            camera.camera_mat_na().unwrap(),
+            expected.camera_mat_na().unwrap()
+        );
+
+        // the camera matrix overrides the fields of view
+        let camera = CameraProperty::from_intrinsics(
+            (1920, 1080),
+            (None, Some(50.0)),
+            Some(&[1400.0, 1350.0, 1000.0, 520.0]),
+            Some(&[0.1, -0.05, 0.0, 0.0, 0.0]),
+        )
+        .unwrap();
+        assert_eq!(
+            camera.camera_mat_na().unwrap(),
+            na::Matrix3::new(1400.0, 0.0, 1000.0, 0.0, 1350.0, 520.0, 0.0, 0.0, 1.0)
+        );
+        assert_eq!(
+            camera.distortion().data_typed::<f64>().unwrap(),
+            [0.1, -0.05, 0.0, 0.0, 0.0]
+        );
+
+        assert!(
+            CameraProperty::from_intrinsics((1920, 1080), (None, None), Some(&[1400.0]), None)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_pixel_to_ray() {
+        let camera =
+            CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+        let camera_mat = camera.camera_mat_na().unwrap();
+        let center = na::vector![camera_mat[(0, 2)], camera_mat[(1, 2)]];
+        let ray = camera.pixel_to_ray(center).unwrap();
+        assert!((ray - na::Vector3::z()).norm() < 1e-9);
+
+        // the top edge of the image is at half of the vertical field of view above the optical axis
+        let top = camera.pixel_to_ray(na::vector![center.x, 0.0]).unwrap();
+        assert!(top.y < 0.0);
+        assert!((top.angle(&na::Vector3::z()) - f64::to_radians(25.0)).abs() < 1e-9);
+
+        // without distortion, a point is projected with the camera matrix alone
+        let point = na::point![0.3, -0.2, 2.0];
+        let pinhole = camera_mat * point;
+        let projected = camera.project_point(&point).unwrap();
+        assert!((projected - pinhole.coords.xy() / pinhole.z).norm() < 1e-9);
+
+        // un-projecting a distorted projection recovers the ray
+        let camera = camera
+            .with_distortion(&[0.1, -0.05, 0.001, 0.002, 0.01])
+            .unwrap();
+        let expected = na::vector![0.3, -0.2, 1.0].normalize();
+        let projected = camera.project_point(&na::Point3::from(expected)).unwrap();
+        let ray = camera.pixel_to_ray(projected).unwrap();
+        assert!((ray - expected).norm() < 1e-4);
+    }
+
+    #[test]
+    fn test_dropped_frame_counter() {
+        let counter = DroppedFrameCounter::new();
+        counter.frame_produced();
+        counter.frame_consumed();
+        counter.frame_produced();
+        assert_eq!(counter.dropped(), 0);
+        // two more frames arrive before the consumer reads the pending one
+        counter.frame_produced();
+        counter.frame_produced();
+        assert_eq!(counter.dropped(), 2);
+        counter.frame_consumed();
+        counter.frame_produced();
+        assert_eq!(counter.dropped(), 2);
+    }
+
+    #[test]
+    fn test_latest_frame_channel() {
+        let dropped_frames = Arc::new(DroppedFrameCounter::new());
+        let (sender, receiver) = latest_frame_channel(dropped_frames.clone());
+        let start = FrameTimestamp::now();
+        let at = |frame: u32| start + Duration::from_millis(33) * frame;
+
+        // only the newest frame is received, and the replaced frames are counted as dropped
+        assert!(!sender.send(Mat::default(), at(0)));
+        assert!(sender.send(Mat::default(), at(1)));
+        assert!(sender.send(Mat::default(), at(2)));
+        assert_eq!(receiver.recv().unwrap().1, at(2));
+        assert_eq!(dropped_frames.dropped(), 2);
+
+        // the last frame is still received after the sender is dropped
+        assert!(!sender.send(Mat::default(), at(3)));
+        drop(sender);
+        assert_eq!(receiver.recv().unwrap().1, at(3));
+        assert!(receiver.recv().is_none());
+        assert_eq!(dropped_frames.dropped(), 2);
+    }
+
+    #[test]
+    fn test_latest_frame_channel_under_load() {
+        const FRAMES: u32 = 2000;
+        let dropped_frames = Arc::new(DroppedFrameCounter::new());
+        let (sender, receiver) = latest_frame_channel(dropped_frames.clone());
+        let start = FrameTimestamp::now();
+        let consumer = std::thread::spawn(move || {
+            let mut received = Vec::new();
+            while let Some((_, timestamp)) = receiver.recv() {
+                received.push(timestamp);
+                // a slow consumer, which misses some frames
+                if received.len() % 3 == 0 {
+                    std::thread::sleep(Duration::from_micros(200));
+                }
+            }
+            received
+        });
+        for frame in 0..FRAMES {
+            sender.send(Mat::default(), start + Duration::from_micros(1) * frame);
+        }
+        drop(sender);
+        let received = consumer.join().unwrap();
+
+        // no frame is received twice or out of order, and the last frame is never missed
+        assert!(
+            received
+                .windows(2)
+                .all(|pair| pair[0].instant < pair[1].instant)
+        );
+        assert_eq!(
+            received.last().unwrap().instant,
+            start.instant + Duration::from_micros(1) * (FRAMES - 1)
+        );
+        assert_eq!(dropped_frames.dropped(), FRAMES as usize - received.len());
+    }
+
+    #[test]
+    fn test_capture_age() {
+        let age = capture_age(1000.0, 1030.0).unwrap();
+        assert!((age.as_secs_f64() - 0.03).abs() < 1e-9);
+        // missing timestamp
+        assert_eq!(capture_age(0.0, 1030.0), None);
+        // timestamps in the future or too old are bogus
+        assert_eq!(capture_age(1100.0, 1030.0), None);
+        assert_eq!(capture_age(1000.0, 5000.0), None);
     }
-    Ok(())
 }
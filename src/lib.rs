@@ -4,7 +4,9 @@
 
 pub mod camera;
 pub mod facial;
+pub mod metrics;
 pub mod net;
+pub mod recording;
 pub mod tag;
 pub mod utils;
 
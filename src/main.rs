@@ -1,23 +1,43 @@
 use std::error::Error;
-use std::fs::File;
-use std::path::Path;
+use std::fs::{self, File};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::AtomicBool;
-use std::sync::{Condvar, Mutex, RwLock};
-use std::time::SystemTime;
+use std::sync::{Condvar, Mutex};
 use std::{collections::HashMap, sync::Arc};
 use std::{env, thread};
 
-use clap::Parser;
+use clap::{ArgGroup, Parser};
 use map_macro::hash_map;
 use opencv::prelude::*;
-use opencv::videoio;
+use opencv::{core, videoio};
 
-use xDIMScreen_locator::camera::{CameraProperty, camera_thread_main};
-use xDIMScreen_locator::net::server_thread_main;
-use xDIMScreen_locator::tag::apriltag::{ApriltagDetector, ApriltagFamily, ApriltagFamilyType};
+use xDIMScreen_locator::camera::{
+    CameraProperty, DroppedFrameCounter, FrameTransform, PauseControl, camera_thread_main,
+    latest_frame_channel, read_frame_resolution,
+};
+use xDIMScreen_locator::metrics::Metrics;
+#[cfg(feature = "http")]
+use xDIMScreen_locator::net::http::http_thread_main;
+use xDIMScreen_locator::net::packet::{AxisRemap, RotationFormat, WireFormat};
+#[cfg(all(feature = "shm", unix))]
+use xDIMScreen_locator::net::shm::shm_thread_main;
+#[cfg(feature = "tls")]
+use xDIMScreen_locator::net::tls::load_server_config;
+use xDIMScreen_locator::net::{
+    EmitThreshold, ServerSecurity, resample_thread_main, server_thread_main,
+};
+use xDIMScreen_locator::recording::{FrameRecorder, recorder_thread_main};
+use xDIMScreen_locator::tag::apriltag::{
+    ApriltagDetector, ApriltagFamily, ApriltagFamilySet, DEFAULT_BITS_CORRECTED,
+};
 use xDIMScreen_locator::tag::locator::{LocatedObjects, TaggedObjectLocator};
-use xDIMScreen_locator::tag::locator_thread_main;
-use xDIMScreen_locator::tag::tagged_object::{TagIndex, TaggedObject};
+use xDIMScreen_locator::tag::tagged_object::{
+    TagIndex, TaggedObject, ValidationMode, load_tagobj_dir_with_mode,
+};
+use xDIMScreen_locator::tag::{
+    Preprocessing, detect_only_thread_main, locator_thread_main, self_test_thread_main,
+};
 
 #[cfg(feature = "visualize")]
 use xDIMScreen_locator::visualize::visualize_thread_main;
@@ -38,8 +58,93 @@ fn load_object_from_resources(
     Ok(ret)
 }
 
+/// A single tag registered as a standalone object, parsed from `<family>:<id>:<size>`.
+#[derive(Debug, Clone)]
+struct SimpleTag {
+    name: String,
+    family: ApriltagFamily,
+    id: i32,
+    size: f64,
+}
+
+fn parse_simple_tag(value: &str) -> Result<SimpleTag, String> {
+    let [family_name, id, size] = value.split(':').collect::<Vec<_>>()[..] else {
+        return Err(format!(
+            "Expected `<family>:<id>:<size>`, e.g. `tag36h11:5:60`, but got `{}`",
+            value
+        ));
+    };
+    let family = ApriltagFamily::try_from(family_name).map_err(|e| e.to_string())?;
+    let id = id
+        .parse::<i32>()
+        .map_err(|e| format!("Invalid tag ID `{}`: {}", id, e))?;
+    let size = size
+        .parse::<f64>()
+        .map_err(|e| format!("Invalid tag size `{}`: {}", size, e))?;
+    if size.is_nan() || size <= 0.0 {
+        return Err(format!("Tag size must be positive, but got {}", size));
+    }
+    Ok(SimpleTag {
+        name: format!("tag_{}_{}", family_name.trim_start_matches("tag"), id),
+        family,
+        id,
+        size,
+    })
+}
+
+fn parse_family(value: &str) -> Result<ApriltagFamily, String> {
+    ApriltagFamily::try_from(value.trim()).map_err(|e| e.to_string())
+}
+
+fn parse_roi(value: &str) -> Result<core::Rect, String> {
+    let [x, y, width, height] = value
+        .split(',')
+        .map(|v| {
+            v.trim()
+                .parse::<i32>()
+                .map_err(|e| format!("Invalid ROI value `{}`: {}", v, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?[..]
+    else {
+        return Err(format!(
+            "Expected `<x>,<y>,<width>,<height>`, e.g. `320,180,640,360`, but got `{}`",
+            value
+        ));
+    };
+    if x < 0 || y < 0 || width <= 0 || height <= 0 {
+        return Err(format!(
+            "ROI must have a non-negative position and a positive size, but got `{}`",
+            value
+        ));
+    }
+    Ok(core::Rect::new(x, y, width, height))
+}
+
+fn parse_detect_scale(value: &str) -> Result<f64, String> {
+    let scale = value
+        .trim()
+        .parse::<f64>()
+        .map_err(|e| format!("Invalid detection scale `{}`: {}", value, e))?;
+    if !(scale > 0.0 && scale <= 1.0) {
+        return Err(format!(
+            "Detection scale must be in (0, 1], but got `{}`",
+            value
+        ));
+    }
+    Ok(scale)
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about)]
+// The camera's intrinsics come from exactly one source, needed up front: its field of view, or its calibrated
+// camera matrix, which conflicts with the fields of view. Both fields of view may be given together, since
+// they constrain the x and y focal lengths independently.
+#[command(group(
+    ArgGroup::new("intrinsics")
+        .required(true)
+        .multiple(true)
+        .args(["cam_fov_x", "cam_fov_y", "camera_mat"])
+))]
 struct Args {
     /// The device index of the camera to calibrate. Laptop's builtin camera is usually at index 0.
     #[arg(short, long, default_value_t = 0)]
@@ -53,34 +158,220 @@ struct Args {
     #[arg(long, default_value_t = 1080)]
     cam_res_y: u32,
 
-    /// The camera's field of view on x direction. Unit: degrees. Not necessary if the camera matrix is provided.
+    /// The camera's field of view on x direction. Unit: degrees. Either this, `--cam-fov-y`, or
+    /// `--camera-mat` is required; it can be calculated from the camera matrix printed by the
+    /// `camera_calibration` binary as `2 * atan((cam_res_x - 1) / (2 * fx))`.
     #[arg(long)]
     cam_fov_x: Option<f64>,
 
-    /// The camera's field of view on x direction. Unit: degrees. Not necessary if the camera matrix is provided.
+    /// The camera's field of view on y direction. Unit: degrees. Either this, `--cam-fov-x`, or
+    /// `--camera-mat` is required; it can be calculated from the camera matrix printed by the
+    /// `camera_calibration` binary as `2 * atan((cam_res_y - 1) / (2 * fy))`.
     #[arg(long)]
     cam_fov_y: Option<f64>,
 
+    /// The camera matrix printed by the `camera_calibration` binary, as `fx,fy,cx,cy` in pixels. Used instead
+    /// of the fields of view and the principal point.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        conflicts_with_all = ["cam_fov_x", "cam_fov_y", "cam_cx", "cam_cy"]
+    )]
+    camera_mat: Option<Vec<f64>>,
+
+    /// The frame rate requested from the camera. Unit: frames per second. The driver's default if unset.
+    #[arg(long)]
+    cam_fps: Option<f64>,
+
+    /// The camera's lens distortion coefficients, separated by commas, e.g. `k1,k2,p1,p2,k3`. No distortion
+    /// if unset.
+    #[arg(long, value_delimiter = ',', allow_negative_numbers = true)]
+    distortion: Option<Vec<f64>>,
+
+    /// The x coordinate of the camera's principal point. Unit: pixels. The image center if unset.
+    #[arg(long)]
+    cam_cx: Option<f64>,
+
+    /// The y coordinate of the camera's principal point. Unit: pixels. The image center if unset.
+    #[arg(long)]
+    cam_cy: Option<f64>,
+
+    /// The flip or rotation applied to each frame before detection, e.g. `rot180` for a camera mounted upside
+    /// down. The objects are located relative to the transformed frame, and the resolution, field of view
+    /// and camera matrix above are those of the untransformed frame.
+    #[arg(long, value_enum, default_value_t = FrameTransform::None)]
+    frame_transform: FrameTransform,
+
+    /// The variance of each detected tag corner's coordinates. Unit: pixels squared.
+    #[arg(long, default_value_t = 2.0)]
+    corner_variance: f64,
+
     /// Number of threads used by the apriltag detector.
     #[arg(long, default_value_t = 4)]
     detector_nthreads: usize,
+
+    /// The format in which the server sends the located objects.
+    #[arg(long, value_enum, default_value_t = WireFormat::Json)]
+    format: WireFormat,
+
+    /// The representation of the objects' rotations in the packets sent by the server.
+    #[arg(long, value_enum, default_value_t = RotationFormat::Quaternion)]
+    rotation_format: RotationFormat,
+
+    /// The axes of the located objects' transforms in the packets sent by the server, as three
+    /// comma-separated signed axes of the camera frame (+x right, +y down, +z forward). For example,
+    /// `x,-y,z` converts to Unity's left-handed frame with +y up.
+    #[arg(long, default_value = "x,y,z", allow_hyphen_values = true)]
+    axis_remap: AxisRemap,
+
+    /// The length unit of the tag sizes in the tagobj files, which is also the unit of the located
+    /// objects' translations. Sent to each client when it connects.
+    #[arg(long, default_value = "mm")]
+    unit: String,
+
+    /// The address at which the TCP server accepts clients. Bind to `0.0.0.0:30002` to accept clients on other
+    /// machines, preferably with `--server-token-file` and TLS.
+    #[arg(long, default_value = "127.0.0.1:30002")]
+    server_address: SocketAddr,
+
+    /// A file containing the token that each client must send before it receives any packet. Clients are
+    /// not authenticated if unset.
+    #[arg(long)]
+    server_token_file: Option<PathBuf>,
+
+    /// The PEM file of the TCP server's certificate chain. Together with `--tls-key`, clients are connected
+    /// over TLS instead of plain TCP.
+    #[cfg(feature = "tls")]
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// The PEM file of the TCP server's private key.
+    #[cfg(feature = "tls")]
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Send the located objects at a fixed rate (in Hz) instead of once per camera frame. Between the
+    /// frames, the objects are extrapolated from their estimated velocities.
+    #[arg(long)]
+    send_rate: Option<f64>,
+
+    /// Send all tags detected in each frame (family, ID, corners and decision margin) to the clients before
+    /// the frame's located objects, e.g. to debug the detection.
+    #[arg(long, conflicts_with = "send_rate")]
+    send_detections: bool,
+
+    /// The distance an object must move since its last packet for the TCP server to send it again, in the
+    /// unit of the objects' tags. This saves bandwidth on static objects. If `--emit-rotation-threshold` is
+    /// also set, an object is sent again if it moves or rotates enough. The translation is ignored if unset.
+    #[arg(long, default_value_t = 0.0)]
+    emit_translation_threshold: f64,
+
+    /// The angle an object must rotate since its last packet for the TCP server to send it again. Unit:
+    /// degrees. The rotation is ignored if unset, and every packet is sent if neither threshold is set.
+    #[arg(long, default_value_t = 0.0)]
+    emit_rotation_threshold: f64,
+
+    /// The port of the HTTP server streaming the located objects to browsers as Server-Sent Events at
+    /// `/poses`, with the latest frame at `/snapshot` and the pipeline's metrics at `/metrics`. The HTTP server
+    /// is not started if unset.
+    #[cfg(feature = "http")]
+    #[arg(long)]
+    http_port: Option<u16>,
+
+    /// A file to which the located objects of each frame are published as a shared-memory ring buffer, for
+    /// consumers on the same machine, e.g. `/dev/shm/xdimscreen-poses`. See `net::shm` for the layout. Not
+    /// published if unset.
+    #[cfg(all(feature = "shm", unix))]
+    #[arg(long)]
+    shm_path: Option<PathBuf>,
+
+    /// A directory from which all version 2 tagobj files are loaded, in addition to the built-in objects.
+    #[arg(long)]
+    tagobj_dir: Option<PathBuf>,
+
+    /// Fail with the JSON path of the invalid field when a tag entry in the tagobj files of `--tagobj-dir`
+    /// is invalid, instead of skipping the tag with a warning.
+    #[arg(long)]
+    strict_tagobj: bool,
+
+    /// Register a single tag as a standalone object, in the format `<family>:<id>:<size>`, e.g.
+    /// `tag36h11:5:60`. The object is named after the tag, e.g. `tag_36h11_5`. Can be repeated.
+    #[arg(long, value_parser = parse_simple_tag)]
+    simple_tag: Vec<SimpleTag>,
+
+    /// The maximum number of bit errors corrected in each detected tag. More bits detect more tags at the
+    /// price of more false positives.
+    #[arg(long, default_value_t = DEFAULT_BITS_CORRECTED)]
+    bits_corrected: i32,
+
+    /// The sigma (in pixels) of the gaussian blur applied to each frame before detection. Helps detection
+    /// on noisy footage. No blur is applied if unset.
+    #[arg(long)]
+    preblur_sigma: Option<f64>,
+
+    /// Stretch each frame's contrast to the full intensity range before detection.
+    #[arg(long)]
+    stretch_contrast: bool,
+
+    /// Only detect tags in a region of the frame, in the format `<x>,<y>,<width>,<height>` in pixels. Speeds
+    /// up detection at high resolutions when the objects only appear in part of the frame.
+    #[arg(long, value_parser = parse_roi)]
+    roi: Option<core::Rect>,
+
+    /// Downscale each frame by this factor, e.g. `0.5`, before detection, and scale the detected corners
+    /// back to the full resolution. Speeds up detection at high resolutions at the cost of missing small
+    /// tags. Unlike the detector's quad decimation, the whole detection runs on the downscaled frame.
+    #[arg(long, value_parser = parse_detect_scale)]
+    detect_scale: Option<f64>,
+
+    /// Only run the apriltag detector and log the detections of each frame, without locating objects or
+    /// starting the server.
+    #[arg(long)]
+    detect_only: bool,
+
+    /// Only locate a single reference tag in the format of `--simple-tag`, e.g. `tag36h11:0:60`, held facing
+    /// the camera at `--expected-distance`, and log how far off its located distance is every second. This
+    /// checks the intrinsics and the tag size of a new setup.
+    #[arg(long, value_parser = parse_simple_tag, requires = "expected_distance")]
+    self_test: Option<SimpleTag>,
+
+    /// The distance between the camera and the tag of `--self-test` along the camera's optical axis, measured
+    /// by hand, in the same unit as the tag's size.
+    #[arg(long, requires = "self_test")]
+    expected_distance: Option<f64>,
+
+    /// The tag families detected in `--detect-only` mode, as comma-separated names, e.g. `tag36h11,tag25h9`.
+    /// When locating objects, the families used by the registered objects are detected instead.
+    #[arg(long, value_parser = parse_family, value_delimiter = ',', default_value = "tag36h11")]
+    families: Vec<ApriltagFamily>,
+
+    /// A directory to which the captured frames are saved as PNG files named by their capture time, with
+    /// a `manifest.csv` listing each file's capture time. Frames are dropped if the disk falls behind.
+    #[arg(long)]
+    record_frames: Option<PathBuf>,
+
+    /// Only record every Nth captured frame with `--record-frames`.
+    #[arg(long, default_value_t = 1)]
+    record_every: usize,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
+    // `RUST_LOG` may enable more logs, e.g. `RUST_LOG=poses=debug` for the located objects' summaries
     env_logger::Builder::new()
         .filter_level(log::LevelFilter::Info)
+        .parse_default_env()
         .try_init()?;
     let args = Args::parse();
 
     // prepare camera
-    let camera_prop = CameraProperty::new(
+    let camera_prop = CameraProperty::from_intrinsics(
         (args.cam_res_x, args.cam_res_y),
-        (
-            args.cam_fov_x.map(f64::to_radians),
-            args.cam_fov_y.map(f64::to_radians),
-        ),
-        None,
-    )?;
+        (args.cam_fov_x, args.cam_fov_y),
+        args.camera_mat.as_deref(),
+        args.distortion.as_deref(),
+    )?
+    .with_corner_variance((args.corner_variance, args.corner_variance))
+    .with_principal_point((args.cam_cx, args.cam_cy))?;
     log::info!("Camera matrix: {}", camera_prop.camera_mat_na().unwrap());
     let mut cam = videoio::VideoCapture::new(args.cam_id, videoio::CAP_ANY)?;
     cam.set(
@@ -91,9 +382,132 @@ fn main() -> Result<(), Box<dyn Error>> {
         videoio::CAP_PROP_FRAME_HEIGHT,
         camera_prop.resolution.1 as f64,
     )?;
+    if let Some(fps) = args.cam_fps {
+        cam.set(videoio::CAP_PROP_FPS, fps)?;
+    }
+    let actual_fps = cam.get(videoio::CAP_PROP_FPS)?;
+    log::info!("Camera frame rate: {} fps", actual_fps);
+    if let Some(fps) = args.cam_fps
+        && (actual_fps - fps).abs() > 0.5
+    {
+        log::warn!(
+            "Requested {} fps from the camera, but the driver runs it at {} fps.",
+            fps,
+            actual_fps
+        );
+    }
+    // the driver may ignore the requested resolution, which the camera matrix is built for
+    let frame_resolution = read_frame_resolution(&mut cam)?;
+    let camera_prop = camera_prop
+        .fitted_to_frames(frame_resolution)?
+        .with_fps(actual_fps)
+        .transformed(args.frame_transform)?;
+    if args.frame_transform != FrameTransform::None {
+        log::info!(
+            "Transformed camera matrix: {}",
+            camera_prop.camera_mat_na().unwrap()
+        );
+    }
+
+    // the frame recorder is handed to the camera thread, and the frames are written in their own thread
+    let (recorder, recorded_frames) = match &args.record_frames {
+        Some(_) => {
+            let (recorder, receiver) = FrameRecorder::new(args.record_every);
+            (Some(recorder), Some(receiver))
+        }
+        None => (None, None),
+    };
+
+    let server_security = ServerSecurity {
+        token: match &args.server_token_file {
+            Some(path) => Some(fs::read_to_string(path)?.trim().to_string()),
+            None => None,
+        },
+        #[cfg(feature = "tls")]
+        tls: match (&args.tls_cert, &args.tls_key) {
+            (Some(cert), Some(key)) => Some(load_server_config(cert, key)?),
+            _ => None,
+        },
+    };
+    if server_security.token.as_deref() == Some("") {
+        return Err("The server token file is empty!".into());
+    }
+
+    let preprocessing = Preprocessing {
+        blur_sigma: args.preblur_sigma,
+        stretch_contrast: args.stretch_contrast,
+        detect_scale: args.detect_scale,
+    };
+
+    if let (Some(tag), Some(expected_distance)) = (&args.self_test, args.expected_distance) {
+        let tag_object = TaggedObject::new_simple(tag.name.clone(), tag.family, tag.id, tag.size);
+        thread::scope(|s| {
+            let termination_signal = Arc::new(AtomicBool::new(false));
+            let dropped_frames = Arc::new(DroppedFrameCounter::new());
+            let (frame_sender, frame_receiver) = latest_frame_channel(dropped_frames.clone());
+            let pause = Arc::new(PauseControl::new());
+            let metrics = Arc::new(Metrics::new(dropped_frames.clone()));
+
+            // start self-test thread
+            let termination_signal_clone = termination_signal.clone();
+            let tag_object = &tag_object;
+            let camera_prop = camera_prop.clone();
+            let _ = s.spawn(move || {
+                // the families must outlive the detector
+                let family_set = ApriltagFamilySet::new();
+                let detector = ApriltagDetector::new_multithreading(args.detector_nthreads)
+                    .quad_sigma(-10.0)
+                    .set_families(&family_set, &[tag.family], args.bits_corrected);
+
+                self_test_thread_main(
+                    termination_signal_clone,
+                    frame_receiver,
+                    detector,
+                    preprocessing,
+                    camera_prop,
+                    tag_object,
+                    expected_distance,
+                )
+                .unwrap();
+            });
+
+            // start camera thread
+            let _ = s.spawn(move || {
+                camera_thread_main(
+                    termination_signal,
+                    cam,
+                    args.frame_transform,
+                    frame_sender,
+                    dropped_frames,
+                    pause,
+                    metrics,
+                    None,
+                )
+                .unwrap();
+            });
+        });
+        return Ok(());
+    }
 
     // load objects
+    let dir_objects = match &args.tagobj_dir {
+        Some(dir) => load_tagobj_dir_with_mode(
+            dir,
+            if args.strict_tagobj {
+                ValidationMode::Strict
+            } else {
+                ValidationMode::Lenient
+            },
+        )?,
+        None => Vec::new(),
+    };
+    let simple_objects = args
+        .simple_tag
+        .iter()
+        .map(|tag| TaggedObject::new_simple(tag.name.clone(), tag.family, tag.id, tag.size))
+        .collect::<Vec<_>>();
     let mut locator = TaggedObjectLocator::new(camera_prop.clone());
+    locator.set_record_detections(args.send_detections);
     let handheld_screen = load_object_from_resources(
         "handheld-screen.tagobj",
         "handheld screen",
@@ -141,53 +555,202 @@ fn main() -> Result<(), Box<dyn Error>> {
         },
     )?;
     locator.add(&fractal_tag)?;
+    for object in &dir_objects {
+        locator.add(object)?;
+    }
+    for object in &simple_objects {
+        locator.add(object)?;
+        log::info!("Registered simple tag object \"{}\"", object.name);
+    }
 
     // A thread scope is used here to resolve the lifetime issue.
     // Otherwise, the compiler will think that the objects need to be borrowed for 'static.
+    if args.detect_only {
+        thread::scope(|s| {
+            let termination_signal = Arc::new(AtomicBool::new(false));
+            let dropped_frames = Arc::new(DroppedFrameCounter::new());
+            let (frame_sender, frame_receiver) = latest_frame_channel(dropped_frames.clone());
+            let pause = Arc::new(PauseControl::new());
+            let metrics = Arc::new(Metrics::new(dropped_frames.clone()));
+
+            // start detector thread
+            let termination_signal_clone = termination_signal.clone();
+            let _ = s.spawn(move || {
+                // the families must outlive the detector
+                let family_set = ApriltagFamilySet::new();
+                let detector = ApriltagDetector::new_multithreading(args.detector_nthreads)
+                    .quad_sigma(-10.0)
+                    .set_families(&family_set, &args.families, args.bits_corrected);
+
+                detect_only_thread_main(
+                    termination_signal_clone,
+                    frame_receiver,
+                    detector,
+                    preprocessing,
+                )
+                .unwrap();
+            });
+
+            // start frame writer thread
+            if let (Some(dir), Some(recorded_frames)) = (&args.record_frames, recorded_frames) {
+                let _ =
+                    s.spawn(move || recorder_thread_main(dir.clone(), recorded_frames).unwrap());
+            }
+
+            // start camera thread
+            let _ = s.spawn(move || {
+                camera_thread_main(
+                    termination_signal,
+                    cam,
+                    args.frame_transform,
+                    frame_sender,
+                    dropped_frames,
+                    pause,
+                    metrics,
+                    recorder,
+                )
+                .unwrap();
+            });
+        });
+        return Ok(());
+    }
+
     thread::scope(|s| {
         let termination_signal = Arc::new(AtomicBool::new(false));
-        let shared_frame = Arc::new(RwLock::new((Mat::default(), SystemTime::UNIX_EPOCH)));
+        let dropped_frames = Arc::new(DroppedFrameCounter::new());
+        let (frame_sender, frame_receiver) = latest_frame_channel(dropped_frames.clone());
+        let pause = Arc::new(PauseControl::new());
+        let metrics = Arc::new(Metrics::new(dropped_frames.clone()));
         let located_objects = Arc::new((Mutex::new(LocatedObjects::new()), Condvar::new()));
+        // the objects sent by the servers, which are resampled at a fixed rate if requested
+        let sent_objects = match args.send_rate {
+            Some(_) => Arc::new((Mutex::new(LocatedObjects::new()), Condvar::new())),
+            None => located_objects.clone(),
+        };
+
+        // start resample thread
+        if let Some(send_rate) = args.send_rate {
+            let termination_signal_clone = termination_signal.clone();
+            let located_objects_clone = located_objects.clone();
+            let sent_objects_clone = sent_objects.clone();
+            let _ = s.spawn(move || {
+                resample_thread_main(
+                    termination_signal_clone,
+                    send_rate,
+                    located_objects_clone,
+                    sent_objects_clone,
+                )
+                .unwrap()
+            });
+        }
 
         // start server thread
         let termination_signal_clone = termination_signal.clone();
-        let located_objects_clone = located_objects.clone();
+        let located_objects_clone = sent_objects.clone();
+        let metrics_clone = metrics.clone();
         let _ = s.spawn(move || {
-            server_thread_main(termination_signal_clone, 30002, located_objects_clone).unwrap()
+            server_thread_main(
+                termination_signal_clone,
+                args.server_address,
+                args.format,
+                args.rotation_format,
+                args.axis_remap,
+                &args.unit,
+                located_objects_clone,
+                metrics_clone,
+                server_security,
+                args.send_detections,
+                EmitThreshold {
+                    translation: args.emit_translation_threshold,
+                    rotation: args.emit_rotation_threshold.to_radians(),
+                },
+            )
+            .unwrap()
         });
 
+        // start HTTP server thread
+        #[cfg(feature = "http")]
+        if let Some(http_port) = args.http_port {
+            let termination_signal_clone = termination_signal.clone();
+            let located_objects_clone = sent_objects.clone();
+            let metrics_clone = metrics.clone();
+            let _ = s.spawn(move || {
+                http_thread_main(
+                    termination_signal_clone,
+                    http_port,
+                    args.rotation_format,
+                    args.axis_remap,
+                    located_objects_clone,
+                    metrics_clone,
+                )
+                .unwrap()
+            });
+        }
+
+        // start shared-memory thread
+        #[cfg(all(feature = "shm", unix))]
+        if let Some(shm_path) = &args.shm_path {
+            let termination_signal_clone = termination_signal.clone();
+            let located_objects_clone = sent_objects.clone();
+            let _ = s.spawn(move || {
+                shm_thread_main(
+                    termination_signal_clone,
+                    shm_path,
+                    args.axis_remap,
+                    located_objects_clone,
+                )
+                .unwrap()
+            });
+        }
+
         // start locator thread
         let termination_signal_clone = termination_signal.clone();
-        let shared_frame_clone = shared_frame.clone();
+        let pause_clone = pause.clone();
+        let metrics_clone = metrics.clone();
         let located_objects_clone = located_objects.clone();
 
         #[cfg(feature = "visualize")]
         let object_map = locator.get_object_map(); // this object need to be created before locator thread launches
 
-        let locator_thread = s.spawn(move || {
-            // construct the apriltag detector in the locator thread
-            let mut family_tag36h11 = ApriltagFamilyType::new(ApriltagFamily::Tag36h11);
-            let detector = ApriltagDetector::new_multithreading(args.detector_nthreads)
-                .add_family(&mut family_tag36h11)
-                .quad_sigma(-10.0);
+        let _ = s.spawn(move || {
+            // construct the apriltag detector in the locator thread. The families are added by the locator
+            // thread according to the registered objects, and must outlive the detector.
+            let family_set = ApriltagFamilySet::new();
+            let detector =
+                ApriltagDetector::new_multithreading(args.detector_nthreads).quad_sigma(-10.0);
 
             locator_thread_main(
                 termination_signal_clone,
-                shared_frame_clone,
+                frame_receiver,
+                pause_clone,
+                metrics_clone,
                 detector,
+                &family_set,
+                args.bits_corrected,
+                preprocessing,
+                args.roi,
                 locator,
                 located_objects_clone,
             )
             .unwrap();
         });
 
+        // start frame writer thread
+        if let (Some(dir), Some(recorded_frames)) = (&args.record_frames, recorded_frames) {
+            let _ = s.spawn(move || recorder_thread_main(dir.clone(), recorded_frames).unwrap());
+        }
+
         // start camera thread
         let _ = s.spawn(move || {
             camera_thread_main(
                 termination_signal,
                 cam,
-                shared_frame,
-                vec![locator_thread.thread()],
+                args.frame_transform,
+                frame_sender,
+                dropped_frames,
+                pause,
+                metrics,
+                recorder,
             )
             .unwrap();
         });
@@ -201,3 +764,51 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::error::ErrorKind;
+
+    use super::*;
+
+    #[test]
+    fn test_intrinsics_required() {
+        let error = Args::try_parse_from(["xDIMScreen_locator"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::MissingRequiredArgument);
+        // the error names the flags to pass
+        let message = error.to_string();
+        assert!(message.contains("--cam-fov-x"));
+        assert!(message.contains("--cam-fov-y"));
+
+        let args = Args::try_parse_from(["xDIMScreen_locator", "--cam-fov-y", "50"]).unwrap();
+        assert_eq!((args.cam_fov_x, args.cam_fov_y), (None, Some(50.0)));
+        let args = Args::try_parse_from([
+            "xDIMScreen_locator",
+            "--cam-fov-x",
+            "80",
+            "--cam-fov-y",
+            "50",
+        ])
+        .unwrap();
+        assert_eq!((args.cam_fov_x, args.cam_fov_y), (Some(80.0), Some(50.0)));
+        let args =
+            Args::try_parse_from(["xDIMScreen_locator", "--camera-mat", "1400,1400,960,540"])
+                .unwrap();
+        assert_eq!(args.camera_mat, Some(vec![1400.0, 1400.0, 960.0, 540.0]));
+    }
+
+    #[test]
+    fn test_intrinsics_conflict() {
+        for fov in ["--cam-fov-x", "--cam-fov-y"] {
+            let error = Args::try_parse_from([
+                "xDIMScreen_locator",
+                "--camera-mat",
+                "1400,1400,960,540",
+                fov,
+                "50",
+            ])
+            .unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::ArgumentConflict);
+        }
+    }
+}
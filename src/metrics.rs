@@ -0,0 +1,161 @@
+//! Counters reporting the health of the tracking pipeline, updated by the camera, locator, and server
+//! threads.
+
+use std::fmt::Display;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::camera::DroppedFrameCounter;
+
+/// The interval at which the locator thread logs a snapshot of the metrics.
+pub const METRICS_LOG_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Counters shared by the threads of the tracking pipeline. Every counter only grows, except for the number
+/// of tracked objects and connected clients.
+#[derive(Debug)]
+pub struct Metrics {
+    dropped_frames: Arc<DroppedFrameCounter>,
+    frames_captured: AtomicUsize,
+    frames_processed: AtomicUsize,
+    detections: AtomicUsize,
+    /// The total time spent locating the objects, in microseconds.
+    solve_time_us: AtomicU64,
+    tracked_objects: AtomicUsize,
+    clients: AtomicUsize,
+}
+
+impl Metrics {
+    /// Create the metrics of a pipeline whose dropped camera frames are counted by `dropped_frames`.
+    pub fn new(dropped_frames: Arc<DroppedFrameCounter>) -> Self {
+        Self {
+            dropped_frames,
+            frames_captured: AtomicUsize::new(0),
+            frames_processed: AtomicUsize::new(0),
+            detections: AtomicUsize::new(0),
+            solve_time_us: AtomicU64::new(0),
+            tracked_objects: AtomicUsize::new(0),
+            clients: AtomicUsize::new(0),
+        }
+    }
+
+    /// Called by the camera thread after capturing a new frame.
+    pub fn frame_captured(&self) {
+        self.frames_captured.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Called by the locator thread after processing a frame with `detections` detected tags, in which
+    /// locating the objects took `solve_time` and `tracked_objects` objects are located.
+    pub fn frame_processed(&self, detections: usize, solve_time: Duration, tracked_objects: usize) {
+        self.frames_processed.fetch_add(1, Ordering::Relaxed);
+        self.detections.fetch_add(detections, Ordering::Relaxed);
+        self.solve_time_us
+            .fetch_add(solve_time.as_micros() as u64, Ordering::Relaxed);
+        self.tracked_objects
+            .store(tracked_objects, Ordering::Relaxed);
+    }
+
+    /// Called by a server thread when a client connects. The client is counted until the returned guard is
+    /// dropped.
+    pub fn client_connected(&self) -> ClientConnection<'_> {
+        self.clients.fetch_add(1, Ordering::Relaxed);
+        ClientConnection { metrics: self }
+    }
+
+    /// Read all counters. The counters are read one by one, so they may be off by a frame from each other.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let frames_processed = self.frames_processed.load(Ordering::Relaxed);
+        let average = |total: f64| {
+            if frames_processed == 0 {
+                0.0
+            } else {
+                total / frames_processed as f64
+            }
+        };
+        MetricsSnapshot {
+            frames_captured: self.frames_captured.load(Ordering::Relaxed),
+            frames_processed,
+            dropped_frames: self.dropped_frames.dropped(),
+            average_detections: average(self.detections.load(Ordering::Relaxed) as f64),
+            average_solve_time_ms: average(
+                self.solve_time_us.load(Ordering::Relaxed) as f64 / 1000.0,
+            ),
+            tracked_objects: self.tracked_objects.load(Ordering::Relaxed),
+            clients: self.clients.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Keeps a client counted in `Metrics` while it is connected.
+pub struct ClientConnection<'m> {
+    metrics: &'m Metrics,
+}
+
+impl Drop for ClientConnection<'_> {
+    fn drop(&mut self) {
+        self.metrics.clients.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// The values of the counters in `Metrics` at some point in time. The averages are taken over all the
+/// processed frames.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricsSnapshot {
+    pub frames_captured: usize,
+    pub frames_processed: usize,
+    pub dropped_frames: usize,
+    pub average_detections: f64,
+    pub average_solve_time_ms: f64,
+    pub tracked_objects: usize,
+    pub clients: usize,
+}
+
+impl Display for MetricsSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} frames captured, {} processed, {} dropped; {:.1} tags detected and {:.2} ms spent locating per frame; {} objects tracked; {} clients connected",
+            self.frames_captured,
+            self.frames_processed,
+            self.dropped_frames,
+            self.average_detections,
+            self.average_solve_time_ms,
+            self.tracked_objects,
+            self.clients
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_snapshot() {
+        let dropped_frames = Arc::new(DroppedFrameCounter::new());
+        let metrics = Metrics::new(dropped_frames.clone());
+        assert_eq!(metrics.snapshot().average_detections, 0.0);
+
+        for _ in 0..3 {
+            metrics.frame_captured();
+            dropped_frames.frame_produced();
+        }
+        dropped_frames.frame_consumed();
+        metrics.frame_processed(4, Duration::from_millis(2), 1);
+        metrics.frame_processed(2, Duration::from_millis(4), 2);
+        let connection = metrics.client_connected();
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.frames_captured, 3);
+        assert_eq!(snapshot.frames_processed, 2);
+        assert_eq!(snapshot.dropped_frames, 2);
+        assert_eq!(snapshot.average_detections, 3.0);
+        assert_eq!(snapshot.average_solve_time_ms, 3.0);
+        assert_eq!(snapshot.tracked_objects, 2);
+        assert_eq!(snapshot.clients, 1);
+
+        drop(connection);
+        assert_eq!(metrics.snapshot().clients, 0);
+    }
+}
@@ -0,0 +1,215 @@
+use std::io::BufReader;
+use std::net::{TcpStream, ToSocketAddrs};
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+
+use crate::net::Connection;
+use crate::net::packet::{
+    AuthPacket, DetectionsPacket, HandshakePacket, ObjectLocationPacket, WireFormat,
+};
+
+/// How a client authenticates to the server and secures the connection, matching the server's
+/// `ServerSecurity`. The default connects over plain TCP without a token.
+#[derive(Clone, Default)]
+pub struct ClientSecurity {
+    /// The token sent to the server before the handshake.
+    pub token: Option<String>,
+    /// Encrypt the connection with this TLS configuration, verifying that the server's certificate is valid
+    /// for the server name. See `tls::load_client_config`.
+    #[cfg(feature = "tls")]
+    pub tls: Option<(
+        Arc<rustls::ClientConfig>,
+        rustls::pki_types::ServerName<'static>,
+    )>,
+}
+
+/// A client receiving object locations from the locator server.
+///
+/// The wire format must be the same as the one the server is started with.
+pub struct PoseClient {
+    stream: BufReader<Box<dyn Connection>>,
+    format: WireFormat,
+    handshake: HandshakePacket,
+    /// The detections of the frame whose packets are being received, if the server sends them.
+    detections: Option<DetectionsPacket>,
+    /// The number of packets of the frame that are not received yet.
+    pending: usize,
+}
+
+impl PoseClient {
+    pub fn connect<A: ToSocketAddrs>(
+        addr: A,
+        format: WireFormat,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::connect_with(addr, format, &ClientSecurity::default())
+    }
+
+    /// Connect to a server that requires a token or TLS, as configured by `security`.
+    pub fn connect_with<A: ToSocketAddrs>(
+        addr: A,
+        format: WireFormat,
+        security: &ClientSecurity,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let stream = TcpStream::connect(addr)?;
+        #[cfg(feature = "tls")]
+        let mut stream: Box<dyn Connection> = match &security.tls {
+            Some((config, server_name)) => Box::new(rustls::StreamOwned::new(
+                rustls::ClientConnection::new(config.clone(), server_name.clone())?,
+                stream,
+            )),
+            None => Box::new(stream),
+        };
+        #[cfg(not(feature = "tls"))]
+        let mut stream: Box<dyn Connection> = Box::new(stream);
+        if let Some(token) = &security.token {
+            format.write(
+                &mut stream,
+                &AuthPacket {
+                    token: token.clone(),
+                },
+            )?;
+        }
+        let mut stream = BufReader::new(stream);
+        let handshake = format.read(&mut stream)?;
+        Ok(Self {
+            stream,
+            format,
+            handshake,
+            detections: None,
+            pending: 0,
+        })
+    }
+
+    pub fn format(&self) -> WireFormat {
+        self.format
+    }
+
+    /// The length unit of the translations in the received packets, as declared by the server.
+    pub fn unit(&self) -> &str {
+        &self.handshake.unit
+    }
+
+    /// Block until the next packet is received from the server.
+    ///
+    /// If the server sends detections, the `DetectionsPacket` before each frame's packets is received
+    /// along the way, and kept in `detections`.
+    pub fn recv(&mut self) -> Result<ObjectLocationPacket, Box<dyn std::error::Error>> {
+        if self.handshake.detections {
+            // frames with detections but without objects have no packet to return
+            while self.pending == 0 {
+                let detections: DetectionsPacket = self.format.read(&mut self.stream)?;
+                self.pending = detections.objects;
+                self.detections = Some(detections);
+            }
+            self.pending -= 1;
+        }
+        self.format.read_packet(&mut self.stream)
+    }
+
+    /// Whether the server sends the detections of each frame.
+    pub fn has_detections(&self) -> bool {
+        self.handshake.detections
+    }
+
+    /// The detections of the frame of the last packet returned by `recv`, if the server sends detections.
+    pub fn detections(&self) -> Option<&DetectionsPacket> {
+        self.detections.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::TcpListener;
+    use std::thread;
+
+    use crate::net::{ServerSecurity, accept_client};
+
+    #[test]
+    fn test_token() {
+        let format = WireFormat::Json;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_security = ServerSecurity {
+            token: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let server = thread::spawn(move || {
+            let mut accepted = Vec::new();
+            for _ in 0..3 {
+                let (stream, _) = listener.accept().unwrap();
+                match accept_client(stream, format, &server_security) {
+                    Ok(mut connection) => {
+                        let handshake = HandshakePacket {
+                            unit: "mm".to_string(),
+                            detections: false,
+                        };
+                        format.write(&mut connection, &handshake).unwrap();
+                        accepted.push(true);
+                    }
+                    // dropping the connection closes it
+                    Err(_) => accepted.push(false),
+                }
+            }
+            accepted
+        });
+
+        let with_token = |token: Option<&str>| ClientSecurity {
+            token: token.map(str::to_string),
+            ..Default::default()
+        };
+        let client = PoseClient::connect_with(addr, format, &with_token(Some("secret"))).unwrap();
+        assert_eq!(client.unit(), "mm");
+        assert!(PoseClient::connect_with(addr, format, &with_token(Some("wrong"))).is_err());
+        // a client without a token is disconnected once its first packet fails to parse
+        let mut stream = TcpStream::connect(addr).unwrap();
+        std::io::Write::write_all(&mut stream, b"{}\n").unwrap();
+        assert_eq!(server.join().unwrap(), [true, false, false]);
+    }
+
+    #[test]
+    fn test_detections() {
+        let format = WireFormat::Msgpack;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let packet = |time: u128, name: &str| ObjectLocationPacket {
+            time,
+            name: name.to_string(),
+            transform: nalgebra::Isometry3::identity(),
+            lost: false,
+            extrapolated: false,
+            confidence: None,
+            rotation_format: Default::default(),
+        };
+        let detections = |time: u128, objects: usize| DetectionsPacket {
+            time,
+            objects,
+            detections: Vec::new(),
+        };
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let handshake = HandshakePacket {
+                unit: "mm".to_string(),
+                detections: true,
+            };
+            format.write(&mut stream, &handshake).unwrap();
+            // a frame with detections but without objects, followed by a frame with two objects
+            format.write(&mut stream, &detections(1, 0)).unwrap();
+            format.write(&mut stream, &detections(2, 2)).unwrap();
+            format.write_packet(&mut stream, &packet(2, "a")).unwrap();
+            format.write_packet(&mut stream, &packet(2, "b")).unwrap();
+            format.write(&mut stream, &detections(3, 1)).unwrap();
+            format.write_packet(&mut stream, &packet(3, "a")).unwrap();
+        });
+
+        let mut client = PoseClient::connect(addr, format).unwrap();
+        assert!(client.has_detections());
+        assert!(client.detections().is_none());
+        for (time, name) in [(2, "a"), (2, "b"), (3, "a")] {
+            assert_eq!(client.recv().unwrap(), packet(time, name));
+            assert_eq!(client.detections().unwrap().time, time);
+        }
+        server.join().unwrap();
+    }
+}
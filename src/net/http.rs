@@ -0,0 +1,257 @@
+//! A minimal HTTP server for browser-based consumers, which cannot open raw TCP connections.
+//!
+//! * `GET /poses` streams the packets of each frame as Server-Sent Events, one JSON packet per `data:` event.
+//! * `GET /snapshot` returns the packets of the latest frame as a JSON array, for polling clients.
+//! * `GET /metrics` returns a snapshot of the pipeline's `Metrics` as a JSON object, for monitoring.
+//!
+//! The packets are the same as the ones sent by the TCP server in JSON format.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use crate::metrics::Metrics;
+use crate::net::packet::{AxisRemap, RotationFormat};
+use crate::net::{frame_packets, wait_for_next_frame};
+use crate::tag::locator::LocatedObjects;
+
+pub fn http_thread_main<'a>(
+    termination_signal: Arc<AtomicBool>,
+    port: u16,
+    rotation_format: RotationFormat,
+    axis_remap: AxisRemap,
+    located_objects: Arc<(Mutex<LocatedObjects<'a>>, Condvar)>,
+    metrics: Arc<Metrics>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", port))?;
+    log::info!("HTTP server started at port {}", port);
+    serve(
+        listener,
+        &termination_signal,
+        rotation_format,
+        axis_remap,
+        &located_objects,
+        &metrics,
+    );
+    Ok(())
+}
+
+/// Accept clients from `listener` until terminated, serving each client on its own thread.
+fn serve(
+    listener: TcpListener,
+    termination_signal: &AtomicBool,
+    rotation_format: RotationFormat,
+    axis_remap: AxisRemap,
+    located_objects: &(Mutex<LocatedObjects<'_>>, Condvar),
+    metrics: &Metrics,
+) {
+    thread::scope(|s| {
+        while !termination_signal.load(Ordering::Relaxed) {
+            let (stream, addr) = match listener.accept() {
+                Ok(conn) => conn,
+                Err(e) => {
+                    log::error!("An error occurred at HTTP server: {}", e);
+                    continue;
+                }
+            };
+            s.spawn(move || {
+                if let Err(e) = handle_client(
+                    stream,
+                    termination_signal,
+                    rotation_format,
+                    axis_remap,
+                    located_objects,
+                    metrics,
+                ) {
+                    log::error!("Error occurred with HTTP client {}: {}", addr, e);
+                }
+            });
+        }
+    });
+}
+
+fn handle_client(
+    mut stream: TcpStream,
+    termination_signal: &AtomicBool,
+    rotation_format: RotationFormat,
+    axis_remap: AxisRemap,
+    located_objects: &(Mutex<LocatedObjects<'_>>, Condvar),
+    metrics: &Metrics,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // the request headers are not needed
+    let mut header = String::new();
+    loop {
+        header.clear();
+        if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts
+        .next()
+        .unwrap_or_default()
+        .split('?')
+        .next()
+        .unwrap_or_default();
+    match (method, path) {
+        ("GET", "/poses") => {
+            let _connection = metrics.client_connected();
+            stream_poses(
+                &mut stream,
+                termination_signal,
+                rotation_format,
+                axis_remap,
+                located_objects,
+            )
+        }
+        ("GET", "/snapshot") => {
+            let packets = frame_packets(
+                &located_objects.0.lock().unwrap(),
+                rotation_format,
+                axis_remap,
+            )?;
+            let body = serde_json::to_vec(&packets)?;
+            write_response(&mut stream, "200 OK", "application/json", &body)
+        }
+        ("GET", "/metrics") => {
+            let body = serde_json::to_vec(&metrics.snapshot())?;
+            write_response(&mut stream, "200 OK", "application/json", &body)
+        }
+        ("GET", _) => write_response(&mut stream, "404 Not Found", "text/plain", b"Not found\n"),
+        _ => write_response(
+            &mut stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            b"Method not allowed\n",
+        ),
+    }
+}
+
+/// Write a complete response and close the connection.
+fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Stream the packets of each new frame as Server-Sent Events, until the client disconnects.
+fn stream_poses(
+    stream: &mut TcpStream,
+    termination_signal: &AtomicBool,
+    rotation_format: RotationFormat,
+    axis_remap: AxisRemap,
+    located_objects: &(Mutex<LocatedObjects<'_>>, Condvar),
+) -> Result<(), Box<dyn std::error::Error>> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nAccess-Control-Allow-Origin: *\r\nConnection: keep-alive\r\n\r\n"
+    )?;
+    stream.flush()?;
+    let mut last_timestamp = Instant::now();
+    while !termination_signal.load(Ordering::Relaxed) {
+        // release the lock before writing, so that a slow client does not block the others
+        let packets = {
+            let locked_located_objects = wait_for_next_frame(
+                located_objects,
+                located_objects.0.lock().unwrap(),
+                last_timestamp,
+            );
+            last_timestamp = locked_located_objects.timestamp();
+            frame_packets(&locked_located_objects, rotation_format, axis_remap)?
+        };
+        for packet in packets {
+            write!(stream, "data: {}\n\n", serde_json::to_string(&packet)?)?;
+        }
+        stream.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::camera::DroppedFrameCounter;
+
+    /// Start a server on a free port, and return its address.
+    fn start_server() -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let located_objects: &'static (Mutex<LocatedObjects<'static>>, Condvar) = Box::leak(
+            Box::new((Mutex::new(LocatedObjects::new()), Condvar::new())),
+        );
+        let termination_signal: &'static AtomicBool = Box::leak(Box::new(AtomicBool::new(false)));
+        let metrics: &'static Metrics =
+            Box::leak(Box::new(Metrics::new(Arc::new(DroppedFrameCounter::new()))));
+        thread::spawn(move || {
+            serve(
+                listener,
+                termination_signal,
+                RotationFormat::Quaternion,
+                AxisRemap::identity(),
+                located_objects,
+                metrics,
+            )
+        });
+        addr
+    }
+
+    fn request(addr: std::net::SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let addr = start_server();
+        let response = request(addr, "GET /snapshot HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: application/json\r\n"));
+        // no object is located yet
+        assert!(response.ends_with("\r\n\r\n[]"));
+    }
+
+    #[test]
+    fn test_metrics() {
+        let addr = start_server();
+        let response = request(addr, "GET /metrics HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        let snapshot: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(snapshot["frames_processed"], 0);
+        assert_eq!(snapshot["clients"], 0);
+    }
+
+    #[test]
+    fn test_unknown_requests() {
+        let addr = start_server();
+        let response = request(addr, "GET /unknown HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        let response = request(addr, "POST /snapshot HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    }
+}
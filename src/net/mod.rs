@@ -1,23 +1,253 @@
-use std::io::Write;
-use std::net::TcpListener;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Condvar, Mutex};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
+use crate::camera::FrameTimestamp;
+use crate::metrics::Metrics;
 use crate::tag::locator::LocatedObjects;
+use crate::utils::rotation;
 
+extern crate nalgebra as na;
+
+pub mod client;
+#[cfg(feature = "http")]
+pub mod http;
 pub mod packet;
+#[cfg(all(feature = "shm", unix))]
+pub mod shm;
+#[cfg(feature = "tls")]
+pub mod tls;
+
+/// The maximum duration for which the resample thread extrapolates the objects past their latest frame.
+pub const MAX_RESAMPLE_EXTRAPOLATION: Duration = Duration::from_millis(200);
+
+/// The time a client has to complete the TLS handshake and send its token (if the server requires them) after
+/// connecting.
+pub const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A connection to a client or a server, either over plain TCP or TLS.
+pub trait Connection: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Connection for T {}
+
+/// How the TCP server authenticates its clients and secures the connections. The default accepts any
+/// client over plain TCP, which is meant for clients on the same machine.
+#[derive(Clone, Default)]
+pub struct ServerSecurity {
+    /// The token each client must send in an `AuthPacket` before it receives any packet.
+    pub token: Option<String>,
+    /// Encrypt the connections with this TLS configuration. See `tls::load_server_config`.
+    #[cfg(feature = "tls")]
+    pub tls: Option<Arc<rustls::ServerConfig>>,
+}
+
+/// The minimum change of an object's pose since its last sent packet for the TCP server to send the object
+/// again, e.g. to save the bandwidth of static objects. The default of zero sends every packet.
+///
+/// A packet is suppressed if both the translation and the rotation change less than their thresholds. A zero
+/// threshold ignores its component, so that e.g. setting only the translation threshold suppresses the
+/// packets of objects that do not move, whatever their rotation. Packets of lost objects are always sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EmitThreshold {
+    /// The distance, in the unit of the objects' tags.
+    pub translation: f64,
+    /// The angle, in radians.
+    pub rotation: f64,
+}
+
+/// Suppresses the packets of objects whose poses have not changed beyond an `EmitThreshold` since their last
+/// sent packets. Each client has its own, so that a new client receives every object at first.
+#[derive(Debug, Default)]
+pub(crate) struct PoseDeduplicator {
+    threshold: EmitThreshold,
+    /// The pose in the last sent packet of each object that is not lost since.
+    last_sent: HashMap<String, na::Isometry3<f64>>,
+}
+
+impl PoseDeduplicator {
+    pub(crate) fn new(threshold: EmitThreshold) -> Self {
+        Self {
+            threshold,
+            last_sent: HashMap::new(),
+        }
+    }
+
+    /// Remove the packets that are not worth sending from a frame's `packets`, and remember the poses of the
+    /// others as sent.
+    pub(crate) fn retain(&mut self, packets: &mut Vec<packet::ObjectLocationPacket>) {
+        packets.retain(|packet| {
+            if packet.lost {
+                self.last_sent.remove(&packet.name);
+                return true;
+            }
+            let EmitThreshold {
+                translation,
+                rotation,
+            } = self.threshold;
+            if (translation > 0.0 || rotation > 0.0)
+                && let Some(last_sent) = self.last_sent.get(&packet.name)
+                && (translation <= 0.0
+                    || (packet.transform.translation.vector - last_sent.translation.vector).norm()
+                        < translation)
+                && (rotation <= 0.0
+                    || rotation::angle_between(&last_sent.rotation, &packet.transform.rotation)
+                        < rotation)
+            {
+                return false;
+            }
+            self.last_sent.insert(packet.name.clone(), packet.transform);
+            true
+        });
+    }
+}
+
+/// Compare two tokens in a time that does not depend on where they differ.
+fn tokens_match(expected: &str, actual: &str) -> bool {
+    expected.len() == actual.len()
+        && expected
+            .bytes()
+            .zip(actual.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
 
+/// Secure a newly accepted client's connection as required by `security`, and check the client's token.
+fn accept_client(
+    stream: TcpStream,
+    format: packet::WireFormat,
+    security: &ServerSecurity,
+) -> Result<Box<dyn Connection>, Box<dyn std::error::Error>> {
+    // a handle to the socket to set its timeout, since the TLS stream owns it
+    let socket = stream.try_clone()?;
+    // the server serves a single client, so a client stalling before it is accepted must not block it
+    socket.set_read_timeout(Some(AUTH_TIMEOUT))?;
+    #[cfg(feature = "tls")]
+    let mut connection: Box<dyn Connection> = match &security.tls {
+        Some(config) => {
+            let mut stream =
+                rustls::StreamOwned::new(rustls::ServerConnection::new(config.clone())?, stream);
+            while stream.conn.is_handshaking() {
+                stream.conn.complete_io(&mut stream.sock)?;
+            }
+            Box::new(stream)
+        }
+        None => Box::new(stream),
+    };
+    #[cfg(not(feature = "tls"))]
+    let mut connection: Box<dyn Connection> = Box::new(stream);
+    if let Some(token) = &security.token {
+        // the client sends nothing after its token until it receives the handshake, so the buffer does not
+        // take any other data. `read` rejects frames longer than `MAX_PACKET_LEN` before buffering them, so
+        // an unauthenticated client cannot make the server allocate a large buffer.
+        let auth: packet::AuthPacket = format.read(&mut BufReader::new(&mut connection))?;
+        if !tokens_match(token, &auth.token) {
+            return Err("The client sent a wrong token!".into());
+        }
+    }
+    socket.set_read_timeout(None)?;
+    Ok(connection)
+}
+
+/// Block until a frame newer than `last_timestamp` with any located or lost object, or any recorded
+/// detection, is published.
+pub(crate) fn wait_for_next_frame<'g, 'a>(
+    located_objects: &'g (Mutex<LocatedObjects<'a>>, Condvar),
+    guard: MutexGuard<'g, LocatedObjects<'a>>,
+    last_timestamp: Instant,
+) -> MutexGuard<'g, LocatedObjects<'a>> {
+    located_objects
+        .1
+        .wait_while(guard, |v| {
+            (v.timestamp() == last_timestamp)
+                || (v.name_map().is_empty()
+                    && v.lost_map().is_empty()
+                    && v.detections().is_none_or(<[_]>::is_empty))
+        })
+        .unwrap()
+}
+
+/// Convert the objects of a frame into packets: one for each located object, followed by one for each
+/// object lost in this frame.
+pub fn frame_packets(
+    located_objects: &LocatedObjects,
+    rotation_format: packet::RotationFormat,
+    axis_remap: packet::AxisRemap,
+) -> Result<Vec<packet::ObjectLocationPacket>, Box<dyn std::error::Error>> {
+    let time = located_objects
+        .system_time()
+        .duration_since(UNIX_EPOCH)?
+        .as_millis();
+    let located = located_objects
+        .name_map()
+        .iter()
+        .map(|(name, location)| (name, location, false));
+    let lost = located_objects
+        .lost_map()
+        .iter()
+        .map(|(name, location)| (name, location, true));
+    let extrapolated = located_objects.extrapolated();
+    let confidence = located_objects.confidence();
+    Ok(located
+        .chain(lost)
+        .map(|(name, location, lost)| packet::ObjectLocationPacket {
+            time,
+            name: name.to_string(),
+            transform: axis_remap.apply(location),
+            lost,
+            extrapolated: !lost && extrapolated.contains(name),
+            confidence: confidence.get(name).copied().filter(|_| !lost),
+            rotation_format,
+        })
+        .collect())
+}
+
+/// The packet sent before the `objects` packets of a frame, listing the frame's detections recorded by the
+/// locator (see `TaggedObjectLocator::set_record_detections`), or none if they are not recorded.
+pub(crate) fn frame_detections_packet(
+    located_objects: &LocatedObjects,
+    objects: usize,
+) -> Result<packet::DetectionsPacket, Box<dyn std::error::Error>> {
+    Ok(packet::DetectionsPacket {
+        time: located_objects
+            .system_time()
+            .duration_since(UNIX_EPOCH)?
+            .as_millis(),
+        objects,
+        detections: located_objects.detections().unwrap_or_default().to_vec(),
+    })
+}
+
+/// Main function of the TCP server, which serves one client at a time at `address`.
+///
+/// Each client receives a `HandshakePacket`, followed by the packets of each frame, except for the objects
+/// that have not moved beyond `emit_threshold` since their last packets. If `detections` is set, each
+/// frame's packets are preceded by a `DetectionsPacket`. With `security`, the connections are encrypted,
+/// and clients are rejected unless they first send the right token.
 pub fn server_thread_main<'a>(
     termination_signal: Arc<AtomicBool>,
-    port: u16,
+    address: SocketAddr,
+    format: packet::WireFormat,
+    rotation_format: packet::RotationFormat,
+    axis_remap: packet::AxisRemap,
+    unit: &str,
     located_objects: Arc<(Mutex<LocatedObjects<'a>>, Condvar)>,
+    metrics: Arc<Metrics>,
+    security: ServerSecurity,
+    detections: bool,
+    emit_threshold: EmitThreshold,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // open server
-    let listener = TcpListener::bind(format!("127.0.0.1:{}", port))?;
-    log::info!("Server started at port {}", port);
+    let listener = TcpListener::bind(address)?;
+    log::info!("Server started at {} ({:?} format)", address, format);
+    #[cfg(feature = "tls")]
+    if security.tls.is_some() {
+        log::info!("Clients are connected over TLS");
+    }
     while !termination_signal.load(Ordering::Relaxed) {
-        let (mut stream, addr) = loop {
+        let (stream, addr) = loop {
             let conn = listener.accept();
             match conn {
                 Ok((stream, addr)) => {
@@ -28,32 +258,46 @@ pub fn server_thread_main<'a>(
                 }
             }
         };
+        let mut stream = match accept_client(stream, format, &security) {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::warn!("Rejected client {}: {}", addr, e);
+                continue;
+            }
+        };
         log::info!("Accepted client {}. Connection established.", addr);
+        let _connection = metrics.client_connected();
+        let handshake = packet::HandshakePacket {
+            unit: unit.to_string(),
+            detections,
+        };
+        if let Err(e) = format.write(&mut stream, &handshake) {
+            log::error!("Error occurred with client {}: {}", addr, e);
+            continue;
+        }
+
+        let mut deduplicator = PoseDeduplicator::new(emit_threshold);
 
         // set up conditional variable
         let mut locked_located_objects = located_objects.0.lock().unwrap();
-        let mut last_timestamp = SystemTime::now();
+        let mut last_timestamp = Instant::now();
         'main_loop: while !termination_signal.load(Ordering::Relaxed) {
             // get all the detected objects
-            locked_located_objects = located_objects
-                .1
-                .wait_while(locked_located_objects, |v| {
-                    (v.timestamp() == last_timestamp) || v.name_map().is_empty()
-                })
-                .unwrap();
+            locked_located_objects =
+                wait_for_next_frame(&located_objects, locked_located_objects, last_timestamp);
             last_timestamp = locked_located_objects.timestamp();
-            // convert the map to a list of packets
-            for (name, location) in locked_located_objects.name_map() {
-                let packet = packet::ObjectLocationPacket {
-                    time: last_timestamp.duration_since(UNIX_EPOCH)?.as_millis(),
-                    name: name.to_string(),
-                    transform: location.clone(),
-                };
-                let serialized = serde_json::to_string(&packet)?;
-                let stream_write_result = stream
-                    .write(serialized.as_bytes())
-                    .and_then(|_| stream.write(b"\n"));
-                match stream_write_result {
+            let mut packets = frame_packets(&locked_located_objects, rotation_format, axis_remap)?;
+            deduplicator.retain(&mut packets);
+            if detections {
+                let detections_packet =
+                    frame_detections_packet(&locked_located_objects, packets.len())?;
+                if let Err(e) = format.write(&mut stream, &detections_packet) {
+                    log::error!("Error occurred with client {}: {}", addr, e);
+                    break 'main_loop;
+                }
+            }
+            for packet in packets {
+                match format.write_packet(&mut stream, &packet) {
                     Ok(_) => {}
                     Err(e) => {
                         // Error occurred when writing to the stream. Reopen the server.
@@ -66,3 +310,282 @@ pub fn server_thread_main<'a>(
     }
     Ok(())
 }
+
+/// Main function of the resample thread.
+///
+/// Publishes the objects in `located_objects` to `resampled_objects` at a fixed `rate` (in Hz), instead of
+/// the camera's irregular cadence. On each tick, the objects of the latest frame are extrapolated to the
+/// tick's time from their velocities (see `LocatedObjects::resampled`). The objects lost in any frame
+/// since the last tick are published once in the next tick.
+pub fn resample_thread_main<'a>(
+    termination_signal: Arc<AtomicBool>,
+    rate: f64,
+    located_objects: Arc<(Mutex<LocatedObjects<'a>>, Condvar)>,
+    resampled_objects: Arc<(Mutex<LocatedObjects<'a>>, Condvar)>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !(rate > 0.0 && rate.is_finite()) {
+        return Err(format!("The resample rate must be positive, but got {}", rate).into());
+    }
+    let period = Duration::from_secs_f64(1.0 / rate);
+    log::info!("Publishing the located objects at {} Hz", rate);
+    let mut next_tick = Instant::now() + period;
+    let mut last_timestamp = None;
+    let mut lost_map = BTreeMap::new();
+    let mut frame = located_objects.0.lock().unwrap();
+    while !termination_signal.load(Ordering::Relaxed) {
+        // collect the objects lost in every frame until the tick
+        loop {
+            if last_timestamp != Some(frame.timestamp()) {
+                last_timestamp = Some(frame.timestamp());
+                lost_map.extend(
+                    frame
+                        .lost_map()
+                        .iter()
+                        .map(|(name, location)| (*name, *location)),
+                );
+            }
+            let now = Instant::now();
+            if now >= next_tick {
+                break;
+            }
+            frame = located_objects
+                .1
+                .wait_timeout(frame, next_tick - now)
+                .unwrap()
+                .0;
+        }
+        let resampled = frame.resampled(
+            FrameTimestamp::now(),
+            MAX_RESAMPLE_EXTRAPOLATION,
+            std::mem::take(&mut lost_map),
+        );
+        *resampled_objects.0.lock().unwrap() = resampled;
+        resampled_objects.1.notify_all();
+        // skip the ticks that are already missed instead of publishing them in a burst
+        next_tick += period;
+        let now = Instant::now();
+        if next_tick <= now {
+            next_tick = now + period;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pose_deduplicator() {
+        let packet = |name: &str, x: f64, lost: bool| packet::ObjectLocationPacket {
+            time: 0,
+            name: name.to_string(),
+            transform: na::Isometry3::translation(x, 0.0, 10.0),
+            lost,
+            extrapolated: false,
+            confidence: None,
+            rotation_format: Default::default(),
+        };
+        let names = |packets: &[packet::ObjectLocationPacket]| {
+            packets
+                .iter()
+                .map(|packet| packet.name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        // a static anchor jittering by a thousandth, and a wand moving by a tenth in each frame
+        let frame = |i: usize| {
+            let jitter = if i % 2 == 0 { 0.0 } else { 0.001 };
+            vec![
+                packet("anchor", jitter, false),
+                packet("wand", i as f64 * 0.1, false),
+            ]
+        };
+        let mut deduplicator = PoseDeduplicator::new(EmitThreshold {
+            translation: 0.01,
+            rotation: 0.01,
+        });
+        let mut everything = PoseDeduplicator::new(EmitThreshold::default());
+        for i in 0..10 {
+            let mut packets = frame(i);
+            deduplicator.retain(&mut packets);
+            if i == 0 {
+                assert_eq!(names(&packets), ["anchor", "wand"]);
+            } else {
+                assert_eq!(names(&packets), ["wand"]);
+            }
+            let mut packets = frame(i);
+            everything.retain(&mut packets);
+            assert_eq!(names(&packets), ["anchor", "wand"]);
+        }
+
+        // small moves add up until the pose differs enough from the last sent one
+        let mut sent = Vec::new();
+        for i in 0..10 {
+            let mut packets = vec![packet("anchor", i as f64 * 0.004, false)];
+            deduplicator.retain(&mut packets);
+            sent.push(!packets.is_empty());
+        }
+        assert_eq!(
+            sent,
+            [
+                false, false, false, true, false, false, true, false, false, true
+            ]
+        );
+
+        // lost objects are always sent, and sent again once they are found
+        let mut packets = vec![packet("anchor", 0.036, true)];
+        deduplicator.retain(&mut packets);
+        assert_eq!(packets.len(), 1);
+        let mut packets = vec![packet("anchor", 0.036, false)];
+        deduplicator.retain(&mut packets);
+        assert_eq!(packets.len(), 1);
+
+        // with only the translation threshold set, the rotation is ignored
+        let mut translation_only = PoseDeduplicator::new(EmitThreshold {
+            translation: 0.01,
+            rotation: 0.0,
+        });
+        for i in 0..10 {
+            let mut packets = frame(i);
+            for packet in &mut packets {
+                packet.transform.rotation =
+                    na::UnitQuaternion::from_euler_angles(0.0, i as f64 * 0.1, 0.0);
+            }
+            translation_only.retain(&mut packets);
+            if i == 0 {
+                assert_eq!(names(&packets), ["anchor", "wand"]);
+            } else {
+                assert_eq!(names(&packets), ["wand"]);
+            }
+        }
+
+        // with only the rotation threshold set, the translation is ignored
+        let mut rotation_only = PoseDeduplicator::new(EmitThreshold {
+            translation: 0.0,
+            rotation: 0.01,
+        });
+        for i in 0..10 {
+            let mut packets = frame(i);
+            rotation_only.retain(&mut packets);
+            if i == 0 {
+                assert_eq!(names(&packets), ["anchor", "wand"]);
+            } else {
+                assert!(packets.is_empty());
+            }
+        }
+    }
+
+    /// Accept a single client on a loopback listener with `security`, and send it the handshake.
+    fn serve_handshake(
+        listener: TcpListener,
+        format: packet::WireFormat,
+        security: ServerSecurity,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let (stream, _) = listener.accept()?;
+        let mut connection = accept_client(stream, format, &security).map_err(|e| e.to_string())?;
+        let handshake = packet::HandshakePacket {
+            unit: "cm".to_string(),
+            detections: false,
+        };
+        format
+            .write(&mut connection, &handshake)
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_oversized_auth_packet_is_rejected() {
+        let oversized = [
+            (
+                packet::WireFormat::Json,
+                vec![b' '; packet::MAX_PACKET_LEN + 1],
+            ),
+            (packet::WireFormat::Msgpack, u32::MAX.to_be_bytes().to_vec()),
+        ];
+        for (format, data) in oversized {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap();
+            let server = std::thread::spawn(move || {
+                serve_handshake(
+                    listener,
+                    format,
+                    ServerSecurity {
+                        token: Some("secret".to_string()),
+                        ..Default::default()
+                    },
+                )
+            });
+            let mut stream = TcpStream::connect(address).unwrap();
+            let start = Instant::now();
+            // the server may close the connection before the whole frame is written
+            let _ = stream.write_all(&data);
+            assert!(server.join().unwrap().is_err());
+            // the frame is rejected as soon as it is too long, rather than when the client times out
+            assert!(start.elapsed() < AUTH_TIMEOUT);
+        }
+    }
+
+    /// Write a self-signed certificate for `localhost` and its private key to a temporary directory, and
+    /// return their paths. The certificate is generated for each test, so that no private key is committed.
+    #[cfg(feature = "tls")]
+    fn generate_tls_files(name: &str) -> (std::path::PathBuf, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("tls-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+        std::fs::write(&cert_path, certified.cert.pem()).unwrap();
+        std::fs::write(&key_path, certified.signing_key.serialize_pem()).unwrap();
+        (cert_path, key_path)
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn test_tls_handshake() {
+        let (cert_path, key_path) = generate_tls_files("handshake");
+        let security = ServerSecurity {
+            token: Some("secret".to_string()),
+            tls: Some(tls::load_server_config(&cert_path, &key_path).unwrap()),
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            serve_handshake(listener, packet::WireFormat::Json, security)
+        });
+
+        let client_security = client::ClientSecurity {
+            token: Some("secret".to_string()),
+            tls: Some((
+                tls::load_client_config(&cert_path).unwrap(),
+                rustls::pki_types::ServerName::try_from("localhost").unwrap(),
+            )),
+        };
+        let client =
+            client::PoseClient::connect_with(address, packet::WireFormat::Json, &client_security)
+                .unwrap();
+        assert_eq!(client.unit(), "cm");
+        server.join().unwrap().unwrap();
+        std::fs::remove_dir_all(cert_path.parent().unwrap()).unwrap();
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn test_stalled_client_times_out() {
+        let (cert_path, key_path) = generate_tls_files("stalled");
+        let security = ServerSecurity {
+            token: None,
+            tls: Some(tls::load_server_config(&cert_path, &key_path).unwrap()),
+        };
+        std::fs::remove_dir_all(cert_path.parent().unwrap()).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            serve_handshake(listener, packet::WireFormat::Json, security)
+        });
+        // the client connects but never starts the TLS handshake, and the server requires no token
+        let _stream = TcpStream::connect(address).unwrap();
+        let start = Instant::now();
+        assert!(server.join().unwrap().is_err());
+        assert!(start.elapsed() < AUTH_TIMEOUT * 2);
+    }
+}
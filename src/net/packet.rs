@@ -1,10 +1,18 @@
+use std::io::{BufRead, Read, Write};
+
+use serde::de::DeserializeOwned;
 use serde::de::{MapAccess, Visitor};
-use serde::ser::SerializeMap;
+use serde::ser::{SerializeMap, SerializeStruct};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+use crate::tag::apriltag::DetectionSnapshot;
+use crate::utils::rotation::{
+    euler_to_quat, isometry_to_matrix4, matrix_to_quat, matrix4_to_isometry, quat_to_euler,
+};
+
 extern crate nalgebra as na;
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct ObjectLocationPacket {
     /// The timestamp at which the object is located, in miliseconds
     pub time: u128,
@@ -12,25 +20,349 @@ pub struct ObjectLocationPacket {
     /// Name of the object
     pub name: String,
 
-    /// Transform relative to the camera's reference frame
-    #[serde(
-        serialize_with = "serialize_isometry",
-        deserialize_with = "deserialize_isometry"
-    )]
+    /// Transform relative to the camera's reference frame, or to the ground plane if the locator has one
+    #[serde(deserialize_with = "deserialize_isometry")]
     pub transform: na::Isometry3<f64>,
+
+    /// Whether the object has just been lost from tracking. When set, `transform` is the object's last
+    /// known location.
+    #[serde(default)]
+    pub lost: bool,
+
+    /// Whether the object is not located in this frame, and `transform` is extrapolated from its last
+    /// located poses.
+    #[serde(default)]
+    pub extrapolated: bool,
+
+    /// The confidence between 0 and 1 of the object's location, if it is located from its tags in this
+    /// frame. Extrapolated and lost objects have no confidence.
+    #[serde(default)]
+    pub confidence: Option<f64>,
+
+    /// The representation of `transform`'s rotation when serializing the packet. Deserialization accepts
+    /// all representations, so this field is always the default after deserializing.
+    #[serde(skip)]
+    pub rotation_format: RotationFormat,
+}
+
+/// The first packet a client sends to a server that requires a token. See `ServerSecurity::token`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthPacket {
+    pub token: String,
+}
+
+/// The first packet the server sends to each client after the connection is established.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HandshakePacket {
+    /// The length unit of the translations in all following packets (e.g. "mm" or "m"). This is the unit
+    /// of the tag sizes in the tagobj files.
+    pub unit: String,
+
+    /// Whether the packets of each frame are preceded by a `DetectionsPacket`. Servers not sending
+    /// detections omit this field.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub detections: bool,
+}
+
+/// The tags detected in a frame, sent before the frame's `ObjectLocationPacket`s if the handshake has
+/// `detections` set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DetectionsPacket {
+    /// The timestamp of the frame, in miliseconds
+    pub time: u128,
+
+    /// The number of `ObjectLocationPacket`s of the frame following this packet
+    pub objects: usize,
+
+    /// All tags detected in the frame, in the image's pixel coordinates
+    pub detections: Vec<DetectionSnapshot>,
+}
+
+impl Serialize for ObjectLocationPacket {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let len = 3
+            + self.lost as usize
+            + self.extrapolated as usize
+            + self.confidence.is_some() as usize;
+        let mut state = serializer.serialize_struct("ObjectLocationPacket", len)?;
+        state.serialize_field("time", &self.time)?;
+        state.serialize_field("name", &self.name)?;
+        state.serialize_field(
+            "transform",
+            &SerializeIsometry(&self.transform, self.rotation_format),
+        )?;
+        // only lost packets have the "lost" field
+        if self.lost {
+            state.serialize_field("lost", &self.lost)?;
+        } else {
+            state.skip_field("lost")?;
+        }
+        // likewise, only extrapolated packets have the "extrapolated" field
+        if self.extrapolated {
+            state.serialize_field("extrapolated", &self.extrapolated)?;
+        } else {
+            state.skip_field("extrapolated")?;
+        }
+        match &self.confidence {
+            Some(confidence) => state.serialize_field("confidence", confidence)?,
+            None => state.skip_field("confidence")?,
+        }
+        state.end()
+    }
 }
 
-/// Serialize `na::Isometry3<f64>` type.
-fn serialize_isometry<S: Serializer>(
-    isometry: &na::Isometry3<f64>,
-    serializer: S,
-) -> Result<S::Ok, S::Error> {
-    let mut map = serializer.serialize_map(Some(2))?;
-    map.serialize_key("rq")?;
-    map.serialize_value(isometry.rotation.as_vector().as_slice())?; // in the order of (i, j, k, 1)
-    map.serialize_key("t")?;
-    map.serialize_value(isometry.translation.vector.as_slice())?;
-    map.end()
+/// The representation of a rotation in the serialized packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum RotationFormat {
+    /// Unit quaternion under the key `rq`, in the order of `[i, j, k, w]`.
+    #[default]
+    Quaternion,
+
+    /// Euler angles in radians under the key `euler`, in the order of `[roll, pitch, yaw]`.
+    ///
+    /// The rotation is applied as a roll around the camera's x axis, then a pitch around the camera's y
+    /// axis, then a yaw around the camera's z axis (all axes fixed to the camera's reference frame). In
+    /// other words, the rotation matrix is `Rz(yaw) * Ry(pitch) * Rx(roll)`. Pitch is in `[-pi/2, pi/2]`.
+    Euler,
+
+    /// 3x3 rotation matrix under the key `rm`, flattened in row-major order.
+    Matrix,
+
+    /// Axis-angle (Rodrigues) vector under the key `rv`, whose direction is the rotation axis and whose norm
+    /// is the rotation angle in radians. This is the representation of OpenCV's `solvePnP`.
+    AxisAngle,
+
+    /// The whole transform as a 4x4 homogeneous matrix under the key `matrix`, as a list of 4 rows. The
+    /// rotation is the upper-left 3x3 block and the translation is the last column, so there is no separate
+    /// `t` key.
+    Homogeneous,
+}
+
+/// A remapping of the axes of the located objects' transforms, to convert them from the locator's camera
+/// frame to the convention of the consumer.
+///
+/// The locator's camera frame is the one of OpenCV and AprilTag: right-handed, with +x pointing right, +y
+/// pointing down, and +z pointing forward out of the camera. The remapping is a signed permutation of these
+/// axes, which may flip the handedness (e.g. Unity's left-handed frame with +y pointing up).
+///
+/// Each output axis is a signed source axis. For example, `x,-y,z` converts to Unity's frame, where the
+/// output y axis is the negated camera y axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AxisRemap {
+    /// The signed permutation matrix mapping camera coordinates to output coordinates.
+    matrix: na::Matrix3<f64>,
+}
+
+impl AxisRemap {
+    pub fn identity() -> Self {
+        Self {
+            matrix: na::Matrix3::identity(),
+        }
+    }
+
+    /// Conversion from OpenCV's camera frame to Unity's left-handed frame with +y pointing up.
+    pub fn opencv_to_unity() -> Self {
+        Self {
+            matrix: na::Matrix3::from_diagonal(&na::vector![1.0, -1.0, 1.0]),
+        }
+    }
+
+    pub fn matrix(&self) -> &na::Matrix3<f64> {
+        &self.matrix
+    }
+
+    /// Convert a transform from the camera frame into the output frame.
+    ///
+    /// The translation `t` becomes `S * t`, and the rotation `R` becomes `S * R * S^T`, where `S` is the
+    /// signed permutation matrix. This keeps the rotation proper even when the handedness is flipped.
+    pub fn apply(&self, transform: &na::Isometry3<f64>) -> na::Isometry3<f64> {
+        let translation = self.matrix * transform.translation.vector;
+        let rotation = self.matrix
+            * transform.rotation.to_rotation_matrix().matrix()
+            * self.matrix.transpose();
+        na::Isometry3::from_parts(
+            translation.into(),
+            na::UnitQuaternion::from_matrix(&rotation),
+        )
+    }
+}
+
+impl Default for AxisRemap {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl std::str::FromStr for AxisRemap {
+    type Err = String;
+
+    /// Parse a remapping from three comma-separated signed axes, e.g. `x,-y,z`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let axes = s.split(',').map(str::trim).collect::<Vec<_>>();
+        if axes.len() != 3 {
+            return Err(format!("Expected 3 comma-separated axes, but got `{}`", s));
+        }
+        let mut matrix = na::Matrix3::zeros();
+        for (row, axis) in axes.iter().enumerate() {
+            let (sign, name) = match axis.strip_prefix('-') {
+                Some(name) => (-1.0, name),
+                None => (1.0, axis.strip_prefix('+').unwrap_or(axis)),
+            };
+            let column = match name {
+                "x" => 0,
+                "y" => 1,
+                "z" => 2,
+                _ => return Err(format!("Unknown axis `{}` in `{}`", axis, s)),
+            };
+            matrix[(row, column)] = sign;
+        }
+        // each source axis must be used exactly once
+        if (matrix.abs() * na::Vector3::repeat(1.0)) != na::Vector3::repeat(1.0)
+            || (matrix.abs().transpose() * na::Vector3::repeat(1.0)) != na::Vector3::repeat(1.0)
+        {
+            return Err(format!("Each axis must appear exactly once in `{}`", s));
+        }
+        Ok(Self { matrix })
+    }
+}
+
+/// The maximum length in bytes of a frame of any wire format, so that a peer cannot make the reader allocate
+/// an arbitrarily large buffer, e.g. with a forged MessagePack length prefix.
+pub const MAX_PACKET_LEN: usize = 1 << 20;
+
+/// The format in which packets are written to the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum WireFormat {
+    /// Newline-delimited JSON text.
+    #[default]
+    Json,
+
+    /// MessagePack frames, each prefixed by its length as a 4-byte big-endian integer.
+    Msgpack,
+}
+
+impl WireFormat {
+    /// Serialize a packet into a frame of this format and write it to `writer`.
+    pub fn write_packet<W: Write>(
+        &self,
+        writer: &mut W,
+        packet: &ObjectLocationPacket,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.write(writer, packet)
+    }
+
+    /// Read a frame of this format from `reader` and deserialize it into a packet.
+    pub fn read_packet<R: BufRead>(
+        &self,
+        reader: &mut R,
+    ) -> Result<ObjectLocationPacket, Box<dyn std::error::Error>> {
+        self.read(reader)
+    }
+
+    /// Serialize any message into a frame of this format and write it to `writer`. Fails if the frame is
+    /// longer than `MAX_PACKET_LEN`, which the reader would reject.
+    pub fn write<W: Write, T: Serialize>(
+        &self,
+        writer: &mut W,
+        packet: &T,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Self::Json => {
+                let serialized = serde_json::to_vec(packet)?;
+                // the newline counts towards the frame length
+                if serialized.len() >= MAX_PACKET_LEN {
+                    return Err("The packet is too long to send!".into());
+                }
+                writer.write_all(&serialized)?;
+                writer.write_all(b"\n")?;
+            }
+            Self::Msgpack => {
+                // Struct fields are serialized by name, so that optional fields can be skipped.
+                let serialized = rmp_serde::to_vec_named(packet)?;
+                if serialized.len() > MAX_PACKET_LEN {
+                    return Err("The packet is too long to send!".into());
+                }
+                let len = u32::try_from(serialized.len())?;
+                writer.write_all(&len.to_be_bytes())?;
+                writer.write_all(&serialized)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a frame of this format from `reader` and deserialize it into any message. Fails without reading
+    /// the whole frame if it is longer than `MAX_PACKET_LEN`.
+    pub fn read<R: BufRead, T: DeserializeOwned>(
+        &self,
+        reader: &mut R,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        match self {
+            Self::Json => {
+                let mut line = String::new();
+                let len = reader
+                    .by_ref()
+                    .take(MAX_PACKET_LEN as u64)
+                    .read_line(&mut line)?;
+                if len == 0 {
+                    return Err("Connection closed by the server.".into());
+                }
+                if len == MAX_PACKET_LEN && !line.ends_with('\n') {
+                    return Err("The packet is too long!".into());
+                }
+                Ok(serde_json::from_str(&line)?)
+            }
+            Self::Msgpack => {
+                let mut len = [0u8; 4];
+                reader.read_exact(&mut len)?;
+                let len = u32::from_be_bytes(len) as usize;
+                if len > MAX_PACKET_LEN {
+                    return Err(format!("The packet of {} bytes is too long!", len).into());
+                }
+                let mut buf = vec![0u8; len];
+                reader.read_exact(&mut buf)?;
+                Ok(rmp_serde::from_slice(&buf)?)
+            }
+        }
+    }
+}
+
+/// A utility struct for serializing `na::Isometry3<f64>` type with the given rotation format.
+struct SerializeIsometry<'a>(&'a na::Isometry3<f64>, RotationFormat);
+
+impl<'a> Serialize for SerializeIsometry<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let SerializeIsometry(isometry, rotation_format) = self;
+        if *rotation_format == RotationFormat::Homogeneous {
+            let mut map = serializer.serialize_map(Some(1))?;
+            map.serialize_entry("matrix", &isometry_to_matrix4(isometry))?;
+            return map.end();
+        }
+        let mut map = serializer.serialize_map(Some(2))?;
+        match rotation_format {
+            RotationFormat::Quaternion => {
+                map.serialize_key("rq")?;
+                map.serialize_value(isometry.rotation.as_vector().as_slice())?; // in the order of (i, j, k, 1)
+            }
+            RotationFormat::Euler => {
+                map.serialize_key("euler")?;
+                map.serialize_value(quat_to_euler(&isometry.rotation).as_slice())?;
+            }
+            RotationFormat::Matrix => {
+                let matrix = isometry.rotation.to_rotation_matrix();
+                map.serialize_key("rm")?;
+                // nalgebra stores matrices in column-major order, so the transpose is serialized
+                map.serialize_value(matrix.matrix().transpose().as_slice())?;
+            }
+            RotationFormat::AxisAngle => {
+                map.serialize_key("rv")?;
+                map.serialize_value(isometry.rotation.scaled_axis().as_slice())?;
+            }
+            RotationFormat::Homogeneous => unreachable!(),
+        }
+        map.serialize_key("t")?;
+        map.serialize_value(isometry.translation.vector.as_slice())?;
+        map.end()
+    }
 }
 
 /// A utility struct for deserializing into `na::Isometry3<f64>` type
@@ -41,31 +373,70 @@ impl<'de> Visitor<'de> for IsometryVisitor {
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
         formatter.write_str("A map of format {\"rq\": [i, j, k, w], \"t\": [x, y, z]}\n")?;
-        formatter.write_str("Where \"rq\" is the rotation quaternion, \"t\" is the translation vector relative to the camera's reference frame")
+        formatter.write_str("Where \"rq\" is the rotation quaternion, \"t\" is the translation vector relative to the camera's reference frame.\n")?;
+        formatter.write_str("The rotation may also be given as \"euler\": [roll, pitch, yaw], a row-major \"rm\": [...9 elements], or an axis-angle \"rv\": [x, y, z]\n")?;
+        formatter.write_str("Alternatively, the whole transform may be given as a homogeneous \"matrix\": [[...4 elements] x 4 rows]")
     }
 
     fn visit_map<M>(self, mut access: M) -> Result<Self::Value, M::Error>
     where
         M: MapAccess<'de>,
     {
-        let mut rotation: Option<Vec<f64>> = None;
+        let mut rotation: Option<na::UnitQuaternion<f64>> = None;
         let mut translation: Option<Vec<f64>> = None;
-        while let Some((key, value)) = access.next_entry::<&str, Vec<f64>>()? {
-            if key == "rq" {
-                rotation = Some(value);
-            } else if key == "t" {
-                translation = Some(value);
+        while let Some(key) = access.next_key::<&str>()? {
+            if key == "matrix" {
+                let rows = access.next_value::<Vec<Vec<f64>>>()?;
+                if rows.len() != 4 || rows.iter().any(|row| row.len() != 4) {
+                    return Err(serde::de::Error::invalid_length(rows.len(), &"4 rows of 4"));
+                }
+                let isometry = matrix4_to_isometry(&std::array::from_fn(|row| {
+                    std::array::from_fn(|col| rows[row][col])
+                }));
+                rotation = Some(isometry.rotation);
+                translation = Some(isometry.translation.vector.as_slice().to_vec());
+                continue;
+            }
+            let expected_len = match key {
+                "rq" => 4,
+                "euler" | "rv" | "t" => 3,
+                "rm" => 9,
+                _ => {
+                    access.next_value::<serde::de::IgnoredAny>()?;
+                    continue;
+                }
+            };
+            let value = access.next_value::<Vec<f64>>()?;
+            if value.len() != expected_len {
+                return Err(serde::de::Error::invalid_length(
+                    value.len(),
+                    &expected_len.to_string().as_str(),
+                ));
+            }
+            match key {
+                "rq" => {
+                    rotation = Some(na::UnitQuaternion::from_quaternion(na::Quaternion::new(
+                        value[3], value[0], value[1], value[2],
+                    )))
+                }
+                "euler" => {
+                    rotation = Some(euler_to_quat(&na::Vector3::new(
+                        value[0], value[1], value[2],
+                    )))
+                }
+                "rm" => rotation = Some(matrix_to_quat(&na::Matrix3::from_row_slice(&value))),
+                "rv" => {
+                    rotation = Some(na::UnitQuaternion::from_scaled_axis(na::Vector3::new(
+                        value[0], value[1], value[2],
+                    )))
+                }
+                _ => translation = Some(value),
             }
         }
         match (rotation, translation) {
             (Some(rotation), Some(translation)) => Ok(na::Isometry3::from_parts(
                 na::Translation3::new(translation[0], translation[1], translation[2]),
-                na::UnitQuaternion::from_quaternion(na::Quaternion::new(
-                    rotation[3],
-                    rotation[0],
-                    rotation[1],
-                    rotation[2],
-                )),
+                rotation,
             )),
             (Some(_), None) => Err(serde::de::Error::missing_field("t")),
             _ => Err(serde::de::Error::missing_field("rq")),
@@ -83,12 +454,18 @@ fn deserialize_isometry<'de, D: Deserializer<'de>>(
 mod tests {
     use super::*;
 
+    use crate::tag::apriltag::ApriltagFamily;
+
     #[test]
     fn test_serialize_json() {
         let packet = ObjectLocationPacket {
             time: 1145141919810,
             name: "object".to_string(),
             transform: na::Isometry3::identity(),
+            lost: false,
+            extrapolated: false,
+            confidence: None,
+            rotation_format: RotationFormat::Quaternion,
         };
         let serialized = serde_json::to_string(&packet).unwrap();
         let deserialized: serde_json::Value = serde_json::from_str(&serialized).unwrap();
@@ -108,6 +485,10 @@ mod tests {
             time: 0,
             name: "&*\'|\"\\()[]~`.xXyY123啊啊".to_string(),
             transform: na::Isometry3::translation(1.0, 2.0, -3.0),
+            lost: false,
+            extrapolated: false,
+            confidence: None,
+            rotation_format: RotationFormat::Quaternion,
         };
         let serialized = serde_json::to_string(&packet).unwrap();
         let deserialized: serde_json::Value = serde_json::from_str(&serialized).unwrap();
@@ -123,4 +504,486 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn test_serialize_lost_json() {
+        let packet = ObjectLocationPacket {
+            time: 1000,
+            name: "object".to_string(),
+            transform: na::Isometry3::translation(1.0, 2.0, 3.0),
+            lost: true,
+            extrapolated: false,
+            confidence: None,
+            rotation_format: RotationFormat::Quaternion,
+        };
+        let serialized = serde_json::to_string(&packet).unwrap();
+        let deserialized: serde_json::Value = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(
+            deserialized,
+            serde_json::json!({
+                "time": 1000u128,
+                "name": "object",
+                "transform": {
+                    "rq": [0.0, 0.0, 0.0, 1.0],
+                    "t": [1.0, 2.0, 3.0],
+                },
+                "lost": true,
+            })
+        );
+        let deserialized: ObjectLocationPacket = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, packet);
+
+        // packets without the "lost" field are not lost
+        let deserialized: ObjectLocationPacket = serde_json::from_str(
+            r#"{"time": 0, "name": "object", "transform": {"rq": [0, 0, 0, 1], "t": [0, 0, 0]}}"#,
+        )
+        .unwrap();
+        assert!(!deserialized.lost);
+        assert!(!deserialized.extrapolated);
+        assert_eq!(deserialized.confidence, None);
+    }
+
+    #[test]
+    fn test_serialize_extrapolated_json() {
+        let packet = ObjectLocationPacket {
+            time: 1000,
+            name: "object".to_string(),
+            transform: na::Isometry3::translation(1.0, 2.0, 3.0),
+            lost: false,
+            extrapolated: true,
+            confidence: None,
+            rotation_format: RotationFormat::Quaternion,
+        };
+        let serialized = serde_json::to_string(&packet).unwrap();
+        let deserialized: serde_json::Value = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized["extrapolated"], serde_json::json!(true));
+        assert!(deserialized.get("lost").is_none());
+        let deserialized: ObjectLocationPacket = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, packet);
+    }
+
+    #[test]
+    fn test_serialize_confidence_json() {
+        let packet = ObjectLocationPacket {
+            time: 1000,
+            name: "object".to_string(),
+            transform: na::Isometry3::translation(1.0, 2.0, 3.0),
+            lost: false,
+            extrapolated: false,
+            confidence: Some(0.75),
+            rotation_format: RotationFormat::Quaternion,
+        };
+        let serialized = serde_json::to_string(&packet).unwrap();
+        let deserialized: serde_json::Value = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized["confidence"], serde_json::json!(0.75));
+        let deserialized: ObjectLocationPacket = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, packet);
+        let deserialized: ObjectLocationPacket =
+            rmp_serde::from_slice(&rmp_serde::to_vec_named(&packet).unwrap()).unwrap();
+        assert_eq!(deserialized, packet);
+    }
+
+    #[test]
+    fn test_serialize_axis_angle_json() {
+        // small angles, and angles close to pi, where converting through other representations loses precision
+        for scaled_axis in [
+            na::vector![1e-9, -2e-9, 0.0],
+            na::vector![0.3, -0.5, 0.2],
+            na::vector![0.0, 0.0, std::f64::consts::PI - 1e-6],
+        ] {
+            let packet = ObjectLocationPacket {
+                time: 1000,
+                name: "object".to_string(),
+                transform: na::Isometry3::new(na::vector![1.0, 2.0, 3.0], scaled_axis),
+                lost: false,
+                extrapolated: false,
+                confidence: None,
+                rotation_format: RotationFormat::AxisAngle,
+            };
+            let serialized = serde_json::to_string(&packet).unwrap();
+            let value: serde_json::Value = serde_json::from_str(&serialized).unwrap();
+            let rv = value["transform"]["rv"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|v| v.as_f64().unwrap())
+                .collect::<Vec<_>>();
+            assert!((na::Vector3::from_column_slice(&rv) - scaled_axis).norm() < 1e-8);
+
+            let deserialized: ObjectLocationPacket = serde_json::from_str(&serialized).unwrap();
+            let expected = na::UnitQuaternion::from_scaled_axis(scaled_axis);
+            assert!(deserialized.transform.rotation.angle_to(&expected) < 1e-7);
+        }
+    }
+
+    #[test]
+    fn test_serialize_homogeneous_json() {
+        let transform =
+            na::Isometry3::new(na::vector![1.0, -2.0, 3.0], na::vector![0.3, -0.5, 0.2]);
+        let packet = ObjectLocationPacket {
+            time: 1000,
+            name: "object".to_string(),
+            transform,
+            lost: false,
+            extrapolated: false,
+            confidence: None,
+            rotation_format: RotationFormat::Homogeneous,
+        };
+        let serialized = serde_json::to_string(&packet).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&serialized).unwrap();
+        assert!(value["transform"].get("t").is_none());
+        let rows = value["transform"]["matrix"].as_array().unwrap();
+        assert_eq!(rows.len(), 4);
+        // the translation is the last column, and the last row is [0, 0, 0, 1]
+        assert_eq!(rows[0][3], serde_json::json!(1.0));
+        assert_eq!(rows[1][3], serde_json::json!(-2.0));
+        assert_eq!(rows[2][3], serde_json::json!(3.0));
+        assert_eq!(rows[3], serde_json::json!([0.0, 0.0, 0.0, 1.0]));
+
+        for deserialized in [
+            serde_json::from_str::<ObjectLocationPacket>(&serialized).unwrap(),
+            rmp_serde::from_slice(&rmp_serde::to_vec_named(&packet).unwrap()).unwrap(),
+        ] {
+            let error = deserialized.transform.inverse() * transform;
+            assert!(error.translation.vector.norm() < 1e-9);
+            assert!(error.rotation.angle() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_axis_remap_to_unity() {
+        let remap: AxisRemap = "x,-y,z".parse().unwrap();
+        assert_eq!(remap, AxisRemap::opencv_to_unity());
+
+        let transform = na::Isometry3::new(na::vector![1.0, 2.0, 3.0], na::vector![0.3, -0.5, 0.2]);
+        let remapped = remap.apply(&transform);
+        assert!((remapped.translation.vector - na::vector![1.0, -2.0, 3.0]).norm() < 1e-9);
+        // mapping a point then remapping it is the same as remapping the point then mapping it
+        let point = na::point![0.4, -0.7, 1.1];
+        let expected = remap.matrix() * transform.transform_point(&point).coords;
+        let actual = remapped.transform_point(&(remap.matrix() * point.coords).into());
+        assert!((actual.coords - expected).norm() < 1e-9);
+
+        // a rotation around the camera's x axis is reversed, since the y axis is flipped
+        let pitch_down = na::Isometry3::rotation(na::vector![0.4, 0.0, 0.0]);
+        let remapped = remap.apply(&pitch_down);
+        let expected = na::UnitQuaternion::from_scaled_axis(na::vector![-0.4, 0.0, 0.0]);
+        assert!(remapped.rotation.angle_to(&expected) < 1e-9);
+    }
+
+    #[test]
+    fn test_parse_axis_remap() {
+        assert_eq!("x,y,z".parse::<AxisRemap>().unwrap(), AxisRemap::identity());
+        let remap: AxisRemap = "z, -x, +y".parse().unwrap();
+        assert_eq!(
+            *remap.matrix(),
+            na::Matrix3::new(0.0, 0.0, 1.0, -1.0, 0.0, 0.0, 0.0, 1.0, 0.0)
+        );
+        assert!("x,y".parse::<AxisRemap>().is_err());
+        assert!("x,x,z".parse::<AxisRemap>().is_err());
+        assert!("x,y,w".parse::<AxisRemap>().is_err());
+    }
+
+    #[test]
+    fn test_wire_format_round_trip() {
+        let packets = [
+            ObjectLocationPacket {
+                time: 1145141919810,
+                name: "object".to_string(),
+                transform: na::Isometry3::new(
+                    na::vector![1.0, -2.0, 3.0],
+                    na::vector![0.1, 0.2, -0.3],
+                ),
+                lost: false,
+                extrapolated: false,
+                confidence: None,
+                rotation_format: RotationFormat::Quaternion,
+            },
+            ObjectLocationPacket {
+                time: 0,
+                name: "lost object".to_string(),
+                transform: na::Isometry3::translation(4.0, 5.0, 6.0),
+                lost: true,
+                extrapolated: false,
+                confidence: None,
+                rotation_format: RotationFormat::Quaternion,
+            },
+        ];
+        for format in [WireFormat::Json, WireFormat::Msgpack] {
+            let mut buf = Vec::new();
+            for packet in &packets {
+                format.write_packet(&mut buf, packet).unwrap();
+            }
+            let mut reader = std::io::Cursor::new(buf);
+            for packet in &packets {
+                let deserialized = format.read_packet(&mut reader).unwrap();
+                assert_eq!(deserialized.time, packet.time);
+                assert_eq!(deserialized.name, packet.name);
+                assert_eq!(deserialized.lost, packet.lost);
+                assert!(
+                    (deserialized.transform.to_homogeneous() - packet.transform.to_homogeneous())
+                        .norm()
+                        < 1e-12,
+                    "Transform mismatch in format {:?}",
+                    format
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_oversized_frame() {
+        // a forged prefix must be rejected before its length is allocated
+        let mut reader = std::io::Cursor::new(u32::MAX.to_be_bytes().to_vec());
+        let error = WireFormat::Msgpack
+            .read::<_, AuthPacket>(&mut reader)
+            .unwrap_err();
+        assert!(error.to_string().contains("too long"));
+
+        let mut reader = std::io::Cursor::new(vec![b' '; MAX_PACKET_LEN + 1]);
+        assert!(WireFormat::Json.read::<_, AuthPacket>(&mut reader).is_err());
+        // only the first `MAX_PACKET_LEN` bytes are read
+        assert_eq!(reader.position(), MAX_PACKET_LEN as u64);
+
+        let auth = AuthPacket {
+            token: "x".repeat(MAX_PACKET_LEN),
+        };
+        for format in [WireFormat::Json, WireFormat::Msgpack] {
+            assert!(format.write(&mut Vec::new(), &auth).is_err());
+        }
+    }
+
+    #[test]
+    fn test_random_transform_round_trip() {
+        use rand::Rng;
+
+        let mut rng = rand::rng();
+        let mut rotations = (0..500)
+            .map(|_| {
+                let axis = na::Unit::new_normalize(na::vector![
+                    rng.random_range(-1.0..1.0),
+                    rng.random_range(-1.0..1.0),
+                    rng.random_range(-1.0..1.0)
+                ]);
+                na::UnitQuaternion::from_axis_angle(
+                    &axis,
+                    rng.random_range(0.0..std::f64::consts::PI),
+                )
+            })
+            .collect::<Vec<_>>();
+        // the rotations where sign conventions and singularities bite
+        let axes = [
+            na::Vector3::x_axis(),
+            na::Vector3::y_axis(),
+            na::Vector3::z_axis(),
+            na::Unit::new_normalize(na::vector![1.0, -1.0, 1.0]),
+        ];
+        for axis in &axes {
+            for angle in [
+                0.0,
+                1e-9,
+                1e-4,
+                std::f64::consts::FRAC_PI_2,
+                std::f64::consts::PI - 1e-4,
+            ] {
+                rotations.push(na::UnitQuaternion::from_axis_angle(axis, angle));
+                rotations.push(na::UnitQuaternion::from_axis_angle(axis, -angle));
+            }
+            rotations.push(na::UnitQuaternion::from_axis_angle(
+                axis,
+                std::f64::consts::PI,
+            ));
+            rotations.push(na::UnitQuaternion::from_axis_angle(
+                axis,
+                std::f64::consts::PI - 1e-9,
+            ));
+        }
+        // the quaternion with a negative real part represents the same rotation
+        rotations.push(na::UnitQuaternion::new_unchecked(
+            -*rotations[0].quaternion(),
+        ));
+
+        for rotation_format in [
+            RotationFormat::Quaternion,
+            RotationFormat::Euler,
+            RotationFormat::Matrix,
+            RotationFormat::AxisAngle,
+            RotationFormat::Homogeneous,
+        ] {
+            for format in [WireFormat::Json, WireFormat::Msgpack] {
+                for rotation in &rotations {
+                    let translation = na::vector![
+                        rng.random_range(-1000.0..1000.0),
+                        rng.random_range(-1000.0..1000.0),
+                        rng.random_range(-1000.0..1000.0)
+                    ];
+                    let transform = na::Isometry3::from_parts(translation.into(), *rotation);
+                    let packet = ObjectLocationPacket {
+                        time: 0,
+                        name: "object".to_string(),
+                        transform,
+                        lost: false,
+                        extrapolated: false,
+                        confidence: None,
+                        rotation_format,
+                    };
+                    let mut buf = Vec::new();
+                    format.write_packet(&mut buf, &packet).unwrap();
+                    let deserialized = format
+                        .read_packet(&mut std::io::Cursor::new(buf))
+                        .unwrap()
+                        .transform;
+                    // Euler angles lose some precision near the gimbal lock at a pitch of 90 degrees
+                    let angle = deserialized.rotation.angle_to(&transform.rotation);
+                    assert!(
+                        angle < 1e-7,
+                        "Rotation {:?} is off by {} rad in format {:?} with rotation format {:?}",
+                        rotation,
+                        angle,
+                        format,
+                        rotation_format
+                    );
+                    assert!(
+                        (deserialized.translation.vector - translation).norm() < 1e-9,
+                        "Translation {} mismatch in format {:?}",
+                        translation,
+                        format
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rotation_format_round_trip() {
+        let transform =
+            na::Isometry3::new(na::vector![1.0, -2.0, 3.0], na::vector![0.4, -0.2, 0.9]);
+        for (rotation_format, key, len) in [
+            (RotationFormat::Quaternion, "rq", 4),
+            (RotationFormat::Euler, "euler", 3),
+            (RotationFormat::Matrix, "rm", 9),
+        ] {
+            let packet = ObjectLocationPacket {
+                time: 1000,
+                name: "object".to_string(),
+                transform,
+                lost: false,
+                extrapolated: false,
+                confidence: None,
+                rotation_format,
+            };
+            let serialized: serde_json::Value = serde_json::to_value(&packet).unwrap();
+            assert_eq!(
+                serialized["transform"][key].as_array().unwrap().len(),
+                len,
+                "Unexpected rotation in format {:?}",
+                rotation_format
+            );
+            for format in [WireFormat::Json, WireFormat::Msgpack] {
+                let mut buf = Vec::new();
+                format.write_packet(&mut buf, &packet).unwrap();
+                let deserialized = format.read_packet(&mut std::io::Cursor::new(buf)).unwrap();
+                assert!(
+                    (deserialized.transform.to_homogeneous() - transform.to_homogeneous()).norm()
+                        < 1e-12,
+                    "Transform mismatch in format {:?} with rotation format {:?}",
+                    format,
+                    rotation_format
+                );
+            }
+        }
+
+        // the Euler angles follow the documented convention
+        let packet = ObjectLocationPacket {
+            time: 0,
+            name: "object".to_string(),
+            transform: na::Isometry3::rotation(na::vector![0.0, 0.0, 0.5]),
+            lost: false,
+            extrapolated: false,
+            confidence: None,
+            rotation_format: RotationFormat::Euler,
+        };
+        let serialized: serde_json::Value = serde_json::to_value(&packet).unwrap();
+        let euler = serialized["transform"]["euler"].as_array().unwrap();
+        assert!(euler[0].as_f64().unwrap().abs() < 1e-12);
+        assert!(euler[1].as_f64().unwrap().abs() < 1e-12);
+        assert!((euler[2].as_f64().unwrap() - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_handshake() {
+        let handshake = HandshakePacket {
+            unit: "mm".to_string(),
+            detections: false,
+        };
+        let packet = ObjectLocationPacket {
+            time: 1000,
+            name: "object".to_string(),
+            transform: na::Isometry3::translation(1.0, 2.0, 3.0),
+            lost: false,
+            extrapolated: false,
+            confidence: None,
+            rotation_format: RotationFormat::Quaternion,
+        };
+        assert_eq!(
+            serde_json::to_value(&handshake).unwrap(),
+            serde_json::json!({ "unit": "mm" })
+        );
+        for format in [WireFormat::Json, WireFormat::Msgpack] {
+            let mut buf = Vec::new();
+            format.write(&mut buf, &handshake).unwrap();
+            format.write_packet(&mut buf, &packet).unwrap();
+            let mut reader = std::io::Cursor::new(buf);
+            let deserialized: HandshakePacket = format.read(&mut reader).unwrap();
+            assert_eq!(deserialized, handshake);
+            assert_eq!(format.read_packet(&mut reader).unwrap(), packet);
+        }
+    }
+
+    #[test]
+    fn test_detections_packet() {
+        let handshake = HandshakePacket {
+            unit: "mm".to_string(),
+            detections: true,
+        };
+        let detections = DetectionsPacket {
+            time: 1000,
+            objects: 1,
+            detections: vec![DetectionSnapshot {
+                family: ApriltagFamily::Tag36h11,
+                id: 3,
+                hamming: 1,
+                decision_margin: 42.5,
+                center: [100.5, 200.5],
+                corners: [[90.0, 210.0], [110.0, 210.0], [110.0, 190.0], [90.0, 190.0]],
+            }],
+        };
+        let packet = ObjectLocationPacket {
+            time: 1000,
+            name: "object".to_string(),
+            transform: na::Isometry3::translation(1.0, 2.0, 3.0),
+            lost: false,
+            extrapolated: false,
+            confidence: None,
+            rotation_format: RotationFormat::Quaternion,
+        };
+        let serialized = serde_json::to_value(&detections).unwrap();
+        assert_eq!(serialized["detections"][0]["family"], "tag36h11");
+        for format in [WireFormat::Json, WireFormat::Msgpack] {
+            let mut buf = Vec::new();
+            format.write(&mut buf, &handshake).unwrap();
+            format.write(&mut buf, &detections).unwrap();
+            format.write_packet(&mut buf, &packet).unwrap();
+            let mut reader = std::io::Cursor::new(buf);
+            let deserialized: HandshakePacket = format.read(&mut reader).unwrap();
+            assert_eq!(deserialized, handshake);
+            let deserialized: DetectionsPacket = format.read(&mut reader).unwrap();
+            assert_eq!(deserialized, detections);
+            assert_eq!(format.read_packet(&mut reader).unwrap(), packet);
+        }
+        // unknown families are rejected
+        let mut serialized = serialized;
+        serialized["detections"][0]["family"] = "tag99h99".into();
+        assert!(serde_json::from_value::<DetectionsPacket>(serialized).is_err());
+    }
 }
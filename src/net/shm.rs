@@ -0,0 +1,615 @@
+//! A shared-memory output for consumers on the same machine, e.g. a compositor, which cannot afford the
+//! latency of a TCP connection over localhost.
+//!
+//! The writer maps a file (preferably under `/dev/shm` on Linux) and publishes the objects of each frame
+//! into a ring buffer of slots, which any number of other processes map read-only with `ShmPoseReader`.
+//!
+//! # Layout
+//!
+//! All integers and floats are in the machine's native byte order, and all offsets are in bytes.
+//!
+//! The file starts with a 64-byte header:
+//!
+//! | Offset | Type       | Field                                                                      |
+//! |--------|------------|----------------------------------------------------------------------------|
+//! | 0      | `[u8; 8]`  | `SHM_MAGIC`                                                                |
+//! | 8      | `u32`      | `SHM_VERSION`                                                              |
+//! | 12     | `u32`      | The number of slots in the ring buffer                                     |
+//! | 16     | `u32`      | The maximum number of pose records in a slot                               |
+//! | 20     | `u32`      | The size of a pose record, 96 in this version                              |
+//! | 24     | `u64`      | The sequence number of the latest published frame, starting at 1. 0 if none |
+//! | 32     | `[u8; 32]` | Reserved                                                                   |
+//!
+//! The header is followed by the slots. The frame with sequence number `n` is written to the slot
+//! `(n - 1) % slot_count`, which starts with a 32-byte slot header:
+//!
+//! | Offset | Type       | Field                                                                      |
+//! |--------|------------|----------------------------------------------------------------------------|
+//! | 0      | `u64`      | The sequence number of the frame in the slot, or 0 while it is written     |
+//! | 8      | `u64`      | The system time at which the frame is captured, in microseconds since the UNIX epoch |
+//! | 16     | `u32`      | The number of pose records in the slot                                     |
+//! | 20     | `[u8; 12]` | Reserved                                                                   |
+//!
+//! followed by the pose records:
+//!
+//! | Offset | Type       | Field                                                                      |
+//! |--------|------------|----------------------------------------------------------------------------|
+//! | 0      | `[u8; 32]` | The object's name in UTF-8, padded with zeros                              |
+//! | 32     | `u32`      | The pose's flags: `SHM_FLAG_LOST`, `SHM_FLAG_EXTRAPOLATED`, `SHM_FLAG_DEGRADED` |
+//! | 36     | `u32`      | Reserved                                                                   |
+//! | 40     | `[f64; 3]` | The translation                                                            |
+//! | 64     | `[f64; 4]` | The rotation as a unit quaternion `(x, y, z, w)`                           |
+//!
+//! A reader loads the header's sequence number, and copies the slot if the slot's sequence number equals
+//! it both before and after copying. Otherwise the slot is overwritten meanwhile, and the reader retries.
+
+use std::fs::{File, OpenOptions};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::sync::atomic::{self, AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::net::packet::AxisRemap;
+use crate::net::wait_for_next_frame;
+use crate::tag::locator::LocatedObjects;
+
+extern crate nalgebra as na;
+
+/// The first 8 bytes of a shared-memory pose file.
+pub const SHM_MAGIC: [u8; 8] = *b"XDIMPOSE";
+
+/// The version of the layout. Readers reject files of other versions.
+pub const SHM_VERSION: u32 = 1;
+
+/// The number of slots in the ring buffer created by `shm_thread_main`.
+pub const DEFAULT_SHM_SLOTS: usize = 4;
+
+/// The maximum number of pose records in a slot created by `shm_thread_main`. The other objects of a frame
+/// are not published.
+pub const DEFAULT_SHM_MAX_RECORDS: usize = 64;
+
+/// The maximum length of an object's name in bytes. Longer names are truncated.
+pub const SHM_NAME_LEN: usize = 32;
+
+/// Set in a pose record's flags if the object has just been lost from tracking, and the pose is its last
+/// known location.
+pub const SHM_FLAG_LOST: u32 = 1 << 0;
+
+/// Set in a pose record's flags if the object is not located in the frame, and the pose is extrapolated.
+pub const SHM_FLAG_EXTRAPOLATED: u32 = 1 << 1;
+
+/// Set in a pose record's flags if the object is located from fewer tags than it is usually located from.
+pub const SHM_FLAG_DEGRADED: u32 = 1 << 2;
+
+/// The number of times a reader retries copying a slot that is overwritten while it is copied.
+const READ_ATTEMPTS: usize = 16;
+
+#[repr(C)]
+struct Header {
+    magic: [u8; 8],
+    version: u32,
+    slot_count: u32,
+    max_records: u32,
+    record_size: u32,
+    sequence: AtomicU64,
+    reserved: [u8; 32],
+}
+
+#[repr(C)]
+struct SlotHeader {
+    sequence: AtomicU64,
+    timestamp_us: u64,
+    record_count: u32,
+    reserved: [u8; 12],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PoseRecord {
+    name: [u8; SHM_NAME_LEN],
+    flags: u32,
+    reserved: u32,
+    translation: [f64; 3],
+    rotation: [f64; 4],
+}
+
+/// The pose of an object published to or read from the shared memory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShmPose {
+    pub name: String,
+    pub location: na::Isometry3<f64>,
+    /// A combination of `SHM_FLAG_LOST`, `SHM_FLAG_EXTRAPOLATED`, and `SHM_FLAG_DEGRADED`.
+    pub flags: u32,
+}
+
+/// The poses of a frame read from the shared memory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShmFrame {
+    /// The sequence number of the frame, which increases by 1 for each published frame. A gap between two
+    /// read frames means the frames in between are missed.
+    pub sequence: u64,
+    pub system_time: SystemTime,
+    pub poses: Vec<ShmPose>,
+}
+
+fn file_size(slot_count: usize, max_records: usize) -> usize {
+    size_of::<Header>() + slot_count * slot_size(max_records)
+}
+
+fn slot_size(max_records: usize) -> usize {
+    size_of::<SlotHeader>() + max_records * size_of::<PoseRecord>()
+}
+
+/// A memory mapping of a whole file, unmapped when dropped.
+struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Mapping {
+    fn new(file: &File, len: usize, writable: bool) -> std::io::Result<Self> {
+        let prot = if writable {
+            libc::PROT_READ | libc::PROT_WRITE
+        } else {
+            libc::PROT_READ
+        };
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                prot,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self {
+            ptr: ptr as *mut u8,
+            len,
+        })
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { &*(self.ptr as *const Header) }
+    }
+
+    fn slot(&self, index: usize, max_records: usize) -> *mut SlotHeader {
+        unsafe {
+            self.ptr
+                .add(size_of::<Header>() + index * slot_size(max_records))
+                as *mut SlotHeader
+        }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+// The mapping is only written through `ShmPoseWriter`, which needs `&mut self` to do so.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+/// Publishes the poses of each frame into a shared-memory ring buffer. See the module's documentation
+/// for the layout.
+pub struct ShmPoseWriter {
+    mapping: Mapping,
+    slot_count: usize,
+    max_records: usize,
+    sequence: u64,
+}
+
+impl ShmPoseWriter {
+    /// Create (or overwrite) the file at `path`, with a ring buffer of `slot_count` slots of up to
+    /// `max_records` poses each.
+    ///
+    /// A reader copying a slot while it is overwritten retries, so more slots make retries rarer for
+    /// readers that are slow to copy.
+    pub fn create(
+        path: &Path,
+        slot_count: usize,
+        max_records: usize,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        if slot_count == 0 || max_records == 0 {
+            return Err("The shared memory needs at least one slot of at least one record".into());
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let len = file_size(slot_count, max_records);
+        // the file is filled with zeros, so every slot's sequence number is 0
+        file.set_len(len as u64)?;
+        let mapping = Mapping::new(&file, len, true)?;
+        unsafe {
+            let header = mapping.ptr as *mut Header;
+            (*header).slot_count = slot_count as u32;
+            (*header).max_records = max_records as u32;
+            (*header).record_size = size_of::<PoseRecord>() as u32;
+            (*header).version = SHM_VERSION;
+            // readers check the magic first, so it is written last
+            atomic::fence(Ordering::Release);
+            (*header).magic = SHM_MAGIC;
+        }
+        Ok(Self {
+            mapping,
+            slot_count,
+            max_records,
+            sequence: 0,
+        })
+    }
+
+    /// Publish the `poses` of a frame captured at `system_time`, and return the frame's sequence number.
+    ///
+    /// Only the first `max_records` poses are published, and names longer than `SHM_NAME_LEN` bytes are
+    /// truncated.
+    pub fn write_frame(
+        &mut self,
+        system_time: SystemTime,
+        poses: &[ShmPose],
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let timestamp_us = u64::try_from(system_time.duration_since(UNIX_EPOCH)?.as_micros())?;
+        let sequence = self.sequence + 1;
+        let slot = self.mapping.slot(
+            ((sequence - 1) % self.slot_count as u64) as usize,
+            self.max_records,
+        );
+        let poses = &poses[..poses.len().min(self.max_records)];
+        unsafe {
+            // mark the slot as being written before touching its contents
+            (*slot).sequence.store(0, Ordering::Relaxed);
+            atomic::fence(Ordering::Release);
+            (*slot).timestamp_us = timestamp_us;
+            (*slot).record_count = poses.len() as u32;
+            let records = slot.add(1) as *mut PoseRecord;
+            for (i, pose) in poses.iter().enumerate() {
+                records.add(i).write(PoseRecord::from(pose));
+            }
+            (*slot).sequence.store(sequence, Ordering::Release);
+        }
+        self.mapping
+            .header()
+            .sequence
+            .store(sequence, Ordering::Release);
+        self.sequence = sequence;
+        Ok(sequence)
+    }
+
+    /// Publish the objects of a frame, in the same order as the packets sent by the TCP server: the located
+    /// objects, followed by the objects lost in the frame.
+    pub fn write(
+        &mut self,
+        located_objects: &LocatedObjects,
+        axis_remap: AxisRemap,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let located = located_objects
+            .name_map()
+            .iter()
+            .map(|(name, location)| (name, location, false));
+        let lost = located_objects
+            .lost_map()
+            .iter()
+            .map(|(name, location)| (name, location, true));
+        let poses = located
+            .chain(lost)
+            .map(|(name, location, lost)| {
+                let mut flags = 0;
+                if lost {
+                    flags |= SHM_FLAG_LOST;
+                } else {
+                    if located_objects.extrapolated().contains(name) {
+                        flags |= SHM_FLAG_EXTRAPOLATED;
+                    }
+                    if located_objects.degraded().contains(name) {
+                        flags |= SHM_FLAG_DEGRADED;
+                    }
+                }
+                ShmPose {
+                    name: name.to_string(),
+                    location: axis_remap.apply(location),
+                    flags,
+                }
+            })
+            .collect::<Vec<_>>();
+        self.write_frame(located_objects.system_time(), &poses)
+    }
+}
+
+impl From<&ShmPose> for PoseRecord {
+    fn from(pose: &ShmPose) -> Self {
+        let mut name = [0; SHM_NAME_LEN];
+        // truncate on a character boundary, so that the name stays valid UTF-8
+        let mut len = pose.name.len().min(SHM_NAME_LEN);
+        while !pose.name.is_char_boundary(len) {
+            len -= 1;
+        }
+        name[..len].copy_from_slice(&pose.name.as_bytes()[..len]);
+        let translation = pose.location.translation.vector;
+        let rotation = pose.location.rotation.coords;
+        Self {
+            name,
+            flags: pose.flags,
+            reserved: 0,
+            translation: [translation.x, translation.y, translation.z],
+            rotation: [rotation.x, rotation.y, rotation.z, rotation.w],
+        }
+    }
+}
+
+impl From<&PoseRecord> for ShmPose {
+    fn from(record: &PoseRecord) -> Self {
+        let len = record
+            .name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(SHM_NAME_LEN);
+        let [x, y, z] = record.translation;
+        let [i, j, k, w] = record.rotation;
+        Self {
+            name: String::from_utf8_lossy(&record.name[..len]).into_owned(),
+            location: na::Isometry3::from_parts(
+                na::Translation3::new(x, y, z),
+                na::UnitQuaternion::new_normalize(na::Quaternion::new(w, i, j, k)),
+            ),
+            flags: record.flags,
+        }
+    }
+}
+
+/// Reads the poses published by a `ShmPoseWriter`, possibly in another process, from a read-only mapping.
+pub struct ShmPoseReader {
+    mapping: Mapping,
+    slot_count: usize,
+    max_records: usize,
+}
+
+impl ShmPoseReader {
+    /// Map the file at `path` written by a `ShmPoseWriter`.
+    ///
+    /// Returns an error if the file is not a shared-memory pose file of version `SHM_VERSION`.
+    pub fn open(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len < size_of::<Header>() {
+            return Err(format!("{} is not a shared-memory pose file", path.display()).into());
+        }
+        let mapping = Mapping::new(&file, len, false)?;
+        let header = mapping.header();
+        if header.magic != SHM_MAGIC {
+            return Err(format!("{} is not a shared-memory pose file", path.display()).into());
+        }
+        atomic::fence(Ordering::Acquire);
+        if header.version != SHM_VERSION {
+            return Err(format!(
+                "Expected version {} of the shared-memory layout, but got {}",
+                SHM_VERSION, header.version
+            )
+            .into());
+        }
+        let (slot_count, max_records) = (header.slot_count as usize, header.max_records as usize);
+        if header.record_size as usize != size_of::<PoseRecord>()
+            || len < file_size(slot_count, max_records)
+        {
+            return Err(format!("{} is truncated or corrupted", path.display()).into());
+        }
+        Ok(Self {
+            mapping,
+            slot_count,
+            max_records,
+        })
+    }
+
+    /// The sequence number of the latest published frame, or 0 if no frame is published yet.
+    pub fn sequence(&self) -> u64 {
+        self.mapping.header().sequence.load(Ordering::Acquire)
+    }
+
+    /// Copy the latest published frame, or return `None` if no frame is published yet, or if the writer
+    /// keeps overwriting the slot while it is copied.
+    pub fn latest(&self) -> Option<ShmFrame> {
+        for _ in 0..READ_ATTEMPTS {
+            let sequence = self.sequence();
+            if sequence == 0 {
+                return None;
+            }
+            let slot = self.mapping.slot(
+                ((sequence - 1) % self.slot_count as u64) as usize,
+                self.max_records,
+            );
+            unsafe {
+                if (*slot).sequence.load(Ordering::Acquire) != sequence {
+                    continue;
+                }
+                let timestamp_us = std::ptr::read_volatile(&raw const (*slot).timestamp_us);
+                let record_count =
+                    std::ptr::read_volatile(&raw const (*slot).record_count) as usize;
+                let records = slot.add(1) as *const PoseRecord;
+                let poses = (0..record_count.min(self.max_records))
+                    .map(|i| ShmPose::from(&std::ptr::read_volatile(records.add(i))))
+                    .collect();
+                atomic::fence(Ordering::Acquire);
+                if (*slot).sequence.load(Ordering::Relaxed) != sequence {
+                    continue;
+                }
+                return Some(ShmFrame {
+                    sequence,
+                    system_time: UNIX_EPOCH + Duration::from_micros(timestamp_us),
+                    poses,
+                });
+            }
+        }
+        None
+    }
+}
+
+/// Main function of the shared-memory thread, which publishes the objects of each frame to the file at
+/// `path` until terminated.
+pub fn shm_thread_main<'a>(
+    termination_signal: Arc<AtomicBool>,
+    path: &Path,
+    axis_remap: AxisRemap,
+    located_objects: Arc<(Mutex<LocatedObjects<'a>>, Condvar)>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = ShmPoseWriter::create(path, DEFAULT_SHM_SLOTS, DEFAULT_SHM_MAX_RECORDS)?;
+    log::info!("Publishing poses to shared memory at {}", path.display());
+    let mut last_timestamp = Instant::now();
+    while !termination_signal.load(Ordering::Relaxed) {
+        let locked_located_objects = wait_for_next_frame(
+            &located_objects,
+            located_objects.0.lock().unwrap(),
+            last_timestamp,
+        );
+        last_timestamp = locked_located_objects.timestamp();
+        writer.write(&locked_located_objects, axis_remap)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    use super::*;
+
+    /// The environment variable through which `test_child_process` passes the file to its child.
+    const CHILD_PATH_VAR: &str = "XDIMSCREEN_SHM_TEST_PATH";
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("xdimscreen-{}-{}", name, std::process::id()))
+    }
+
+    fn frame_poses(frame: u64) -> Vec<ShmPose> {
+        vec![
+            ShmPose {
+                name: "screen".to_string(),
+                location: na::Isometry3::new(
+                    na::vector![0.5, -0.3, frame as f64],
+                    na::vector![0.1, -0.6, 0.2],
+                ),
+                flags: 0,
+            },
+            ShmPose {
+                name: "wand".to_string(),
+                location: na::Isometry3::translation(1.0, 2.0, 3.0),
+                flags: SHM_FLAG_LOST,
+            },
+        ]
+    }
+
+    fn assert_frame(frame: &ShmFrame, sequence: u64) {
+        assert_eq!(frame.sequence, sequence);
+        assert_eq!(
+            frame.system_time,
+            UNIX_EPOCH + Duration::from_micros(1_700_000_000_000_000 + sequence)
+        );
+        let expected = frame_poses(sequence);
+        assert_eq!(frame.poses.len(), expected.len());
+        for (pose, expected) in frame.poses.iter().zip(&expected) {
+            assert_eq!(pose.name, expected.name);
+            assert_eq!(pose.flags, expected.flags);
+            assert!(
+                (pose.location.to_homogeneous() - expected.location.to_homogeneous()).norm()
+                    < 1e-12
+            );
+        }
+    }
+
+    fn write_frames(writer: &mut ShmPoseWriter, frames: std::ops::RangeInclusive<u64>) {
+        for frame in frames {
+            let system_time = UNIX_EPOCH + Duration::from_micros(1_700_000_000_000_000 + frame);
+            assert_eq!(
+                writer
+                    .write_frame(system_time, &frame_poses(frame))
+                    .unwrap(),
+                frame
+            );
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let path = temp_path("shm-round-trip");
+        let mut writer = ShmPoseWriter::create(&path, 3, 4).unwrap();
+        let reader = ShmPoseReader::open(&path).unwrap();
+        assert_eq!(reader.latest(), None);
+
+        // the ring buffer wraps around after 3 frames
+        write_frames(&mut writer, 1..=7);
+        assert_frame(&reader.latest().unwrap(), 7);
+
+        // extra poses are dropped, and long names are truncated
+        let long_name = "a".repeat(SHM_NAME_LEN - 1) + "é";
+        let poses = (0..6)
+            .map(|i| ShmPose {
+                name: long_name.clone(),
+                location: na::Isometry3::translation(i as f64, 0.0, 0.0),
+                flags: SHM_FLAG_EXTRAPOLATED | SHM_FLAG_DEGRADED,
+            })
+            .collect::<Vec<_>>();
+        writer.write_frame(SystemTime::now(), &poses).unwrap();
+        let frame = reader.latest().unwrap();
+        assert_eq!(frame.sequence, 8);
+        assert_eq!(frame.poses.len(), 4);
+        assert_eq!(frame.poses[3].name, "a".repeat(SHM_NAME_LEN - 1));
+        assert_eq!(frame.poses[3].location.translation.x, 3.0);
+
+        drop(reader);
+        drop(writer);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reject_invalid_file() {
+        let path = temp_path("shm-invalid");
+        std::fs::write(&path, [0; 256]).unwrap();
+        assert!(ShmPoseReader::open(&path).is_err());
+
+        drop(ShmPoseWriter::create(&path, 2, 2).unwrap());
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[8..12].copy_from_slice(&(SHM_VERSION + 1).to_ne_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(ShmPoseReader::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_child_process() {
+        let path = temp_path("shm-child");
+        let mut writer = ShmPoseWriter::create(&path, 2, 4).unwrap();
+        write_frames(&mut writer, 1..=5);
+
+        // run `child_reader` in a child process, which maps the file read-only
+        let output = Command::new(std::env::current_exe().unwrap())
+            .args(["net::shm::tests::child_reader", "--exact", "--ignored"])
+            .env(CHILD_PATH_VAR, &path)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        // a filter that matches no test also succeeds
+        assert!(String::from_utf8_lossy(&output.stdout).contains("1 passed"));
+
+        drop(writer);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[ignore = "run in a child process by test_child_process"]
+    fn child_reader() {
+        let Some(path) = std::env::var_os(CHILD_PATH_VAR) else {
+            return;
+        };
+        let reader = ShmPoseReader::open(Path::new(&path)).unwrap();
+        assert_frame(&reader.latest().unwrap(), 5);
+    }
+}
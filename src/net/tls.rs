@@ -0,0 +1,46 @@
+//! Loading the TLS configurations of the TCP server and its clients from PEM files.
+//!
+//! A self-signed certificate is enough for a private deployment, e.g. one generated with
+//! `openssl req -x509 -newkey rsa:2048 -nodes -days 365 -subj "/CN=<host>" -addext "subjectAltName=DNS:<host>"
+//! -addext "basicConstraints=critical,CA:FALSE" -keyout key.pem -out cert.pem`. The server loads `cert.pem`
+//! and `key.pem`, and each client trusts `cert.pem` as its root certificate. rustls only matches the host
+//! against the subject alternative name, and rejects a CA certificate (the default of `openssl req -x509`) as
+//! the server's certificate.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+
+/// Load the server's certificate chain and private key.
+pub fn load_server_config(
+    cert_path: &Path,
+    key_path: &Path,
+) -> Result<Arc<ServerConfig>, Box<dyn std::error::Error>> {
+    let certs = CertificateDer::pem_file_iter(cert_path)?.collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(format!("No certificate is found in {}!", cert_path.display()).into());
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)?;
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok(Arc::new(config))
+}
+
+/// Load a client configuration that trusts the certificates in `ca_cert_path`, e.g. the server's
+/// self-signed certificate.
+pub fn load_client_config(
+    ca_cert_path: &Path,
+) -> Result<Arc<ClientConfig>, Box<dyn std::error::Error>> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(ca_cert_path)? {
+        roots.add(cert?)?;
+    }
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
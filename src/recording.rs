@@ -0,0 +1,212 @@
+//! Recording of the captured frames to disk, e.g. to build regression datasets that can be replayed later.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::time::{Instant, UNIX_EPOCH};
+
+use opencv::{core, imgcodecs, prelude::*};
+
+use crate::camera::{DROPPED_FRAME_LOG_INTERVAL, FrameTimestamp};
+
+/// The number of recorded frames that may wait for the writer thread before new frames are dropped.
+pub const RECORDING_QUEUE_SIZE: usize = 16;
+
+/// The name of the manifest file in the recording directory, which lists each recorded frame's file name
+/// and capture time.
+pub const MANIFEST_FILE_NAME: &str = "manifest.csv";
+
+/// Sends every `every`-th captured frame to the writer thread, without ever blocking the capture loop.
+///
+/// A failure to record a frame never stops the capture. Instead, the recording is stopped after logging the
+/// error.
+pub struct FrameRecorder {
+    /// `None` once the recording is stopped after an error.
+    sender: Option<SyncSender<(Mat, FrameTimestamp)>>,
+    every: usize,
+    captured: usize,
+    dropped: usize,
+    /// When the number of dropped frames is last logged, and the number at that time.
+    last_dropped_log: (Instant, usize),
+}
+
+impl FrameRecorder {
+    /// Create a recorder that keeps every `every`-th frame, and the receiver to pass to
+    /// `recorder_thread_main`.
+    pub fn new(every: usize) -> (Self, Receiver<(Mat, FrameTimestamp)>) {
+        let (sender, receiver) = mpsc::sync_channel(RECORDING_QUEUE_SIZE);
+        let recorder = Self {
+            sender: Some(sender),
+            every: every.max(1),
+            captured: 0,
+            dropped: 0,
+            last_dropped_log: (Instant::now(), 0),
+        };
+        (recorder, receiver)
+    }
+
+    /// Called by the camera thread after capturing `frame`. The frame is dropped if the writer thread falls
+    /// behind by more than `RECORDING_QUEUE_SIZE` frames, and the number of dropped frames is logged at most
+    /// every `DROPPED_FRAME_LOG_INTERVAL`.
+    ///
+    /// If the frame cannot be copied or the writer thread has stopped, the error is logged once and the
+    /// recording is stopped.
+    pub fn record(&mut self, frame: &Mat, timestamp: FrameTimestamp) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        self.captured += 1;
+        if (self.captured - 1) % self.every != 0 {
+            return;
+        }
+        let result = match frame.try_clone() {
+            Ok(frame) => match sender.try_send((frame, timestamp)) {
+                Ok(()) => Ok(()),
+                Err(TrySendError::Full(_)) => {
+                    self.dropped += 1;
+                    Ok(())
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    Err("the frame writer thread has stopped".to_string())
+                }
+            },
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = result {
+            log::error!("Stopped recording frames: {}", e);
+            self.sender = None;
+            return;
+        }
+        let (last_log, last_dropped) = self.last_dropped_log;
+        if self.dropped > last_dropped && last_log.elapsed() >= DROPPED_FRAME_LOG_INTERVAL {
+            log::warn!(
+                "The frame writer falls behind. Dropped {} recorded frames in the last {:.1} seconds ({} in \
+                 total).",
+                self.dropped - last_dropped,
+                last_log.elapsed().as_secs_f64(),
+                self.dropped
+            );
+            self.last_dropped_log = (Instant::now(), self.dropped);
+        }
+    }
+
+    /// Whether frames are still recorded, i.e. the recording is not stopped after an error.
+    pub fn is_recording(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    /// The number of frames that are not recorded since the writer thread fell behind.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+}
+
+/// The file name of a frame captured at `timestamp`, which is its wall-clock time in microseconds since
+/// the Unix epoch.
+fn frame_file_name(timestamp: &FrameTimestamp) -> String {
+    let micros = timestamp
+        .system_time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros();
+    format!("{:016}.png", micros)
+}
+
+/// Write each frame received from the `FrameRecorder` as a PNG file in `dir`, and append it to the
+/// manifest. Returns when the recorder is dropped, e.g. when the camera thread exits.
+pub fn recorder_thread_main(
+    dir: PathBuf,
+    frames: Receiver<(Mat, FrameTimestamp)>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(&dir)?;
+    let manifest_path = dir.join(MANIFEST_FILE_NAME);
+    let is_new = !manifest_path.exists();
+    let mut manifest = BufWriter::new(
+        File::options()
+            .create(true)
+            .append(true)
+            .open(&manifest_path)?,
+    );
+    if is_new {
+        writeln!(manifest, "file,unix_time")?;
+    }
+    log::info!("Recording frames to {}", dir.display());
+    let mut recorded = 0;
+    for (frame, timestamp) in frames {
+        let file_name = frame_file_name(&timestamp);
+        imgcodecs::imwrite(
+            dir.join(&file_name)
+                .to_str()
+                .ok_or("Invalid recording path!")?,
+            &frame,
+            &core::Vector::new(),
+        )?;
+        let unix_time = timestamp
+            .system_time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        writeln!(manifest, "{},{:.6}", file_name, unix_time)?;
+        manifest.flush()?;
+        recorded += 1;
+    }
+    log::info!("Recorded {} frames to {}", recorded, dir.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn test_record_frames() {
+        let dir = std::env::temp_dir().join(format!("recording-test-{}", std::process::id()));
+        let (mut recorder, receiver) = FrameRecorder::new(2);
+        let writer = {
+            let dir = dir.clone();
+            thread::spawn(move || recorder_thread_main(dir, receiver).unwrap())
+        };
+        let frame =
+            Mat::new_rows_cols_with_default(48, 64, core::CV_8UC1, core::Scalar::all(128.0))
+                .unwrap();
+        for _ in 0..5 {
+            recorder.record(&frame, FrameTimestamp::now());
+            thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert_eq!(recorder.dropped(), 0);
+        drop(recorder);
+        writer.join().unwrap();
+
+        // every second frame is recorded
+        let manifest = fs::read_to_string(dir.join(MANIFEST_FILE_NAME)).unwrap();
+        let lines = manifest.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "file,unix_time");
+        assert_eq!(lines.len(), 4);
+        for line in &lines[1..] {
+            let (file_name, _) = line.split_once(',').unwrap();
+            let image = imgcodecs::imread(
+                dir.join(file_name).to_str().unwrap(),
+                imgcodecs::IMREAD_GRAYSCALE,
+            )
+            .unwrap();
+            assert_eq!(image.size().unwrap(), core::Size::new(64, 48));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stopped_writer() {
+        let (mut recorder, receiver) = FrameRecorder::new(1);
+        drop(receiver);
+        let frame =
+            Mat::new_rows_cols_with_default(4, 4, core::CV_8UC1, core::Scalar::all(0.0)).unwrap();
+        // the capture goes on without recording
+        recorder.record(&frame, FrameTimestamp::now());
+        assert!(!recorder.is_recording());
+        recorder.record(&frame, FrameTimestamp::now());
+        assert_eq!(recorder.dropped(), 0);
+    }
+}
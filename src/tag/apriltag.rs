@@ -1,8 +1,12 @@
 use opencv::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::OnceCell;
 use std::ffi::CStr;
 use std::fmt::{Debug, Display};
 use std::marker::PhantomData;
 use std::os::raw;
+use std::sync::OnceLock;
+use std::time::Duration;
 
 extern crate nalgebra as na;
 
@@ -27,6 +31,36 @@ pub enum ApriltagFamily {
     TagStandard52h13,
 }
 
+impl ApriltagFamily {
+    /// All the supported tag families.
+    pub const ALL: [ApriltagFamily; 9] = [
+        Self::Tag16h5,
+        Self::Tag25h9,
+        Self::Tag36h10,
+        Self::Tag36h11,
+        Self::TagCircle21h7,
+        Self::TagCircle49h12,
+        Self::TagCustom48h12,
+        Self::TagStandard41h12,
+        Self::TagStandard52h13,
+    ];
+
+    /// The family's name in the apriltag library, e.g. `tag36h11`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Tag16h5 => "tag16h5",
+            Self::Tag25h9 => "tag25h9",
+            Self::Tag36h10 => "tag36h10",
+            Self::Tag36h11 => "tag36h11",
+            Self::TagCircle21h7 => "tagCircle21h7",
+            Self::TagCircle49h12 => "tagCircle49h12",
+            Self::TagCustom48h12 => "tagCustom48h12",
+            Self::TagStandard41h12 => "tagStandard41h12",
+            Self::TagStandard52h13 => "tagStandard52h13",
+        }
+    }
+}
+
 impl Display for ApriltagFamily {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
@@ -37,20 +71,12 @@ impl TryFrom<&str> for ApriltagFamily {
     type Error = UnsupportedTagFamilyError;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value {
-            "tag16h5" => Ok(Self::Tag16h5),
-            "tag25h9" => Ok(Self::Tag25h9),
-            "tag36h10" => Ok(Self::Tag36h10),
-            "tag36h11" => Ok(Self::Tag36h11),
-            "tagCircle21h7" => Ok(Self::TagCircle21h7),
-            "tagCircle49h12" => Ok(Self::TagCircle49h12),
-            "tagCustom48h12" => Ok(Self::TagCustom48h12),
-            "tagStandard41h12" => Ok(Self::TagStandard41h12),
-            "tagStandard52h13" => Ok(Self::TagStandard52h13),
-            _ => Err(UnsupportedTagFamilyError {
+        Self::ALL
+            .into_iter()
+            .find(|family| family.name() == value)
+            .ok_or_else(|| UnsupportedTagFamilyError {
                 name: value.to_string(),
-            }),
-        }
+            })
     }
 }
 
@@ -62,6 +88,20 @@ impl TryFrom<*const apriltag_family_t> for ApriltagFamily {
     }
 }
 
+/// Families are serialized by their names, e.g. `"tag36h11"`.
+impl Serialize for ApriltagFamily {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for ApriltagFamily {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Self::try_from(name.as_str()).map_err(serde::de::Error::custom)
+    }
+}
+
 /// A wrapper of the C type `apriltag_family_t` that supports memory allocation and deallocation
 pub struct ApriltagFamilyType {
     pub c_type: *mut apriltag_binding::apriltag_family_t,
@@ -96,7 +136,7 @@ impl Drop for ApriltagFamilyType {
                 ApriltagFamily::Tag36h10 => tag36h10_destroy(self.c_type),
                 ApriltagFamily::Tag36h11 => tag36h11_destroy(self.c_type),
                 ApriltagFamily::TagCircle21h7 => tagCircle21h7_destroy(self.c_type),
-                ApriltagFamily::TagCircle49h12 => tagCircle21h7_destroy(self.c_type),
+                ApriltagFamily::TagCircle49h12 => tagCircle49h12_destroy(self.c_type),
                 ApriltagFamily::TagCustom48h12 => tagCustom48h12_destroy(self.c_type),
                 ApriltagFamily::TagStandard41h12 => tagStandard41h12_destroy(self.c_type),
                 ApriltagFamily::TagStandard52h13 => tagStandard52h13_destroy(self.c_type),
@@ -105,6 +145,44 @@ impl Drop for ApriltagFamilyType {
     }
 }
 
+/// Owns at most one `ApriltagFamilyType` of each tag family, created when it is first requested.
+///
+/// A detector that borrows its families from this set can add and remove families at runtime, since the
+/// set outlives the detector and keeps each family at a stable address.
+#[derive(Default)]
+pub struct ApriltagFamilySet {
+    families: [OnceCell<ApriltagFamilyType>; 9],
+}
+
+impl ApriltagFamilySet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the given family, creating it if it is not created yet.
+    pub fn get(&self, family: ApriltagFamily) -> &ApriltagFamilyType {
+        self.families[family as usize].get_or_init(|| ApriltagFamilyType::new(family))
+    }
+}
+
+/// An `ApriltagFamilyType` that is shared between threads and never destroyed.
+struct StaticFamilyType(ApriltagFamilyType);
+
+// SAFETY: the C family is never mutated after it is created, and it is never destroyed.
+unsafe impl Send for StaticFamilyType {}
+unsafe impl Sync for StaticFamilyType {}
+
+/// The families referred to by the detections created by `ApriltagDetection::new`, which are created when
+/// they are first requested.
+static DETECTION_FAMILIES: [OnceLock<StaticFamilyType>; 9] = [const { OnceLock::new() }; 9];
+
+/// Get the never-destroyed family referred to by detections, creating it if it is not created yet.
+fn detection_family(family: ApriltagFamily) -> &'static ApriltagFamilyType {
+    &DETECTION_FAMILIES[family as usize]
+        .get_or_init(|| StaticFamilyType(ApriltagFamilyType::new(family)))
+        .0
+}
+
 /// Error type representing an unsupported tag family
 pub struct UnsupportedTagFamilyError {
     name: String,
@@ -118,10 +196,12 @@ impl UnsupportedTagFamilyError {
 
 impl Debug for UnsupportedTagFamilyError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let supported = ApriltagFamily::ALL.map(|family| family.name());
         write!(
             f,
-            "Tag family \"{}\" is not supported by the current program!",
-            self.name
+            "Tag family \"{}\" is not supported by the current program! Supported families are: {}",
+            self.name,
+            supported.join(", ")
         )
     }
 }
@@ -146,6 +226,96 @@ impl ApriltagDetection {
         Self(raw)
     }
 
+    /// Create a detection from plain data, e.g. the tag corners detected by another program.
+    ///
+    /// `corners` are in the same order as the ones returned by `corners`. The homography is fitted to the
+    /// corners, and the center is the projection of the tag's center by the homography.
+    ///
+    /// Returns `None` if the corners do not form a convex quadrilateral, e.g. when three of them lie on the
+    /// same line.
+    pub fn new(
+        family: ApriltagFamily,
+        id: i32,
+        corners: [na::Vector2<f64>; 4],
+        decision_margin: f32,
+    ) -> Option<Self> {
+        let center = Self::fit_homography(&corners)? * na::Vector3::z();
+        let detection = Self::from_parts(family, id, corners, center.xy() / center.z);
+        Some(detection.with_decision_margin(decision_margin))
+    }
+
+    /// Create a detection with the given corners and center, which are not checked against each other.
+    ///
+    /// The homography is fitted to the corners, or left as zero if they do not form a convex
+    /// quadrilateral. The hamming distance and the decision margin are 0. The C family referred to by the
+    /// detection is shared by all detections created this way and is never destroyed, so the detection
+    /// does not borrow anything.
+    pub fn from_parts(
+        family: ApriltagFamily,
+        id: i32,
+        corners: [na::Vector2<f64>; 4],
+        center: na::Vector2<f64>,
+    ) -> Self {
+        let homography = Self::fit_homography(&corners).unwrap_or_else(na::Matrix3::zeros);
+        let family = detection_family(family);
+        unsafe {
+            // allocated with `malloc`, since `apriltag_detection_destroy` frees the detection and its
+            // homography with `free`
+            let raw =
+                libc::malloc(std::mem::size_of::<apriltag_detection>()) as *mut apriltag_detection;
+            (*raw).family = family.c_type;
+            (*raw).id = id;
+            (*raw).hamming = 0;
+            (*raw).decision_margin = 0.0;
+            (*raw).H = matd_create(3, 3);
+            for row in 0..3 {
+                for col in 0..3 {
+                    matd_set((*raw).H, row, col, homography[(row as usize, col as usize)]);
+                }
+            }
+            (*raw).c = [center.x, center.y];
+            (*raw).p = corners.map(|corner| [corner.x, corner.y]);
+            Self::new_from_raw(raw)
+        }
+    }
+
+    /// Set the decision margin of the detection.
+    pub fn with_decision_margin(self, decision_margin: f32) -> Self {
+        unsafe {
+            (*self.0).decision_margin = decision_margin;
+        }
+        self
+    }
+
+    /// Fit the homography that maps the tag's corners `(±1, ±1)` onto `corners`.
+    fn fit_homography(corners: &[na::Vector2<f64>; 4]) -> Option<na::Matrix3<f64>> {
+        // The corners of a convex quadrilateral turn in the same direction at every corner
+        let turns = std::array::from_fn::<_, 4, _>(|i| {
+            let (a, b, c) = (corners[i], corners[(i + 1) % 4], corners[(i + 2) % 4]);
+            (b - a).perp(&(c - b))
+        });
+        if !(turns.iter().all(|turn| *turn > 0.0) || turns.iter().all(|turn| *turn < 0.0)) {
+            return None;
+        }
+        // The tag's corners in the same order as `corners`
+        const TAG_CORNERS: [(f64, f64); 4] = [(-1.0, 1.0), (1.0, 1.0), (1.0, -1.0), (-1.0, -1.0)];
+        let mut a = na::SMatrix::<f64, 8, 8>::zeros();
+        let mut b = na::SVector::<f64, 8>::zeros();
+        for (i, ((x, y), corner)) in TAG_CORNERS.iter().zip(corners).enumerate() {
+            let (u, v) = (corner.x, corner.y);
+            a.row_mut(2 * i)
+                .copy_from_slice(&[*x, *y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y]);
+            a.row_mut(2 * i + 1)
+                .copy_from_slice(&[0.0, 0.0, 0.0, *x, *y, 1.0, -v * x, -v * y]);
+            b[2 * i] = u;
+            b[2 * i + 1] = v;
+        }
+        let h = a.lu().solve(&b)?;
+        Some(na::Matrix3::new(
+            h[0], h[1], h[2], h[3], h[4], h[5], h[6], h[7], 1.0,
+        ))
+    }
+
     pub fn id(&self) -> i32 {
         unsafe { (*self.0).id as i32 }
     }
@@ -188,8 +358,77 @@ impl ApriltagDetection {
     pub fn corners(&self) -> [na::Vector2<f64>; 4] {
         std::array::from_fn(|i| unsafe { (*self.0).p[i].into() })
     }
+
+    /// Copy the detection's data into a `DetectionSnapshot`, which can outlive the detection and be sent to
+    /// other programs.
+    pub fn snapshot(&self) -> Result<DetectionSnapshot, Box<dyn std::error::Error>> {
+        Ok(DetectionSnapshot {
+            family: self.family()?,
+            id: self.id(),
+            hamming: self.hamming(),
+            decision_margin: self.decision_margin(),
+            center: self.center().into(),
+            corners: self.corners().map(Into::into),
+        })
+    }
+
+    /// Move the detection by `offset` pixels on the image, e.g. to convert a detection in a cropped image
+    /// into the coordinates of the full image.
+    ///
+    /// The center, the corners, and the homography (which maps the tag's coordinates onto the image) are
+    /// all moved.
+    pub fn translate(&mut self, offset: na::Vector2<f64>) {
+        unsafe {
+            let detection = &mut *self.0;
+            detection.c[0] += offset.x;
+            detection.c[1] += offset.y;
+            for corner in detection.p.iter_mut() {
+                corner[0] += offset.x;
+                corner[1] += offset.y;
+            }
+            // left-multiply the homography by the translation
+            let homography = detection.H;
+            for col in 0..3 {
+                let w = matd_get(homography, 2, col);
+                let (x, y) = (matd_get(homography, 0, col), matd_get(homography, 1, col));
+                matd_set(homography, 0, col, x + offset.x * w);
+                matd_set(homography, 1, col, y + offset.y * w);
+            }
+        }
+    }
+
+    /// Scale the detection by `factor` about the image's origin, e.g. to convert a detection in a downscaled
+    /// image into the coordinates of the full image.
+    ///
+    /// The detector puts pixel centers at half-integer coordinates, so the origin is the image's top-left
+    /// edge, which stays in place when the image is resized. The center, the corners, and the homography are
+    /// all scaled.
+    pub fn scale(&mut self, factor: f64) {
+        unsafe {
+            let detection = &mut *self.0;
+            detection.c[0] *= factor;
+            detection.c[1] *= factor;
+            for corner in detection.p.iter_mut() {
+                corner[0] *= factor;
+                corner[1] *= factor;
+            }
+            // left-multiply the homography by the scaling
+            let homography = detection.H;
+            for col in 0..3 {
+                matd_set(homography, 0, col, matd_get(homography, 0, col) * factor);
+                matd_set(homography, 1, col, matd_get(homography, 1, col) * factor);
+            }
+        }
+    }
 }
 
+// SAFETY: `ApriltagDetection` exclusively owns its detection, which is only mutated through `&mut self`
+// and destroyed on drop. `Sync` lets the locator share the detections between the threads solving different
+// objects: every `&self` method only reads the detection and its homography, and the family it points to is
+// never mutated while any detection refers to it.
+unsafe impl Send for ApriltagDetection {}
+unsafe impl Sync for ApriltagDetection {}
+
 impl Drop for ApriltagDetection {
     fn drop(&mut self) {
         unsafe {
@@ -215,27 +454,135 @@ impl Debug for ApriltagDetection {
     }
 }
 
+/// A plain copy of an `ApriltagDetection`'s data, e.g. to report the raw detections of a frame to a
+/// debugging client. See `ApriltagDetection::snapshot`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DetectionSnapshot {
+    pub family: ApriltagFamily,
+    pub id: i32,
+    pub hamming: i32,
+    pub decision_margin: f32,
+    /// The tag's center on the image. Unit: pixels.
+    pub center: [f64; 2],
+    /// The tag's corners on the image, in the same order as `ApriltagDetection::corners`. Unit: pixels.
+    pub corners: [[f64; 2]; 4],
+}
+
+/// The default number of bit errors corrected in each detected tag.
+pub const DEFAULT_BITS_CORRECTED: i32 = 2;
+
+/// The time spent in one stage of a detection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetectStage {
+    /// The stage's name in the apriltag library's time profile, e.g. `decimate` or `decode+refinement`.
+    pub name: String,
+    pub duration: Duration,
+}
+
+/// Statistics of a detection returned by `ApriltagDetector::detect_with_stats`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DetectStats {
+    /// The number of candidate quads found in the image before decoding.
+    pub quads: usize,
+    /// The number of quads decoded as tags of the detector's families.
+    pub detections: usize,
+    /// The time spent in each stage, in the order in which the stages run.
+    pub stages: Vec<DetectStage>,
+}
+
+impl DetectStats {
+    /// The total time spent in all stages.
+    pub fn total(&self) -> Duration {
+        self.stages.iter().map(|stage| stage.duration).sum()
+    }
+
+    /// The time spent in the stage named `name`, or `None` if there is no such stage.
+    pub fn stage(&self, name: &str) -> Option<Duration> {
+        self.stages
+            .iter()
+            .find(|stage| stage.name == name)
+            .map(|stage| stage.duration)
+    }
+}
+
+/// An element of `timeprofile_t::stamps` in the apriltag C library, which is not generated in the
+/// bindings.
+#[repr(C)]
+struct TimeprofileEntry {
+    name: [raw::c_char; 32],
+    utime: i64,
+}
+
 /// Wrapper type of `apriltag_detector` in the apriltag C library.
 ///
 /// The lifetime `'a` is requied such that the tag detector lives shorter than the tag family
-/// objects added to the detector.
-pub struct ApriltagDetector<'a>(*mut apriltag_detector, PhantomData<&'a apriltag_detector>);
+/// objects added to the detector, since destroying the detector frees the lookup tables stored in its
+/// families. Families added with `add_owned_family` are owned by the detector instead, and are destroyed
+/// right after it.
+pub struct ApriltagDetector<'a>(
+    *mut apriltag_detector,
+    Vec<ApriltagFamilyType>,
+    PhantomData<&'a apriltag_detector>,
+);
 
 impl<'a> ApriltagDetector<'a> {
+    /// Create a single-threaded detector without any tag family.
+    ///
+    /// The families and parameters are set by chaining methods that take and return the detector, e.g.
+    /// `ApriltagDetector::new().add_family(&mut family).quad_decimate(1.0)`. Only `update_families` changes
+    /// a detector in use.
     pub fn new() -> Self {
-        unsafe { Self(apriltag_detector_create(), PhantomData) }
+        Self::new_multithreading(1)
     }
 
+    /// Create a detector without any tag family, which uses `num_threads` threads to detect the tags in
+    /// each image. The detections are the same as those of a single-threaded detector.
     pub fn new_multithreading(num_threads: usize) -> Self {
         unsafe {
             let inner = apriltag_detector_create();
             (*inner).nthreads = num_threads as raw::c_int;
-            Self(inner, PhantomData)
+            Self(inner, Vec::new(), PhantomData)
         }
     }
 
+    /// Adds a tag family to the detector, correcting up to `DEFAULT_BITS_CORRECTED` bit errors.
     pub fn add_family(self, tag_family: &'a mut ApriltagFamilyType) -> Self {
-        unsafe { apriltag_detector_add_family_bits(self.0, tag_family.c_type, 2) }
+        self.add_family_bits(tag_family, DEFAULT_BITS_CORRECTED)
+    }
+
+    /// Adds a tag family to the detector, correcting up to `bits_corrected` bit errors in each tag.
+    ///
+    /// Correcting more bits detects more tags (e.g. small or blurry tags at long range), at the price of
+    /// more memory for the family's lookup table and more false positives. Use 0 or 1 when false positives
+    /// are unacceptable.
+    pub fn add_family_bits(
+        self,
+        tag_family: &'a mut ApriltagFamilyType,
+        bits_corrected: i32,
+    ) -> Self {
+        unsafe {
+            apriltag_detector_add_family_bits(
+                self.0,
+                tag_family.c_type,
+                bits_corrected as raw::c_int,
+            )
+        }
+        self
+    }
+
+    /// Creates a tag family owned by the detector and adds it to the detector, correcting up to
+    /// `bits_corrected` bit errors in each tag. The family is destroyed after the detector, so the
+    /// detector does not borrow anything.
+    pub fn add_owned_family(mut self, family: ApriltagFamily, bits_corrected: i32) -> Self {
+        let tag_family = ApriltagFamilyType::new(family);
+        unsafe {
+            apriltag_detector_add_family_bits(
+                self.0,
+                tag_family.c_type,
+                bits_corrected as raw::c_int,
+            )
+        }
+        self.1.push(tag_family);
         self
     }
 
@@ -251,6 +598,41 @@ impl<'a> ApriltagDetector<'a> {
         self
     }
 
+    /// Replace the detector's families with the given families, taken from `family_set`, correcting up to
+    /// `bits_corrected` bit errors in each tag.
+    pub fn set_families(
+        mut self,
+        family_set: &'a ApriltagFamilySet,
+        families: &[ApriltagFamily],
+        bits_corrected: i32,
+    ) -> Self {
+        self.update_families(family_set, families, bits_corrected);
+        self
+    }
+
+    /// Replace the families of a detector in use, like `set_families`, e.g. when the registered objects
+    /// change. It rebuilds the lookup table of each family, so only call it when the required families
+    /// change.
+    pub fn update_families(
+        &mut self,
+        family_set: &'a ApriltagFamilySet,
+        families: &[ApriltagFamily],
+        bits_corrected: i32,
+    ) {
+        unsafe {
+            apriltag_detector_clear_families(self.0);
+        }
+        for family in families {
+            unsafe {
+                apriltag_detector_add_family_bits(
+                    self.0,
+                    family_set.get(*family).c_type,
+                    bits_corrected as raw::c_int,
+                )
+            }
+        }
+    }
+
     /// Sets the `quad_sigma` parameter in the apriltag detector.
     ///
     /// When `quad_sigma` is not 0.0, the image will go through preprocessing, whose effect
@@ -280,12 +662,21 @@ impl<'a> ApriltagDetector<'a> {
         self
     }
 
+    /// Detect the tags in `img`.
+    ///
+    /// The returned detections refer to never-destroyed copies of their families rather than the
+    /// detector's families, so they can outlive both the detector and its families.
     pub fn detect(&self, img: &mut image_u8) -> Vec<ApriltagDetection> {
         let z_array = unsafe { apriltag_detector_detect(self.0, img) };
         let z_array_size = unsafe { (*z_array).size as usize };
         let ret = (0..z_array_size)
             .map(|i| unsafe { *((*z_array).data as *const *mut apriltag_detection).add(i) })
             .map(|apriltag_detection_ptr| unsafe {
+                if let Ok(family) = ApriltagFamily::try_from(
+                    (*apriltag_detection_ptr).family as *const apriltag_family_t,
+                ) {
+                    (*apriltag_detection_ptr).family = detection_family(family).c_type;
+                }
                 ApriltagDetection::new_from_raw(apriltag_detection_ptr)
             })
             .collect::<Vec<_>>();
@@ -307,10 +698,46 @@ impl<'a> ApriltagDetector<'a> {
 
         ret
     }
+
+    /// Detect the tags in `img` like `detect`, and also report how many candidate quads are found and
+    /// how long each stage of the detection takes.
+    ///
+    /// This tells whether a larger `quad_decimate` or the decoding stage would speed up the detection
+    /// at a resolution. The stages are read from the detector's time profile, which the apriltag
+    /// library records in every detection.
+    pub fn detect_with_stats(&self, img: &mut image_u8) -> (Vec<ApriltagDetection>, DetectStats) {
+        let detections = self.detect(img);
+        let (quads, profile) = unsafe { ((*self.0).nquads as usize, (*self.0).tp) };
+        let mut stages = Vec::new();
+        if !profile.is_null() {
+            // `timeprofile_t` stores its stamps as untyped `zarray_t` elements.
+            let (mut last_utime, stamps) = unsafe { ((*profile).utime, (*profile).stamps) };
+            let stamps_size = unsafe { (*stamps).size as usize };
+            for i in 0..stamps_size {
+                let stamp = unsafe { &*((*stamps).data as *const TimeprofileEntry).add(i) };
+                let name = stamp.name.map(|c| c as u8);
+                stages.push(DetectStage {
+                    name: CStr::from_bytes_until_nul(&name)
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                    duration: Duration::from_micros((stamp.utime - last_utime).max(0) as u64),
+                });
+                last_utime = stamp.utime;
+            }
+        }
+        let stats = DetectStats {
+            quads,
+            detections: detections.len(),
+            stages,
+        };
+        (detections, stats)
+    }
 }
 
 impl<'a> Drop for ApriltagDetector<'a> {
     fn drop(&mut self) {
+        // The owned families in `self.1` are dropped after this function returns, so they outlive the
+        // detector.
         unsafe {
             apriltag_detector_destroy(self.0);
         }
@@ -351,10 +778,60 @@ impl Display for ImageConversionError {
 
 impl std::error::Error for ImageConversionError {}
 
+/// Linearly stretch the intensity range of an image to `0..=255` in place.
+fn image_u8_stretch_contrast(img: &mut image_u8) {
+    let (width, height, stride) = (img.width as usize, img.height as usize, img.stride as usize);
+    if width == 0 || height == 0 {
+        return;
+    }
+    let buf = unsafe { std::slice::from_raw_parts_mut(img.buf, stride * (height - 1) + width) };
+    let (min, max) = buf
+        .chunks(stride)
+        .flat_map(|row| &row[..width])
+        .fold((u8::MAX, u8::MIN), |(min, max), &value| {
+            (min.min(value), max.max(value))
+        });
+    if min >= max {
+        return;
+    }
+    let range = (max - min) as u32;
+    for row in buf.chunks_mut(stride) {
+        for value in &mut row[..width] {
+            *value = ((*value - min) as u32 * 255 / range) as u8;
+        }
+    }
+}
+
+/// Copy an image into a new `CV_8UC1` Mat.
+///
+/// Only the first `width` bytes of each row are copied, so the padding at the end of each row (when
+/// `stride` is larger than `width`) is not carried into the Mat.
+fn image_u8_to_mat(img: &image_u8) -> opencv::Result<Mat> {
+    let (width, height, stride) = (img.width as usize, img.height as usize, img.stride as usize);
+    let mut mat = Mat::new_rows_cols_with_default(
+        img.height,
+        img.width,
+        opencv::core::CV_8UC1,
+        opencv::core::Scalar::all(0.0),
+    )?;
+    if width == 0 || height == 0 {
+        return Ok(mat);
+    }
+    let buf = unsafe { std::slice::from_raw_parts(img.buf, stride * (height - 1) + width) };
+    for (y, row) in buf.chunks(stride).enumerate() {
+        mat.at_row_mut::<u8>(y as i32)?
+            .copy_from_slice(&row[..width]);
+    }
+    Ok(mat)
+}
+
 // TODO: Implement these wrapper types with macros after macro_metavar_expr_concat stablizes.
 
 /// Wrapper type of `image_u8` in apriltag C library
-pub struct ImageU8(image_u8);
+///
+/// The image is allocated by the C library, which frees both the pixel buffer and the `image_u8` struct
+/// itself on destruction. So the struct must be kept behind the pointer returned by the C library.
+pub struct ImageU8(*mut image_u8);
 
 impl ImageU8 {
     pub fn new(width: usize, height: usize) -> Self {
@@ -366,32 +843,43 @@ impl ImageU8 {
         }
     }
 
+    /// Take the ownership of an image allocated by the apriltag C library.
     pub unsafe fn new_from_raw(raw_img: *mut image_u8) -> Self {
-        unsafe { Self(*raw_img) }
+        Self(raw_img)
     }
 
     pub fn inner_ref(&self) -> &image_u8 {
-        &self.0
+        unsafe { &*self.0 }
     }
 
     pub fn inner_mut(&mut self) -> &mut image_u8 {
-        &mut self.0
+        unsafe { &mut *self.0 }
     }
 
     pub fn darken(&mut self) {
-        unsafe { image_u8_darken(&mut self.0) }
+        unsafe { image_u8_darken(self.0) }
     }
 
     pub fn gaussian_blur(&mut self, sigma: f64, k: i32) {
         unsafe {
-            image_u8_gaussian_blur(&mut self.0, sigma, k as raw::c_int);
+            image_u8_gaussian_blur(self.0, sigma, k as raw::c_int);
         }
     }
 
+    pub fn stretch_contrast(&mut self) {
+        image_u8_stretch_contrast(self.inner_mut())
+    }
+
+    /// Copy the content of this image into a new `CV_8UC1` Mat, e.g. for saving it with
+    /// `imgcodecs::imwrite`.
+    pub fn to_mat(&self) -> opencv::Result<Mat> {
+        image_u8_to_mat(self.inner_ref())
+    }
+
     pub fn draw_line(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, value: i32, width: i32) {
         unsafe {
             image_u8_draw_line(
-                &mut self.0,
+                self.0,
                 x0,
                 y0,
                 x1,
@@ -403,19 +891,19 @@ impl ImageU8 {
     }
 
     pub fn draw_circle(&mut self, x0: f32, y0: f32, radius: f32, value: i32) {
-        unsafe { image_u8_draw_circle(&mut self.0, x0, y0, radius, value) }
+        unsafe { image_u8_draw_circle(self.0, x0, y0, radius, value) }
     }
 }
 
 impl Clone for ImageU8 {
     fn clone(&self) -> Self {
-        unsafe { Self(*image_u8_copy(&self.0)) }
+        unsafe { Self(image_u8_copy(self.0)) }
     }
 }
 
 impl Drop for ImageU8 {
     fn drop(&mut self) {
-        unsafe { image_u8_destroy(&mut self.0) }
+        unsafe { image_u8_destroy(self.0) }
     }
 }
 
@@ -443,6 +931,37 @@ impl<'a, T: 'a> ImageU8View<'a, T> {
         &mut self.img
     }
 
+    /// Copy the `width` x `height` region of this image whose top-left pixel is at `(x, y)` into a new image.
+    ///
+    /// The region is copied rather than viewed in place, because the apriltag library treats an image's
+    /// buffer as `height * stride` bytes, and a view of a region touching the bottom edge would extend past
+    /// the end of this image's buffer.
+    ///
+    /// # Panics
+    /// Panics if the region is empty or not within this image.
+    pub fn crop(&self, x: i32, y: i32, width: i32, height: i32) -> ImageU8 {
+        assert!(
+            x >= 0 && y >= 0 && width > 0 && height > 0,
+            "The cropped region must not be empty or negative"
+        );
+        assert!(
+            x + width <= self.img.width && y + height <= self.img.height,
+            "The cropped region must be within the image"
+        );
+        let mut cropped = ImageU8::new(width as usize, height as usize);
+        let stride = cropped.inner_ref().stride;
+        for row in 0..height {
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    self.img.buf.add(((y + row) * self.img.stride + x) as usize),
+                    cropped.inner_mut().buf.add((row * stride) as usize),
+                    width as usize,
+                );
+            }
+        }
+        cropped
+    }
+
     pub fn darken(&mut self) {
         unsafe { image_u8_darken(&mut self.img) }
     }
@@ -453,6 +972,41 @@ impl<'a, T: 'a> ImageU8View<'a, T> {
         }
     }
 
+    pub fn stretch_contrast(&mut self) {
+        image_u8_stretch_contrast(&mut self.img)
+    }
+
+    /// Copy the content of this image into a new `CV_8UC1` Mat, e.g. for saving it with
+    /// `imgcodecs::imwrite`.
+    pub fn to_mat(&self) -> opencv::Result<Mat> {
+        image_u8_to_mat(&self.img)
+    }
+
+    /// Resize this image by `scale` on both axes into a new `CV_8UC1` Mat, e.g. to detect tags on a smaller
+    /// image. The pixels are resampled by area, which avoids aliasing when downscaling.
+    pub fn resized(&self, scale: f64) -> opencv::Result<Mat> {
+        // a Mat header over this image's pixels without copying them, which is only read by the resize
+        let source = unsafe {
+            Mat::new_rows_cols_with_data_unsafe(
+                self.img.height,
+                self.img.width,
+                opencv::core::CV_8UC1,
+                self.img.buf as *mut raw::c_void,
+                self.img.stride as usize,
+            )?
+        };
+        let mut resized = Mat::default();
+        opencv::imgproc::resize(
+            &source,
+            &mut resized,
+            opencv::core::Size::default(),
+            scale,
+            scale,
+            opencv::imgproc::INTER_AREA,
+        )?;
+        Ok(resized)
+    }
+
     pub fn draw_line(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, value: i32, width: i32) {
         unsafe {
             image_u8_draw_line(
@@ -472,12 +1026,23 @@ impl<'a, T: 'a> ImageU8View<'a, T> {
     }
 }
 
+impl<'a> From<&'a mut ImageU8> for ImageU8View<'a, ImageU8> {
+    fn from(value: &'a mut ImageU8) -> Self {
+        Self {
+            img: *value.inner_ref(),
+            _parent: value,
+        }
+    }
+}
+
 impl<'a> From<&'a mut Mat> for ImageU8View<'a, Mat> {
     fn from(value: &'a mut Mat) -> Self {
+        // the rows of a non-continuous Mat (e.g. a region of another Mat) are further apart than its width
+        let stride = value.step1(0).map_or(value.cols(), |step| step as i32);
         let img_inner = image_u8 {
             width: value.cols(),
             height: value.rows(),
-            stride: value.cols(),
+            stride,
             buf: value.data_mut(),
         };
         Self {
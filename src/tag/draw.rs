@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+
+use opencv::core;
+use opencv::imgproc;
+use opencv::prelude::*;
+
+extern crate nalgebra as na;
+
+use crate::camera::CameraProperty;
+use crate::tag::apriltag::ApriltagDetection;
+use crate::tag::locator::{LocatedObjects, TAG_CORNERS, reproject_tag_center};
+use crate::tag::tagged_object::{TagIndex, TagLocation};
+use crate::utils::color::{AXES, AXES_COLORS, generate_random_color};
+
+/// The factor by which the reprojection residuals are scaled up when drawn, to make them visible.
+pub const RESIDUAL_SCALE: f64 = 10.0;
+
+/// The residual (in pixels) at which the residual vectors are drawn in full red. Smaller residuals fade
+/// through yellow into green.
+pub const RESIDUAL_FULL_COLOR: f64 = 3.0;
+
+/// The color of a reprojection residual vector of `magnitude` pixels, from green (no residual) through
+/// yellow to red (`RESIDUAL_FULL_COLOR` pixels or more).
+fn residual_color(magnitude: f64) -> (u8, u8, u8) {
+    let t = (magnitude / RESIDUAL_FULL_COLOR).clamp(0.0, 1.0);
+    let red = (2.0 * t).min(1.0);
+    let green = (2.0 * (1.0 - t)).min(1.0);
+    (
+        (red * 255.0).round() as u8,
+        (green * 255.0).round() as u8,
+        0,
+    )
+}
+
+/// Convert an RGB color to an OpenCV scalar, which is in the order of BGR.
+fn to_bgr_scalar((r, g, b): (u8, u8, u8)) -> core::Scalar {
+    core::Scalar::new(b as f64, g as f64, r as f64, 0.0)
+}
+
+fn draw_line(
+    frame: &mut Mat,
+    start: na::Point2<f64>,
+    end: na::Point2<f64>,
+    color: core::Scalar,
+) -> opencv::Result<()> {
+    imgproc::line(
+        frame,
+        core::Point::new(start.x.round() as i32, start.y.round() as i32),
+        core::Point::new(end.x.round() as i32, end.y.round() as i32),
+        color,
+        2,
+        imgproc::LINE_8,
+        0,
+    )
+}
+
+/// Draw the annotations of a frame onto it.
+///
+/// The outline of each detected tag is drawn with its first three edges in cyan, magenta, and yellow, so
+/// that the tag's orientation can be seen. Then the reprojection of all tags on each located object is drawn
+/// in the object's color, together with the x, y, and z axes of each tag in red, green, and blue.
+///
+/// Finally, for each detected corner of a tag on a located object, the residual from the detected corner to
+/// its reprojection is drawn as a line scaled up by `RESIDUAL_SCALE`, colored by the residual's magnitude
+/// (see `RESIDUAL_FULL_COLOR`). Tags that fit poorly stand out with long red lines.
+///
+/// `object_map` maps each object's name to its tags, as returned by
+/// `TaggedObjectLocator::get_object_map`. The reprojections are distorted by `camera`'s lens distortion, so
+/// that they line up with the detections. `frame` must be a BGR image for the colors to be correct.
+pub fn draw_detections(
+    frame: &mut Mat,
+    detections: &[ApriltagDetection],
+    located: &LocatedObjects,
+    object_map: &HashMap<String, Vec<(TagIndex, TagLocation)>>,
+    camera: &CameraProperty,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // draw the detected apriltag on the frame
+    for detection in detections {
+        let corners = detection.corners();
+        for i in 0..4 {
+            let start = corners[i];
+            let end = corners[(i + 1) % 4];
+            draw_line(
+                frame,
+                na::Point2::new(start.x, start.y),
+                na::Point2::new(end.x, end.y),
+                core::Scalar::new(
+                    if i != 0 { 255. } else { 0. },
+                    if i != 1 { 255. } else { 0. },
+                    if i != 2 { 255. } else { 0. },
+                    0.,
+                ),
+            )?;
+        }
+    }
+    // draw each tag's reprojection on the image
+    let project = |point: na::Point3<f64>| -> Result<_, Box<dyn std::error::Error>> {
+        Ok(na::Point2::from(camera.project_point(&point)?))
+    };
+    for (name, loc) in located.name_map() {
+        let Some(object) = object_map.get(*name) else {
+            continue;
+        };
+        let color = generate_random_color(name);
+        let color = to_bgr_scalar((color.0, color.1, color.2));
+        // plot the reprojection of all tags
+        for (_, tag_loc) in object {
+            let corners = TAG_CORNERS
+                .iter()
+                .map(|point| project(loc.transform_point(&tag_loc.transform_point(point))))
+                .collect::<Result<Vec<_>, _>>()?;
+            for i in 0..4 {
+                draw_line(frame, corners[i], corners[i + 1], color)?;
+            }
+            // plot the x, y, and z axes of each tag
+            let axis_origin = na::Point2::from(reproject_tag_center(camera, loc, tag_loc)?);
+            for i in 0..3 {
+                let axis_end = project(loc.transform_point(&tag_loc.transform_point(&AXES[i])))?;
+                draw_line(frame, axis_origin, axis_end, to_bgr_scalar(AXES_COLORS[i]))?;
+            }
+        }
+    }
+    // draw the reprojection residual of each detected corner on the located objects
+    let located_tags = located
+        .name_map()
+        .iter()
+        .filter_map(|(name, loc)| Some((loc, object_map.get(*name)?)))
+        .flat_map(|(loc, object)| {
+            object
+                .iter()
+                .map(move |(index, tag_loc)| (*index, (loc, tag_loc)))
+        })
+        .collect::<HashMap<_, _>>();
+    for detection in detections {
+        let Ok(family) = detection.family() else {
+            continue;
+        };
+        let Some((loc, tag_loc)) = located_tags.get(&TagIndex::new(family, detection.id())) else {
+            continue;
+        };
+        for (corner, tag_corner) in detection.corners().iter().zip(TAG_CORNERS.iter()) {
+            let detected = na::Point2::from(*corner);
+            let residual =
+                project(loc.transform_point(&tag_loc.transform_point(tag_corner)))? - detected;
+            draw_line(
+                frame,
+                detected,
+                detected + residual * RESIDUAL_SCALE,
+                to_bgr_scalar(residual_color(residual.norm())),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tag::apriltag::ApriltagFamily;
+    use crate::tag::tagged_object::TaggedObject;
+
+    #[test]
+    fn test_draw_located_object() {
+        let camera =
+            CameraProperty::new((640, 480), (None, Some(f64::to_radians(50.0))), None).unwrap();
+        let camera_mat = camera.camera_mat_na().unwrap();
+        let object = TaggedObject::new_simple("simple", ApriltagFamily::Tag36h11, 0, 2.0);
+        let object_map = HashMap::from([(
+            object.name.clone(),
+            object.tags.clone().into_iter().collect::<Vec<_>>(),
+        )]);
+        let mut located = LocatedObjects::new();
+        let location = na::Isometry3::translation(0.0, 0.0, 20.0);
+        located.name_map.insert("simple", location);
+
+        let mut frame =
+            Mat::new_rows_cols_with_default(480, 640, core::CV_8UC3, core::Scalar::all(0.0))
+                .unwrap();
+        draw_detections(&mut frame, &[], &located, &object_map, &camera).unwrap();
+        // the reprojected tag's first corner is drawn in the object's color
+        let tag_location = object.tags.values().next().unwrap();
+        let corner =
+            camera_mat * location.transform_point(&tag_location.transform_point(&TAG_CORNERS[0]));
+        let corner = corner.xy() / corner.z;
+        let pixel = *frame
+            .at_2d::<core::Vec3b>(corner.y.round() as i32, corner.x.round() as i32)
+            .unwrap();
+        let color = generate_random_color("simple");
+        assert_eq!(pixel.0, [color.2, color.1, color.0]);
+        // nothing is drawn far away from the tag
+        assert_eq!(frame.at_2d::<core::Vec3b>(0, 0).unwrap().0, [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_residual_color() {
+        assert_eq!(residual_color(0.0), (0, 255, 0));
+        assert_eq!(residual_color(RESIDUAL_FULL_COLOR / 2.0), (255, 255, 0));
+        assert_eq!(residual_color(RESIDUAL_FULL_COLOR), (255, 0, 0));
+        assert_eq!(residual_color(RESIDUAL_FULL_COLOR * 10.0), (255, 0, 0));
+    }
+
+    #[test]
+    fn test_draw_residuals() {
+        let camera =
+            CameraProperty::new((640, 480), (None, Some(f64::to_radians(50.0))), None).unwrap();
+        let camera_mat = camera.camera_mat_na().unwrap();
+        let object = TaggedObject::new_simple("simple", ApriltagFamily::Tag36h11, 0, 2.0);
+        let object_map = HashMap::from([(
+            object.name.clone(),
+            object.tags.clone().into_iter().collect::<Vec<_>>(),
+        )]);
+        let mut located = LocatedObjects::new();
+        let location = na::Isometry3::translation(0.0, 0.0, 20.0);
+        located.name_map.insert("simple", location);
+
+        // every corner is detected 5 pixels to the left of its reprojection
+        let tag_location = object.tags.values().next().unwrap();
+        let reprojected: [na::Vector2<f64>; 4] = std::array::from_fn(|i| {
+            let corner = camera_mat
+                * location.transform_point(&tag_location.transform_point(&TAG_CORNERS[i]));
+            corner.coords.xy() / corner.z
+        });
+        let detection = ApriltagDetection::from_parts(
+            ApriltagFamily::Tag36h11,
+            0,
+            reprojected.map(|corner| corner - na::vector![5.0, 0.0]),
+            na::vector![320.0, 240.0],
+        )
+        .with_decision_margin(100.0);
+
+        let mut frame =
+            Mat::new_rows_cols_with_default(480, 640, core::CV_8UC3, core::Scalar::all(0.0))
+                .unwrap();
+        draw_detections(&mut frame, &[detection], &located, &object_map, &camera).unwrap();
+        // the residual is scaled up and drawn to the right of the reprojected corner, in red
+        let residual_point = reprojected[0] + na::vector![20.0, 0.0];
+        let pixel = *frame
+            .at_2d::<core::Vec3b>(
+                residual_point.y.round() as i32,
+                residual_point.x.round() as i32,
+            )
+            .unwrap();
+        assert_eq!(pixel.0, [0, 0, 255]);
+    }
+}
@@ -37,6 +37,48 @@ impl Display for InvalidFormatError {
 
 impl Error for InvalidFormatError {}
 
+/// This error occurs when a field in a tagobj file is missing, or has an unexpected type or value.
+///
+/// The field is located by its JSON path, e.g. `$.tags["front"].rm.x[1]`. Missing fields are located at
+/// the object that should contain them.
+pub struct InvalidFieldError {
+    path: String,
+    reason: String,
+}
+
+impl InvalidFieldError {
+    pub fn new<P: Into<String>, R: Into<String>>(path: P, reason: R) -> Self {
+        Self {
+            path: path.into(),
+            reason: reason.into(),
+        }
+    }
+
+    /// The JSON path of the field.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Why the field is invalid, e.g. the expected and the found type.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl Debug for InvalidFieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid field \"{}\": {}", self.path, self.reason)
+    }
+}
+
+impl Display for InvalidFieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for InvalidFieldError {}
+
 /// This error occurs when the version number in a .tagobj file is currently not supported.
 pub struct UnsupportedVersionError {
     version: i64,
@@ -96,6 +138,11 @@ impl ConflictingTagError {
             object2: name.to_string(),
         }
     }
+
+    /// The conflicting tag, or `None` if the error is caused by conflicting object names.
+    pub fn tag(&self) -> Option<TagIndex> {
+        self.tag
+    }
 }
 
 impl Debug for ConflictingTagError {
@@ -123,3 +170,36 @@ impl Display for ConflictingTagError {
 }
 
 impl Error for ConflictingTagError {}
+
+/// This error occurs when there are multiple conflicting tags among a collection of objects.
+pub struct ConflictingTagsError {
+    conflicts: Vec<ConflictingTagError>,
+}
+
+impl ConflictingTagsError {
+    pub fn new(conflicts: Vec<ConflictingTagError>) -> Self {
+        Self { conflicts }
+    }
+
+    pub fn conflicts(&self) -> &[ConflictingTagError] {
+        &self.conflicts
+    }
+}
+
+impl Debug for ConflictingTagsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Found {} conflicting tags:", self.conflicts.len())?;
+        for conflict in &self.conflicts {
+            write!(f, "\n{:?}", conflict)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for ConflictingTagsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for ConflictingTagsError {}
@@ -1,21 +1,26 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt::Display;
 use std::sync::{Arc, Condvar, Mutex};
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use opencv::calib3d;
 use opencv::prelude::*;
+use rayon::prelude::*;
 
 extern crate nalgebra as na;
 
-use crate::camera::CameraProperty;
+use crate::camera::{CameraProperty, FrameTimestamp};
 use crate::tag::apriltag;
 use crate::tag::error::ConflictingTagError;
-use crate::tag::tagged_object::{TagIndex, TagLocation, TaggedObject};
-use crate::utils::rotation_jacobian;
+use crate::tag::tagged_object::{BoundingBox, TagIndex, TagLocation, TaggedObject};
+use crate::utils::{right_jacobian, rotation, rotation_jacobian};
 
 /// A square tag's four corners in its local reference frame.
 ///
-/// This array's order is kept consistent with Apriltag and OpenCV library.
+/// This array's order is kept consistent with Apriltag and OpenCV library: the detected corner `p[i]` is
+/// the projection of `TAG_CORNERS[i]`, and the order is the one required by `SOLVEPNP_IPPE_SQUARE`. The
+/// tag's x axis points to the right and its y axis points down on the upright tag image, so the corners
+/// start from the bottom-left corner and wrap counter-clockwise as seen on the image.
 pub const TAG_CORNERS: [na::Point3<f64>; 5] = [
     na::Point3::new(-1.0, 1.0, 0.0),
     na::Point3::new(1.0, 1.0, 0.0),
@@ -24,90 +29,1640 @@ pub const TAG_CORNERS: [na::Point3<f64>; 5] = [
     na::Point3::new(-1.0, 1.0, 0.0), // add the first point again to make drawing the tag easier
 ];
 
-/// The duration after which an object's stored information is forgotten.
+/// The default duration after which an object's stored information is forgotten.
 pub const OBJECT_FORGET_DURATION: Duration = Duration::from_secs(1);
 
+/// The log target of the periodic summary of the located objects. The summary is logged at the debug level,
+/// so it is silent unless enabled for this target, e.g. with `RUST_LOG=poses=debug`.
+pub const POSE_LOG_TARGET: &str = "poses";
+
+/// The default interval at which the located objects are summarized in the log.
+pub const POSE_LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The default solvePnP method used to initialize a multi-tag object's location when there is no
+/// extrinsic guess available.
+pub const DEFAULT_INITIAL_PNP_METHOD: i32 = calib3d::SOLVEPNP_SQPNP;
+
+/// Whether an OpenCV solvePnP method accepts `use_extrinsic_guess = true`.
+///
+/// Only `SOLVEPNP_ITERATIVE` refines an extrinsic guess. All other methods (e.g. `SOLVEPNP_EPNP`,
+/// `SOLVEPNP_SQPNP`, `SOLVEPNP_AP3P`, `SOLVEPNP_IPPE`) compute the location from scratch and must be
+/// called with `use_extrinsic_guess = false`.
+pub fn supports_extrinsic_guess(method: i32) -> bool {
+    method == calib3d::SOLVEPNP_ITERATIVE
+}
+
+/// The maximum difference between the z coordinates of an object's tag corners, in the object's frame, for
+/// the object to be considered planar.
+pub const PLANAR_TOLERANCE: f64 = 1e-6;
+
+/// Whether all tags of an object lie on the same plane `z = c` in the object's frame.
+fn is_object_planar(tagobj: &TaggedObject) -> bool {
+    let (min_z, max_z) = tagobj
+        .tags
+        .values()
+        .flat_map(|tag_location| {
+            TAG_CORNERS
+                .iter()
+                .take(4)
+                .map(|corner| tag_location.transform_point(corner).z)
+        })
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min_z, max_z), z| {
+            (min_z.min(z), max_z.max(z))
+        });
+    max_z - min_z <= PLANAR_TOLERANCE
+}
+
+/// Estimate the linear velocity and angular velocity (as a scaled rotation axis) per second of an object that
+/// moved from `from` to `to` in `elapsed`. Returns `None` if no time has elapsed.
+fn estimate_velocity(
+    from: &na::Isometry3<f64>,
+    to: &na::Isometry3<f64>,
+    elapsed: Duration,
+) -> Option<(na::Vector3<f64>, na::Vector3<f64>)> {
+    let seconds = elapsed.as_secs_f64();
+    if seconds <= 0.0 {
+        return None;
+    }
+    let linear = (to.translation.vector - from.translation.vector) / seconds;
+    let angular = (to.rotation * from.rotation.inverse()).scaled_axis() / seconds;
+    Some((linear, angular))
+}
+
+/// Extrapolate the pose of an object moving at a constant velocity for `elapsed` from `location`.
+fn extrapolate_pose(
+    location: &na::Isometry3<f64>,
+    linear: &na::Vector3<f64>,
+    angular: &na::Vector3<f64>,
+    elapsed: Duration,
+) -> na::Isometry3<f64> {
+    let seconds = elapsed.as_secs_f64();
+    na::Isometry3::from_parts(
+        na::Translation3::from(location.translation.vector + linear * seconds),
+        na::UnitQuaternion::from_scaled_axis(angular * seconds) * location.rotation,
+    )
+}
+
+/// The RMS reprojection error of the tag corners, in pixels, at which the error's score in the pose
+/// confidence is 0.5.
+pub const CONFIDENCE_ERROR_SCALE: f64 = 1.0;
+
+/// The mean decision margin of the tags at and above which the margin's score in the pose confidence is 1.
+pub const CONFIDENCE_FULL_MARGIN: f64 = 50.0;
+
+/// Blend the quality measures of a located pose into a confidence between 0 and 1.
+///
+/// Each measure is first mapped to a score between 0 and 1:
+/// * The RMS reprojection error `e` of the tag corners, in pixels, to `1 / (1 + (e / CONFIDENCE_ERROR_SCALE)^2)`.
+/// * The number of detected tags `n` to `1 - 0.5^n`, so that each additional tag halves the remaining doubt.
+/// * The mean decision margin `m` of the tags to `min(m / CONFIDENCE_FULL_MARGIN, 1)`.
+///
+/// The confidence is the weighted geometric mean of the scores, with a weight of 1/2 on the reprojection
+/// error and 1/4 on each of the other two. A score of 0 in any measure results in a confidence of 0.
+pub fn pose_confidence(rms_error: f64, tag_count: usize, mean_margin: f64) -> f64 {
+    let error_score = 1.0 / (1.0 + (rms_error / CONFIDENCE_ERROR_SCALE).powi(2));
+    let tag_score = 1.0 - 0.5f64.powi(tag_count as i32);
+    let margin_score = (mean_margin / CONFIDENCE_FULL_MARGIN).clamp(0.0, 1.0);
+    error_score.powf(0.5) * tag_score.powf(0.25) * margin_score.powf(0.25)
+}
+
+/// The minimum amount (in pixels) by which a pose that jumps away from an object's last rotation must lower
+/// the RMS reprojection error, compared with the last pose, to be accepted. See
+/// `TaggedObjectLocator::set_flip_threshold`.
+pub const FLIP_MIN_ERROR_GAIN: f64 = 1.0;
+
+/// The result of the checks on a located pose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PoseCheck {
+    Valid,
+    /// The pose is behind the camera or outside the image.
+    Invalid,
+    /// The pose's rotation jumps away from the object's last rotation, without a clearly better fit.
+    Flipped,
+}
+
+/// The default number of consecutive consistent poses after which an object is tracked. See
+/// `TaggedObjectLocator::set_stable_frames`.
+pub const DEFAULT_STABLE_FRAMES: usize = 3;
+
+/// The maximum distance between an object's pose and its predicted pose from the last frame, relative to the
+/// object's distance from the camera, for the two poses to be consistent.
+pub const STABLE_TRANSLATION_TOLERANCE: f64 = 0.01;
+
+/// The maximum angle (in radians, about 2 degrees) between an object's rotation and its predicted rotation
+/// from the last frame, for the two poses to be consistent.
+pub const STABLE_ROTATION_TOLERANCE: f64 = 0.035;
+
+/// The maximum linear speed of a static object, relative to its distance from the camera, per second. See
+/// `TaggedObjectLocator::set_bundle_window`.
+pub const STATIC_LINEAR_SPEED: f64 = 0.1;
+
+/// The maximum angular speed of a static object, in radians per second. See
+/// `TaggedObjectLocator::set_bundle_window`.
+pub const STATIC_ANGULAR_SPEED: f64 = 0.2;
+
+/// The maximum number of Gauss-Newton iterations of the bundle adjustment.
+pub const BUNDLE_ADJUSTMENT_ITERATIONS: usize = 10;
+
+/// The norm of the Gauss-Newton step below which the bundle adjustment is converged.
+pub const BUNDLE_ADJUSTMENT_TOLERANCE: f64 = 1e-10;
+
+/// The maximum condition number of `J^T J` (see `TaggedObjectLocator::calculate_covariance`) for the
+/// covariance of a pose to be computed. Above it, the pose is treated as unconstrained in some direction.
+pub const COVARIANCE_MAX_CONDITION_NUMBER: f64 = 1e12;
+
+/// The default half-life over which the information of an object's past frames fades out of its fused
+/// covariance. See `TaggedObjectLocator::set_covariance_fusion`.
+pub const COVARIANCE_FUSION_HALF_LIFE: Duration = Duration::from_secs(2);
+
+/// The covariance of a located pose. See `TaggedObjectLocator::calculate_covariance`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PoseCovariance {
+    /// The covariance of the pose's translation and rotation vector, in this order.
+    Finite(na::Matrix6<f64>),
+    /// The detected corners do not constrain the pose in some direction, e.g. when the tags are viewed
+    /// edge-on or from too far away, so the uncertainty in that direction is infinite. `condition_number`
+    /// is the condition number of `J^T J`, which is infinite if it is singular.
+    Degenerate { condition_number: f64 },
+}
+
+impl PoseCovariance {
+    /// The covariance matrix, or `None` if the pose is degenerate.
+    pub fn finite(&self) -> Option<&na::Matrix6<f64>> {
+        match self {
+            PoseCovariance::Finite(covariance) => Some(covariance),
+            PoseCovariance::Degenerate { .. } => None,
+        }
+    }
+
+    /// The covariance whose inverse is the information matrix `information`, which is degenerate under the
+    /// same condition as in `TaggedObjectLocator::calculate_covariance`.
+    pub fn from_information(information: &na::Matrix6<f64>) -> Self {
+        // the information matrix is symmetric and positive semi-definite like `J^T J`
+        let eigenvalues = information.symmetric_eigenvalues();
+        let condition_number = if eigenvalues.min() > 0.0 {
+            eigenvalues.max() / eigenvalues.min()
+        } else {
+            f64::INFINITY
+        };
+        match information.try_inverse() {
+            Some(covariance) if condition_number <= COVARIANCE_MAX_CONDITION_NUMBER => {
+                PoseCovariance::Finite(covariance)
+            }
+            _ => PoseCovariance::Degenerate { condition_number },
+        }
+    }
+
+    /// The covariance of `pose` expressed in another reference frame, which is rotated by `rotation` from the
+    /// pose's reference frame.
+    ///
+    /// The translation is rotated like any vector. The rotation vector `w` of the pose becomes
+    /// `w' = log(rotation * exp(w))`, which changes by `J_r(w')^-1 * J_r(w) * dw` to the first order, where
+    /// `J_r` is the right Jacobian (see `utils::right_jacobian`).
+    pub fn rotated(&self, rotation: &na::UnitQuaternion<f64>, pose: &na::Isometry3<f64>) -> Self {
+        match self {
+            PoseCovariance::Finite(covariance) => {
+                let omega = pose.rotation.scaled_axis();
+                let rotated_omega = (rotation * pose.rotation).scaled_axis();
+                // the right Jacobian is invertible for the angles up to pi returned by `scaled_axis`
+                let omega_transform =
+                    right_jacobian(&rotated_omega).try_inverse().unwrap() * right_jacobian(&omega);
+                let mut transform = na::Matrix6::zeros();
+                transform
+                    .fixed_view_mut::<3, 3>(0, 0)
+                    .copy_from(rotation.to_rotation_matrix().matrix());
+                transform
+                    .fixed_view_mut::<3, 3>(3, 3)
+                    .copy_from(&omega_transform);
+                PoseCovariance::Finite(transform * covariance * transform.transpose())
+            }
+            PoseCovariance::Degenerate { .. } => *self,
+        }
+    }
+}
+
+/// The locations of an object's tags detected in a frame and their undistorted corners, kept in the
+/// object's window for the bundle adjustment.
+type WindowFrame = Vec<(TagLocation, [na::Vector2<f64>; 4])>;
+
+/// How reliably an object is tracked, based on how consistent its recent poses are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrackingState {
+    /// The object has not been located since it is registered or lost.
+    #[default]
+    Uninitialized,
+    /// The object is located, but its poses have not been consistent for long enough. The poses may still
+    /// be converging, e.g. right after the object comes into view.
+    Initializing,
+    /// The object's poses have been consistent for the configured number of frames. Objects stay tracked
+    /// until they are lost.
+    Tracking,
+}
+
+/// The tracking state of an object, and the progress towards tracking it.
+#[derive(Debug, Clone, Copy, Default)]
+struct TrackingProgress {
+    state: TrackingState,
+    /// The number of consecutive consistent poses while initializing, counting the first one.
+    consistent_frames: usize,
+    /// The time at which the object starts initializing.
+    since: Option<Instant>,
+}
+
+/// Configurations of how the locator tracks each object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectConfig {
+    /// The duration after which the object's stored information is forgotten. After that, the object's
+    /// last location is no longer used as the extrinsic guess, and the object is reported as lost.
+    ///
+    /// Fast-moving objects benefit from a shorter duration, while static objects can keep their last
+    /// location for longer.
+    pub forget_duration: Duration,
+    /// The minimum number of the object's tags that must be detected in a frame to locate the object.
+    ///
+    /// Poses located from a single tag are much less reliable than those located from multiple tags,
+    /// especially in orientation. Frames with fewer detected tags are treated as if the object is not
+    /// detected at all.
+    pub min_tags: usize,
+    /// The number of recent frames over which the object's pose is jointly refined while it is static.
+    /// Disabled (`None`) by default. See `TaggedObjectLocator::set_bundle_window`.
+    pub bundle_window: Option<usize>,
+    /// The name of the group of mutually exclusive variants that the object belongs to, e.g. interchangeable
+    /// controllers of which only one is used at a time. Objects in the same group may share tags, and only
+    /// the variant that best fits the detected tags is located in each frame. `None` by default, in which
+    /// case no other object may use the object's tags.
+    pub exclusive_group: Option<String>,
+}
+
+impl Default for ObjectConfig {
+    fn default() -> Self {
+        Self {
+            forget_duration: OBJECT_FORGET_DURATION,
+            min_tags: 1,
+            bundle_window: None,
+            exclusive_group: None,
+        }
+    }
+}
+
+/// How the detected tags of an object are weighted by their decision margins when locating the object.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TagWeighting {
+    /// Every detected tag is used once.
+    #[default]
+    Uniform,
+
+    /// Tags detected with a decision margin below `min_margin` are dropped before solving. The others are
+    /// used once.
+    Threshold { min_margin: f32 },
+
+    /// Tags detected with a decision margin below `min_margin` are dropped before solving. The corners of
+    /// each other tag are repeated `floor(margin / min_margin)` times (at most `max_repeats` times) when
+    /// locating an object with more than one tag, as a crude weighting of the least-squares solve.
+    Repeat { min_margin: f32, max_repeats: usize },
+}
+
+impl TagWeighting {
+    /// The number of times the corners of a tag detected with `margin` are used to locate its object. 0
+    /// means the tag is dropped.
+    pub fn repeats(&self, margin: f32) -> usize {
+        match *self {
+            TagWeighting::Uniform => 1,
+            TagWeighting::Threshold { min_margin } => (margin >= min_margin) as usize,
+            TagWeighting::Repeat {
+                min_margin,
+                max_repeats,
+            } => {
+                if margin < min_margin {
+                    0
+                } else {
+                    ((margin / min_margin).floor() as usize).clamp(1, max_repeats.max(1))
+                }
+            }
+        }
+    }
+}
+
+/// The last detection of a registered tag, which is carried forward while the tag is briefly not detected.
+/// See `TaggedObjectLocator::set_tag_persistence`.
+#[derive(Debug, Clone)]
+struct PersistedTag {
+    corners: [na::Vector2<f64>; 4],
+    center: na::Vector2<f64>,
+    decision_margin: f32,
+    /// The number of consecutive frames in which the tag has not been detected.
+    missed_frames: usize,
+}
+
+/// The reprojection of a detected tag's center with its object's located pose.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TagReprojectionError {
+    pub tag: TagIndex,
+    /// The detected center of the tag on the image.
+    pub detected_center: na::Vector2<f64>,
+    /// The tag's center projected onto the image with the object's located pose.
+    pub reprojected_center: na::Vector2<f64>,
+    /// The distance between the detected and the reprojected center, in pixels.
+    pub error: f64,
+}
+
+/// Project the center of a tag at `tag_location` on an object located at `location` onto the image, with the
+/// camera's lens distortion.
+pub fn reproject_tag_center(
+    camera: &CameraProperty,
+    location: &na::Isometry3<f64>,
+    tag_location: &TagLocation,
+) -> Result<na::Vector2<f64>, Box<dyn std::error::Error>> {
+    camera.project_point(
+        &location.transform_point(&tag_location.transform_point(&na::Point3::origin())),
+    )
+}
+
 pub struct TaggedObjectLocator<'a> {
     /// Camera matrix
     camera: CameraProperty,
 
-    /// List of all objects registered in the object locator
-    registry: Vec<&'a TaggedObject>,
+    /// List of all objects registered in the object locator
+    registry: Vec<&'a TaggedObject>,
+
+    /// Mapping from each tag's property to its corresponding objects' indices in the registry array. A tag
+    /// only has more than one object if they are variants in the same exclusive group.
+    tag_map: HashMap<TagIndex, Vec<(usize, TagLocation)>>,
+
+    /// Each object's configuration.
+    ///
+    /// This array's index corresponds to the objects stored in `registry`.
+    configs: Vec<ObjectConfig>,
+
+    /// Each object's last location. These are used as the extrinsic guess for OpenCV's solvePnP function.
+    ///
+    /// This array's index corresponds to the objects stored in `registry`.
+    last_location: Vec<Option<(Mat, Mat, Instant)>>,
+
+    /// Each object's last located pose and the time it was located. These are used to detect when an
+    /// object is lost from tracking.
+    ///
+    /// This array's index corresponds to the objects stored in `registry`.
+    last_pose: Vec<Option<(na::Isometry3<f64>, Instant)>>,
+
+    /// Each object's linear velocity and angular velocity (as a scaled rotation axis), per second, estimated
+    /// from its last two located poses. These are used to extrapolate the object's pose when it is not
+    /// located.
+    ///
+    /// This array's index corresponds to the objects stored in `registry`.
+    velocity: Vec<Option<(na::Vector3<f64>, na::Vector3<f64>)>>,
+
+    /// Whether each object's tags all lie on the same plane.
+    ///
+    /// This array's index corresponds to the objects stored in `registry`.
+    planar: Vec<bool>,
+
+    /// The solvePnP method used to get a global initialization of a multi-tag object's location when
+    /// there is no extrinsic guess (e.g. the object just came into view). The result is then refined with
+    /// `SOLVEPNP_ITERATIVE`. If `None`, `SOLVEPNP_ITERATIVE` is run directly without an initialization.
+    initial_pnp_method: Option<i32>,
+
+    /// The solvePnP method used to locate objects with more than one detected tag.
+    pnp_method: i32,
+
+    /// The solvePnP method used to locate objects with only one detected tag. If `None`,
+    /// `SOLVEPNP_IPPE_SQUARE` is used for square tags and `SOLVEPNP_IPPE` for rectangular tags.
+    single_tag_pnp_method: Option<i32>,
+
+    /// The solvePnP method used to locate planar objects with more than one detected tag. If `None`,
+    /// planar objects are located with `pnp_method` like other objects.
+    planar_pnp_method: Option<i32>,
+
+    /// How the detected tags are weighted by their decision margins. See `set_tag_weighting`.
+    tag_weighting: TagWeighting,
+
+    /// Whether to extrapolate the poses of objects that are not located in a frame. See `set_extrapolation`.
+    extrapolation: bool,
+
+    /// The angle (in radians) by which an object's rotation may change between consecutive locations
+    /// before the new location is checked as a possible flip. See `set_flip_threshold`.
+    flip_threshold: Option<f64>,
+
+    /// The distance (in pixels) between a tag's detected and reprojected center above which the tag is
+    /// dropped from its object's solve. See `set_consistency_threshold`.
+    consistency_threshold: Option<f64>,
+
+    /// The number of frames for which a tag's last detection is carried forward. See `set_tag_persistence`.
+    tag_persistence: usize,
+
+    /// Whether objects located from a single tag keep their last rotation located from multiple tags. See
+    /// `set_single_tag_hold`.
+    single_tag_hold: bool,
+
+    /// The maximum number of objects solved in each frame. See `set_solve_budget`.
+    solve_budget: Option<usize>,
+
+    /// The last time each object is solved, used to schedule the objects within the solve budget.
+    ///
+    /// This array's index corresponds to the objects stored in `registry`.
+    last_solved: Vec<Option<Instant>>,
+
+    /// Whether to copy each frame's detections into the located objects. See `set_record_detections`.
+    record_detections: bool,
+
+    /// The number of consecutive consistent poses after which an object is tracked. See `set_stable_frames`.
+    stable_frames: usize,
+
+    /// The maximum number of extra Levenberg-Marquardt iterations refining a multi-tag object's pose before
+    /// it is tracked. See `set_cold_start_iterations`.
+    cold_start_iterations: Option<i32>,
+
+    /// Each object's tracking state.
+    ///
+    /// This array's index corresponds to the objects stored in `registry`.
+    tracking: Vec<TrackingProgress>,
+
+    /// The time each object took from its first pose to being tracked, the last time it is tracked.
+    ///
+    /// This array's index corresponds to the objects stored in `registry`.
+    time_to_fix: Vec<Option<Duration>>,
+
+    /// The frames in which each object is static, up to its bundle window. See `set_bundle_window`.
+    ///
+    /// This array's index corresponds to the objects stored in `registry`.
+    static_frames: Vec<VecDeque<WindowFrame>>,
+
+    /// The half-life of the information in each object's fused covariance, or `None` if the covariance is not
+    /// fused. See `set_covariance_fusion`.
+    covariance_fusion: Option<Duration>,
+
+    /// Each object's fused information matrix (the inverse of its covariance) and the time it is last
+    /// updated. See `set_covariance_fusion`.
+    ///
+    /// This array's index corresponds to the objects stored in `registry`.
+    fused_information: Vec<Option<(na::Matrix6<f64>, Instant)>>,
+
+    /// Each object's last rotation located from multiple tags, used by the single tag hold.
+    ///
+    /// This array's index corresponds to the objects stored in `registry`.
+    last_multi_tag_rotation: Vec<Option<(na::UnitQuaternion<f64>, Instant)>>,
+
+    /// The last detection of each registered tag detected within the last `tag_persistence` frames.
+    persisted_tags: HashMap<TagIndex, PersistedTag>,
+
+    /// The pose of the ground plane in the camera's reference frame, relative to which the located objects
+    /// are published. See `set_ground_plane`.
+    ground_plane: Option<na::Isometry3<f64>>,
+
+    /// The minimum interval between two summaries of the located objects in the log. See
+    /// `set_pose_log_interval`.
+    pose_log_interval: Duration,
+
+    /// The timestamp of the frame whose located objects are last summarized in the log.
+    last_pose_log: Option<Instant>,
+
+    /// The last time each registered tag is detected. Tags that are never detected are not in this map.
+    tag_last_seen: HashMap<TagIndex, Instant>,
+
+    /// The tag families returned by the last call to `poll_required_families`.
+    polled_families: Vec<apriltag::ApriltagFamily>,
+}
+
+/// A data struct for storing the located objects in each frame.
+///
+/// Lifetime parameter `'a` denotes the lifetime of the objects it is referring to. In other words, the
+/// specific objects (e.g. handheld screen, wand, etc.) must live longer than the `LocatedObjects` referring
+/// to them.
+#[derive(Debug, Clone)]
+pub struct LocatedObjects<'a> {
+    pub(super) timestamp: FrameTimestamp,
+    pub(super) name_map: BTreeMap<&'a str, na::Isometry3<f64>>,
+    pub(super) lost_map: BTreeMap<&'a str, na::Isometry3<f64>>,
+    pub(super) extrapolated: BTreeSet<&'a str>,
+    pub(super) degraded: BTreeSet<&'a str>,
+    pub(super) stale: BTreeSet<&'a str>,
+    pub(super) detections: Option<Vec<apriltag::DetectionSnapshot>>,
+    pub(super) tracking: BTreeMap<&'a str, TrackingState>,
+    pub(super) confidence: BTreeMap<&'a str, f64>,
+    pub(super) persisted_tags: HashSet<TagIndex>,
+    pub(super) inconsistent_tags: HashSet<TagIndex>,
+    pub(super) velocity: BTreeMap<&'a str, (na::Vector3<f64>, na::Vector3<f64>)>,
+    pub(super) covariance: BTreeMap<&'a str, PoseCovariance>,
+}
+
+impl<'a> LocatedObjects<'a> {
+    pub fn new() -> Self {
+        Self {
+            timestamp: FrameTimestamp::now(),
+            name_map: BTreeMap::new(),
+            lost_map: BTreeMap::new(),
+            extrapolated: BTreeSet::new(),
+            degraded: BTreeSet::new(),
+            stale: BTreeSet::new(),
+            detections: None,
+            tracking: BTreeMap::new(),
+            confidence: BTreeMap::new(),
+            persisted_tags: HashSet::new(),
+            inconsistent_tags: HashSet::new(),
+            velocity: BTreeMap::new(),
+            covariance: BTreeMap::new(),
+        }
+    }
+
+    /// The monotonic time of the frame in which the objects are located. Use this to order frames.
+    pub fn timestamp(&self) -> Instant {
+        self.timestamp.instant
+    }
+
+    /// The wall-clock time of the frame in which the objects are located. Only use this for reporting,
+    /// since the wall clock may jump backwards.
+    pub fn system_time(&self) -> SystemTime {
+        self.timestamp.system_time
+    }
+
+    pub fn name_map(&self) -> &BTreeMap<&'a str, na::Isometry3<f64>> {
+        &self.name_map
+    }
+
+    /// Objects that were lost from tracking in this frame, mapped to their last known location.
+    ///
+    /// An object is lost when it has not been located for longer than its forget duration. Each
+    /// lost object only appears in this map once, in the first frame after it is forgotten.
+    pub fn lost_map(&self) -> &BTreeMap<&'a str, na::Isometry3<f64>> {
+        &self.lost_map
+    }
+
+    /// Objects in `name_map` whose locations are extrapolated from their last located poses, since they are
+    /// not located in this frame.
+    pub fn extrapolated(&self) -> &BTreeSet<&'a str> {
+        &self.extrapolated
+    }
+
+    /// Objects in `name_map` located from a single tag, whose rotations are held at their last rotations
+    /// located from multiple tags. See `TaggedObjectLocator::set_single_tag_hold`.
+    pub fn degraded(&self) -> &BTreeSet<&'a str> {
+        &self.degraded
+    }
+
+    /// Objects in `name_map` whose tags are detected in this frame, but which are not solved since the
+    /// solve budget is used up, so their last poses are published. See `TaggedObjectLocator::set_solve_budget`.
+    pub fn stale(&self) -> &BTreeSet<&'a str> {
+        &self.stale
+    }
+
+    /// All tags detected in this frame, including the ones that do not belong to any object. Only recorded
+    /// if enabled by `TaggedObjectLocator::set_record_detections`.
+    pub fn detections(&self) -> Option<&[apriltag::DetectionSnapshot]> {
+        self.detections.as_deref()
+    }
+
+    /// The tracking state of each object in `name_map`. Consumers may wait for an object to be
+    /// `TrackingState::Tracking` before relying on its pose.
+    pub fn tracking(&self) -> &BTreeMap<&'a str, TrackingState> {
+        &self.tracking
+    }
+
+    /// The confidence between 0 and 1 of each object located from its tags in this frame, computed by
+    /// `pose_confidence`. Objects in `name_map` that are not located from their tags in this frame (e.g.
+    /// extrapolated objects) have no confidence.
+    pub fn confidence(&self) -> &BTreeMap<&'a str, f64> {
+        &self.confidence
+    }
+
+    /// The tags that are not detected in this frame, but whose last detections are carried forward to
+    /// locate their objects. See `TaggedObjectLocator::set_tag_persistence`.
+    pub fn persisted_tags(&self) -> &HashSet<TagIndex> {
+        &self.persisted_tags
+    }
+
+    /// The detected tags that are dropped from their objects' solves in this frame, since they are not
+    /// consistent with the other tags. See `TaggedObjectLocator::set_consistency_threshold`.
+    pub fn inconsistent_tags(&self) -> &HashSet<TagIndex> {
+        &self.inconsistent_tags
+    }
+
+    /// The linear velocity and angular velocity (as a scaled rotation axis) per second of each object in
+    /// `name_map` whose pose is located or extrapolated in this frame, in the same reference frame as the
+    /// poses. Objects without a velocity estimate (e.g. located for the first time) are not in this map.
+    pub fn velocity(&self) -> &BTreeMap<&'a str, (na::Vector3<f64>, na::Vector3<f64>)> {
+        &self.velocity
+    }
+
+    /// The fused covariance of each object located from its tags in this frame, in the same reference frame
+    /// as the poses. Only maintained if enabled by `TaggedObjectLocator::set_covariance_fusion`.
+    pub fn covariance(&self) -> &BTreeMap<&'a str, PoseCovariance> {
+        &self.covariance
+    }
+
+    /// All pairs of objects in `name_map` whose bounding boxes intersect when placed at their poses, with the
+    /// names in each pair and the pairs sorted by name.
+    ///
+    /// Rigid objects should not interpenetrate, so an overlapping pair usually means that one of the objects
+    /// is mis-located, e.g. the wand appearing inside the screen. Consumers may reject such frames. Each
+    /// object's box encloses its tags in `object_map` (see `TaggedObjectLocator::get_object_map` and
+    /// `BoundingBox::from_tags`), so parts of an object without tags are not covered. Objects that are not in
+    /// `object_map`, or have no tags, never overlap.
+    pub fn overlapping_pairs(
+        &self,
+        object_map: &HashMap<String, Vec<(TagIndex, TagLocation)>>,
+    ) -> Vec<(&'a str, &'a str)> {
+        let boxes = self
+            .name_map
+            .iter()
+            .filter_map(|(name, location)| {
+                let tags = object_map.get(*name)?;
+                let bounding_box = BoundingBox::from_tags(tags.iter().map(|(_, tag)| tag))?;
+                Some((*name, location, bounding_box))
+            })
+            .collect::<Vec<_>>();
+        let mut pairs = Vec::new();
+        for (i, (name, location, bounding_box)) in boxes.iter().enumerate() {
+            for (other_name, other_location, other_box) in &boxes[i + 1..] {
+                if bounding_box.intersects(location, other_box, other_location) {
+                    pairs.push((*name, *other_name));
+                }
+            }
+        }
+        pairs
+    }
+
+    /// Predict the located objects at `timestamp` from their velocities, e.g. to publish them at a fixed
+    /// rate between the camera frames. Objects without a velocity keep their poses.
+    ///
+    /// The poses are extrapolated for at most `max_extrapolation`, so that they do not drift away when no
+    /// new frame arrives. The lost objects are replaced by `lost_map`. The detections are not kept, since
+    /// they belong to the frame instead of `timestamp`.
+    pub fn resampled(
+        &self,
+        timestamp: FrameTimestamp,
+        max_extrapolation: Duration,
+        lost_map: BTreeMap<&'a str, na::Isometry3<f64>>,
+    ) -> Self {
+        let elapsed = timestamp
+            .instant
+            .saturating_duration_since(self.timestamp.instant)
+            .min(max_extrapolation);
+        let mut resampled = self.clone();
+        resampled.timestamp = timestamp;
+        resampled.lost_map = lost_map;
+        resampled.detections = None;
+        for (name, (linear, angular)) in &self.velocity {
+            if let Some(location) = resampled.name_map.get_mut(name) {
+                *location = extrapolate_pose(location, linear, angular, elapsed);
+            }
+        }
+        resampled
+    }
+
+    /// A one-line summary of the located objects and their translations, e.g.
+    /// `wand (1.00, -2.50, 30.00), screen (extrapolated) (0.00, 0.00, 25.00); lost: anchor`.
+    pub fn summary(&self) -> String {
+        let mut summary = if self.name_map.is_empty() {
+            "no objects located".to_string()
+        } else {
+            self.name_map
+                .iter()
+                .map(|(name, location)| {
+                    let translation = location.translation.vector;
+                    format!(
+                        "{}{} ({:.2}, {:.2}, {:.2})",
+                        name,
+                        self.suffix(name),
+                        translation.x,
+                        translation.y,
+                        translation.z
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        if !self.lost_map.is_empty() {
+            let lost = self.lost_map.keys().copied().collect::<Vec<_>>();
+            summary += &format!("; lost: {}", lost.join(", "));
+        }
+        summary
+    }
+
+    /// The suffix marking the object `name` in `summary` and `Display` if its pose is not fully located in
+    /// this frame.
+    fn suffix(&self, name: &str) -> &'static str {
+        if self.extrapolated.contains(name) {
+            " (extrapolated)"
+        } else if self.degraded.contains(name) {
+            " (degraded)"
+        } else if self.stale.contains(name) {
+            " (stale)"
+        } else {
+            ""
+        }
+    }
+}
+
+impl<'a> Display for LocatedObjects<'a> {
+    /// Print each located object's translation and its rotation as Euler angles (roll, pitch, yaw) in
+    /// degrees, followed by the objects lost in this frame.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let millis = self
+            .system_time()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_millis());
+        write!(f, "Frame at {} ms:", millis)?;
+        let located = self
+            .name_map
+            .iter()
+            .map(|(name, location)| (name, location, self.suffix(name)));
+        let lost = self
+            .lost_map
+            .iter()
+            .map(|(name, location)| (name, location, " (lost)"));
+        for (name, location, suffix) in located.chain(lost) {
+            let translation = location.translation.vector;
+            let euler = rotation::quat_to_euler(&location.rotation);
+            write!(
+                f,
+                "\n  {}{}: translation ({:.3}, {:.3}, {:.3}), rotation ({:.2}, {:.2}, {:.2}) deg",
+                name,
+                suffix,
+                translation.x,
+                translation.y,
+                translation.z,
+                euler.x.to_degrees(),
+                euler.y.to_degrees(),
+                euler.z.to_degrees()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A copy of the located objects in a frame that owns the objects' names, so that it can outlive the objects
+/// and be kept or sent anywhere. See `snapshot`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnedLocatedObjects {
+    pub timestamp: Instant,
+    pub system_time: SystemTime,
+    pub name_map: BTreeMap<String, na::Isometry3<f64>>,
+    pub lost_map: BTreeMap<String, na::Isometry3<f64>>,
+    pub extrapolated: BTreeSet<String>,
+    pub degraded: BTreeSet<String>,
+    pub stale: BTreeSet<String>,
+    pub detections: Option<Vec<apriltag::DetectionSnapshot>>,
+    pub tracking: BTreeMap<String, TrackingState>,
+    pub confidence: BTreeMap<String, f64>,
+    pub persisted_tags: HashSet<TagIndex>,
+    pub inconsistent_tags: HashSet<TagIndex>,
+    pub velocity: BTreeMap<String, (na::Vector3<f64>, na::Vector3<f64>)>,
+    pub covariance: BTreeMap<String, PoseCovariance>,
+}
+
+impl From<&LocatedObjects<'_>> for OwnedLocatedObjects {
+    fn from(located: &LocatedObjects<'_>) -> Self {
+        fn owned_keys<V: Clone>(map: &BTreeMap<&str, V>) -> BTreeMap<String, V> {
+            map.iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect()
+        }
+        fn owned_names(set: &BTreeSet<&str>) -> BTreeSet<String> {
+            set.iter().map(|name| name.to_string()).collect()
+        }
+        Self {
+            timestamp: located.timestamp(),
+            system_time: located.system_time(),
+            name_map: owned_keys(&located.name_map),
+            lost_map: owned_keys(&located.lost_map),
+            extrapolated: owned_names(&located.extrapolated),
+            degraded: owned_names(&located.degraded),
+            stale: owned_names(&located.stale),
+            detections: located.detections.clone(),
+            tracking: owned_keys(&located.tracking),
+            confidence: owned_keys(&located.confidence),
+            persisted_tags: located.persisted_tags.clone(),
+            inconsistent_tags: located.inconsistent_tags.clone(),
+            velocity: owned_keys(&located.velocity),
+            covariance: owned_keys(&located.covariance),
+        }
+    }
+}
+
+/// Copy the latest located objects out of `shared`, holding its lock only for the copy.
+///
+/// Use this to read the current poses instead of locking `shared` directly, which blocks the locator from
+/// publishing the next frame for as long as the lock is held.
+pub fn snapshot(shared: &Arc<(Mutex<LocatedObjects<'_>>, Condvar)>) -> OwnedLocatedObjects {
+    OwnedLocatedObjects::from(&*shared.0.lock().unwrap())
+}
+
+impl<'a> TaggedObjectLocator<'a> {
+    pub fn new(camera: CameraProperty) -> Self {
+        Self {
+            camera,
+            registry: Vec::new(),
+            tag_map: HashMap::new(),
+            configs: Vec::new(),
+            last_location: Vec::new(),
+            last_pose: Vec::new(),
+            velocity: Vec::new(),
+            planar: Vec::new(),
+            initial_pnp_method: Some(DEFAULT_INITIAL_PNP_METHOD),
+            pnp_method: calib3d::SOLVEPNP_ITERATIVE,
+            single_tag_pnp_method: None,
+            planar_pnp_method: Some(calib3d::SOLVEPNP_IPPE),
+            tag_weighting: TagWeighting::Uniform,
+            tag_last_seen: HashMap::new(),
+            extrapolation: false,
+            flip_threshold: None,
+            consistency_threshold: None,
+            tag_persistence: 0,
+            single_tag_hold: false,
+            record_detections: false,
+            stable_frames: DEFAULT_STABLE_FRAMES,
+            cold_start_iterations: None,
+            tracking: Vec::new(),
+            time_to_fix: Vec::new(),
+            static_frames: Vec::new(),
+            covariance_fusion: None,
+            fused_information: Vec::new(),
+            last_multi_tag_rotation: Vec::new(),
+            solve_budget: None,
+            last_solved: Vec::new(),
+            persisted_tags: HashMap::new(),
+            ground_plane: None,
+            pose_log_interval: POSE_LOG_INTERVAL,
+            last_pose_log: None,
+            polled_families: Vec::new(),
+        }
+    }
+
+    pub fn camera(&self) -> &CameraProperty {
+        &self.camera
+    }
+
+    /// Add a new tagged object to the registry with the default configuration.
+    pub fn add(&mut self, tagobj: &'a TaggedObject) -> Result<(), ConflictingTagError> {
+        self.add_with_config(tagobj, ObjectConfig::default())
+    }
+
+    /// Add a new tagged object to the registry.
+    ///
+    /// Returns an error if an object with the same name is already registered, or if any of the object's
+    /// tags is already used by another object outside the object's exclusive group.
+    pub fn add_with_config(
+        &mut self,
+        tagobj: &'a TaggedObject,
+        config: ObjectConfig,
+    ) -> Result<(), ConflictingTagError> {
+        let this_name = &tagobj.name;
+        if self.registry_index(this_name).is_some() {
+            return Err(ConflictingTagError::new_name(this_name));
+        }
+        for (tag_index, _) in &tagobj.tags {
+            if let Some(registry_index) = self.conflicting_object(tag_index, &config, None) {
+                return Err(ConflictingTagError::new(
+                    *tag_index,
+                    unsafe { self.registry.get_unchecked(registry_index).name.clone() },
+                    this_name.clone(),
+                ));
+            }
+        }
+        let this_registry_index = self.registry.len();
+        self.registry.push(tagobj);
+        for (tag_index, tag_location) in &tagobj.tags {
+            // It is guaranteed that at this point, the tag is only shared within the exclusive group
+            self.tag_map
+                .entry(*tag_index)
+                .or_default()
+                .push((this_registry_index, tag_location.clone()));
+        }
+        self.configs.push(config);
+        self.last_location.push(None);
+        self.last_pose.push(None);
+        self.velocity.push(None);
+        self.planar.push(is_object_planar(tagobj));
+        self.last_multi_tag_rotation.push(None);
+        self.last_solved.push(None);
+        self.tracking.push(TrackingProgress::default());
+        self.time_to_fix.push(None);
+        self.static_frames.push(VecDeque::new());
+        self.fused_information.push(None);
+        Ok(())
+    }
+
+    /// Remove the object with the given name from the registry.
+    ///
+    /// Returns `false` if no object with the given name is registered.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let Some(removed_index) = self.registry_index(name) else {
+            return false;
+        };
+        self.registry.remove(removed_index);
+        self.configs.remove(removed_index);
+        self.last_location.remove(removed_index);
+        self.last_pose.remove(removed_index);
+        self.velocity.remove(removed_index);
+        self.planar.remove(removed_index);
+        self.last_multi_tag_rotation.remove(removed_index);
+        self.last_solved.remove(removed_index);
+        self.tracking.remove(removed_index);
+        self.time_to_fix.remove(removed_index);
+        self.static_frames.remove(removed_index);
+        self.fused_information.remove(removed_index);
+        // Remove the object's tags and shift the indices of all objects after it
+        for candidates in self.tag_map.values_mut() {
+            candidates.retain(|(registry_index, _)| *registry_index != removed_index);
+            for (registry_index, _) in candidates {
+                if *registry_index > removed_index {
+                    *registry_index -= 1;
+                }
+            }
+        }
+        self.tag_map.retain(|_, candidates| !candidates.is_empty());
+        self.tag_last_seen
+            .retain(|tag_index, _| self.tag_map.contains_key(tag_index));
+        true
+    }
+
+    /// Replace the registered object that has the same name as `tagobj` with `tagobj`, keeping its
+    /// configuration. If no such object is registered, `tagobj` is added with the default configuration.
+    ///
+    /// The object's last location is discarded, since its tag layout may have changed.
+    pub fn update(&mut self, tagobj: &'a TaggedObject) -> Result<(), ConflictingTagError> {
+        let Some(updated_index) = self.registry_index(&tagobj.name) else {
+            return self.add(tagobj);
+        };
+        for tag_index in tagobj.tags.keys() {
+            if let Some(registry_index) = self.conflicting_object(
+                tag_index,
+                &self.configs[updated_index],
+                Some(updated_index),
+            ) {
+                return Err(ConflictingTagError::new(
+                    *tag_index,
+                    self.registry[registry_index].name.clone(),
+                    tagobj.name.clone(),
+                ));
+            }
+        }
+        self.registry[updated_index] = tagobj;
+        for candidates in self.tag_map.values_mut() {
+            candidates.retain(|(registry_index, _)| *registry_index != updated_index);
+        }
+        self.tag_map.retain(|_, candidates| !candidates.is_empty());
+        for (tag_index, tag_location) in &tagobj.tags {
+            self.tag_map
+                .entry(*tag_index)
+                .or_default()
+                .push((updated_index, tag_location.clone()));
+        }
+        self.tag_last_seen
+            .retain(|tag_index, _| self.tag_map.contains_key(tag_index));
+        self.last_location[updated_index] = None;
+        self.last_pose[updated_index] = None;
+        self.velocity[updated_index] = None;
+        self.planar[updated_index] = is_object_planar(tagobj);
+        self.last_multi_tag_rotation[updated_index] = None;
+        self.last_solved[updated_index] = None;
+        self.tracking[updated_index] = TrackingProgress::default();
+        self.time_to_fix[updated_index] = None;
+        self.static_frames[updated_index].clear();
+        self.fused_information[updated_index] = None;
+        Ok(())
+    }
+
+    /// Find a registered object other than `except` that uses the tag, and may not share it with an object
+    /// configured with `config`, i.e. is not in the same exclusive group.
+    fn conflicting_object(
+        &self,
+        tag_index: &TagIndex,
+        config: &ObjectConfig,
+        except: Option<usize>,
+    ) -> Option<usize> {
+        let candidates = self.tag_map.get(tag_index)?;
+        candidates
+            .iter()
+            .map(|(registry_index, _)| *registry_index)
+            .find(|registry_index| {
+                Some(*registry_index) != except
+                    && (config.exclusive_group.is_none()
+                        || self.configs[*registry_index].exclusive_group != config.exclusive_group)
+            })
+    }
+
+    /// Find the index of an object in the registry by its name.
+    fn registry_index(&self, name: &str) -> Option<usize> {
+        self.registry.iter().position(|object| object.name == name)
+    }
+
+    /// Get the configuration of the object with the given name.
+    pub fn config(&self, name: &str) -> Option<&ObjectConfig> {
+        self.registry_index(name).map(|index| &self.configs[index])
+    }
+
+    /// Set the duration after which the stored information of the object with the given name is
+    /// forgotten.
+    ///
+    /// Returns `false` if no object with the given name is registered.
+    pub fn set_forget_duration(&mut self, name: &str, forget_duration: Duration) -> bool {
+        match self.registry_index(name) {
+            Some(index) => {
+                self.configs[index].forget_duration = forget_duration;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Set the minimum number of tags of the object with the given name that must be detected in a frame
+    /// to locate the object.
+    ///
+    /// Returns `false` if no object with the given name is registered.
+    pub fn set_min_tags(&mut self, name: &str, min_tags: usize) -> bool {
+        match self.registry_index(name) {
+            Some(index) => {
+                self.configs[index].min_tags = min_tags;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Set the number of recent frames over which the pose of the object with the given name is jointly
+    /// refined while it is static, e.g. for an anchor object whose pose should not jitter. Disabled
+    /// (`None`) by default.
+    ///
+    /// The object is static while its velocity is below `STATIC_LINEAR_SPEED` and `STATIC_ANGULAR_SPEED`,
+    /// and its pose solved in each frame is within `STABLE_TRANSLATION_TOLERANCE` and
+    /// `STABLE_ROTATION_TOLERANCE` of its last pose. Its tags' corners detected in the last `frames` static
+    /// frames are then kept, and the published pose minimizes their total reprojection error. This is
+    /// slower than solving each frame on its own, so it is best kept for the objects that rarely move. The
+    /// frames are discarded as soon as the object moves.
+    ///
+    /// Returns `false` if no object with the given name is registered.
+    pub fn set_bundle_window(&mut self, name: &str, frames: Option<usize>) -> bool {
+        match self.registry_index(name) {
+            Some(index) => {
+                self.configs[index].bundle_window = frames;
+                self.static_frames[index].clear();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Enable or disable extrapolating the poses of objects that are not located in a frame, e.g. when they
+    /// are occluded for a moment.
+    ///
+    /// When enabled, an object that is not located keeps being published with a pose extrapolated from its
+    /// estimated velocity, until its forget duration elapses. Extrapolated objects are listed in
+    /// `LocatedObjects::extrapolated`. Disabled by default.
+    pub fn set_extrapolation(&mut self, extrapolation: bool) {
+        self.extrapolation = extrapolation;
+    }
+
+    pub fn extrapolation(&self) -> bool {
+        self.extrapolation
+    }
+
+    /// Set the angle (in radians) above which a change of an object's rotation since its last location is
+    /// treated as a discontinuity, e.g. when solvePnP picks the wrong one of the two ambiguous solutions of
+    /// a tag seen almost head-on. Disabled (`None`) by default.
+    ///
+    /// Such a location is only accepted if its RMS reprojection error is lower than the last location's by
+    /// at least `FLIP_MIN_ERROR_GAIN` pixels. Otherwise, the last location is published instead, until the
+    /// object's forget duration elapses. Unlike smoothing, this never blends the poses.
+    pub fn set_flip_threshold(&mut self, threshold: Option<f64>) {
+        self.flip_threshold = threshold;
+    }
+
+    pub fn flip_threshold(&self) -> Option<f64> {
+        self.flip_threshold
+    }
+
+    /// Set the distance (in pixels) between a tag's detected center and its center reprojected with the
+    /// located pose, above which the tag is dropped from its object's solve. Disabled (`None`) by default.
+    ///
+    /// This catches spurious detections and tags of other objects misread as this object's tags, which
+    /// survive the hamming correction but are not consistent with the other tags. The tag with the largest
+    /// error is dropped and the object is solved again, until all tags are within the threshold or only
+    /// the object's minimum number of tags is left. Dropped tags are listed in
+    /// `LocatedObjects::inconsistent_tags`.
+    pub fn set_consistency_threshold(&mut self, threshold: Option<f64>) {
+        self.consistency_threshold = threshold;
+    }
+
+    pub fn consistency_threshold(&self) -> Option<f64> {
+        self.consistency_threshold
+    }
+
+    /// Set the number of frames for which the last detected corners of a tag are carried forward when the
+    /// tag is not detected, e.g. when it flickers in and out of detection. Set to 0 to disable, which is the
+    /// default.
+    ///
+    /// Unlike extrapolation, this stabilizes the input of the solve instead of the located pose: a carried
+    /// tag is only used while at least one other tag of its object is detected, so that the multi-tag solve
+    /// stays well-conditioned. Carried tags are listed in `LocatedObjects::persisted_tags`.
+    pub fn set_tag_persistence(&mut self, frames: usize) {
+        self.tag_persistence = frames;
+    }
+
+    pub fn tag_persistence(&self) -> usize {
+        self.tag_persistence
+    }
+
+    /// Enable or disable holding the rotation of objects that are located from a single tag, e.g. when a
+    /// handheld screen turns so that only one of its tags is visible. Disabled by default.
+    ///
+    /// The rotation solved from a single tag is much noisier than its position. When enabled, an object
+    /// located from a single tag keeps its last rotation located from multiple tags (within its forget
+    /// duration), and only its translation is updated to keep the tag's center in place. Such objects are
+    /// listed in `LocatedObjects::degraded`.
+    pub fn set_single_tag_hold(&mut self, single_tag_hold: bool) {
+        self.single_tag_hold = single_tag_hold;
+    }
+
+    pub fn single_tag_hold(&self) -> bool {
+        self.single_tag_hold
+    }
+
+    /// Set the number of consecutive consistent poses after which an object is tracked. Defaults to
+    /// `DEFAULT_STABLE_FRAMES`.
+    ///
+    /// Two consecutive poses are consistent if the later one is within `STABLE_TRANSLATION_TOLERANCE` and
+    /// `STABLE_ROTATION_TOLERANCE` of the earlier one, which is moved with the object's velocity once the
+    /// velocity is estimated from consistent poses. See `LocatedObjects::tracking`.
+    pub fn set_stable_frames(&mut self, stable_frames: usize) {
+        self.stable_frames = stable_frames.max(1);
+    }
+
+    pub fn stable_frames(&self) -> usize {
+        self.stable_frames
+    }
+
+    /// Set the maximum number of extra Levenberg-Marquardt iterations refining the pose of a multi-tag
+    /// object that is not tracked yet, e.g. right after it comes into view without an extrinsic guess.
+    /// Disabled (`None`) by default.
+    ///
+    /// This complements the initialization by `set_initial_pnp_method`, at the cost of a slower solve
+    /// until the object is tracked.
+    pub fn set_cold_start_iterations(&mut self, iterations: Option<i32>) {
+        self.cold_start_iterations = iterations;
+    }
+
+    pub fn cold_start_iterations(&self) -> Option<i32> {
+        self.cold_start_iterations
+    }
+
+    /// The tracking state of the object `name`, or `None` if no such object is registered.
+    pub fn tracking_state(&self, name: &str) -> Option<TrackingState> {
+        self.registry_index(name)
+            .map(|registry_index| self.tracking[registry_index].state)
+    }
+
+    /// The time the object `name` took from its first pose to being tracked, the last time it became
+    /// tracked. `None` if the object is not registered or has never been tracked.
+    pub fn time_to_fix(&self, name: &str) -> Option<Duration> {
+        self.registry_index(name)
+            .and_then(|registry_index| self.time_to_fix[registry_index])
+    }
+
+    /// Update the tracking state of an object located at `location` at `timestamp`. Must be called before
+    /// the object's last pose and velocity are updated with `location`.
+    fn update_tracking(
+        &mut self,
+        object_index: usize,
+        location: &na::Isometry3<f64>,
+        timestamp: Instant,
+    ) {
+        let progress = self.tracking[object_index];
+        match progress.state {
+            TrackingState::Uninitialized => {
+                self.tracking[object_index] = TrackingProgress {
+                    state: TrackingState::Initializing,
+                    consistent_frames: 1,
+                    since: Some(timestamp),
+                };
+            }
+            TrackingState::Initializing => {
+                let consistent =
+                    self.last_pose[object_index].is_some_and(|(last_location, last_timestamp)| {
+                        // the velocity is only reliable if it is estimated from consistent poses
+                        let predicted = match self.velocity[object_index] {
+                            Some((linear, angular)) if progress.consistent_frames >= 2 => {
+                                extrapolate_pose(
+                                    &last_location,
+                                    &linear,
+                                    &angular,
+                                    timestamp.saturating_duration_since(last_timestamp),
+                                )
+                            }
+                            _ => last_location,
+                        };
+                        (location.translation.vector - predicted.translation.vector).norm()
+                            <= STABLE_TRANSLATION_TOLERANCE * location.translation.vector.norm()
+                            && location.rotation.angle_to(&predicted.rotation)
+                                <= STABLE_ROTATION_TOLERANCE
+                    });
+                let progress = &mut self.tracking[object_index];
+                progress.consistent_frames = if consistent {
+                    progress.consistent_frames + 1
+                } else {
+                    1
+                };
+                if progress.consistent_frames >= self.stable_frames {
+                    progress.state = TrackingState::Tracking;
+                    let time_to_fix = progress
+                        .since
+                        .map(|since| timestamp.saturating_duration_since(since))
+                        .unwrap_or_default();
+                    self.time_to_fix[object_index] = Some(time_to_fix);
+                    log::info!(
+                        "Object \"{}\" is tracked after {:?}.",
+                        self.registry[object_index].name,
+                        time_to_fix
+                    );
+                }
+            }
+            TrackingState::Tracking => {}
+        }
+    }
+
+    /// Check whether an object located at `location` at `timestamp` is static, i.e. it moves slowly and stays
+    /// close to its last located pose. Must be called before the object's last pose and velocity are updated
+    /// with `location`.
+    fn is_static(
+        &self,
+        object_index: usize,
+        location: &na::Isometry3<f64>,
+        timestamp: Instant,
+    ) -> bool {
+        let distance = location.translation.vector.norm();
+        self.velocity[object_index].is_some_and(|(linear, angular)| {
+            linear.norm() <= STATIC_LINEAR_SPEED * distance
+                && angular.norm() <= STATIC_ANGULAR_SPEED
+        }) && self.last_pose[object_index].is_some_and(|(last_location, last_timestamp)| {
+            timestamp.saturating_duration_since(last_timestamp)
+                <= self.configs[object_index].forget_duration
+                && (location.translation.vector - last_location.translation.vector).norm()
+                    <= STABLE_TRANSLATION_TOLERANCE * distance
+                && location.rotation.angle_to(&last_location.rotation) <= STABLE_ROTATION_TOLERANCE
+        })
+    }
+
+    /// Fuse the covariance of an object located at `location` from `detections` into the object's fused
+    /// information, and return the fused covariance, or `None` if the covariance fusion is disabled. Must be
+    /// called before the object's last pose and velocity are updated with `location`.
+    ///
+    /// `detections` has the same format as function `locate_single_object`, and the `inconsistent_tags` are
+    /// left out.
+    fn fuse_covariance(
+        &mut self,
+        object_index: usize,
+        location: &na::Isometry3<f64>,
+        detections: &[(&apriltag::ApriltagDetection, TagLocation)],
+        inconsistent_tags: &[TagIndex],
+        timestamp: Instant,
+    ) -> Result<Option<PoseCovariance>, Box<dyn std::error::Error>> {
+        let Some(half_life) = self.covariance_fusion else {
+            return Ok(None);
+        };
+        let mut tag_locations = Vec::with_capacity(detections.len());
+        for (detection, tag_location) in detections {
+            if !inconsistent_tags.contains(&TagIndex::new(detection.family()?, detection.id())) {
+                tag_locations.push(tag_location.clone());
+            }
+        }
+        let covariance = Self::calculate_covariance(
+            self.camera.camera_mat_na()?,
+            tag_locations.into_iter(),
+            *location,
+            self.camera.corner_variance,
+        )?;
+        // a degenerate frame is left out, since its information in the constrained directions is unreliable
+        let information = covariance
+            .finite()
+            .and_then(|covariance| covariance.try_inverse())
+            .unwrap_or_else(na::Matrix6::zeros);
+        // the past information only describes the object's pose while it stays static
+        let fused = match self.fused_information[object_index] {
+            Some((last_information, last_timestamp))
+                if self.is_static(object_index, location, timestamp) =>
+            {
+                let half_lives = timestamp
+                    .saturating_duration_since(last_timestamp)
+                    .as_secs_f64()
+                    / half_life.as_secs_f64().max(f64::MIN_POSITIVE);
+                last_information * 0.5_f64.powf(half_lives) + information
+            }
+            _ => information,
+        };
+        self.fused_information[object_index] = Some((fused, timestamp));
+        Ok(Some(PoseCovariance::from_information(&fused)))
+    }
+
+    /// Add the frame's `detections` of a static object located at `location` to the object's window, and
+    /// refine its pose over the window. Must be called before the object's last pose and velocity are
+    /// updated with the refined pose.
+    ///
+    /// Returns `location` unchanged if the object has no bundle window, or it is moving. `detections` has
+    /// the same format as function `locate_single_object`, and the `inconsistent_tags` are left out.
+    fn adjust_static_pose(
+        &mut self,
+        object_index: usize,
+        location: na::Isometry3<f64>,
+        detections: &[(&apriltag::ApriltagDetection, TagLocation)],
+        inconsistent_tags: &[TagIndex],
+        timestamp: Instant,
+    ) -> Result<na::Isometry3<f64>, Box<dyn std::error::Error>> {
+        let Some(window) = self.configs[object_index].bundle_window else {
+            return Ok(location);
+        };
+        if !self.is_static(object_index, &location, timestamp) {
+            self.static_frames[object_index].clear();
+            return Ok(location);
+        }
+
+        let camera_mat = self.camera.camera_mat_na()?;
+        let mut frame = WindowFrame::with_capacity(detections.len());
+        for (detection, tag_location) in detections {
+            if inconsistent_tags.contains(&TagIndex::new(detection.family()?, detection.id())) {
+                continue;
+            }
+            let mut corners = detection.corners();
+            for corner in &mut corners {
+                // the lens distortion is removed, so that the corners are projected with the camera matrix
+                let point = camera_mat * self.camera.pixel_to_ray(*corner)?;
+                *corner = point.xy() / point.z;
+            }
+            frame.push((tag_location.clone(), corners));
+        }
+        let static_frames = &mut self.static_frames[object_index];
+        static_frames.push_back(frame);
+        while static_frames.len() > window {
+            static_frames.pop_front();
+        }
+        if static_frames.len() < 2 {
+            return Ok(location);
+        }
+        match Self::bundle_adjust(camera_mat, static_frames.make_contiguous(), location) {
+            Ok(adjusted) => Ok(adjusted),
+            Err(e) => {
+                log::debug!(
+                    "Failed to refine the pose of object \"{}\" over its static frames: {}",
+                    self.registry[object_index].name,
+                    e
+                );
+                Ok(location)
+            }
+        }
+    }
+
+    /// Set the half-life over which the information of an object's past frames fades out of its fused
+    /// covariance (e.g. `COVARIANCE_FUSION_HALF_LIFE`), or disable the fusion with `None`. Disabled by
+    /// default.
+    ///
+    /// While enabled, the covariance of each object located from its tags is computed in every frame with
+    /// `calculate_covariance`, and fused into a running estimate by summing its inverse (the information
+    /// matrix) with the object's past information, which is halved every `half_life`. The fused covariance
+    /// shrinks as consistent detections of a static object accumulate, and falls back to the frame's own
+    /// covariance once the object moves, since its past poses no longer describe it. The fused covariances
+    /// are published in `LocatedObjects::covariance`.
+    pub fn set_covariance_fusion(&mut self, half_life: Option<Duration>) {
+        self.covariance_fusion = half_life;
+        if half_life.is_none() {
+            self.fused_information.fill(None);
+        }
+    }
+
+    pub fn covariance_fusion(&self) -> Option<Duration> {
+        self.covariance_fusion
+    }
+
+    /// Enable or disable copying all tags detected in each frame into `LocatedObjects::detections`, e.g. to
+    /// debug the detection from a client. Disabled by default to avoid the copies.
+    pub fn set_record_detections(&mut self, record_detections: bool) {
+        self.record_detections = record_detections;
+    }
+
+    pub fn record_detections(&self) -> bool {
+        self.record_detections
+    }
+
+    /// Set the maximum number of objects solved in each frame, to keep the locator real-time when many
+    /// objects are registered. Unlimited (`None`) by default.
+    ///
+    /// When more objects are detected, the objects solved the longest time ago (or never) are solved first,
+    /// so that every detected object is solved in turn. The other objects keep their last poses, and are
+    /// listed in `LocatedObjects::stale`. Since they are still detected, they are not forgotten even if they
+    /// wait longer than their forget durations to be solved.
+    pub fn set_solve_budget(&mut self, budget: Option<usize>) {
+        self.solve_budget = budget;
+    }
+
+    pub fn solve_budget(&self) -> Option<usize> {
+        self.solve_budget
+    }
+
+    /// Remove the objects beyond the solve budget from `tag_classification`, keeping the objects solved the
+    /// longest time ago. Returns the removed objects' indices.
+    fn defer_objects<T>(&self, tag_classification: &mut BTreeMap<usize, T>) -> Vec<usize> {
+        let Some(budget) = self.solve_budget else {
+            return Vec::new();
+        };
+        if tag_classification.len() <= budget {
+            return Vec::new();
+        }
+        let mut scheduled = tag_classification.keys().copied().collect::<Vec<_>>();
+        // `None` (never solved) is ordered first, and the sort is stable, so ties are broken by the index
+        scheduled.sort_by_key(|registry_index| self.last_solved[*registry_index]);
+        let deferred = scheduled.split_off(budget);
+        for registry_index in &deferred {
+            tag_classification.remove(registry_index);
+        }
+        deferred
+    }
+
+    /// Set the minimum interval between two summaries of the located objects, which are logged to
+    /// `POSE_LOG_TARGET` at the debug level. The interval is measured between the frames' timestamps.
+    /// Defaults to `POSE_LOG_INTERVAL`.
+    pub fn set_pose_log_interval(&mut self, interval: Duration) {
+        self.pose_log_interval = interval;
+    }
+
+    pub fn pose_log_interval(&self) -> Duration {
+        self.pose_log_interval
+    }
+
+    /// Whether the located objects of the frame at `timestamp` should be summarized in the log, in which
+    /// case the frame is recorded as the last summarized one.
+    fn should_log_poses(&mut self, timestamp: Instant) -> bool {
+        if self.last_pose_log.is_some_and(|last_pose_log| {
+            timestamp.saturating_duration_since(last_pose_log) < self.pose_log_interval
+        }) {
+            return false;
+        }
+        self.last_pose_log = Some(timestamp);
+        true
+    }
+
+    /// Set the pose of the ground plane (e.g. a floor or a table) in the camera's reference frame. The
+    /// plane's z axis points up from the surface, and its x and y axes lie on the surface.
+    ///
+    /// When set, the poses in `LocatedObjects` are relative to the ground plane instead of the camera. Set to
+    /// `None` to publish the poses relative to the camera, which is the default.
+    pub fn set_ground_plane(&mut self, ground_plane: Option<na::Isometry3<f64>>) {
+        self.ground_plane = ground_plane;
+    }
+
+    pub fn ground_plane(&self) -> Option<na::Isometry3<f64>> {
+        self.ground_plane
+    }
+
+    /// Set the ground plane to the surface on which the object `name` lies flat, e.g. a single tag placed on
+    /// the floor. The plane is taken from the object's last located pose, with the z axis flipped to point
+    /// out of the tags' faces.
+    ///
+    /// Returns `false` without changing the ground plane if the object is not registered or not located.
+    pub fn capture_ground_plane(&mut self, name: &str) -> bool {
+        let Some((pose, _)) = self
+            .registry_index(name)
+            .and_then(|registry_index| self.last_pose[registry_index])
+        else {
+            return false;
+        };
+        // A tag's z axis points into the surface behind it
+        let flip =
+            na::UnitQuaternion::from_axis_angle(&na::Vector3::x_axis(), std::f64::consts::PI);
+        self.ground_plane = Some(pose * flip);
+        true
+    }
 
-    /// Mapping from each tag's property to its corresponding object's index in the registry array
-    tag_map: HashMap<TagIndex, (usize, TagLocation)>,
+    /// Set the solvePnP method used to initialize a multi-tag object's location when there is no extrinsic
+    /// guess, e.g. `calib3d::SOLVEPNP_EPNP` or `calib3d::SOLVEPNP_SQPNP`. Set to `None` to run
+    /// `SOLVEPNP_ITERATIVE` directly without an initialization.
+    pub fn set_initial_pnp_method(&mut self, method: Option<i32>) {
+        self.initial_pnp_method = method;
+    }
 
-    /// Each object's last location. These are used as the extrinsic guess for OpenCV's solvePnP function.
+    pub fn initial_pnp_method(&self) -> Option<i32> {
+        self.initial_pnp_method
+    }
+
+    /// Set the solvePnP method used to locate objects with more than one detected tag. Defaults to
+    /// `calib3d::SOLVEPNP_ITERATIVE`.
     ///
-    /// This array's index corresponds to the objects stored in `registry`.
-    last_location: Vec<Option<(Mat, Mat, SystemTime)>>,
-}
+    /// The objects' last locations are only used as extrinsic guesses, and the initial method set by
+    /// `set_initial_pnp_method` is only run, if the method supports extrinsic guesses (see
+    /// `supports_extrinsic_guess`). Methods that require exactly 4 points (`SOLVEPNP_P3P`,
+    /// `SOLVEPNP_AP3P`) and `SOLVEPNP_IPPE_SQUARE` can't be used here.
+    pub fn set_pnp_method(&mut self, method: i32) {
+        self.pnp_method = method;
+    }
 
-/// A data struct for storing the located objects in each frame.
-///
-/// Lifetime parameter `'a` denotes the lifetime of the objects it is referring to. In other words, the
-/// specific objects (e.g. handheld screen, wand, etc.) must live longer than the `LocatedObjects` referring
-/// to them.
-#[derive(Debug)]
-pub struct LocatedObjects<'a> {
-    pub(super) timestamp: SystemTime,
-    pub(super) name_map: BTreeMap<&'a str, na::Isometry3<f64>>,
-}
+    pub fn pnp_method(&self) -> i32 {
+        self.pnp_method
+    }
 
-impl<'a> LocatedObjects<'a> {
-    pub fn new() -> Self {
-        Self {
-            timestamp: SystemTime::now(),
-            name_map: BTreeMap::new(),
-        }
+    /// Set the solvePnP method used to locate objects with only one detected tag. Set to `None` to use
+    /// `SOLVEPNP_IPPE_SQUARE` for square tags and `SOLVEPNP_IPPE` for rectangular tags.
+    ///
+    /// `SOLVEPNP_IPPE_SQUARE` only works with square tags. Single tags are always located without an
+    /// extrinsic guess.
+    pub fn set_single_tag_pnp_method(&mut self, method: Option<i32>) {
+        self.single_tag_pnp_method = method;
     }
 
-    pub fn timestamp(&self) -> SystemTime {
-        self.timestamp
+    pub fn single_tag_pnp_method(&self) -> Option<i32> {
+        self.single_tag_pnp_method
     }
 
-    pub fn name_map(&self) -> &BTreeMap<&'a str, na::Isometry3<f64>> {
-        &self.name_map
+    /// Set the solvePnP method used to locate planar objects (objects whose tags all lie on the same plane)
+    /// with more than one detected tag. If `None`, planar objects are located with the method set by
+    /// `set_pnp_method`.
+    ///
+    /// The default is `SOLVEPNP_IPPE`, which is faster and more stable than the iterative method on planar
+    /// points.
+    pub fn set_planar_pnp_method(&mut self, method: Option<i32>) {
+        self.planar_pnp_method = method;
     }
-}
 
-impl<'a> TaggedObjectLocator<'a> {
-    pub fn new(camera: CameraProperty) -> Self {
-        Self {
-            camera,
-            registry: Vec::new(),
-            tag_map: HashMap::new(),
-            last_location: Vec::new(),
-        }
+    pub fn planar_pnp_method(&self) -> Option<i32> {
+        self.planar_pnp_method
     }
 
-    pub fn camera(&self) -> &CameraProperty {
-        &self.camera
+    /// Set how the detected tags are weighted by their decision margins when locating objects. Tags with
+    /// low decision margins are more likely to have inaccurate corners.
+    ///
+    /// The default is `TagWeighting::Uniform`, which uses every detected tag once.
+    pub fn set_tag_weighting(&mut self, tag_weighting: TagWeighting) {
+        self.tag_weighting = tag_weighting;
     }
 
-    /// Add a new tagged object to the registry.
-    pub fn add(&mut self, tagobj: &'a TaggedObject) -> Result<(), ConflictingTagError> {
-        let this_name = &tagobj.name;
-        for (tag_index, _) in &tagobj.tags {
-            if let Some((registry_index, _)) = self.tag_map.get(tag_index) {
-                return Err(ConflictingTagError::new(
-                    *tag_index,
-                    unsafe { self.registry.get_unchecked(*registry_index).name.clone() },
-                    this_name.clone(),
-                ));
-            }
+    pub fn tag_weighting(&self) -> TagWeighting {
+        self.tag_weighting
+    }
+
+    /// Whether all tags of the object with the given name lie on the same plane, or `None` if no object
+    /// with the given name is registered.
+    pub fn is_planar(&self, name: &str) -> Option<bool> {
+        self.registry_index(name).map(|index| self.planar[index])
+    }
+
+    /// The solvePnP method used to locate the object at `object_index` with more than one detected tag.
+    fn multi_tag_pnp_method(&self, object_index: usize) -> i32 {
+        match self.planar_pnp_method {
+            Some(method) if self.planar[object_index] => method,
+            _ => self.pnp_method,
         }
-        let this_registry_index = self.registry.len();
-        self.registry.push(tagobj);
-        for (tag_index, tag_location) in &tagobj.tags {
-            // It is guaranteed that at this point, there's no conflict in tag indices
-            self.tag_map
-                .insert(*tag_index, (this_registry_index, tag_location.clone()));
+    }
+
+    /// The last time the tag is detected, or `None` if the tag is not registered or never detected.
+    pub fn tag_last_seen(&self, tag_index: &TagIndex) -> Option<Instant> {
+        self.tag_last_seen.get(tag_index).copied()
+    }
+
+    /// All registered tags that have never been detected since they were registered, sorted by family
+    /// and ID.
+    ///
+    /// Tags that are never seen after running for a while usually indicate a wrong ID mapping, a tag that
+    /// is not printed, or a tag that is always occluded.
+    pub fn never_seen_tags(&self) -> Vec<TagIndex> {
+        let mut tags = self
+            .tag_map
+            .keys()
+            .filter(|tag_index| !self.tag_last_seen.contains_key(tag_index))
+            .copied()
+            .collect::<Vec<_>>();
+        tags.sort_by_key(|tag_index| (tag_index.family as u8, tag_index.id));
+        tags
+    }
+
+    /// All tag families used by the registered objects, sorted and without duplicates.
+    pub fn required_families(&self) -> Vec<apriltag::ApriltagFamily> {
+        let mut families = self
+            .tag_map
+            .keys()
+            .map(|tag_index| tag_index.family)
+            .collect::<Vec<_>>();
+        families.sort_by_key(|family| *family as u8);
+        families.dedup();
+        families
+    }
+
+    /// Get the tag families used by the registered objects if they changed since the last call, so that the
+    /// detector can be updated with `ApriltagDetector::update_families`.
+    ///
+    /// The first call returns the families unless no object is registered.
+    pub fn poll_required_families(&mut self) -> Option<Vec<apriltag::ApriltagFamily>> {
+        let families = self.required_families();
+        if families == self.polled_families {
+            return None;
         }
-        self.last_location.push(None);
-        Ok(())
+        self.polled_families = families.clone();
+        Some(families)
+    }
+
+    /// Iterate over all registered objects' names and tags without cloning them.
+    pub fn objects(&self) -> impl Iterator<Item = (&'a str, &'a HashMap<TagIndex, TagLocation>)> {
+        self.registry
+            .iter()
+            .map(|&object| (object.name.as_str(), &object.tags))
     }
 
+    /// Clone all registered objects' names and tags into an owned map.
     pub fn get_object_map(&self) -> HashMap<String, Vec<(TagIndex, TagLocation)>> {
         self.registry
             .iter()
@@ -123,26 +1678,104 @@ impl<'a> TaggedObjectLocator<'a> {
             .collect()
     }
 
-    /// Locate a single tag with OpenCV's SOLVEPNP_IPPE_SQUARE method.
+    /// Calculate the reprojection error of each detected tag that belongs to the object `name`, given the
+    /// object's located pose.
+    ///
+    /// A tag with a much larger error than the others is likely misplaced in the tagobj file or wrongly
+    /// detected, and drags the object's pose off. Detections of other objects' tags are ignored. Returns
+    /// `None` if no object named `name` is registered.
+    pub fn tag_reprojection_error(
+        &self,
+        name: &str,
+        location: &na::Isometry3<f64>,
+        detections: &[apriltag::ApriltagDetection],
+    ) -> Result<Option<Vec<TagReprojectionError>>, Box<dyn std::error::Error>> {
+        let Some(registry_index) = self.registry_index(name) else {
+            return Ok(None);
+        };
+        let mut errors = Vec::new();
+        for detection in detections {
+            let tag = TagIndex::new(detection.family()?, detection.id());
+            let Some((_, tag_location)) = self.tag_map.get(&tag).and_then(|candidates| {
+                candidates
+                    .iter()
+                    .find(|(tag_registry_index, _)| *tag_registry_index == registry_index)
+            }) else {
+                continue;
+            };
+            let detected_center = detection.center();
+            let reprojected_center = reproject_tag_center(&self.camera, location, tag_location)?;
+            errors.push(TagReprojectionError {
+                tag,
+                detected_center,
+                reprojected_center,
+                error: (reprojected_center - detected_center).norm(),
+            });
+        }
+        Ok(Some(errors))
+    }
+
+    /// Estimate the direction of every object with a detected tag in `detections`, as the un-projected ray
+    /// through the average of its tags' detected centers, weighted by their decision margins.
+    ///
+    /// This is much cheaper than `locate_objects`, since no PnP problem is solved, and is meant as a coarse
+    /// pre-filter, e.g. to check whether an object is roughly where it is expected. The returned unit vector
+    /// only points roughly toward the object's detected tags rather than its origin, and neither the
+    /// object's distance nor its orientation is estimated. Detections of unregistered tags and detections
+    /// with non-positive decision margins are ignored. Tags shared by the variants in an exclusive group count
+    /// towards every variant, since the variants are not told apart without locating them.
+    pub fn quick_centroid(
+        &self,
+        detections: &[apriltag::ApriltagDetection],
+    ) -> Result<BTreeMap<&'a str, na::Vector3<f64>>, Box<dyn std::error::Error>> {
+        let mut weighted_centers: BTreeMap<usize, (na::Vector2<f64>, f64)> = BTreeMap::new();
+        for detection in detections {
+            let tag = TagIndex::new(detection.family()?, detection.id());
+            let weight = detection.decision_margin() as f64;
+            if let Some(candidates) = self.tag_map.get(&tag)
+                && weight > 0.0
+            {
+                for (registry_index, _) in candidates {
+                    let (center_sum, weight_sum) = weighted_centers
+                        .entry(*registry_index)
+                        .or_insert((na::Vector2::zeros(), 0.0));
+                    *center_sum += detection.center() * weight;
+                    *weight_sum += weight;
+                }
+            }
+        }
+        weighted_centers
+            .into_iter()
+            .map(|(registry_index, (center_sum, weight_sum))| {
+                Ok((
+                    self.registry[registry_index].name,
+                    self.camera.pixel_to_ray(center_sum / weight_sum)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Locate a single tag with OpenCV's SOLVEPNP_IPPE_SQUARE method, or SOLVEPNP_IPPE if the tag is
+    /// not square, unless another method is set with `set_single_tag_pnp_method`.
     ///
     /// # Arguments
     /// * `detection` - The detection of the tag to locate.
-    /// * `scale` - The scaling factor to multiply on the TAG_CORNERS array. This equals half of the tag's
-    ///             side length.
+    /// * `half_extents` - The scaling factors to multiply on the x and y coordinates of the TAG_CORNERS
+    ///                    array. These equal half of the tag's side lengths.
     ///
     /// # Returns
     /// The function returns the transformation of the tag from the camera's center.
     fn locate_tag(
         &self,
         detection: &apriltag::ApriltagDetection,
-        scale: f64,
+        half_extents: na::Vector2<f64>,
     ) -> Result<na::Isometry3<f64>, Box<dyn std::error::Error>> {
         let mut object_points_data = [0.0f64; 12]; // `detections.len()` (tags) * `4` (vertices / tag) * `3` (coordinates / vertex)
         let mut image_points_data = [0.0f64; 8];
         for (i, corner) in detection.corners().iter().enumerate() {
-            object_points_data[i * 3] = TAG_CORNERS[i].x * scale;
-            object_points_data[i * 3 + 1] = TAG_CORNERS[i].y * scale;
-            object_points_data[i * 3 + 2] = TAG_CORNERS[i].z * scale;
+            object_points_data[i * 3] = TAG_CORNERS[i].x * half_extents.x;
+            object_points_data[i * 3 + 1] = TAG_CORNERS[i].y * half_extents.y;
+            object_points_data[i * 3 + 2] = TAG_CORNERS[i].z;
             image_points_data[i * 2] = corner.x;
             image_points_data[i * 2 + 1] = corner.y;
         }
@@ -159,7 +1792,12 @@ impl<'a> TaggedObjectLocator<'a> {
             &mut rvec,
             &mut tvec,
             false,
-            calib3d::SOLVEPNP_IPPE_SQUARE,
+            self.single_tag_pnp_method
+                .unwrap_or(if half_extents.x == half_extents.y {
+                    calib3d::SOLVEPNP_IPPE_SQUARE
+                } else {
+                    calib3d::SOLVEPNP_IPPE
+                }),
         )?;
 
         let rvec = unsafe {
@@ -179,68 +1817,99 @@ impl<'a> TaggedObjectLocator<'a> {
         Ok(na::Isometry3::new(tvec, rvec))
     }
 
+    /// Get the rotation and translation vectors of the object's last location, if the object is not
+    /// forgotten at `timestamp`.
+    fn extrinsic_guess(
+        &self,
+        object_index: usize,
+        timestamp: Instant,
+    ) -> Result<Option<(Mat, Mat)>, Box<dyn std::error::Error>> {
+        if let Some((rvec, tvec, last_timestamp)) = &self.last_location[object_index]
+            && timestamp.saturating_duration_since(*last_timestamp)
+                <= self.configs[object_index].forget_duration
+        {
+            return Ok(Some((rvec.clone(), tvec.clone())));
+        }
+        Ok(None)
+    }
+
     /// Locate a single object based on the detected tag locations.
     ///
     /// # Arguments
     /// * `detections` - Stores a list of pairs of apriltag detections with their relative transformation
     ///                  from the object's center. This is created by filtering out the tags belonging to
     ///                  the object of interest from all tag detections in one frame.
-    /// * `object_index` - The object's index in the `registry` array. If `object_id` is `None`, then the
-    ///                    returned rotation and translation vectors won't be stored.
-    /// * `timestamp` - The timestamp when the object location occurs.
+    /// * `object_index` - The object's index in the registry.
+    /// * `extrinsic_guess` - The rotation and translation vectors of the object's last location, if any.
     ///
     /// # Returns
-    /// The function returns the transformation of the object's center in the camera's frame, or throw an
-    /// error.
+    /// The function returns the transformation of the object's center in the camera's frame, the
+    /// rotation and translation vectors to be used as the extrinsic guess in the next frame (`None` if
+    /// only one tag is detected), and the confidence of the location, or throw an error.
     fn locate_single_object<'b, 'c>(
-        &mut self,
+        &self,
+        object_index: usize,
         detections: &'b [(&'c apriltag::ApriltagDetection, TagLocation)],
-        object_index: Option<usize>,
-        timestamp: SystemTime,
-    ) -> Result<na::Isometry3<f64>, Box<dyn std::error::Error>> {
-        let mut rvec = Mat::default();
-        let mut tvec = Mat::default();
-        // load the object's last location
-        let mut use_extrinsic_guess = false;
-        if let Some(object_index) = object_index
-            && let Some(last_location) = &self.last_location[object_index]
-        {
-            if timestamp.duration_since(last_location.2)? <= OBJECT_FORGET_DURATION {
-                // The object is not forgotten. Load `rvec` and `tvec` from `last_location`.
-                rvec = last_location.0.clone();
-                tvec = last_location.1.clone();
-                use_extrinsic_guess = true;
-            }
-        }
+        extrinsic_guess: Option<(Mat, Mat)>,
+    ) -> Result<(na::Isometry3<f64>, Option<(Mat, Mat)>, f64), Box<dyn std::error::Error>> {
+        let pnp_method = self.multi_tag_pnp_method(object_index);
+        let refine_with_guess = supports_extrinsic_guess(pnp_method);
+        let extrinsic_guess = extrinsic_guess.filter(|_| refine_with_guess);
+        let mut use_extrinsic_guess = extrinsic_guess.is_some();
+        let (mut rvec, mut tvec) = extrinsic_guess.unwrap_or_default();
 
         if detections.len() == 1 {
             // Only one tag is present. Use `locate_tag` function to achieve better performance.
             let (detection, tag_to_object) = &detections[0];
-            let tag_to_cam = self.locate_tag(detection, tag_to_object.0.scaling())?;
+            let tag_to_cam = self.locate_tag(detection, tag_to_object.half_extents)?;
             let tag_to_object_iso = na::Isometry3::new(
-                tag_to_object.0.isometry.translation.vector,
-                tag_to_object.0.isometry.rotation.scaled_axis(),
+                tag_to_object.isometry.translation.vector,
+                tag_to_object.isometry.rotation.scaled_axis(),
             );
-            return Ok(tag_to_cam * tag_to_object_iso.inverse());
+            let location = tag_to_cam * tag_to_object_iso.inverse();
+            let confidence = self.object_confidence(&location, detections)?;
+            return Ok((location, None, confidence));
         }
 
         // More than 1 tag is present. Use `solve_pnp` in OpenCV.
         let mut object_points_data = Vec::<f64>::with_capacity(detections.len() * 12); // `detections.len()` (tags) * `4` (vertices / tag) * `3` (coordinates / vertex)
         let mut image_points_data = Vec::<f64>::with_capacity(detections.len() * 8);
         for (detection, tag_location) in detections {
-            for (i, corner) in detection.corners().iter().enumerate() {
-                let object_point = tag_location.0.transform_point(&TAG_CORNERS[i]);
-                object_points_data.push(object_point.x);
-                object_points_data.push(object_point.y);
-                object_points_data.push(object_point.z);
-                image_points_data.push(corner.x);
-                image_points_data.push(corner.y);
+            // repeating a tag's corners weights them more in the solve
+            for _ in 0..self.tag_weighting.repeats(detection.decision_margin()) {
+                for (i, corner) in detection.corners().iter().enumerate() {
+                    // scaled by the tag's own size, so tags of different sizes can be mixed
+                    let object_point = tag_location.transform_point(&TAG_CORNERS[i]);
+                    object_points_data.push(object_point.x);
+                    object_points_data.push(object_point.y);
+                    object_points_data.push(object_point.z);
+                    image_points_data.push(corner.x);
+                    image_points_data.push(corner.y);
+                }
             }
         }
-        let points_cnt = (detections.len() * 4) as i32;
+        let points_cnt = (image_points_data.len() / 2) as i32;
         let object_points = Mat::new_rows_cols_with_data(points_cnt, 3, &object_points_data)?;
         let image_points = Mat::new_rows_cols_with_data(points_cnt, 2, &image_points_data)?;
 
+        if refine_with_guess
+            && !use_extrinsic_guess
+            && let Some(initial_pnp_method) = self.initial_pnp_method
+        {
+            // No extrinsic guess available. Get a global initialization first, since the iterative
+            // method may converge to a local minimum far from the true location.
+            use_extrinsic_guess = calib3d::solve_pnp(
+                &object_points,
+                &image_points,
+                &self.camera.camera_mat,
+                &self.camera.distortion,
+                &mut rvec,
+                &mut tvec,
+                false,
+                initial_pnp_method,
+            )?;
+        }
+
         calib3d::solve_pnp(
             &object_points,
             &image_points,
@@ -249,8 +1918,26 @@ impl<'a> TaggedObjectLocator<'a> {
             &mut rvec,
             &mut tvec,
             use_extrinsic_guess,
-            calib3d::SOLVEPNP_ITERATIVE,
+            pnp_method,
         )?;
+        if let Some(iterations) = self.cold_start_iterations
+            && self.tracking[object_index].state != TrackingState::Tracking
+        {
+            // The pose may not have converged without a good extrinsic guess
+            calib3d::solve_pnp_refine_lm(
+                &object_points,
+                &image_points,
+                &self.camera.camera_mat,
+                &self.camera.distortion,
+                &mut rvec,
+                &mut tvec,
+                opencv::core::TermCriteria::new(
+                    opencv::core::TermCriteria_COUNT | opencv::core::TermCriteria_EPS,
+                    iterations,
+                    f64::EPSILON,
+                )?,
+            )?;
+        }
 
         // TODO: invert xyz and rotation here, since solvePnP always returns location on the +z plane.
 
@@ -269,52 +1956,684 @@ impl<'a> TaggedObjectLocator<'a> {
             )
         };
 
-        if let Some(object_index) = object_index {
-            // write the rvec and tvec to the object's last location
-            self.last_location[object_index] = Some((rvec, tvec, timestamp));
+        let location = na::Isometry3::new(tvec_na, rvec_na);
+        let confidence = self.object_confidence(&location, detections)?;
+        Ok((location, Some((rvec, tvec)), confidence))
+    }
+
+    /// The confidence of an object located at `location` from `detections`, blended by `pose_confidence`
+    /// from the RMS reprojection error of the tag corners, the number of tags, and their mean decision
+    /// margin.
+    ///
+    /// `detections` has the same format as function `locate_single_object`.
+    fn object_confidence(
+        &self,
+        location: &na::Isometry3<f64>,
+        detections: &[(&apriltag::ApriltagDetection, TagLocation)],
+    ) -> Result<f64, Box<dyn std::error::Error>> {
+        let rms_error = self.rms_reprojection_error(location, detections)?;
+        let mean_margin = detections
+            .iter()
+            .map(|(detection, _)| detection.decision_margin() as f64)
+            .sum::<f64>()
+            / detections.len() as f64;
+        Ok(pose_confidence(rms_error, detections.len(), mean_margin))
+    }
+
+    /// The RMS distance (in pixels) between the detected tag corners and their projections, with the object
+    /// located at `location`.
+    ///
+    /// `detections` has the same format as function `locate_single_object`.
+    fn rms_reprojection_error(
+        &self,
+        location: &na::Isometry3<f64>,
+        detections: &[(&apriltag::ApriltagDetection, TagLocation)],
+    ) -> Result<f64, Box<dyn std::error::Error>> {
+        let mut squared_error = 0.0;
+        for (detection, tag_location) in detections {
+            let corners = TAG_CORNERS[..4]
+                .iter()
+                .map(|corner| location.transform_point(&tag_location.transform_point(corner)))
+                .collect::<Vec<_>>();
+            let projected = self.camera.project_points(&corners)?;
+            for (projected, corner) in projected.iter().zip(detection.corners()) {
+                squared_error += (projected - corner).norm_squared();
+            }
+        }
+        Ok((squared_error / (detections.len() * 4) as f64).sqrt())
+    }
+
+    /// Locate a single object and check whether its location is valid and continuous with its last
+    /// location.
+    ///
+    /// The error is converted into a string, so that this function can run on other threads.
+    fn solve_object<'b, 'c>(
+        &self,
+        object_index: usize,
+        detections: &'b [(&'c apriltag::ApriltagDetection, TagLocation)],
+        extrinsic_guess: Option<(Mat, Mat)>,
+        timestamp: Instant,
+    ) -> Result<
+        (
+            na::Isometry3<f64>,
+            Option<(Mat, Mat)>,
+            f64,
+            PoseCheck,
+            Vec<TagIndex>,
+        ),
+        String,
+    > {
+        let mut detections = detections.to_vec();
+        let mut inconsistent_tags = Vec::new();
+        let (location, vectors, confidence) = loop {
+            let (location, vectors, confidence) = self
+                .locate_single_object(object_index, &detections, extrinsic_guess.clone())
+                .map_err(|e| e.to_string())?;
+            match self
+                .most_inconsistent_tag(object_index, &location, &detections)
+                .map_err(|e| e.to_string())?
+            {
+                Some(tag) => {
+                    let (detection, _) = detections.remove(tag);
+                    inconsistent_tags.push(TagIndex::new(
+                        detection.family().map_err(|e| e.to_string())?,
+                        detection.id(),
+                    ));
+                }
+                None => break (location, vectors, confidence),
+            }
+        };
+        let detections = detections.as_slice();
+        let check = if !self
+            .is_pose_valid(&location, detections)
+            .map_err(|e| e.to_string())?
+        {
+            PoseCheck::Invalid
+        } else if self
+            .is_pose_flipped(object_index, &location, detections, timestamp)
+            .map_err(|e| e.to_string())?
+        {
+            PoseCheck::Flipped
+        } else {
+            PoseCheck::Valid
+        };
+        Ok((location, vectors, confidence, check, inconsistent_tags))
+    }
+
+    /// Pick the variant that best fits its detected tags in each exclusive group with more than one solved
+    /// variant, and return the registry indices of the other variants.
+    ///
+    /// Valid locations are preferred, then the variants with more detected tags, since a variant matching a
+    /// single tag fits it trivially, and then the variant with the lowest RMS reprojection error over all of
+    /// its detected tags, including the ones dropped as inconsistent. `solutions` are in the same order as
+    /// `tag_classification`.
+    fn reject_variants(
+        &self,
+        tag_classification: &BTreeMap<usize, Vec<(&apriltag::ApriltagDetection, TagLocation)>>,
+        solutions: &[(
+            na::Isometry3<f64>,
+            Option<(Mat, Mat)>,
+            f64,
+            PoseCheck,
+            Vec<TagIndex>,
+        )],
+    ) -> Result<HashSet<usize>, Box<dyn std::error::Error>> {
+        let mut best_variants: HashMap<&str, (usize, bool, usize, f64)> = HashMap::new();
+        let mut rejected = HashSet::new();
+        for ((registry_index, detections), (location, _, _, check, _)) in
+            tag_classification.iter().zip(solutions)
+        {
+            let Some(group) = self.configs[*registry_index].exclusive_group.as_deref() else {
+                continue;
+            };
+            let variant = (
+                *registry_index,
+                *check == PoseCheck::Valid,
+                detections.len(),
+                self.rms_reprojection_error(location, detections)?,
+            );
+            let Some(best) = best_variants.get_mut(group) else {
+                best_variants.insert(group, variant);
+                continue;
+            };
+            let is_better = (variant.1, variant.2)
+                .cmp(&(best.1, best.2))
+                .then_with(|| best.3.total_cmp(&variant.3))
+                .is_gt();
+            if is_better {
+                rejected.insert(best.0);
+                *best = variant;
+            } else {
+                rejected.insert(variant.0);
+            }
+        }
+        Ok(rejected)
+    }
+
+    /// Find the detected tag whose center is farthest from its center reprojected with `location`, if the
+    /// distance is larger than the consistency threshold and the object has more than its minimum number
+    /// of tags.
+    ///
+    /// `detections` has the same format as function `locate_single_object`.
+    fn most_inconsistent_tag(
+        &self,
+        object_index: usize,
+        location: &na::Isometry3<f64>,
+        detections: &[(&apriltag::ApriltagDetection, TagLocation)],
+    ) -> Result<Option<usize>, Box<dyn std::error::Error>> {
+        let Some(threshold) = self.consistency_threshold else {
+            return Ok(None);
+        };
+        if detections.len() <= self.configs[object_index].min_tags.max(1) {
+            return Ok(None);
+        }
+        let errors = detections
+            .iter()
+            .map(|(detection, tag_location)| {
+                Ok((reproject_tag_center(&self.camera, location, tag_location)?
+                    - detection.center())
+                .norm())
+            })
+            .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+        Ok(errors
+            .into_iter()
+            .enumerate()
+            .filter(|(_, error)| *error > threshold)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(index, _)| index))
+    }
+
+    /// Replace the rotation of the object at `location`, which is located from the single tag at
+    /// `tag_location`, with its last rotation located from multiple tags, moving the object such that the
+    /// tag's center stays in place.
+    ///
+    /// Returns `None` if the single tag hold is disabled, or the object has no multi-tag rotation within its
+    /// forget duration.
+    fn hold_rotation(
+        &self,
+        object_index: usize,
+        location: &na::Isometry3<f64>,
+        tag_location: &TagLocation,
+        timestamp: Instant,
+    ) -> Option<na::Isometry3<f64>> {
+        if !self.single_tag_hold {
+            return None;
+        }
+        let (rotation, last_timestamp) = self.last_multi_tag_rotation[object_index]?;
+        if timestamp.saturating_duration_since(last_timestamp)
+            > self.configs[object_index].forget_duration
+        {
+            return None;
+        }
+        // The tag's center is well determined by a single tag, unlike its rotation
+        let tag_center = tag_location.transform_point(&na::Point3::origin());
+        let camera_tag_center = location.transform_point(&tag_center);
+        let translation = camera_tag_center.coords - rotation * tag_center.coords;
+        Some(na::Isometry3::from_parts(translation.into(), rotation))
+    }
+
+    /// Check whether the object's rotation at `location` jumps away from its last located rotation by more
+    /// than the flip threshold, without lowering the reprojection error by at least `FLIP_MIN_ERROR_GAIN`.
+    ///
+    /// `detections` has the same format as function `locate_single_object`.
+    fn is_pose_flipped(
+        &self,
+        object_index: usize,
+        location: &na::Isometry3<f64>,
+        detections: &[(&apriltag::ApriltagDetection, TagLocation)],
+        timestamp: Instant,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let Some(threshold) = self.flip_threshold else {
+            return Ok(false);
+        };
+        let Some((last_location, last_timestamp)) = self.last_pose[object_index] else {
+            return Ok(false);
+        };
+        if timestamp.saturating_duration_since(last_timestamp)
+            > self.configs[object_index].forget_duration
+            || location.rotation.angle_to(&last_location.rotation) <= threshold
+        {
+            return Ok(false);
+        }
+        let error = self.rms_reprojection_error(location, detections)?;
+        let last_error = self.rms_reprojection_error(&last_location, detections)?;
+        Ok(last_error - error < FLIP_MIN_ERROR_GAIN)
+    }
+
+    /// Check whether the located object is in front of the camera, and at least one of its detected
+    /// tags' corners is projected inside the image.
+    ///
+    /// `detections` has the same format as function `locate_single_object`.
+    fn is_pose_valid(
+        &self,
+        location: &na::Isometry3<f64>,
+        detections: &[(&apriltag::ApriltagDetection, TagLocation)],
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        if location.translation.z <= 0.0 {
+            return Ok(false);
         }
+        let width = self.camera.resolution.0 as f64;
+        let height = self.camera.resolution.1 as f64;
+        // only the corners in front of the camera are projected
+        let corners = detections
+            .iter()
+            .flat_map(|(_, tag_location)| {
+                TAG_CORNERS[..4]
+                    .iter()
+                    .map(|corner| location.transform_point(&tag_location.transform_point(corner)))
+            })
+            .filter(|point| point.z > 0.0)
+            .collect::<Vec<_>>();
+        if corners.is_empty() {
+            return Ok(false);
+        }
+        let is_visible = self
+            .camera
+            .project_points(&corners)?
+            .iter()
+            .any(|projected| {
+                (0.0..width).contains(&projected.x) && (0.0..height).contains(&projected.y)
+            });
+        Ok(is_visible)
+    }
 
-        Ok(na::Isometry3::new(tvec_na, rvec_na))
+    /// Remember the last detection of each registered tag in `detections`, and return the remembered
+    /// detections to carry forward into this frame. See `set_tag_persistence`.
+    fn persist_tags(
+        &mut self,
+        detections: &[apriltag::ApriltagDetection],
+    ) -> Result<Vec<(TagIndex, apriltag::ApriltagDetection)>, Box<dyn std::error::Error>> {
+        if self.tag_persistence == 0 {
+            self.persisted_tags.clear();
+            return Ok(Vec::new());
+        }
+        let mut detected_tags = HashSet::new();
+        let mut detected_objects = HashSet::new();
+        for detection in detections {
+            let tag_index = TagIndex::new(detection.family()?, detection.id());
+            if let Some(candidates) = self.tag_map.get(&tag_index) {
+                detected_tags.insert(tag_index);
+                detected_objects
+                    .extend(candidates.iter().map(|(registry_index, _)| *registry_index));
+                self.persisted_tags.insert(
+                    tag_index,
+                    PersistedTag {
+                        corners: detection.corners(),
+                        center: detection.center(),
+                        decision_margin: detection.decision_margin(),
+                        missed_frames: 0,
+                    },
+                );
+            }
+        }
+        // Forget the tags missed for too long, and the tags of removed objects
+        let tag_map = &self.tag_map;
+        let tag_persistence = self.tag_persistence;
+        self.persisted_tags.retain(|tag_index, tag| {
+            if !detected_tags.contains(tag_index) {
+                tag.missed_frames += 1;
+            }
+            tag.missed_frames <= tag_persistence && tag_map.contains_key(tag_index)
+        });
+        // Only carry the tags whose objects have other tags detected in this frame
+        Ok(self
+            .persisted_tags
+            .iter()
+            .filter(|(tag_index, tag)| {
+                tag.missed_frames > 0
+                    && self.tag_map[*tag_index]
+                        .iter()
+                        .any(|(registry_index, _)| detected_objects.contains(registry_index))
+            })
+            .map(|(tag_index, tag)| {
+                let detection = apriltag::ApriltagDetection::from_parts(
+                    tag_index.family,
+                    tag_index.id,
+                    tag.corners,
+                    tag.center,
+                )
+                .with_decision_margin(tag.decision_margin);
+                (*tag_index, detection)
+            })
+            .collect())
     }
 
     /// Locate every object registered in this tagged object locator, then store the results in a
     /// shared mapping from each object's name to their transformation from the camera's frame.
-    pub fn locate_objects<'b>(
+    ///
+    /// `timestamp` is the time at which the frame containing `detections` is captured.
+    pub fn locate_objects(
         &mut self,
-        timestamp: SystemTime,
-        detections: &'b [apriltag::ApriltagDetection],
+        timestamp: FrameTimestamp,
+        detections: &[apriltag::ApriltagDetection],
         result: Arc<(Mutex<LocatedObjects<'a>>, Condvar)>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        // Classify each tag into their respective object
+        let located = self.locate_objects_owned(timestamp, detections)?;
+        // Only lock the result dictionary to swap in the new results
+        *result.0.lock().unwrap() = located;
+        // signal all other threads waiting on this conditional variable
+        result.1.notify_all();
+        Ok(())
+    }
+
+    /// Locate every object registered in this tagged object locator, and return the located objects
+    /// directly instead of storing them in a shared mapping.
+    ///
+    /// This is the synchronous counterpart of `locate_objects`, for callers that do not run the locator in
+    /// its own thread, e.g. ones locating objects from detections that are not made on a camera's frames.
+    pub fn locate_objects_owned(
+        &mut self,
+        timestamp: FrameTimestamp,
+        detections: &[apriltag::ApriltagDetection],
+    ) -> Result<LocatedObjects<'a>, Box<dyn std::error::Error>> {
+        let snapshots = if self.record_detections {
+            Some(
+                detections
+                    .iter()
+                    .map(apriltag::ApriltagDetection::snapshot)
+                    .collect::<Result<Vec<_>, _>>()?,
+            )
+        } else {
+            None
+        };
+        let persisted = self.persist_tags(detections)?;
+
+        // Classify each tag into their respective objects. A tag shared by the variants in an exclusive group
+        // is classified into every variant, and the variants are told apart after solving them.
         let mut tag_classification: BTreeMap<
             usize,
-            Vec<(&'b apriltag::ApriltagDetection, TagLocation)>,
+            Vec<(&apriltag::ApriltagDetection, TagLocation)>,
         > = BTreeMap::new();
         for detection in detections {
             let tag_index = TagIndex::new(detection.family()?, detection.id());
-            if let Some((registry_index, location)) = self.tag_map.get(&tag_index) {
-                tag_classification
-                    .entry(*registry_index)
-                    .or_default()
-                    .push((detection, location.clone()));
+            if let Some(candidates) = self.tag_map.get(&tag_index) {
+                self.tag_last_seen.insert(tag_index, timestamp.instant);
+                if self.tag_weighting.repeats(detection.decision_margin()) == 0 {
+                    // The tag is too unreliable to locate its object
+                    continue;
+                }
+                for (registry_index, location) in candidates {
+                    tag_classification
+                        .entry(*registry_index)
+                        .or_default()
+                        .push((detection, location.clone()));
+                }
+            }
+        }
+        for (tag_index, detection) in &persisted {
+            if let Some(candidates) = self.tag_map.get(tag_index)
+                && self.tag_weighting.repeats(detection.decision_margin()) > 0
+            {
+                for (registry_index, location) in candidates {
+                    tag_classification
+                        .entry(*registry_index)
+                        .or_default()
+                        .push((detection, location.clone()));
+                }
             }
         }
+        // Objects with too few detected tags are treated as not detected
+        tag_classification.retain(|registry_index, detections| {
+            detections.len() >= self.configs[*registry_index].min_tags
+        });
+        let deferred = self.defer_objects(&mut tag_classification);
+        for registry_index in tag_classification.keys() {
+            self.last_solved[*registry_index] = Some(timestamp.instant);
+        }
 
-        // Lock the result dictionary and write the location results
-        let mut locked_result = result.0.lock().unwrap();
-        locked_result.timestamp = timestamp;
-        locked_result.name_map.clear();
-        for (registry_index, detections) in tag_classification {
-            let name = self.registry[registry_index].name.as_str();
-            locked_result.name_map.insert(
-                name,
-                self.locate_single_object(&detections, Some(registry_index), timestamp)?,
+        // Solve all objects' locations before locking the result dictionary, so that the lock is only held
+        // for a short time. Objects are solved in parallel on rayon's thread pool, which is bounded by the
+        // number of CPUs.
+        let extrinsic_guesses = tag_classification
+            .keys()
+            .map(|registry_index| self.extrinsic_guess(*registry_index, timestamp.instant))
+            .collect::<Result<Vec<_>, _>>()?;
+        let this = &*self;
+        let solutions = tag_classification
+            .iter()
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .zip(extrinsic_guesses)
+            .map(|((registry_index, detections), extrinsic_guess)| {
+                this.solve_object(
+                    *registry_index,
+                    detections,
+                    extrinsic_guess,
+                    timestamp.instant,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let rejected_variants = self.reject_variants(&tag_classification, &solutions)?;
+
+        // Collect the results locally, so that consumers never observe a partially written frame
+        let mut located = LocatedObjects {
+            timestamp,
+            name_map: BTreeMap::new(),
+            lost_map: BTreeMap::new(),
+            extrapolated: BTreeSet::new(),
+            degraded: BTreeSet::new(),
+            stale: BTreeSet::new(),
+            detections: snapshots,
+            tracking: BTreeMap::new(),
+            confidence: BTreeMap::new(),
+            persisted_tags: persisted.iter().map(|(tag_index, _)| *tag_index).collect(),
+            inconsistent_tags: HashSet::new(),
+            velocity: BTreeMap::new(),
+            covariance: BTreeMap::new(),
+        };
+        for (
+            (registry_index, detections),
+            (location, vectors, confidence, check, inconsistent_tags),
+        ) in tag_classification.iter().zip(solutions)
+        {
+            if rejected_variants.contains(registry_index) {
+                // The variant is treated as not detected in this frame
+                continue;
+            }
+            let name = self.registry[*registry_index].name.as_str();
+            let used_tags = detections.len() - inconsistent_tags.len();
+            if !inconsistent_tags.is_empty() {
+                log::debug!(
+                    "Dropped tags {:?} of object \"{}\", since they are not consistent with its other tags.",
+                    inconsistent_tags,
+                    name
+                );
+                located
+                    .inconsistent_tags
+                    .extend(inconsistent_tags.iter().copied());
+            }
+            if check == PoseCheck::Flipped {
+                log::warn!(
+                    "Rejected the location of object \"{}\", since its rotation jumps away from its last location.",
+                    name
+                );
+                // Keep the last location as the extrinsic guess, so that the next frame is solved from the
+                // last rotation
+                if let Some((last_location, _)) = self.last_pose[*registry_index] {
+                    located.name_map.insert(name, last_location);
+                }
+                continue;
+            }
+            if let Some((rvec, tvec)) = vectors {
+                // write the rvec and tvec to the object's last location
+                self.last_location[*registry_index] = Some((rvec, tvec, timestamp.instant));
+            }
+            if check == PoseCheck::Valid {
+                let location = match &detections[..] {
+                    [(_, tag_location)] => {
+                        match self.hold_rotation(
+                            *registry_index,
+                            &location,
+                            tag_location,
+                            timestamp.instant,
+                        ) {
+                            Some(held) => {
+                                located.degraded.insert(name);
+                                held
+                            }
+                            None => location,
+                        }
+                    }
+                    _ => {
+                        if used_tags >= 2 {
+                            self.last_multi_tag_rotation[*registry_index] =
+                                Some((location.rotation, timestamp.instant));
+                        }
+                        location
+                    }
+                };
+                // the rotation held from multiple tags is not refined with a single tag's corners
+                let location = if located.degraded.contains(name) {
+                    location
+                } else {
+                    self.adjust_static_pose(
+                        *registry_index,
+                        location,
+                        detections,
+                        &inconsistent_tags,
+                        timestamp.instant,
+                    )?
+                };
+                if let Some(covariance) = self.fuse_covariance(
+                    *registry_index,
+                    &location,
+                    detections,
+                    &inconsistent_tags,
+                    timestamp.instant,
+                )? {
+                    located.covariance.insert(name, covariance);
+                }
+                located.name_map.insert(name, location);
+                located.confidence.insert(name, confidence);
+                self.update_tracking(*registry_index, &location, timestamp.instant);
+                self.velocity[*registry_index] =
+                    self.last_pose[*registry_index].and_then(|(last_location, last_timestamp)| {
+                        // frames without usable capture timestamps may be stamped at the same instant, in
+                        // which case one nominal frame period has passed between them
+                        let elapsed =
+                            Some(timestamp.instant.saturating_duration_since(last_timestamp))
+                                .filter(|elapsed| !elapsed.is_zero())
+                                .or(self.camera.frame_period())?;
+                        estimate_velocity(&last_location, &location, elapsed)
+                    });
+                self.last_pose[*registry_index] = Some((location, timestamp.instant));
+                continue;
+            }
+            log::warn!(
+                "Rejected the location of object \"{}\", since it is behind the camera or outside the image.",
+                name
             );
+            // The rejected location must not be used as the extrinsic guess in the next frame
+            self.last_location[*registry_index] = None;
+            if self.tracking[*registry_index].state == TrackingState::Initializing {
+                self.tracking[*registry_index].consistent_frames = 0;
+            }
+            if let Some((last_location, last_timestamp)) = self.last_pose[*registry_index]
+                && timestamp.instant.saturating_duration_since(last_timestamp)
+                    <= self.configs[*registry_index].forget_duration
+            {
+                // Fall back to the last known good location
+                located.name_map.insert(name, last_location);
+            }
         }
-        drop(locked_result);
-        // signal all other threads waiting on this conditional variable
-        result.1.notify_all();
-        Ok(())
+        // Publish the last poses of the objects deferred by the solve budget. They are detected in this frame,
+        // so they are not forgotten however long ago they were last solved.
+        for registry_index in deferred {
+            if let Some((last_location, _)) = self.last_pose[registry_index] {
+                let name = self.registry[registry_index].name.as_str();
+                located.name_map.insert(name, last_location);
+                located.stale.insert(name);
+            }
+        }
+        // Extrapolate the objects that are not located in this frame from their last poses
+        if self.extrapolation {
+            for registry_index in 0..self.registry.len() {
+                let name = self.registry[registry_index].name.as_str();
+                if located.name_map.contains_key(name) {
+                    continue;
+                }
+                if let Some((last_location, last_timestamp)) = self.last_pose[registry_index]
+                    && let Some((linear, angular)) = self.velocity[registry_index]
+                {
+                    let elapsed = timestamp.instant.saturating_duration_since(last_timestamp);
+                    if elapsed <= self.configs[registry_index].forget_duration {
+                        located.name_map.insert(
+                            name,
+                            extrapolate_pose(&last_location, &linear, &angular, elapsed),
+                        );
+                        located.extrapolated.insert(name);
+                    }
+                }
+            }
+        }
+        // Report the objects that have not been located for too long as lost
+        for registry_index in 0..self.registry.len() {
+            if located
+                .name_map
+                .contains_key(self.registry[registry_index].name.as_str())
+            {
+                continue;
+            }
+            if let Some((location, last_timestamp)) = self.last_pose[registry_index]
+                && timestamp.instant.saturating_duration_since(last_timestamp)
+                    > self.configs[registry_index].forget_duration
+            {
+                let name = self.registry[registry_index].name.as_str();
+                located.lost_map.insert(name, location);
+                self.last_pose[registry_index] = None;
+                self.velocity[registry_index] = None;
+                self.tracking[registry_index] = TrackingProgress::default();
+                self.static_frames[registry_index].clear();
+                self.fused_information[registry_index] = None;
+            }
+        }
+        for (registry_index, object) in self.registry.iter().enumerate() {
+            let name = object.name.as_str();
+            if located.name_map.contains_key(name) {
+                located
+                    .tracking
+                    .insert(name, self.tracking[registry_index].state);
+            }
+        }
+        // Only the poses located or extrapolated in this frame move with the object's velocity, unlike the
+        // last known poses published in place of rejected locations
+        for (registry_index, object) in self.registry.iter().enumerate() {
+            let name = object.name.as_str();
+            if (located.confidence.contains_key(name) || located.extrapolated.contains(name))
+                && let Some(velocity) = self.velocity[registry_index]
+            {
+                located.velocity.insert(name, velocity);
+            }
+        }
+        // Publish the poses relative to the ground plane. The poses stored in the locator stay relative to
+        // the camera.
+        if let Some(ground_plane) = self.ground_plane {
+            let to_ground = ground_plane.inverse();
+            // the covariances depend on the poses relative to the camera, so they are converted first
+            for (name, covariance) in located.covariance.iter_mut() {
+                if let Some(location) = located.name_map.get(name) {
+                    *covariance = covariance.rotated(&to_ground.rotation, location);
+                }
+            }
+            for location in located
+                .name_map
+                .values_mut()
+                .chain(located.lost_map.values_mut())
+            {
+                *location = to_ground * *location;
+            }
+            for (linear, angular) in located.velocity.values_mut() {
+                *linear = to_ground.rotation * *linear;
+                *angular = to_ground.rotation * *angular;
+            }
+        }
+        if log::log_enabled!(target: POSE_LOG_TARGET, log::Level::Debug)
+            && self.should_log_poses(timestamp.instant)
+        {
+            log::debug!(target: POSE_LOG_TARGET, "{}", located.summary());
+        }
+        Ok(located)
     }
 
     /// Calculate the Jacobian matrix of the projection mapping.
@@ -344,12 +2663,12 @@ impl<'a> TaggedObjectLocator<'a> {
     ) -> Result<na::MatrixXx6<f64>, Box<dyn std::error::Error>> {
         let n = detections.clone().count();
         let mut ans = na::MatrixXx6::<f64>::zeros(8 * n);
+        let rotation = location.rotation.to_rotation_matrix();
         for (i, tag_loc) in detections.enumerate() {
-            let rotation = tag_loc.0.isometry.rotation;
             for (j, corner) in TAG_CORNERS.iter().take(4).enumerate() {
                 let index = i * 4 + j;
                 let u_index = index * 2;
-                let local_position = tag_loc.0.transform_point(&corner);
+                let local_position = tag_loc.transform_point(&corner);
                 let a = camera_mat * location.transform_point(&local_position);
                 let d_mat = na::Matrix2x3::<f64>::new(
                     1.0 / a.z,
@@ -370,6 +2689,73 @@ impl<'a> TaggedObjectLocator<'a> {
         Ok(ans)
     }
 
+    /// Refine the pose of an object from `initial` with the Gauss-Newton method, minimizing the squared
+    /// distance between the detected tag corners and their projections, without calling OpenCV.
+    ///
+    /// `detections` has the same format as function `locate_single_object`. The corners are used as they
+    /// are detected, so the camera's lens distortion is ignored like in `calculate_covariance`.
+    pub fn refine_pose(
+        &self,
+        initial: na::Isometry3<f64>,
+        detections: &[(&apriltag::ApriltagDetection, TagLocation)],
+    ) -> Result<na::Isometry3<f64>, Box<dyn std::error::Error>> {
+        let frame = detections
+            .iter()
+            .map(|(detection, tag_location)| (tag_location.clone(), detection.corners()))
+            .collect::<WindowFrame>();
+        Self::bundle_adjust(self.camera.camera_mat_na()?, &[frame], initial)
+    }
+
+    /// Refine the pose of an object from `initial` with the Gauss-Newton method, minimizing the total squared
+    /// distance between the tag corners in all `frames` and their projections.
+    ///
+    /// Each frame lists the locations of the tags detected in it and their corners, with the lens distortion
+    /// removed. The object is assumed to be at the same pose in all frames.
+    fn bundle_adjust(
+        camera_mat: na::Matrix3<f64>,
+        frames: &[WindowFrame],
+        initial: na::Isometry3<f64>,
+    ) -> Result<na::Isometry3<f64>, Box<dyn std::error::Error>> {
+        let mut location = initial;
+        for _ in 0..BUNDLE_ADJUSTMENT_ITERATIONS {
+            // accumulate the normal equations `J^T J * step = J^T r` of all frames
+            let mut normal = na::Matrix6::<f64>::zeros();
+            let mut gradient = na::Vector6::<f64>::zeros();
+            for frame in frames {
+                let jacobian = Self::calculate_projection_jacobian(
+                    camera_mat,
+                    frame.iter().map(|(tag_location, _)| tag_location.clone()),
+                    location,
+                )?;
+                let mut residuals = na::DVector::<f64>::zeros(jacobian.nrows());
+                for (i, (tag_location, corners)) in frame.iter().enumerate() {
+                    for (j, corner) in corners.iter().enumerate() {
+                        let projected = camera_mat
+                            * location
+                                .transform_point(&tag_location.transform_point(&TAG_CORNERS[j]));
+                        residuals
+                            .fixed_rows_mut::<2>((i * 4 + j) * 2)
+                            .copy_from(&(corner - projected.coords.xy() / projected.z));
+                    }
+                }
+                normal += jacobian.transpose() * &jacobian;
+                gradient += jacobian.transpose() * residuals;
+            }
+            let step = normal
+                .try_inverse()
+                .ok_or("(J^T * J) does not have an inverse matrix!")?
+                * gradient;
+            location = na::Isometry3::new(
+                location.translation.vector + step.fixed_rows::<3>(0),
+                location.rotation.scaled_axis() + step.fixed_rows::<3>(3),
+            );
+            if step.norm() < BUNDLE_ADJUSTMENT_TOLERANCE {
+                break;
+            }
+        }
+        Ok(location)
+    }
+
     /// Calculate the covariance matrix of the detection result.
     ///
     /// This function returns a symmetric 3x3 matrix $C_{ij}$, where $C_{xx}$ is the variance (or
@@ -379,24 +2765,38 @@ impl<'a> TaggedObjectLocator<'a> {
     /// The function takes in a pair of numbers `detection_variance`, representing the x and y variance of
     /// each detected corner. This function assumes that each corner's measured coordinate is independent
     /// and identically distributed, with no correlation between the x and y components.
+    ///
+    /// If the condition number of $J^T J$ is above `COVARIANCE_MAX_CONDITION_NUMBER`, the covariance is not
+    /// computed and `PoseCovariance::Degenerate` is returned instead, since the corners do not constrain
+    /// the pose in some direction.
     pub fn calculate_covariance<D: Iterator<Item = TagLocation> + Clone>(
         camera_mat: na::Matrix3<f64>,
         detections: D,
         location: na::Isometry3<f64>,
         detection_variance: (f64, f64),
-    ) -> Result<na::Matrix6<f64>, Box<dyn std::error::Error>> {
+    ) -> Result<PoseCovariance, Box<dyn std::error::Error>> {
         let jacobian = Self::calculate_projection_jacobian(camera_mat, detections, location)?;
+        let normal = jacobian.transpose() * jacobian.clone();
+        // `J^T J` is symmetric and positive semi-definite, so its eigenvalues are its singular values
+        let eigenvalues = normal.symmetric_eigenvalues();
+        let condition_number = if eigenvalues.min() > 0.0 {
+            eigenvalues.max() / eigenvalues.min()
+        } else {
+            f64::INFINITY
+        };
+        if condition_number > COVARIANCE_MAX_CONDITION_NUMBER {
+            return Ok(PoseCovariance::Degenerate { condition_number });
+        }
         let iter = [detection_variance.0, detection_variance.1]
             .into_iter()
             .cycle();
         let mut y = na::DMatrix::zeros(jacobian.nrows(), jacobian.nrows());
         y.set_partial_diagonal(iter); // fills the diagonal of Y matrix with [vx, vy, vx, vy, ...]
-        let a = jacobian.transpose() * y * jacobian.clone();
-        let b = (jacobian.transpose() * jacobian)
+        let a = jacobian.transpose() * y * jacobian;
+        let b = normal
             .try_inverse()
             .ok_or("(J^T * J) does not have an inverse matrix!")?;
-        Ok(b * a * b)
-        // TODO: this function is not tested. Write a test for this function.
+        Ok(PoseCovariance::Finite(b * a * b))
     }
 }
 
@@ -1,12 +1,11 @@
 extern crate nalgebra as na;
 
-use std::ops::DerefMut;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
 
 use rand::Rng;
 
-use crate::tag::apriltag::{
-    ApriltagDetection, ApriltagFamily, ApriltagFamilyType, apriltag_binding,
-};
+use crate::tag::apriltag::{ApriltagDetection, ApriltagFamily};
 
 use super::*;
 
@@ -19,13 +18,68 @@ fn project_tag(
     for (j, (_, tag_location)) in object.tags.iter().enumerate() {
         for k in 0..4 {
             let point = camera_mat
-                * object_location.transform_point(&tag_location.0.transform_point(&TAG_CORNERS[k]));
+                * object_location.transform_point(&tag_location.transform_point(&TAG_CORNERS[k]));
             ret[j * 8 + k * 2] = point.x / point.z;
             ret[j * 8 + k * 2 + 1] = point.y / point.z;
         }
     }
 }
 
+/// Create a detection whose corners are the projection of a tag placed at `tag_location` on an object
+/// located at `object_location`.
+fn project_detection(
+    family: ApriltagFamily,
+    id: i32,
+    tag_location: &TagLocation,
+    object_location: &na::Isometry3<f64>,
+    camera_mat: &na::Matrix3<f64>,
+) -> ApriltagDetection {
+    let project = |point: &na::Point3<f64>| {
+        let point =
+            camera_mat * object_location.transform_point(&tag_location.transform_point(point));
+        [point.x / point.z, point.y / point.z]
+    };
+    detection_from_corners(
+        family,
+        id,
+        project(&na::Point3::origin()),
+        std::array::from_fn(|i| project(&TAG_CORNERS[i])),
+    )
+}
+
+/// Create a detection with the given center and corners in image coordinates.
+fn detection_from_corners(
+    family: ApriltagFamily,
+    id: i32,
+    center: [f64; 2],
+    corners: [[f64; 2]; 4],
+) -> ApriltagDetection {
+    detection_with_margin(family, id, center, corners, 100.0)
+}
+
+/// Create a detection with the given center, corners, and decision margin.
+fn detection_with_margin(
+    family: ApriltagFamily,
+    id: i32,
+    center: [f64; 2],
+    corners: [[f64; 2]; 4],
+    decision_margin: f32,
+) -> ApriltagDetection {
+    ApriltagDetection::from_parts(family, id, corners.map(Into::into), center.into())
+        .with_decision_margin(decision_margin)
+}
+
+fn assert_isometry_eq(actual: &na::Isometry3<f64>, expected: &na::Isometry3<f64>, eps: f64) {
+    let translation_error = (actual.translation.vector - expected.translation.vector).norm();
+    let rotation_error = actual.rotation.angle_to(&expected.rotation);
+    assert!(
+        translation_error <= eps && rotation_error <= eps,
+        "Isometries are not equal. Actual: {:?}, expected: {:?}",
+        actual,
+        expected
+    );
+}
+
 #[test]
 fn test_projection_jacobian() {
     let camera =
@@ -33,7 +87,6 @@ fn test_projection_jacobian() {
     let camera_mat = camera.camera_mat_na().unwrap();
 
     let mut object = TaggedObject::new("test object");
-    let tag36h11_family = ApriltagFamilyType::new(apriltag::ApriltagFamily::Tag36h11);
     object.tags.insert(
         TagIndex {
             family: ApriltagFamily::Tag36h11,
@@ -46,14 +99,14 @@ fn test_projection_jacobian() {
             family: ApriltagFamily::Tag36h11,
             id: 1,
         },
-        TagLocation::new(1.0, na::Vector3::default(), na::vector![0.0, 1.0, 2.0]),
+        TagLocation::new(0.5, na::Vector3::default(), na::vector![0.0, 1.0, 2.0]),
     );
     object.tags.insert(
         TagIndex {
             family: ApriltagFamily::Tag36h11,
             id: 2,
         },
-        TagLocation::new(1.0, na::Vector3::default(), na::vector![-2.0, 1.0, 0.0]),
+        TagLocation::new(2.0, na::Vector3::default(), na::vector![-2.0, 1.0, 0.0]),
     );
     let mut locator = TaggedObjectLocator::new(camera);
     locator.add(&object).unwrap();
@@ -72,29 +125,20 @@ fn test_projection_jacobian() {
         let mut detections = Vec::with_capacity(object.tags.len());
         for (index, tag_location) in &object.tags {
             let center = camera_mat
-                * object_location.transform_point(
-                    &tag_location
-                        .0
-                        .transform_point(&na::Point3::new(0.0, 0.0, 0.0)),
-                );
+                * object_location
+                    .transform_point(&tag_location.transform_point(&na::Point3::origin()));
             let corners = std::array::from_fn(|i| {
                 let point = camera_mat
                     * object_location
-                        .transform_point(&tag_location.0.transform_point(&TAG_CORNERS[i]));
-                [point.x / point.z, point.y / point.z]
+                        .transform_point(&tag_location.transform_point(&TAG_CORNERS[i]));
+                point.coords.xy() / point.z
             });
-            let dummy_h_matd = unsafe { apriltag_binding::matd_create(2, 2) };
-            let mut detection_raw = Box::new(apriltag_binding::apriltag_detection {
-                family: tag36h11_family.c_type,
-                id: index.id,
-                hamming: 0,
-                decision_margin: 0.0,
-                H: dummy_h_matd,
-                c: [center.x / center.z, center.y / center.z],
-                p: corners,
-            });
-            let detection = unsafe { ApriltagDetection::new_from_raw(detection_raw.deref_mut()) };
-            std::mem::forget(detection_raw);
+            let detection = ApriltagDetection::from_parts(
+                ApriltagFamily::Tag36h11,
+                index.id,
+                corners,
+                center.coords.xy() / center.z,
+            );
             detections.push((detection, tag_location.clone()));
         }
 
@@ -173,6 +217,2712 @@ fn test_projection_jacobian() {
                     calculated_i_j
                 );
             }
+            // the rotation block has entries close to 0, so an absolute tolerance is added
+            for j in 3..6 {
+                let measured_i_j = measured_projection_jacobian.get((i, j)).unwrap();
+                let calculated_i_j = calculated_projection_jacobian.get((i, j)).unwrap();
+                assert!(
+                    (measured_i_j - calculated_i_j).abs() <= 0.01 * calculated_i_j.abs() + 1e-3,
+                    "Assertion failed on index {} {}, with the measured value being {} and calculated value being {}",
+                    i,
+                    j,
+                    measured_i_j,
+                    calculated_i_j
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_covariance() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let tags = [
+        TagLocation::new(1.0, na::Vector3::default(), na::vector![-2.0, 0.0, 0.0]),
+        TagLocation::new(1.0, na::Vector3::default(), na::vector![2.0, 0.0, 0.0]),
+        TagLocation::new(1.0, na::vector![0.0, 0.5, 0.0], na::vector![0.0, 2.0, 0.5]),
+    ];
+    let location = na::Isometry3::new(na::vector![0.5, -0.3, 10.0], na::vector![0.1, 0.2, 0.0]);
+    let covariance = TaggedObjectLocator::calculate_covariance(
+        camera_mat,
+        tags.iter().cloned(),
+        location,
+        (2.0, 2.0),
+    )
+    .unwrap();
+    let covariance = covariance.finite().unwrap();
+    assert!((covariance - covariance.transpose()).amax() <= 1e-9 * covariance.amax());
+    assert!(covariance.diagonal().iter().all(|variance| *variance > 0.0));
+
+    // a single tag close to the camera constrains the pose
+    let single_tag = TaggedObjectLocator::calculate_covariance(
+        camera_mat,
+        tags[..1].iter().cloned(),
+        location,
+        (2.0, 2.0),
+    )
+    .unwrap();
+    assert!(matches!(single_tag, PoseCovariance::Finite(_)));
+
+    // far away, a single tag's depth and tilt are lost in the rounding
+    let far_away = na::Isometry3::new(na::vector![0.0, 0.0, 1e8], na::vector![0.1, 0.2, 0.0]);
+    let degenerate = TaggedObjectLocator::calculate_covariance(
+        camera_mat,
+        tags[..1].iter().cloned(),
+        far_away,
+        (2.0, 2.0),
+    )
+    .unwrap();
+    assert!(
+        matches!(degenerate, PoseCovariance::Degenerate { condition_number } if condition_number > COVARIANCE_MAX_CONDITION_NUMBER),
+        "{:?}",
+        degenerate
+    );
+    assert_eq!(degenerate.finite(), None);
+
+    // a tag shrunk to a point only constrains its position on the image
+    let point = TagLocation::new(0.0, na::Vector3::default(), na::Vector3::default());
+    let rank_deficient = TaggedObjectLocator::calculate_covariance(
+        camera_mat,
+        [point].into_iter(),
+        location,
+        (2.0, 2.0),
+    )
+    .unwrap();
+    assert!(matches!(rank_deficient, PoseCovariance::Degenerate { .. }));
+}
+
+#[test]
+fn test_rotated_covariance() {
+    let pose = na::Isometry3::new(na::vector![1.0, -2.0, 15.0], na::vector![0.4, -0.6, 0.9]);
+    let rotation = na::UnitQuaternion::from_scaled_axis(na::vector![-0.5, 0.3, 0.7]);
+    let factor = na::Matrix6::from_fn(|i, j| ((i * 7 + j * 3) % 5) as f64 * 0.01)
+        + na::Matrix6::identity() * 0.05;
+    let covariance = factor * factor.transpose();
+
+    // propagate the covariance through the change of frame with a numerical Jacobian
+    let to_frame = |x: &na::Vector6<f64>| {
+        let translation = rotation * x.fixed_rows::<3>(0).into_owned();
+        let omega = (rotation
+            * na::UnitQuaternion::from_scaled_axis(x.fixed_rows::<3>(3).into_owned()))
+        .scaled_axis();
+        na::Vector6::new(
+            translation.x,
+            translation.y,
+            translation.z,
+            omega.x,
+            omega.y,
+            omega.z,
+        )
+    };
+    let omega = pose.rotation.scaled_axis();
+    let t = pose.translation.vector;
+    let x = na::Vector6::new(t.x, t.y, t.z, omega.x, omega.y, omega.z);
+    const EPS: f64 = 1e-6;
+    let mut jacobian = na::Matrix6::zeros();
+    for i in 0..6 {
+        let dx = na::Vector6::from_fn(|j, _| if i == j { EPS } else { 0.0 });
+        jacobian.set_column(
+            i,
+            &((to_frame(&(x + dx)) - to_frame(&(x - dx))) / (2.0 * EPS)),
+        );
+    }
+    let expected = jacobian * covariance * jacobian.transpose();
+
+    let rotated = PoseCovariance::Finite(covariance).rotated(&rotation, &pose);
+    let rotated = rotated.finite().unwrap();
+    assert!(
+        (rotated - expected).amax() <= 1e-6 * expected.amax(),
+        "{} != {}",
+        rotated,
+        expected
+    );
+    // rotating the covariance matrix like the translation is wrong for the rotation block
+    let naive = rotation.to_rotation_matrix().into_inner();
+    let naive = naive * covariance.fixed_view::<3, 3>(3, 3) * naive.transpose();
+    assert!((naive - expected.fixed_view::<3, 3>(3, 3)).amax() > 1e-3 * expected.amax());
+}
+
+#[test]
+fn test_refine_pose() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let mut object = TaggedObject::new("object");
+    for (id, rotation, translation) in [
+        (0, na::Vector3::default(), na::vector![-2.0, 0.0, 0.0]),
+        (1, na::vector![0.0, 0.5, 0.0], na::vector![2.0, 0.0, 0.5]),
+        (2, na::vector![-0.3, 0.0, 0.0], na::vector![0.0, 2.0, 0.2]),
+    ] {
+        object.tags.insert(
+            TagIndex::new(family, id),
+            TagLocation::new(1.0, rotation, translation),
+        );
+    }
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+
+    let mut rng = rand::rng();
+    for _ in 0..20 {
+        let object_location = na::Isometry3::new(
+            na::vector![
+                rng.random_range(-2.0..2.0),
+                rng.random_range(-1.0..1.0),
+                rng.random_range(8.0..12.0)
+            ],
+            na::vector![
+                rng.random_range(-0.3..0.3),
+                rng.random_range(-0.3..0.3),
+                rng.random_range(-0.3..0.3)
+            ],
+        );
+        // noisy corners, so that the poses minimizing the reprojection error are not exactly the true pose
+        let detections = object
+            .tags
+            .iter()
+            .map(|(tag_index, tag_location)| {
+                let mut project = |point: &na::Point3<f64>| {
+                    let point = camera_mat
+                        * object_location.transform_point(&tag_location.transform_point(point));
+                    [
+                        point.x / point.z + rng.random_range(-0.5..0.5),
+                        point.y / point.z + rng.random_range(-0.5..0.5),
+                    ]
+                };
+                let center = project(&na::Point3::origin());
+                let corners = std::array::from_fn(|i| project(&TAG_CORNERS[i]));
+                (
+                    detection_from_corners(family, tag_index.id, center, corners),
+                    tag_location.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let detections = detections
+            .iter()
+            .map(|(detection, tag_location)| (detection, tag_location.clone()))
+            .collect::<Vec<_>>();
+
+        let mut object_points_data = Vec::new();
+        let mut image_points_data = Vec::new();
+        for (detection, tag_location) in &detections {
+            for (i, corner) in detection.corners().iter().enumerate() {
+                let object_point = tag_location.transform_point(&TAG_CORNERS[i]);
+                object_points_data.extend([object_point.x, object_point.y, object_point.z]);
+                image_points_data.extend([corner.x, corner.y]);
+            }
+        }
+        let points_cnt = (image_points_data.len() / 2) as i32;
+        let mut rvec = Mat::default();
+        let mut tvec = Mat::default();
+        calib3d::solve_pnp(
+            &Mat::new_rows_cols_with_data(points_cnt, 3, &object_points_data).unwrap(),
+            &Mat::new_rows_cols_with_data(points_cnt, 2, &image_points_data).unwrap(),
+            &locator.camera.camera_mat,
+            &locator.camera.distortion,
+            &mut rvec,
+            &mut tvec,
+            false,
+            calib3d::SOLVEPNP_ITERATIVE,
+        )
+        .unwrap();
+        let at = |vec: &Mat, i: i32| *vec.at::<f64>(i).unwrap();
+        let expected = na::Isometry3::new(
+            na::vector![at(&tvec, 0), at(&tvec, 1), at(&tvec, 2)],
+            na::vector![at(&rvec, 0), at(&rvec, 1), at(&rvec, 2)],
+        );
+
+        // start away from the true pose
+        let initial = na::Isometry3::new(
+            object_location.translation.vector + na::vector![0.2, -0.1, 0.3],
+            object_location.rotation.scaled_axis() + na::vector![0.05, 0.0, -0.05],
+        );
+        let refined = locator.refine_pose(initial, &detections).unwrap();
+        assert_isometry_eq(&refined, &expected, 1e-4);
+    }
+}
+
+#[test]
+fn test_mixed_tag_sizes() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    // a big central tag surrounded by small tags
+    let mut object = TaggedObject::new("mixed");
+    object
+        .tags
+        .insert(TagIndex::new(family, 0), TagLocation::new_size(4.0));
+    for (id, (x, y)) in [
+        (1, (-3.0, -3.0)),
+        (2, (3.0, -3.0)),
+        (3, (3.0, 3.0)),
+        (4, (-3.0, 3.0)),
+    ] {
+        object.tags.insert(
+            TagIndex::new(family, id),
+            TagLocation::new(1.0, na::Vector3::default(), na::vector![x, y, 0.0]),
+        );
+    }
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+
+    let object_location =
+        na::Isometry3::new(na::vector![1.0, -0.5, 30.0], na::vector![0.3, -0.2, 0.1]);
+    let detections = object
+        .tags
+        .iter()
+        .map(|(tag_index, tag_location)| {
+            project_detection(
+                family,
+                tag_index.id,
+                tag_location,
+                &object_location,
+                &camera_mat,
+            )
+        })
+        .collect::<Vec<_>>();
+    let start = FrameTimestamp::now();
+
+    // the multi-tag solve scales each tag's corners by its own size
+    let located = locator.locate_objects_owned(start, &detections).unwrap();
+    assert_isometry_eq(&located.name_map()["mixed"], &object_location, 1e-4);
+    let errors = locator
+        .tag_reprojection_error("mixed", &located.name_map()["mixed"], &detections)
+        .unwrap()
+        .unwrap();
+    assert_eq!(errors.len(), object.tags.len());
+    assert!(errors.iter().all(|error| error.error < 1e-3));
+
+    // so does the single tag solve, with either the big or a small tag
+    for id in [0, 3] {
+        let tag_location = &object.tags[&TagIndex::new(family, id)];
+        let single = [project_detection(
+            family,
+            id,
+            tag_location,
+            &object_location,
+            &camera_mat,
+        )];
+        let located = locator.locate_objects_owned(start, &single).unwrap();
+        assert_isometry_eq(&located.name_map()["mixed"], &object_location, 1e-3);
+    }
+}
+
+#[test]
+fn test_locate_rectangular_tag() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+
+    // a single tag that is twice as wide as it is high
+    let mut object = TaggedObject::new("rectangle");
+    let tag_location =
+        TagLocation::new_rect((2.0, 1.0), na::Vector3::default(), na::Vector3::default());
+    assert!(!tag_location.is_square());
+    assert_eq!(
+        tag_location.transform_point(&TAG_CORNERS[1]),
+        na::Point3::new(1.0, 0.5, 0.0)
+    );
+    object.tags.insert(
+        TagIndex::new(ApriltagFamily::Tag36h11, 0),
+        tag_location.clone(),
+    );
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+    let result = Arc::new((Mutex::new(LocatedObjects::new()), Condvar::new()));
+
+    let object_location =
+        na::Isometry3::new(na::vector![0.5, -0.3, 10.0], na::vector![0.2, 0.1, 0.3]);
+    let detection = project_detection(family, 0, &tag_location, &object_location, &camera_mat);
+    locator
+        .locate_objects(FrameTimestamp::now(), &[detection], result.clone())
+        .unwrap();
+    let located = *result
+        .0
+        .lock()
+        .unwrap()
+        .name_map()
+        .get("rectangle")
+        .unwrap();
+    assert_isometry_eq(&located, &object_location, 1e-4);
+}
+
+#[test]
+fn test_locate_rectangular_tags_multiple() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+
+    let mut object = TaggedObject::new("rectangles");
+    object.tags.insert(
+        TagIndex::new(ApriltagFamily::Tag36h11, 0),
+        TagLocation::new_rect(
+            (2.0, 1.0),
+            na::Vector3::default(),
+            na::vector![-1.5, 0.0, 0.0],
+        ),
+    );
+    object.tags.insert(
+        TagIndex::new(ApriltagFamily::Tag36h11, 1),
+        TagLocation::new_rect(
+            (2.0, 1.0),
+            na::vector![0.0, std::f64::consts::FRAC_PI_2, 0.0],
+            na::vector![1.0, 0.0, -1.0],
+        ),
+    );
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+    let result = Arc::new((Mutex::new(LocatedObjects::new()), Condvar::new()));
+
+    let object_location =
+        na::Isometry3::new(na::vector![0.5, -0.3, 12.0], na::vector![0.1, -0.6, 0.2]);
+    let detections = object
+        .tags
+        .iter()
+        .map(|(index, tag_location)| {
+            project_detection(
+                family,
+                index.id,
+                tag_location,
+                &object_location,
+                &camera_mat,
+            )
+        })
+        .collect::<Vec<_>>();
+    locator
+        .locate_objects(FrameTimestamp::now(), &detections, result.clone())
+        .unwrap();
+    let located = *result
+        .0
+        .lock()
+        .unwrap()
+        .name_map()
+        .get("rectangles")
+        .unwrap();
+    assert_isometry_eq(&located, &object_location, 1e-4);
+}
+
+#[test]
+fn test_reject_pose_behind_camera() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let object = TaggedObject::new_simple("simple", ApriltagFamily::Tag36h11, 0, 1.0);
+    let tag_location = object.tags.values().next().unwrap().clone();
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+
+    let object_location =
+        na::Isometry3::new(na::vector![0.2, 0.1, 10.0], na::vector![0.1, 0.0, 0.0]);
+    let detection = project_detection(family, 0, &tag_location, &object_location, &camera_mat);
+    let detections = [(&detection, tag_location)];
+
+    assert!(
+        locator
+            .is_pose_valid(&object_location, &detections)
+            .unwrap()
+    );
+    // the mirrored solution behind the camera projects to the same image points, but is invalid
+    let behind_camera = na::Isometry3::new(
+        -object_location.translation.vector,
+        object_location.rotation.scaled_axis(),
+    );
+    assert!(!locator.is_pose_valid(&behind_camera, &detections).unwrap());
+    // an object far outside the camera's field of view is invalid
+    let outside_image = na::Isometry3::new(na::vector![100.0, 0.0, 10.0], na::Vector3::default());
+    assert!(!locator.is_pose_valid(&outside_image, &detections).unwrap());
+}
+
+#[test]
+fn test_object_forget_duration() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+
+    let mut wand = TaggedObject::new("wand");
+    let mut anchor = TaggedObject::new("anchor");
+    for (object, first_id) in [(&mut wand, 0), (&mut anchor, 2)] {
+        object.tags.insert(
+            TagIndex::new(ApriltagFamily::Tag36h11, first_id),
+            TagLocation::new(1.0, na::Vector3::default(), na::vector![-1.0, 0.0, 0.0]),
+        );
+        object.tags.insert(
+            TagIndex::new(ApriltagFamily::Tag36h11, first_id + 1),
+            TagLocation::new(1.0, na::vector![0.0, 0.5, 0.0], na::vector![1.0, 0.0, -0.5]),
+        );
+    }
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&wand).unwrap();
+    locator.add(&anchor).unwrap();
+    assert!(locator.set_forget_duration("wand", Duration::from_millis(100)));
+    assert!(!locator.set_forget_duration("nonexistent", Duration::from_millis(100)));
+    assert_eq!(
+        locator.config("wand").unwrap().forget_duration,
+        Duration::from_millis(100)
+    );
+    assert_eq!(
+        locator.config("anchor").unwrap().forget_duration,
+        OBJECT_FORGET_DURATION
+    );
+
+    let result = Arc::new((Mutex::new(LocatedObjects::new()), Condvar::new()));
+    let object_location =
+        na::Isometry3::new(na::vector![0.0, 0.0, 10.0], na::vector![0.1, 0.2, 0.0]);
+    let detections = [&wand, &anchor]
+        .into_iter()
+        .flat_map(|object| object.tags.iter())
+        .map(|(index, tag_location)| {
+            project_detection(
+                family,
+                index.id,
+                tag_location,
+                &object_location,
+                &camera_mat,
+            )
+        })
+        .collect::<Vec<_>>();
+    let start = FrameTimestamp::now();
+    locator
+        .locate_objects(start, &detections, result.clone())
+        .unwrap();
+
+    // both objects have an extrinsic guess right after being located
+    let wand_index = locator.registry_index("wand").unwrap();
+    let anchor_index = locator.registry_index("anchor").unwrap();
+    assert!(
+        locator
+            .extrinsic_guess(wand_index, start.instant)
+            .unwrap()
+            .is_some()
+    );
+    assert!(
+        locator
+            .extrinsic_guess(anchor_index, start.instant)
+            .unwrap()
+            .is_some()
+    );
+
+    // the wand forgets its last location sooner than the anchor
+    let later = start + Duration::from_millis(500);
+    assert!(
+        locator
+            .extrinsic_guess(wand_index, later.instant)
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        locator
+            .extrinsic_guess(anchor_index, later.instant)
+            .unwrap()
+            .is_some()
+    );
+
+    // the wand is reported as lost, while the anchor is not
+    locator.locate_objects(later, &[], result.clone()).unwrap();
+    let result_lock = result.0.lock().unwrap();
+    assert!(result_lock.lost_map().contains_key("wand"));
+    assert!(!result_lock.lost_map().contains_key("anchor"));
+}
+
+/// Check that every tag in `tag_map` points to the registered objects containing the tag, which are all in
+/// the same exclusive group if there are more than one of them.
+fn assert_tag_map_consistent(locator: &TaggedObjectLocator) {
+    let tag_count = locator
+        .registry
+        .iter()
+        .map(|object| object.tags.len())
+        .sum::<usize>();
+    assert_eq!(
+        locator.tag_map.values().map(Vec::len).sum::<usize>(),
+        tag_count
+    );
+    for (tag_index, candidates) in &locator.tag_map {
+        assert!(!candidates.is_empty());
+        for (registry_index, _) in candidates {
+            assert!(
+                locator.registry[*registry_index]
+                    .tags
+                    .contains_key(tag_index)
+            );
+        }
+        if candidates.len() > 1 {
+            let group = &locator.configs[candidates[0].0].exclusive_group;
+            assert!(group.is_some());
+            assert!(candidates.iter().all(|(registry_index, _)| {
+                &locator.configs[*registry_index].exclusive_group == group
+            }));
         }
     }
+    assert_eq!(locator.configs.len(), locator.registry.len());
+    assert_eq!(locator.last_location.len(), locator.registry.len());
+    assert_eq!(locator.last_pose.len(), locator.registry.len());
+}
+
+#[test]
+fn test_remove_and_update_object() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let objects = (0..4)
+        .map(|i| {
+            TaggedObject::new_simple(format!("object {}", i), ApriltagFamily::Tag36h11, i, 1.0)
+        })
+        .collect::<Vec<_>>();
+    let mut locator = TaggedObjectLocator::new(camera);
+    for object in &objects {
+        locator.add(object).unwrap();
+    }
+    locator.set_forget_duration("object 2", Duration::from_millis(100));
+
+    // remove an object in the middle of the registry
+    assert!(locator.remove("object 1"));
+    assert!(!locator.remove("object 1"));
+    assert_tag_map_consistent(&locator);
+    assert!(
+        !locator
+            .tag_map
+            .contains_key(&TagIndex::new(ApriltagFamily::Tag36h11, 1))
+    );
+    for i in [0, 2, 3] {
+        let [(registry_index, _)] =
+            &locator.tag_map[&TagIndex::new(ApriltagFamily::Tag36h11, i)][..]
+        else {
+            panic!("tag {} is not owned by exactly one object", i);
+        };
+        assert_eq!(
+            locator.registry[*registry_index].name,
+            format!("object {}", i)
+        );
+    }
+    // configurations move together with their objects
+    assert_eq!(
+        locator.config("object 2").unwrap().forget_duration,
+        Duration::from_millis(100)
+    );
+
+    // the removed tag can be used by another object
+    let mut updated = TaggedObject::new("object 3");
+    updated.tags.insert(
+        TagIndex::new(ApriltagFamily::Tag36h11, 1),
+        TagLocation::new_size(2.0),
+    );
+    updated.tags.insert(
+        TagIndex::new(ApriltagFamily::Tag36h11, 5),
+        TagLocation::new_size(2.0),
+    );
+    locator.update(&updated).unwrap();
+    assert_tag_map_consistent(&locator);
+    assert!(
+        !locator
+            .tag_map
+            .contains_key(&TagIndex::new(ApriltagFamily::Tag36h11, 3))
+    );
+
+    // updating an object with a tag of another object fails
+    let conflicting = TaggedObject::new_simple("object 0", ApriltagFamily::Tag36h11, 2, 1.0);
+    assert!(locator.update(&conflicting).is_err());
+    assert_tag_map_consistent(&locator);
+}
+
+#[test]
+fn test_exclusive_variants() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+
+    // two controllers with the same tag IDs in different layouts
+    let mut variant_a = TaggedObject::new("controller a");
+    let mut variant_b = TaggedObject::new("controller b");
+    for (id, position_a, position_b) in [
+        (0, na::vector![-3.0, 0.0, 0.0], na::vector![-3.0, 0.0, 0.0]),
+        (1, na::vector![0.0, 0.0, 0.0], na::vector![0.0, 3.0, 0.0]),
+        (2, na::vector![3.0, 0.0, 0.0], na::vector![3.0, -1.0, 0.0]),
+    ] {
+        let tag_index = TagIndex::new(family, id);
+        variant_a.tags.insert(
+            tag_index,
+            TagLocation::new(1.0, na::Vector3::default(), position_a),
+        );
+        variant_b.tags.insert(
+            tag_index,
+            TagLocation::new(1.0, na::Vector3::default(), position_b),
+        );
+    }
+    let config = ObjectConfig {
+        exclusive_group: Some("controller".to_string()),
+        ..Default::default()
+    };
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add_with_config(&variant_a, config.clone()).unwrap();
+    locator.add_with_config(&variant_b, config).unwrap();
+    assert_tag_map_consistent(&locator);
+
+    // objects outside the group still may not use the variants' tags
+    let other = TaggedObject::new_simple("other", family, 0, 1.0);
+    let error = locator.add(&other).unwrap_err();
+    assert_eq!(error.tag(), Some(TagIndex::new(family, 0)));
+    let other_group = ObjectConfig {
+        exclusive_group: Some("other".to_string()),
+        ..Default::default()
+    };
+    assert!(locator.add_with_config(&other, other_group).is_err());
+    assert_tag_map_consistent(&locator);
+
+    // the variant whose layout matches the detected tags is located, and the other one is not
+    let location = na::Isometry3::new(na::vector![0.5, -0.3, 20.0], na::vector![0.2, -0.3, 0.1]);
+    for (variant, name, other_name) in [
+        (&variant_a, "controller a", "controller b"),
+        (&variant_b, "controller b", "controller a"),
+    ] {
+        let detections = variant
+            .tags
+            .iter()
+            .map(|(index, tag_location)| {
+                project_detection(family, index.id, tag_location, &location, &camera_mat)
+            })
+            .collect::<Vec<_>>();
+        let located = locator
+            .locate_objects_owned(FrameTimestamp::now(), &detections)
+            .unwrap();
+        assert!(!located.name_map().contains_key(other_name));
+        assert_isometry_eq(&located.name_map()[name], &location, 1e-4);
+    }
+
+    // removing a variant leaves the shared tags to the other one
+    assert!(locator.remove("controller a"));
+    assert_tag_map_consistent(&locator);
+    assert_eq!(locator.tag_map[&TagIndex::new(family, 1)].len(), 1);
+}
+
+#[test]
+fn test_objects_iterator() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let first = TaggedObject::new_simple("first", ApriltagFamily::Tag36h11, 0, 1.0);
+    let second = TaggedObject::new_simple("second", ApriltagFamily::Tag16h5, 0, 2.0);
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&first).unwrap();
+    locator.add(&second).unwrap();
+
+    let objects = locator.objects().collect::<Vec<_>>();
+    assert_eq!(objects.len(), 2);
+    assert_eq!(objects[0].0, "first");
+    assert!(
+        objects[0]
+            .1
+            .contains_key(&TagIndex::new(ApriltagFamily::Tag36h11, 0))
+    );
+    assert_eq!(objects[1].0, "second");
+    assert!(
+        objects[1]
+            .1
+            .contains_key(&TagIndex::new(ApriltagFamily::Tag16h5, 0))
+    );
+    // the borrowing iterator agrees with the owned object map
+    let object_map = locator.get_object_map();
+    for (name, tags) in locator.objects() {
+        assert_eq!(object_map[name].len(), tags.len());
+    }
+}
+
+#[test]
+fn test_initial_pnp_method() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+
+    let mut object = TaggedObject::new("cube");
+    object.tags.insert(
+        TagIndex::new(ApriltagFamily::Tag36h11, 0),
+        TagLocation::new(1.0, na::Vector3::default(), na::vector![0.0, 0.0, -1.0]),
+    );
+    object.tags.insert(
+        TagIndex::new(ApriltagFamily::Tag36h11, 1),
+        TagLocation::new(
+            1.0,
+            na::vector![0.0, -std::f64::consts::FRAC_PI_2, 0.0],
+            na::vector![1.0, 0.0, 0.0],
+        ),
+    );
+
+    // a strongly rotated object seen for the first time
+    let object_location =
+        na::Isometry3::new(na::vector![-0.4, 0.3, 9.0], na::vector![0.3, 0.7, -0.2]);
+    let detections = object
+        .tags
+        .iter()
+        .map(|(index, tag_location)| {
+            project_detection(
+                family,
+                index.id,
+                tag_location,
+                &object_location,
+                &camera_mat,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    for method in [Some(calib3d::SOLVEPNP_EPNP), Some(calib3d::SOLVEPNP_SQPNP)] {
+        let mut locator = TaggedObjectLocator::new(camera.clone());
+        locator.add(&object).unwrap();
+        locator.set_initial_pnp_method(method);
+        assert_eq!(locator.initial_pnp_method(), method);
+        let result = Arc::new((Mutex::new(LocatedObjects::new()), Condvar::new()));
+        locator
+            .locate_objects(FrameTimestamp::now(), &detections, result.clone())
+            .unwrap();
+        let located = *result.0.lock().unwrap().name_map().get("cube").unwrap();
+        assert_isometry_eq(&located, &object_location, 1e-4);
+    }
+}
+
+#[test]
+fn test_pnp_method() {
+    assert!(supports_extrinsic_guess(calib3d::SOLVEPNP_ITERATIVE));
+    assert!(!supports_extrinsic_guess(calib3d::SOLVEPNP_EPNP));
+    assert!(!supports_extrinsic_guess(calib3d::SOLVEPNP_SQPNP));
+
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+
+    let mut object = TaggedObject::new("object");
+    object.tags.insert(
+        TagIndex::new(ApriltagFamily::Tag36h11, 0),
+        TagLocation::new(1.0, na::Vector3::default(), na::vector![-1.5, 0.0, 0.0]),
+    );
+    object.tags.insert(
+        TagIndex::new(ApriltagFamily::Tag36h11, 1),
+        TagLocation::new(1.0, na::vector![0.0, 0.3, 0.0], na::vector![1.5, 0.0, 0.5]),
+    );
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+    locator.set_pnp_method(calib3d::SOLVEPNP_SQPNP);
+    locator.set_single_tag_pnp_method(Some(calib3d::SOLVEPNP_IPPE));
+    assert_eq!(locator.pnp_method(), calib3d::SOLVEPNP_SQPNP);
+    assert_eq!(
+        locator.single_tag_pnp_method(),
+        Some(calib3d::SOLVEPNP_IPPE)
+    );
+
+    let object_location =
+        na::Isometry3::new(na::vector![0.3, 0.2, 10.0], na::vector![0.1, -0.2, 0.1]);
+    let detections = object
+        .tags
+        .iter()
+        .map(|(index, tag_location)| {
+            project_detection(
+                family,
+                index.id,
+                tag_location,
+                &object_location,
+                &camera_mat,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // locate with both tags, then with only one of them
+    for detections in [&detections[..], &detections[..1]] {
+        let result = Arc::new((Mutex::new(LocatedObjects::new()), Condvar::new()));
+        locator
+            .locate_objects(FrameTimestamp::now(), detections, result.clone())
+            .unwrap();
+        let located = *result.0.lock().unwrap().name_map().get("object").unwrap();
+        assert_isometry_eq(&located, &object_location, 1e-4);
+    }
+}
+
+#[test]
+fn test_reject_duplicate_name() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let wand1 = TaggedObject::new_simple("wand", ApriltagFamily::Tag36h11, 0, 1.0);
+    let wand2 = TaggedObject::new_simple("wand", ApriltagFamily::Tag36h11, 1, 1.0);
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&wand1).unwrap();
+    let error = locator.add(&wand2).unwrap_err();
+    assert_eq!(error.tag(), None);
+    assert_eq!(locator.objects().count(), 1);
+    assert_tag_map_consistent(&locator);
+}
+
+#[test]
+fn test_backwards_clock_step() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let object = TaggedObject::new_simple("simple", ApriltagFamily::Tag36h11, 0, 1.0);
+    let tag_location = object.tags.values().next().unwrap().clone();
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+    let result = Arc::new((Mutex::new(LocatedObjects::new()), Condvar::new()));
+
+    let object_location =
+        na::Isometry3::new(na::vector![0.2, 0.1, 10.0], na::vector![0.1, 0.0, 0.0]);
+    let start = FrameTimestamp::now();
+    let detection = project_detection(family, 0, &tag_location, &object_location, &camera_mat);
+    locator
+        .locate_objects(start, &[detection], result.clone())
+        .unwrap();
+
+    // the wall clock jumps backwards (e.g. an NTP adjustment) between two frames
+    let next = FrameTimestamp {
+        instant: start.instant + Duration::from_millis(30),
+        system_time: start.system_time - Duration::from_secs(10),
+    };
+    let detection = project_detection(family, 0, &tag_location, &object_location, &camera_mat);
+    locator
+        .locate_objects(next, &[detection], result.clone())
+        .unwrap();
+    let result_lock = result.0.lock().unwrap();
+    assert!(result_lock.timestamp() > start.instant);
+    assert_eq!(result_lock.system_time(), next.system_time);
+    assert!(result_lock.name_map().contains_key("simple"));
+    assert!(result_lock.lost_map().is_empty());
+}
+
+#[test]
+fn test_never_seen_tags() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+
+    let mut object = TaggedObject::new("object");
+    for id in 0..3 {
+        object.tags.insert(
+            TagIndex::new(ApriltagFamily::Tag36h11, id),
+            TagLocation::new(
+                1.0,
+                na::Vector3::default(),
+                na::vector![id as f64 * 2.0, 0.0, 0.0],
+            ),
+        );
+    }
+    let other = TaggedObject::new_simple("other", ApriltagFamily::Tag36h11, 5, 1.0);
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+    locator.add(&other).unwrap();
+    assert_eq!(locator.never_seen_tags().len(), 4);
+
+    // only tag 1 is detected
+    let tag_index = TagIndex::new(ApriltagFamily::Tag36h11, 1);
+    let object_location =
+        na::Isometry3::new(na::vector![0.0, 0.0, 10.0], na::vector![0.1, 0.0, 0.0]);
+    let detection = project_detection(
+        family,
+        1,
+        &object.tags[&tag_index],
+        &object_location,
+        &camera_mat,
+    );
+    let timestamp = FrameTimestamp::now();
+    let result = Arc::new((Mutex::new(LocatedObjects::new()), Condvar::new()));
+    locator
+        .locate_objects(timestamp, &[detection], result)
+        .unwrap();
+    assert_eq!(locator.tag_last_seen(&tag_index), Some(timestamp.instant));
+    assert_eq!(
+        locator.never_seen_tags(),
+        [0, 2, 5].map(|id| TagIndex::new(ApriltagFamily::Tag36h11, id))
+    );
+
+    // removed tags are no longer reported
+    assert!(locator.remove("other"));
+    assert_eq!(
+        locator.never_seen_tags(),
+        [0, 2].map(|id| TagIndex::new(ApriltagFamily::Tag36h11, id))
+    );
+}
+
+#[test]
+fn test_display_located_objects() {
+    let mut located_objects = LocatedObjects::new();
+    located_objects.name_map.insert(
+        "wand",
+        na::Isometry3::new(
+            na::vector![1.0, -2.5, 30.0],
+            na::vector![0.0, 0.0, std::f64::consts::FRAC_PI_2],
+        ),
+    );
+    located_objects
+        .lost_map
+        .insert("handheld screen", na::Isometry3::identity());
+    let formatted = located_objects.to_string();
+    assert!(formatted.contains("wand: translation (1.000, -2.500, 30.000)"));
+    assert!(formatted.contains("90.00) deg"));
+    assert!(formatted.contains("handheld screen (lost): translation (0.000, 0.000, 0.000)"));
+}
+
+#[test]
+fn test_located_objects_summary() {
+    let mut located_objects = LocatedObjects::new();
+    assert_eq!(located_objects.summary(), "no objects located");
+    located_objects
+        .name_map
+        .insert("wand", na::Isometry3::translation(1.0, -2.5, 30.0));
+    located_objects
+        .name_map
+        .insert("screen", na::Isometry3::translation(0.0, 0.0, 25.0));
+    located_objects.extrapolated.insert("screen");
+    located_objects
+        .lost_map
+        .insert("anchor", na::Isometry3::identity());
+    assert_eq!(
+        located_objects.summary(),
+        "screen (extrapolated) (0.00, 0.00, 25.00), wand (1.00, -2.50, 30.00); lost: anchor"
+    );
+}
+
+#[test]
+fn test_pose_log_rate_limit() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let mut locator = TaggedObjectLocator::new(camera);
+    assert_eq!(locator.pose_log_interval(), POSE_LOG_INTERVAL);
+    locator.set_pose_log_interval(Duration::from_millis(100));
+
+    let start = Instant::now();
+    let logged = (0..10u32)
+        .filter(|frame| locator.should_log_poses(start + Duration::from_millis(30) * *frame))
+        .collect::<Vec<_>>();
+    // frames are logged at 0, 120, and 240 ms
+    assert_eq!(logged, [0, 4, 8]);
+}
+
+#[test]
+fn test_results_published_atomically() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let objects = [
+        TaggedObject::new_simple("left", ApriltagFamily::Tag36h11, 0, 1.0),
+        TaggedObject::new_simple("right", ApriltagFamily::Tag36h11, 1, 1.0),
+    ];
+    let mut locator = TaggedObjectLocator::new(camera);
+    for object in &objects {
+        locator.add(object).unwrap();
+    }
+    let result = Arc::new((Mutex::new(LocatedObjects::new()), Condvar::new()));
+    let initial_timestamp = result.0.lock().unwrap().timestamp();
+    let frame_count = 20;
+    let start = FrameTimestamp::now();
+
+    // each frame moves both objects to a height identifying the frame
+    let height = |i: u64| 0.1 * i as f64;
+    let done = AtomicBool::new(false);
+
+    thread::scope(|s| {
+        // poll the results without waiting for a notification, so that a frame published in parts would be
+        // observed between its parts
+        let consumer = s.spawn(|| {
+            let mut snapshots = 0;
+            while !done.load(Ordering::Acquire) {
+                {
+                    let locked = result.0.lock().unwrap();
+                    if locked.timestamp() == initial_timestamp {
+                        assert!(locked.name_map().is_empty());
+                    } else {
+                        let frame =
+                            (locked.timestamp() - start.instant).as_millis() as u64 / 30 - 1;
+                        // the objects must come from the same frame as the timestamp
+                        for name in ["left", "right"] {
+                            let location = locked.name_map().get(name).unwrap_or_else(|| {
+                                panic!("{} is missing from the frame {}", name, frame)
+                            });
+                            assert!(
+                                (location.translation.y - height(frame)).abs() < 0.01,
+                                "{} at height {} is not from the frame {}",
+                                name,
+                                location.translation.y,
+                                frame
+                            );
+                        }
+                        snapshots += 1;
+                    }
+                }
+                thread::yield_now();
+            }
+            snapshots
+        });
+
+        for i in 0..frame_count {
+            let detections = objects
+                .iter()
+                .enumerate()
+                .map(|(j, object)| {
+                    let object_location = na::Isometry3::new(
+                        na::vector![j as f64 * 4.0 - 2.0, height(i), 15.0],
+                        na::vector![0.0, 0.3, 0.0],
+                    );
+                    project_detection(
+                        family,
+                        j as i32,
+                        object.tags.values().next().unwrap(),
+                        &object_location,
+                        &camera_mat,
+                    )
+                })
+                .collect::<Vec<_>>();
+            let timestamp = start + Duration::from_millis(30 * (i + 1));
+            locator
+                .locate_objects(timestamp, &detections, result.clone())
+                .unwrap();
+        }
+        done.store(true, Ordering::Release);
+        assert!(consumer.join().unwrap() > 0);
+    });
+}
+
+#[test]
+fn test_tag_reprojection_error() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let object = TaggedObject::new_simple("simple", ApriltagFamily::Tag36h11, 0, 1.0);
+    let other = TaggedObject::new_simple("other", ApriltagFamily::Tag36h11, 1, 1.0);
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+    locator.add(&other).unwrap();
+
+    let object_location =
+        na::Isometry3::new(na::vector![0.5, -0.3, 12.0], na::vector![0.1, -0.6, 0.2]);
+    let detections = [
+        project_detection(
+            family,
+            0,
+            object.tags.values().next().unwrap(),
+            &object_location,
+            &camera_mat,
+        ),
+        project_detection(
+            family,
+            1,
+            other.tags.values().next().unwrap(),
+            &object_location,
+            &camera_mat,
+        ),
+    ];
+
+    // the other object's tag is ignored, and the correct pose has no error
+    let errors = locator
+        .tag_reprojection_error("simple", &object_location, &detections)
+        .unwrap()
+        .unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].tag, TagIndex::new(ApriltagFamily::Tag36h11, 0));
+    assert!(errors[0].error < 1e-6);
+
+    // a shifted pose moves the reprojected center away from the detected one
+    let shifted_location = na::Translation3::new(0.2, 0.0, 0.0) * object_location;
+    let errors = locator
+        .tag_reprojection_error("simple", &shifted_location, &detections)
+        .unwrap()
+        .unwrap();
+    assert!(errors[0].error > 1.0);
+    assert!(
+        (errors[0].reprojected_center - errors[0].detected_center).norm() - errors[0].error < 1e-9
+    );
+
+    assert!(
+        locator
+            .tag_reprojection_error("unknown", &object_location, &detections)
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn test_reprojection_error_with_distortion() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let camera = camera
+        .with_distortion(&[-0.2, 0.05, 0.0, 0.0, 0.0])
+        .unwrap();
+    let object = TaggedObject::new_simple("simple", ApriltagFamily::Tag36h11, 0, 1.0);
+    let mut locator = TaggedObjectLocator::new(camera.clone());
+    locator.add(&object).unwrap();
+
+    // a tag near the image's corner, where the distortion is strong, detected at its distorted projection
+    let object_location =
+        na::Isometry3::new(na::vector![-7.0, -3.5, 12.0], na::vector![0.1, -0.3, 0.0]);
+    let tag_location = object.tags.values().next().unwrap();
+    let project = |point: &na::Point3<f64>| {
+        let projected = camera
+            .project_point(&object_location.transform_point(&tag_location.transform_point(point)))
+            .unwrap();
+        [projected.x, projected.y]
+    };
+    let detection = detection_from_corners(
+        ApriltagFamily::Tag36h11,
+        0,
+        project(&na::Point3::origin()),
+        std::array::from_fn(|i| project(&TAG_CORNERS[i])),
+    );
+    let pinhole = project_detection(
+        ApriltagFamily::Tag36h11,
+        0,
+        tag_location,
+        &object_location,
+        &camera_mat,
+    );
+    assert!((pinhole.center() - detection.center()).norm() > 5.0);
+
+    // the reprojection is distorted like the detection
+    let errors = locator
+        .tag_reprojection_error("simple", &object_location, &[detection])
+        .unwrap()
+        .unwrap();
+    assert!(errors[0].error < 1e-6);
+}
+
+#[test]
+fn test_quick_centroid() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+
+    let mut object = TaggedObject::new("screen");
+    object.tags.insert(
+        TagIndex::new(ApriltagFamily::Tag36h11, 0),
+        TagLocation::new(1.0, na::Vector3::default(), na::vector![-1.0, 0.0, 0.0]),
+    );
+    object.tags.insert(
+        TagIndex::new(ApriltagFamily::Tag36h11, 1),
+        TagLocation::new(1.0, na::Vector3::default(), na::vector![1.0, 0.0, 0.0]),
+    );
+    let other = TaggedObject::new_simple("other", ApriltagFamily::Tag36h11, 2, 1.0);
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+    locator.add(&other).unwrap();
+
+    let object_location =
+        na::Isometry3::new(na::vector![1.5, -0.8, 12.0], na::vector![0.1, 0.4, 0.0]);
+    let detections = object
+        .tags
+        .iter()
+        .map(|(index, tag_location)| {
+            project_detection(
+                family,
+                index.id,
+                tag_location,
+                &object_location,
+                &camera_mat,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // with equal margins, the ray points toward the midpoint of the tags located by the full solve
+    let located = locator
+        .locate_objects_owned(FrameTimestamp::now(), &detections)
+        .unwrap();
+    let located_location = located.name_map()["screen"];
+    let centroids = locator.quick_centroid(&detections).unwrap();
+    assert_eq!(
+        centroids.keys().copied().collect::<Vec<_>>(),
+        vec!["screen"]
+    );
+    let expected = located_location
+        .transform_point(&na::Point3::origin())
+        .coords
+        .normalize();
+    assert!(centroids["screen"].angle(&expected) < f64::to_radians(0.5));
+
+    // a tag with a much larger margin pulls the ray toward itself
+    let tag_direction = |id: i32| {
+        let tag_location = &object.tags[&TagIndex::new(ApriltagFamily::Tag36h11, id)];
+        located_location
+            .transform_point(&tag_location.transform_point(&na::Point3::origin()))
+            .coords
+            .normalize()
+    };
+    let project = |id: i32| {
+        project_detection(
+            family,
+            id,
+            &object.tags[&TagIndex::new(ApriltagFamily::Tag36h11, id)],
+            &object_location,
+            &camera_mat,
+        )
+    };
+    let (heavy, light) = (project(0), project(1));
+    let center = heavy.center();
+    let corners = heavy.corners().map(|corner| [corner.x, corner.y]);
+    let weighted = [
+        detection_with_margin(family, 0, [center.x, center.y], corners, 10000.0),
+        light,
+    ];
+    let direction = locator.quick_centroid(&weighted).unwrap()["screen"];
+    assert!(direction.angle(&tag_direction(0)) < direction.angle(&tag_direction(1)));
+    assert!(direction.angle(&tag_direction(0)) < f64::to_radians(0.2));
+}
+
+#[test]
+fn test_min_tags() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+
+    let mut object = TaggedObject::new("screen");
+    object.tags.insert(
+        TagIndex::new(ApriltagFamily::Tag36h11, 0),
+        TagLocation::new(1.0, na::Vector3::default(), na::vector![-1.0, 0.0, 0.0]),
+    );
+    object.tags.insert(
+        TagIndex::new(ApriltagFamily::Tag36h11, 1),
+        TagLocation::new(1.0, na::Vector3::default(), na::vector![1.0, 0.0, 0.0]),
+    );
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+    assert_eq!(locator.config("screen").unwrap().min_tags, 1);
+    assert!(locator.set_min_tags("screen", 2));
+    assert!(!locator.set_min_tags("nonexistent", 2));
+
+    let result = Arc::new((Mutex::new(LocatedObjects::new()), Condvar::new()));
+    let object_location =
+        na::Isometry3::new(na::vector![0.0, 0.0, 10.0], na::vector![0.1, 0.2, 0.0]);
+    let detections = object
+        .tags
+        .iter()
+        .map(|(index, tag_location)| {
+            project_detection(
+                family,
+                index.id,
+                tag_location,
+                &object_location,
+                &camera_mat,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // only one of the two tags is detected
+    let one_tag = [project_detection(
+        family,
+        0,
+        &object.tags[&TagIndex::new(ApriltagFamily::Tag36h11, 0)],
+        &object_location,
+        &camera_mat,
+    )];
+    let start = FrameTimestamp::now();
+    locator
+        .locate_objects(start, &one_tag, result.clone())
+        .unwrap();
+    assert!(result.0.lock().unwrap().name_map().is_empty());
+
+    // both tags are detected
+    locator
+        .locate_objects(
+            start + Duration::from_millis(30),
+            &detections,
+            result.clone(),
+        )
+        .unwrap();
+    let located = *result.0.lock().unwrap().name_map().get("screen").unwrap();
+    assert_isometry_eq(&located, &object_location, 1e-4);
+}
+
+#[test]
+fn test_planar_object() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+
+    // a flat board of tags, some of them rotated within the board's plane
+    let mut board = TaggedObject::new("board");
+    for (i, (x, y)) in [(-2.0, -2.0), (2.0, -2.0), (-2.0, 2.0), (2.0, 2.0)]
+        .into_iter()
+        .enumerate()
+    {
+        board.tags.insert(
+            TagIndex::new(ApriltagFamily::Tag36h11, i as i32),
+            TagLocation::new(
+                1.0,
+                na::vector![0.0, 0.0, i as f64 * 0.4],
+                na::vector![x, y, 0.0],
+            ),
+        );
+    }
+    let mut cube = TaggedObject::new("cube");
+    cube.tags.insert(
+        TagIndex::new(ApriltagFamily::Tag36h11, 10),
+        TagLocation::new(1.0, na::Vector3::default(), na::vector![0.0, 0.0, -1.0]),
+    );
+    cube.tags.insert(
+        TagIndex::new(ApriltagFamily::Tag36h11, 11),
+        TagLocation::new(
+            1.0,
+            na::vector![0.0, -std::f64::consts::FRAC_PI_2, 0.0],
+            na::vector![1.0, 0.0, 0.0],
+        ),
+    );
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&board).unwrap();
+    locator.add(&cube).unwrap();
+    assert_eq!(locator.is_planar("board"), Some(true));
+    assert_eq!(locator.is_planar("cube"), Some(false));
+    assert_eq!(locator.is_planar("nonexistent"), None);
+
+    // the planar board is solved with IPPE, while the cube uses the general method
+    assert_eq!(locator.multi_tag_pnp_method(0), calib3d::SOLVEPNP_IPPE);
+    assert_eq!(locator.multi_tag_pnp_method(1), calib3d::SOLVEPNP_ITERATIVE);
+    locator.set_planar_pnp_method(None);
+    assert_eq!(locator.planar_pnp_method(), None);
+    assert_eq!(locator.multi_tag_pnp_method(0), calib3d::SOLVEPNP_ITERATIVE);
+    locator.set_planar_pnp_method(Some(calib3d::SOLVEPNP_IPPE));
+
+    let object_location =
+        na::Isometry3::new(na::vector![0.5, -0.3, 20.0], na::vector![0.4, -0.3, 0.2]);
+    let detections = board
+        .tags
+        .iter()
+        .map(|(index, tag_location)| {
+            project_detection(
+                family,
+                index.id,
+                tag_location,
+                &object_location,
+                &camera_mat,
+            )
+        })
+        .collect::<Vec<_>>();
+    let result = Arc::new((Mutex::new(LocatedObjects::new()), Condvar::new()));
+    locator
+        .locate_objects(FrameTimestamp::now(), &detections, result.clone())
+        .unwrap();
+    let located = *result.0.lock().unwrap().name_map().get("board").unwrap();
+    assert_isometry_eq(&located, &object_location, 1e-4);
+}
+
+#[test]
+fn test_poll_required_families() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let tag36h11 = TaggedObject::new_simple("tag36h11", ApriltagFamily::Tag36h11, 0, 1.0);
+    let tag16h5 = TaggedObject::new_simple("tag16h5", ApriltagFamily::Tag16h5, 0, 1.0);
+    let another_tag36h11 = TaggedObject::new_simple("another", ApriltagFamily::Tag36h11, 1, 1.0);
+    let mut locator = TaggedObjectLocator::new(camera);
+    assert_eq!(locator.poll_required_families(), None);
+
+    locator.add(&tag36h11).unwrap();
+    assert_eq!(
+        locator.poll_required_families(),
+        Some(vec![ApriltagFamily::Tag36h11])
+    );
+    assert_eq!(locator.poll_required_families(), None);
+
+    // a new family is required
+    locator.add(&tag16h5).unwrap();
+    assert_eq!(
+        locator.poll_required_families(),
+        Some(vec![ApriltagFamily::Tag16h5, ApriltagFamily::Tag36h11])
+    );
+
+    // the required families stay the same
+    locator.add(&another_tag36h11).unwrap();
+    assert_eq!(locator.poll_required_families(), None);
+
+    locator.remove("tag16h5");
+    assert_eq!(
+        locator.poll_required_families(),
+        Some(vec![ApriltagFamily::Tag36h11])
+    );
+    assert_eq!(locator.required_families(), vec![ApriltagFamily::Tag36h11]);
+}
+
+#[test]
+fn test_corner_order() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let locator = TaggedObjectLocator::new(camera);
+    let half_extents = na::vector![1.0, 1.0];
+    let (cx, cy) = (camera_mat[(0, 2)], camera_mat[(1, 2)]);
+
+    // A tag facing the camera. Apriltag's corners wrap counter-clockwise in the image (whose y axis points
+    // down), starting from the bottom-left corner of the upright tag, which is `TAG_CORNERS[0]`.
+    let upright = detection_from_corners(
+        family,
+        0,
+        [cx, cy],
+        [
+            [cx - 20.0, cy + 20.0],
+            [cx + 20.0, cy + 20.0],
+            [cx + 20.0, cy - 20.0],
+            [cx - 20.0, cy - 20.0],
+        ],
+    );
+    let location = locator.locate_tag(&upright, half_extents).unwrap();
+    assert!(location.rotation.angle() < 1e-6);
+
+    // The same tag rotated by 90 degrees clockwise in the image, i.e. around the camera's z axis, so that
+    // the first corner is now at the top-left.
+    let rotated = detection_from_corners(
+        family,
+        0,
+        [cx, cy],
+        [
+            [cx - 20.0, cy - 20.0],
+            [cx - 20.0, cy + 20.0],
+            [cx + 20.0, cy + 20.0],
+            [cx + 20.0, cy - 20.0],
+        ],
+    );
+    let location = locator.locate_tag(&rotated, half_extents).unwrap();
+    let expected =
+        na::UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), std::f64::consts::FRAC_PI_2);
+    assert!(location.rotation.angle_to(&expected) < 1e-6);
+
+    // each corner `p[i]` is the projection of `TAG_CORNERS[i]`
+    for (i, corner) in rotated.corners().iter().enumerate() {
+        let projected = camera_mat * location.transform_point(&TAG_CORNERS[i]);
+        let projected = projected.coords.xy() / projected.z;
+        assert!((projected - corner).norm() < 1e-6);
+    }
+}
+
+#[test]
+fn test_extrapolation() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let object = TaggedObject::new_simple("simple", ApriltagFamily::Tag36h11, 0, 1.0);
+    let tag_location = object.tags.values().next().unwrap().clone();
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+    assert!(!locator.extrapolation());
+    locator.set_extrapolation(true);
+    let result = Arc::new((Mutex::new(LocatedObjects::new()), Condvar::new()));
+
+    // the object moves along the x axis at 1 unit per second, while spinning around the z axis
+    let pose_at = |seconds: f64| {
+        na::Isometry3::new(
+            na::vector![seconds, 0.0, 10.0],
+            na::vector![0.0, 0.0, 0.5 * seconds],
+        )
+    };
+    let start = FrameTimestamp::now();
+    for (frame, seconds) in [0.0, 0.1].into_iter().enumerate() {
+        let detection = project_detection(family, 0, &tag_location, &pose_at(seconds), &camera_mat);
+        locator
+            .locate_objects(
+                start + Duration::from_millis(100 * frame as u64),
+                &[detection],
+                result.clone(),
+            )
+            .unwrap();
+        assert!(result.0.lock().unwrap().extrapolated().is_empty());
+    }
+
+    // the object is occluded in the next frame
+    locator
+        .locate_objects(start + Duration::from_millis(300), &[], result.clone())
+        .unwrap();
+    {
+        let result_lock = result.0.lock().unwrap();
+        assert!(result_lock.extrapolated().contains("simple"));
+        assert!(result_lock.lost_map().is_empty());
+        let extrapolated = result_lock.name_map().get("simple").unwrap();
+        assert_isometry_eq(extrapolated, &pose_at(0.3), 1e-3);
+    }
+
+    // after the forget duration, the object is lost instead of extrapolated
+    locator
+        .locate_objects(
+            start + Duration::from_millis(100) + OBJECT_FORGET_DURATION + Duration::from_millis(1),
+            &[],
+            result.clone(),
+        )
+        .unwrap();
+    let result_lock = result.0.lock().unwrap();
+    assert!(result_lock.name_map().is_empty());
+    assert!(result_lock.extrapolated().is_empty());
+    assert!(result_lock.lost_map().contains_key("simple"));
+}
+
+#[test]
+fn test_resampled() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let object = TaggedObject::new_simple("simple", family, 0, 1.0);
+    let tag_location = object.tags.values().next().unwrap().clone();
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+
+    // the object moves along the x axis at 1 unit per second, while spinning around the z axis
+    let pose_at = |seconds: f64| {
+        na::Isometry3::new(
+            na::vector![seconds, 0.0, 10.0],
+            na::vector![0.0, 0.0, 0.5 * seconds],
+        )
+    };
+    let start = FrameTimestamp::now();
+    let mut located = LocatedObjects::new();
+    for (frame, seconds) in [0.0, 0.1].into_iter().enumerate() {
+        let detection = project_detection(family, 0, &tag_location, &pose_at(seconds), &camera_mat);
+        located = locator
+            .locate_objects_owned(
+                start + Duration::from_millis(100) * frame as u32,
+                &[detection],
+            )
+            .unwrap();
+        // the velocity is estimated from the second frame on
+        assert_eq!(located.velocity().contains_key("simple"), frame > 0);
+    }
+    let (linear, angular) = located.velocity()["simple"];
+    assert!((linear - na::vector![1.0, 0.0, 0.0]).norm() < 1e-3);
+    assert!((angular - na::vector![0.0, 0.0, 0.5]).norm() < 1e-3);
+
+    // the object is predicted between the frames, for at most the given duration
+    let lost = BTreeMap::from([("other", na::Isometry3::identity())]);
+    let tick = start + Duration::from_millis(150);
+    let resampled = located.resampled(tick, Duration::from_millis(100), lost.clone());
+    assert_eq!(resampled.timestamp(), tick.instant);
+    assert_eq!(resampled.lost_map(), &lost);
+    assert_isometry_eq(&resampled.name_map()["simple"], &pose_at(0.15), 1e-3);
+    let resampled = located.resampled(
+        tick + Duration::from_secs(1),
+        Duration::from_millis(100),
+        lost,
+    );
+    assert_isometry_eq(&resampled.name_map()["simple"], &pose_at(0.2), 1e-3);
+}
+
+#[test]
+fn test_pose_confidence() {
+    let perfect = pose_confidence(0.0, 4, CONFIDENCE_FULL_MARGIN);
+    assert!((perfect - (1.0 - 0.5f64.powi(4)).powf(0.25)).abs() < 1e-12);
+    // worse measures lower the confidence
+    assert!(pose_confidence(2.0, 4, CONFIDENCE_FULL_MARGIN) < perfect);
+    assert!(pose_confidence(0.0, 1, CONFIDENCE_FULL_MARGIN) < perfect);
+    assert!(pose_confidence(0.0, 4, CONFIDENCE_FULL_MARGIN / 2.0) < perfect);
+    // margins above the full margin do not raise the confidence
+    assert_eq!(
+        pose_confidence(0.0, 4, CONFIDENCE_FULL_MARGIN * 2.0),
+        perfect
+    );
+    assert_eq!(pose_confidence(0.0, 4, 0.0), 0.0);
+    assert_eq!(pose_confidence(0.0, 0, CONFIDENCE_FULL_MARGIN), 0.0);
+}
+
+#[test]
+fn test_located_confidence() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+
+    let mut object = TaggedObject::new("screen");
+    object.tags.insert(
+        TagIndex::new(ApriltagFamily::Tag36h11, 0),
+        TagLocation::new(1.0, na::Vector3::default(), na::vector![-1.0, 0.0, 0.0]),
+    );
+    object.tags.insert(
+        TagIndex::new(ApriltagFamily::Tag36h11, 1),
+        TagLocation::new(1.0, na::Vector3::default(), na::vector![1.0, 0.0, 0.0]),
+    );
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+
+    let result = Arc::new((Mutex::new(LocatedObjects::new()), Condvar::new()));
+    let object_location =
+        na::Isometry3::new(na::vector![0.0, 0.0, 10.0], na::vector![0.1, 0.2, 0.0]);
+    let detections = object
+        .tags
+        .iter()
+        .map(|(index, tag_location)| {
+            project_detection(
+                family,
+                index.id,
+                tag_location,
+                &object_location,
+                &camera_mat,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // the detections are exact projections with large margins, so only the tag count lowers the confidence
+    let start = FrameTimestamp::now();
+    locator
+        .locate_objects(start, &detections[..1], result.clone())
+        .unwrap();
+    let one_tag_confidence = result.0.lock().unwrap().confidence()["screen"];
+    assert!((one_tag_confidence - 0.5f64.powf(0.25)).abs() < 1e-3);
+
+    locator
+        .locate_objects(
+            start + Duration::from_millis(30),
+            &detections,
+            result.clone(),
+        )
+        .unwrap();
+    let two_tag_confidence = result.0.lock().unwrap().confidence()["screen"];
+    assert!((two_tag_confidence - 0.75f64.powf(0.25)).abs() < 1e-3);
+
+    // moving one corner by a few pixels lowers the confidence
+    let (exact, moved) = (&detections[0], &detections[1]);
+    let mut corners = moved.corners().map(|corner| [corner.x, corner.y]);
+    corners[0][0] += 4.0;
+    let center = moved.center();
+    let noisy = [
+        project_detection(
+            family,
+            exact.id(),
+            &object.tags[&TagIndex::new(ApriltagFamily::Tag36h11, exact.id())],
+            &object_location,
+            &camera_mat,
+        ),
+        detection_from_corners(family, moved.id(), [center.x, center.y], corners),
+    ];
+    locator
+        .locate_objects(start + Duration::from_millis(60), &noisy, result.clone())
+        .unwrap();
+    assert!(result.0.lock().unwrap().confidence()["screen"] < two_tag_confidence);
+}
+
+#[test]
+fn test_tag_weighting() {
+    assert_eq!(TagWeighting::Uniform.repeats(0.0), 1);
+    let threshold = TagWeighting::Threshold { min_margin: 30.0 };
+    assert_eq!(threshold.repeats(20.0), 0);
+    assert_eq!(threshold.repeats(100.0), 1);
+    let repeat = TagWeighting::Repeat {
+        min_margin: 30.0,
+        max_repeats: 3,
+    };
+    assert_eq!(repeat.repeats(20.0), 0);
+    assert_eq!(repeat.repeats(45.0), 1);
+    assert_eq!(repeat.repeats(60.0), 2);
+    assert_eq!(repeat.repeats(1000.0), 3);
+
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let mut object = TaggedObject::new("board");
+    for (id, (x, y)) in [(-2.0, -2.0), (2.0, -2.0), (0.0, 2.0)]
+        .into_iter()
+        .enumerate()
+    {
+        object.tags.insert(
+            TagIndex::new(ApriltagFamily::Tag36h11, id as i32),
+            TagLocation::new(1.0, na::Vector3::default(), na::vector![x, y, 0.0]),
+        );
+    }
+    let object_location =
+        na::Isometry3::new(na::vector![0.5, -0.3, 15.0], na::vector![0.2, 0.3, 0.1]);
+
+    // the detections of tags 0 and 1 are exact, while tag 2 is detected with noisy corners and a low margin
+    let detections = (0..3)
+        .map(|id| {
+            let tag_location = &object.tags[&TagIndex::new(ApriltagFamily::Tag36h11, id)];
+            let exact = project_detection(family, id, tag_location, &object_location, &camera_mat);
+            if id < 2 {
+                return exact;
+            }
+            let center = exact.center();
+            let mut corners = exact.corners().map(|corner| [corner.x, corner.y]);
+            corners[0][0] += 6.0;
+            corners[1][1] -= 6.0;
+            detection_with_margin(family, id, [center.x, center.y], corners, 40.0)
+        })
+        .collect::<Vec<_>>();
+
+    let locate = |tag_weighting: TagWeighting| {
+        let mut locator = TaggedObjectLocator::new(camera.clone());
+        locator.add(&object).unwrap();
+        locator.set_tag_weighting(tag_weighting);
+        assert_eq!(locator.tag_weighting(), tag_weighting);
+        let result = Arc::new((Mutex::new(LocatedObjects::new()), Condvar::new()));
+        locator
+            .locate_objects(FrameTimestamp::now(), &detections, result.clone())
+            .unwrap();
+        let located = *result.0.lock().unwrap().name_map().get("board").unwrap();
+        (located.inverse() * object_location)
+            .translation
+            .vector
+            .norm()
+    };
+    let uniform_error = locate(TagWeighting::Uniform);
+    let threshold_error = locate(TagWeighting::Threshold { min_margin: 50.0 });
+    let repeat_error = locate(TagWeighting::Repeat {
+        min_margin: 20.0,
+        max_repeats: 5,
+    });
+    // dropping the noisy tag leaves only exact detections
+    assert!(threshold_error < 1e-4);
+    // the exact tags are repeated 5 times, and the noisy tag twice
+    assert!(repeat_error < uniform_error);
+}
+
+#[test]
+fn test_flip_threshold() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let object = TaggedObject::new_simple("simple", ApriltagFamily::Tag36h11, 0, 1.0);
+    let tag_location = object.tags.values().next().unwrap().clone();
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+    assert_eq!(locator.flip_threshold(), None);
+    locator.set_flip_threshold(Some(f64::to_radians(30.0)));
+    let result = Arc::new((Mutex::new(LocatedObjects::new()), Condvar::new()));
+
+    // a small tag far away, tilted back and forth, is the ambiguous case of solvePnP: both poses project
+    // to almost the same corners
+    let tilted = na::Isometry3::new(na::vector![0.0, 0.0, 30.0], na::vector![0.35, 0.0, 0.0]);
+    let flipped = na::Isometry3::new(na::vector![0.0, 0.0, 30.0], na::vector![-0.35, 0.0, 0.0]);
+    // spinning the tag in its plane changes the corners a lot
+    let spun = tilted * na::UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), 1.0);
+    let start = FrameTimestamp::now();
+    let mut locate = |frame: u64, location: &na::Isometry3<f64>| {
+        let detection = project_detection(family, 0, &tag_location, location, &camera_mat);
+        locator
+            .locate_objects(
+                start + Duration::from_millis(30 * frame),
+                &[detection],
+                result.clone(),
+            )
+            .unwrap();
+        *result.0.lock().unwrap().name_map().get("simple").unwrap()
+    };
+
+    assert_isometry_eq(&locate(0, &tilted), &tilted, 1e-6);
+    // the sudden flip does not fit the detection clearly better than the last pose, so it is suppressed
+    assert_isometry_eq(&locate(1, &flipped), &tilted, 1e-6);
+    assert_isometry_eq(&locate(2, &flipped), &tilted, 1e-6);
+    // a large rotation that clearly fits the detection better is accepted
+    assert_isometry_eq(&locate(3, &spun), &spun, 1e-6);
+}
+
+#[test]
+fn test_locate_objects_owned() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let object = TaggedObject::new_simple("tag", ApriltagFamily::Tag36h11, 0, 2.0);
+    let tag_location = TagLocation::new_size(2.0);
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+
+    // build the detection from plain corners, as a program that does not run the detector would do
+    let object_location =
+        na::Isometry3::new(na::vector![0.5, -0.3, 10.0], na::vector![0.2, 0.1, 0.3]);
+    let project = |point: &na::Point3<f64>| {
+        let point =
+            camera_mat * object_location.transform_point(&tag_location.transform_point(point));
+        na::Vector2::new(point.x / point.z, point.y / point.z)
+    };
+    let corners = std::array::from_fn(|i| project(&TAG_CORNERS[i]));
+    let detection = ApriltagDetection::new(ApriltagFamily::Tag36h11, 0, corners, 100.0).unwrap();
+    assert_eq!(detection.family().unwrap(), ApriltagFamily::Tag36h11);
+    assert_eq!(detection.id(), 0);
+    assert_eq!(detection.corners(), corners);
+    assert!((detection.center() - project(&na::Point3::origin())).norm() < 1e-6);
+
+    let located = locator
+        .locate_objects_owned(FrameTimestamp::now(), &[detection])
+        .unwrap();
+    assert_isometry_eq(
+        located.name_map().get("tag").unwrap(),
+        &object_location,
+        1e-4,
+    );
+
+    // degenerate corners cannot be fitted with a homography
+    let collinear = std::array::from_fn(|i| na::Vector2::new(i as f64, i as f64));
+    assert!(ApriltagDetection::new(ApriltagFamily::Tag36h11, 0, collinear, 100.0).is_none());
+}
+
+#[test]
+fn test_record_detections() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let object = TaggedObject::new_simple("tag", family, 0, 2.0);
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+    let object_location = na::Isometry3::translation(0.5, -0.3, 10.0);
+    // the second tag does not belong to any object
+    let detections = [0, 1].map(|id| {
+        project_detection(
+            family,
+            id,
+            &TagLocation::new_size(2.0),
+            &(object_location * na::Translation3::new(id as f64 * 3.0, 0.0, 0.0)),
+            &camera_mat,
+        )
+        .with_decision_margin(50.0)
+    });
+
+    // the detections are not recorded by default
+    assert!(!locator.record_detections());
+    let located = locator
+        .locate_objects_owned(FrameTimestamp::now(), &detections)
+        .unwrap();
+    assert!(located.detections().is_none());
+
+    locator.set_record_detections(true);
+    let located = locator
+        .locate_objects_owned(FrameTimestamp::now(), &detections)
+        .unwrap();
+    let snapshots = located.detections().unwrap();
+    assert_eq!(snapshots.len(), detections.len());
+    for (snapshot, detection) in snapshots.iter().zip(&detections) {
+        assert_eq!(snapshot.family, family);
+        assert_eq!(snapshot.id, detection.id());
+        assert_eq!(snapshot.decision_margin, 50.0);
+        assert_eq!(snapshot.center, <[f64; 2]>::from(detection.center()));
+        assert_eq!(snapshot.corners, detection.corners().map(<[f64; 2]>::from));
+    }
+    // the resampled objects do not repeat the frame's detections
+    let resampled = located.resampled(
+        FrameTimestamp::now(),
+        Duration::from_millis(100),
+        BTreeMap::new(),
+    );
+    assert!(resampled.detections().is_none());
+}
+
+#[test]
+fn test_tag_persistence() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let mut object = TaggedObject::new("object");
+    for (id, x) in [(0, -2.0), (1, 0.0), (2, 2.0)] {
+        object.tags.insert(
+            TagIndex::new(family, id),
+            TagLocation::new(1.0, na::Vector3::default(), na::vector![x, 0.0, 0.0]),
+        );
+    }
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+    assert_eq!(locator.tag_persistence(), 0);
+
+    let object_location =
+        na::Isometry3::new(na::vector![0.5, -0.3, 15.0], na::vector![0.2, 0.1, 0.3]);
+    let detect = |ids: &[i32]| {
+        ids.iter()
+            .map(|id| {
+                let tag_location = &object.tags[&TagIndex::new(family, *id)];
+                project_detection(family, *id, tag_location, &object_location, &camera_mat)
+            })
+            .collect::<Vec<_>>()
+    };
+    let start = FrameTimestamp::now();
+    let at = |frame: u32| start + Duration::from_millis(33) * frame;
+    let carried = HashSet::from([TagIndex::new(family, 2)]);
+
+    // tags are not carried forward by default
+    locator
+        .locate_objects_owned(at(0), &detect(&[0, 1, 2]))
+        .unwrap();
+    let located = locator
+        .locate_objects_owned(at(1), &detect(&[0, 1]))
+        .unwrap();
+    assert!(located.persisted_tags().is_empty());
+
+    // tag 2 drops out, and is carried forward for up to 2 frames
+    locator.set_tag_persistence(2);
+    locator
+        .locate_objects_owned(at(2), &detect(&[0, 1, 2]))
+        .unwrap();
+    for frame in [3, 4] {
+        let located = locator
+            .locate_objects_owned(at(frame), &detect(&[0, 1]))
+            .unwrap();
+        assert_eq!(located.persisted_tags(), &carried);
+        assert_isometry_eq(&located.name_map()["object"], &object_location, 1e-4);
+    }
+    let located = locator
+        .locate_objects_owned(at(5), &detect(&[0, 1]))
+        .unwrap();
+    assert!(located.persisted_tags().is_empty());
+
+    // the tag is carried again after it is detected again
+    locator
+        .locate_objects_owned(at(6), &detect(&[0, 1, 2]))
+        .unwrap();
+    let located = locator
+        .locate_objects_owned(at(7), &detect(&[0, 1]))
+        .unwrap();
+    assert_eq!(located.persisted_tags(), &carried);
+
+    // a carried tag alone does not locate its object
+    let located = locator.locate_objects_owned(at(8), &[]).unwrap();
+    assert!(located.persisted_tags().is_empty());
+    assert!(!located.name_map().contains_key("object"));
+}
+
+#[test]
+fn test_single_tag_hold() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let mut object = TaggedObject::new("object");
+    for (id, x) in [(0, -2.0), (1, 2.0)] {
+        object.tags.insert(
+            TagIndex::new(family, id),
+            TagLocation::new(1.0, na::Vector3::default(), na::vector![x, 0.0, 0.0]),
+        );
+    }
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+    assert!(!locator.single_tag_hold());
+
+    let object_location =
+        na::Isometry3::new(na::vector![0.5, -0.3, 15.0], na::vector![0.2, 0.1, 0.3]);
+    // the single tag's pose is solved with a rotation error around the tag's center
+    let tag_location = &object.tags[&TagIndex::new(family, 0)];
+    let tag_center = na::Translation3::new(-2.0, 0.0, 0.0);
+    let noisy_location = object_location
+        * tag_center
+        * na::UnitQuaternion::from_euler_angles(0.0, 0.1, 0.0)
+        * tag_center.inverse();
+    let both_tags = object
+        .tags
+        .iter()
+        .map(|(tag_index, tag_location)| {
+            project_detection(
+                family,
+                tag_index.id,
+                tag_location,
+                &object_location,
+                &camera_mat,
+            )
+        })
+        .collect::<Vec<_>>();
+    let single_tag = [project_detection(
+        family,
+        0,
+        tag_location,
+        &noisy_location,
+        &camera_mat,
+    )];
+    let start = FrameTimestamp::now();
+    let at = |frame: u32| start + Duration::from_millis(33) * frame;
+
+    // without the hold, the single tag's rotation is published
+    locator.locate_objects_owned(at(0), &both_tags).unwrap();
+    let located = locator.locate_objects_owned(at(1), &single_tag).unwrap();
+    assert!(located.degraded().is_empty());
+    assert_isometry_eq(&located.name_map()["object"], &noisy_location, 1e-4);
+
+    // with the hold, the rotation of the last multi-tag frame is kept, and the tag stays in place
+    locator.set_single_tag_hold(true);
+    let located = locator.locate_objects_owned(at(2), &both_tags).unwrap();
+    assert!(located.degraded().is_empty());
+    let located = locator.locate_objects_owned(at(3), &single_tag).unwrap();
+    assert_eq!(located.degraded(), &BTreeSet::from(["object"]));
+    assert_isometry_eq(&located.name_map()["object"], &object_location, 1e-4);
+
+    // the rotation is no longer held after the object's forget duration
+    let forget_duration = locator.config("object").unwrap().forget_duration;
+    let later = at(2) + forget_duration + Duration::from_millis(1);
+    let located = locator.locate_objects_owned(later, &single_tag).unwrap();
+    assert!(located.degraded().is_empty());
+    assert_isometry_eq(&located.name_map()["object"], &noisy_location, 1e-4);
+}
+
+#[test]
+fn test_solve_budget() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let names = ["a", "b", "c", "d", "e"];
+    let mut locator = TaggedObjectLocator::new(camera);
+    let mut detections = Vec::new();
+    let mut object_locations = BTreeMap::new();
+    for (id, name) in names.iter().enumerate() {
+        let mut object = TaggedObject::new(*name);
+        let tag_location = TagLocation::new_size(1.0);
+        object
+            .tags
+            .insert(TagIndex::new(family, id as i32), tag_location.clone());
+        locator.add(&object).unwrap();
+        let object_location = na::Isometry3::new(
+            na::vector![id as f64 * 2.0 - 4.0, 0.0, 15.0],
+            na::vector![0.1, 0.2, 0.0],
+        );
+        detections.push(project_detection(
+            family,
+            id as i32,
+            &tag_location,
+            &object_location,
+            &camera_mat,
+        ));
+        object_locations.insert(*name, object_location);
+    }
+    assert_eq!(locator.solve_budget(), None);
+    locator.set_solve_budget(Some(2));
+    let start = FrameTimestamp::now();
+    let at = |frame: u32| start + Duration::from_millis(33) * frame;
+
+    // the objects are solved in turn, least recently solved first
+    let expected_solved = [["a", "b"], ["c", "d"], ["a", "e"], ["b", "c"], ["a", "d"]];
+    let mut last_solved = BTreeMap::new();
+    for (frame, expected) in expected_solved.iter().enumerate() {
+        let located = locator
+            .locate_objects_owned(at(frame as u32), &detections)
+            .unwrap();
+        let solved = located
+            .confidence()
+            .keys()
+            .copied()
+            .collect::<BTreeSet<_>>();
+        assert_eq!(solved, BTreeSet::from(*expected));
+        for name in &solved {
+            last_solved.insert(*name, frame);
+        }
+        // every object solved before keeps its last pose, flagged stale
+        let stale = last_solved
+            .keys()
+            .copied()
+            .filter(|name| !solved.contains(name))
+            .collect::<BTreeSet<_>>();
+        assert_eq!(located.stale(), &stale);
+        assert_eq!(located.name_map().len(), last_solved.len());
+        for (name, location) in located.name_map() {
+            assert_isometry_eq(location, &object_locations[name], 1e-4);
+        }
+        assert!(located.lost_map().is_empty());
+    }
+
+    // all objects are solved without the budget
+    locator.set_solve_budget(None);
+    let located = locator.locate_objects_owned(at(5), &detections).unwrap();
+    assert_eq!(located.confidence().len(), names.len());
+    assert!(located.stale().is_empty());
+}
+
+#[test]
+fn test_solve_budget_longer_than_forget_duration() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let names = ["a", "b", "c", "d"];
+    let forget_duration = Duration::from_millis(50);
+    let mut locator = TaggedObjectLocator::new(camera);
+    let mut detections = Vec::new();
+    for (id, name) in names.iter().enumerate() {
+        let mut object = TaggedObject::new(*name);
+        let tag_location = TagLocation::new_size(1.0);
+        object
+            .tags
+            .insert(TagIndex::new(family, id as i32), tag_location.clone());
+        locator.add(&object).unwrap();
+        locator.set_forget_duration(name, forget_duration);
+        let object_location = na::Isometry3::new(
+            na::vector![id as f64 * 2.0 - 3.0, 0.0, 15.0],
+            na::vector![0.1, 0.2, 0.0],
+        );
+        detections.push(project_detection(
+            family,
+            id as i32,
+            &tag_location,
+            &object_location,
+            &camera_mat,
+        ));
+    }
+    locator.set_solve_budget(Some(1));
+    let start = FrameTimestamp::now();
+    let at = |frame: u32| start + Duration::from_millis(33) * frame;
+
+    // each object is solved every 4 frames, longer than its forget duration, but it stays located while it
+    // is detected
+    for frame in 0..12 {
+        let located = locator
+            .locate_objects_owned(at(frame), &detections)
+            .unwrap();
+        assert_eq!(located.confidence().len(), 1);
+        assert_eq!(
+            located.name_map().len(),
+            names.len().min(frame as usize + 1)
+        );
+        assert!(located.lost_map().is_empty());
+    }
+
+    // an object that is no longer detected is forgotten as usual
+    let located = locator
+        .locate_objects_owned(at(12), &detections[1..])
+        .unwrap();
+    assert!(located.lost_map().contains_key("a"));
+    assert!(!located.name_map().contains_key("a"));
+}
+
+#[test]
+fn test_tracking_state() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let mut object = TaggedObject::new("object");
+    for (id, x) in [(0, -2.0), (1, 2.0)] {
+        object.tags.insert(
+            TagIndex::new(family, id),
+            TagLocation::new(1.0, na::Vector3::default(), na::vector![x, 0.0, 0.0]),
+        );
+    }
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+    locator.set_cold_start_iterations(Some(50));
+    assert_eq!(locator.stable_frames(), DEFAULT_STABLE_FRAMES);
+    assert_eq!(
+        locator.tracking_state("object"),
+        Some(TrackingState::Uninitialized)
+    );
+
+    let project = |object_location: &na::Isometry3<f64>| {
+        object
+            .tags
+            .iter()
+            .map(|(tag_index, tag_location)| {
+                project_detection(
+                    family,
+                    tag_index.id,
+                    tag_location,
+                    object_location,
+                    &camera_mat,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let start = FrameTimestamp::now();
+    let at = |frame: u32| start + Duration::from_millis(33) * frame;
+    let first = na::Isometry3::new(na::vector![0.5, -0.3, 15.0], na::vector![0.2, 0.1, 0.3]);
+    let second = na::Isometry3::new(na::vector![-1.0, 0.5, 12.0], na::vector![0.0, 0.4, 0.0]);
+
+    // a jump while initializing restarts the count of consistent poses
+    let expected = [
+        (&first, TrackingState::Initializing),
+        (&first, TrackingState::Initializing),
+        (&second, TrackingState::Initializing),
+        (&second, TrackingState::Initializing),
+        (&second, TrackingState::Tracking),
+        // tracked objects stay tracked until they are lost
+        (&first, TrackingState::Tracking),
+    ];
+    for (frame, (object_location, state)) in expected.into_iter().enumerate() {
+        let located = locator
+            .locate_objects_owned(at(frame as u32), &project(object_location))
+            .unwrap();
+        assert_isometry_eq(&located.name_map()["object"], object_location, 1e-4);
+        assert_eq!(located.tracking(), &BTreeMap::from([("object", state)]));
+        assert_eq!(locator.tracking_state("object"), Some(state));
+    }
+    // the time to fix is measured from the first pose, and not reset by the jump
+    assert_eq!(
+        locator.time_to_fix("object"),
+        Some(Duration::from_millis(33) * 4)
+    );
+
+    // a lost object is initialized again
+    let forget_duration = locator.config("object").unwrap().forget_duration;
+    let later = at(5) + forget_duration + Duration::from_millis(1);
+    let located = locator.locate_objects_owned(later, &[]).unwrap();
+    assert!(located.lost_map().contains_key("object"));
+    assert!(located.tracking().is_empty());
+    assert_eq!(
+        locator.tracking_state("object"),
+        Some(TrackingState::Uninitialized)
+    );
+    let located = locator
+        .locate_objects_owned(later + Duration::from_millis(33), &project(&first))
+        .unwrap();
+    assert_eq!(located.tracking()["object"], TrackingState::Initializing);
+}
+
+#[test]
+fn test_consistency_threshold() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let mut object = TaggedObject::new("object");
+    for (id, x, y) in [
+        (0, -1.5, -1.5),
+        (1, 1.5, -1.5),
+        (2, -1.5, 1.5),
+        (3, 1.5, 1.5),
+    ] {
+        object.tags.insert(
+            TagIndex::new(family, id),
+            TagLocation::new(1.0, na::Vector3::default(), na::vector![x, y, 0.0]),
+        );
+    }
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+    assert_eq!(locator.consistency_threshold(), None);
+
+    let object_location =
+        na::Isometry3::new(na::vector![0.5, -0.3, 15.0], na::vector![0.2, 0.1, 0.3]);
+    // tag 3 is detected where the object would be if it were moved aside, like a misread tag of another
+    // object
+    let misplaced_location = na::Translation3::new(2.0, 1.0, 0.0) * object_location;
+    let detections = (0..4)
+        .map(|id| {
+            let location = if id == 3 {
+                &misplaced_location
+            } else {
+                &object_location
+            };
+            let tag_location = &object.tags[&TagIndex::new(family, id)];
+            project_detection(family, id, tag_location, location, &camera_mat)
+        })
+        .collect::<Vec<_>>();
+    let start = FrameTimestamp::now();
+
+    // without the threshold, the misplaced tag drags the pose away
+    let located = locator.locate_objects_owned(start, &detections).unwrap();
+    assert!(located.inconsistent_tags().is_empty());
+    let error =
+        located.name_map()["object"].translation.vector - object_location.translation.vector;
+    assert!(error.norm() > 0.1);
+
+    // with the threshold, the misplaced tag is dropped
+    locator.set_consistency_threshold(Some(5.0));
+    let located = locator
+        .locate_objects_owned(start + Duration::from_millis(33), &detections)
+        .unwrap();
+    assert_eq!(
+        located.inconsistent_tags(),
+        &HashSet::from([TagIndex::new(family, 3)])
+    );
+    assert_isometry_eq(&located.name_map()["object"], &object_location, 1e-4);
+
+    // consistent tags are kept
+    let located = locator
+        .locate_objects_owned(start + Duration::from_millis(66), &detections[..3])
+        .unwrap();
+    assert!(located.inconsistent_tags().is_empty());
+    assert_isometry_eq(&located.name_map()["object"], &object_location, 1e-4);
+
+    // the object's minimum number of tags is never dropped
+    locator.set_min_tags("object", 4);
+    let located = locator
+        .locate_objects_owned(start + Duration::from_millis(99), &detections)
+        .unwrap();
+    assert!(located.inconsistent_tags().is_empty());
+}
+
+#[test]
+fn test_ground_plane() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let floor = TaggedObject::new_simple("floor", family, 0, 2.0);
+    let cube = TaggedObject::new_simple("cube", family, 1, 1.0);
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&floor).unwrap();
+    locator.add(&cube).unwrap();
+    assert!(!locator.capture_ground_plane("floor"));
+    assert_eq!(locator.ground_plane(), None);
+
+    // the floor tag is seen from above at an angle, and the cube's tag faces up 3 units above the floor
+    let floor_location =
+        na::Isometry3::new(na::vector![-1.0, 2.0, 20.0], na::vector![-0.6, 0.1, 0.2]);
+    let cube_location = floor_location * na::Translation3::new(4.0, 1.0, -3.0);
+    let detections = [
+        project_detection(
+            family,
+            0,
+            &floor.tags[&TagIndex::new(family, 0)],
+            &floor_location,
+            &camera_mat,
+        ),
+        project_detection(
+            family,
+            1,
+            &cube.tags[&TagIndex::new(family, 1)],
+            &cube_location,
+            &camera_mat,
+        ),
+    ];
+    let start = FrameTimestamp::now();
+    let located = locator.locate_objects_owned(start, &detections).unwrap();
+    assert_isometry_eq(&located.name_map()["cube"], &cube_location, 1e-4);
+
+    // the poses are published relative to the captured plane, with z pointing up
+    assert!(locator.capture_ground_plane("floor"));
+    assert!(!locator.capture_ground_plane("nonexistent"));
+    let later = start + Duration::from_millis(33);
+    let located = locator.locate_objects_owned(later, &detections).unwrap();
+    let cube_on_ground = located.name_map()["cube"];
+    assert!((cube_on_ground.translation.vector - na::vector![4.0, -1.0, 3.0]).norm() < 1e-4);
+    assert!(cube_on_ground.rotation.angle() > std::f64::consts::PI - 1e-4);
+    assert!(located.name_map()["floor"].translation.vector.norm() < 1e-4);
+
+    // a plane set explicitly is used as given
+    let ground_plane = na::Isometry3::translation(0.0, 0.0, 20.0);
+    locator.set_ground_plane(Some(ground_plane));
+    let located = locator
+        .locate_objects_owned(later + Duration::from_millis(33), &detections)
+        .unwrap();
+    assert_isometry_eq(
+        &located.name_map()["cube"],
+        &(ground_plane.inverse() * cube_location),
+        1e-4,
+    );
+}
+
+#[test]
+fn test_bundle_window() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let mut object = TaggedObject::new("anchor");
+    for (id, (x, y)) in [
+        (0, (-2.0, -2.0)),
+        (1, (2.0, -2.0)),
+        (2, (2.0, 2.0)),
+        (3, (-2.0, 2.0)),
+    ] {
+        object.tags.insert(
+            TagIndex::new(family, id),
+            TagLocation::new(1.0, na::Vector3::default(), na::vector![x, y, 0.0]),
+        );
+    }
+    let mut single_frame = TaggedObjectLocator::new(camera.clone());
+    single_frame.add(&object).unwrap();
+    let mut adjusted = TaggedObjectLocator::new(camera);
+    adjusted.add(&object).unwrap();
+    assert_eq!(adjusted.config("anchor").unwrap().bundle_window, None);
+    assert!(adjusted.set_bundle_window("anchor", Some(15)));
+    assert!(!adjusted.set_bundle_window("missing", Some(15)));
+
+    // the corners of a static object are detected with noise in every frame
+    let mut rng = rand::rng();
+    let mut project = |object_location: &na::Isometry3<f64>, noise: f64| {
+        object
+            .tags
+            .iter()
+            .map(|(tag_index, tag_location)| {
+                let mut project = |point: &na::Point3<f64>| {
+                    let point = camera_mat
+                        * object_location.transform_point(&tag_location.transform_point(point));
+                    [
+                        point.x / point.z + rng.random_range(-noise..=noise),
+                        point.y / point.z + rng.random_range(-noise..=noise),
+                    ]
+                };
+                let center = project(&na::Point3::origin());
+                let corners = std::array::from_fn(|i| project(&TAG_CORNERS[i]));
+                detection_from_corners(family, tag_index.id, center, corners)
+            })
+            .collect::<Vec<_>>()
+    };
+    let start = FrameTimestamp::now();
+    let at = |frame: u32| start + Duration::from_millis(33) * frame;
+    let anchor = na::Isometry3::new(na::vector![0.3, -0.2, 8.0], na::vector![0.1, -0.2, 0.05]);
+    let mut single_frame_poses = Vec::new();
+    let mut adjusted_poses = Vec::new();
+    for frame in 0..60 {
+        let detections = project(&anchor, 0.5);
+        let single_frame_pose = single_frame
+            .locate_objects_owned(at(frame), &detections)
+            .unwrap()
+            .name_map()["anchor"];
+        let adjusted_pose = adjusted
+            .locate_objects_owned(at(frame), &detections)
+            .unwrap()
+            .name_map()["anchor"];
+        if frame >= 20 {
+            single_frame_poses.push(single_frame_pose);
+            adjusted_poses.push(adjusted_pose);
+        }
+    }
+    let mean_squared_error = |poses: &[na::Isometry3<f64>]| {
+        let translation = poses
+            .iter()
+            .map(|pose| (pose.translation.vector - anchor.translation.vector).norm_squared())
+            .sum::<f64>()
+            / poses.len() as f64;
+        let rotation = poses
+            .iter()
+            .map(|pose| pose.rotation.angle_to(&anchor.rotation).powi(2))
+            .sum::<f64>()
+            / poses.len() as f64;
+        (translation, rotation)
+    };
+    let (single_frame_translation, single_frame_rotation) = mean_squared_error(&single_frame_poses);
+    let (adjusted_translation, adjusted_rotation) = mean_squared_error(&adjusted_poses);
+    assert!(
+        adjusted_translation < 0.5 * single_frame_translation,
+        "translation error {} is not lower than {} of the single frame poses",
+        adjusted_translation,
+        single_frame_translation
+    );
+    assert!(
+        adjusted_rotation < 0.5 * single_frame_rotation,
+        "rotation error {} is not lower than {} of the single frame poses",
+        adjusted_rotation,
+        single_frame_rotation
+    );
+    assert_isometry_eq(adjusted_poses.last().unwrap(), &anchor, 0.05);
+
+    // the static frames are discarded as soon as the object moves
+    let moved = na::Isometry3::new(na::vector![-1.0, 0.5, 10.0], na::vector![0.0, 0.3, 0.0]);
+    let located = adjusted
+        .locate_objects_owned(at(60), &project(&moved, 0.0))
+        .unwrap();
+    assert_isometry_eq(&located.name_map()["anchor"], &moved, 1e-4);
+}
+
+#[test]
+fn test_snapshot() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let object = TaggedObject::new_simple("object", family, 0, 1.0);
+    let mut locator = TaggedObjectLocator::new(camera);
+    locator.add(&object).unwrap();
+    let shared = Arc::new((Mutex::new(LocatedObjects::new()), Condvar::new()));
+    let frame_count = 100;
+    let start = FrameTimestamp::now();
+    let at = |frame: u32| start + Duration::from_millis(33) * frame;
+    // the object moves along x by a known amount in each frame
+    let location_at = |frame: u32| {
+        na::Isometry3::new(
+            na::vector![frame as f64 * 0.05 - 2.5, 0.0, 15.0],
+            na::zero(),
+        )
+    };
+    let done = std::sync::atomic::AtomicBool::new(false);
+
+    thread::scope(|s| {
+        let readers = (0..4)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut last_timestamp = None;
+                    let mut snapshots = 0;
+                    while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                        let located = snapshot(&shared);
+                        snapshots += 1;
+                        let Some(location) = located.name_map.get("object") else {
+                            continue;
+                        };
+                        // the pose belongs to the frame of the timestamp, and the frames never go back
+                        let frame = (located.timestamp.duration_since(start.instant).as_millis()
+                            / 33) as u32;
+                        assert_isometry_eq(location, &location_at(frame), 1e-4);
+                        assert!(last_timestamp <= Some(located.timestamp));
+                        last_timestamp = Some(located.timestamp);
+                    }
+                    snapshots
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for frame in 0..frame_count {
+            let detections = [project_detection(
+                family,
+                0,
+                &object.tags[&TagIndex::new(family, 0)],
+                &location_at(frame),
+                &camera_mat,
+            )];
+            locator
+                .locate_objects(at(frame), &detections, shared.clone())
+                .unwrap();
+        }
+        done.store(true, std::sync::atomic::Ordering::Relaxed);
+        for reader in readers {
+            assert!(reader.join().unwrap() > 0);
+        }
+    });
+
+    // the last snapshot owns its names, so it outlives the object and the locator
+    let last = snapshot(&shared);
+    drop(shared);
+    drop(locator);
+    drop(object);
+    assert_isometry_eq(
+        &last.name_map["object"],
+        &location_at(frame_count - 1),
+        1e-4,
+    );
+    assert_eq!(last.timestamp, at(frame_count - 1).instant);
+}
+
+#[test]
+fn test_covariance_fusion() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let mut object = TaggedObject::new("anchor");
+    for (id, (x, y)) in [
+        (0, (-2.0, -2.0)),
+        (1, (2.0, -2.0)),
+        (2, (2.0, 2.0)),
+        (3, (-2.0, 2.0)),
+    ] {
+        object.tags.insert(
+            TagIndex::new(family, id),
+            TagLocation::new(1.0, na::Vector3::default(), na::vector![x, y, 0.0]),
+        );
+    }
+    let mut locator = TaggedObjectLocator::new(camera.clone());
+    locator.add(&object).unwrap();
+    assert_eq!(locator.covariance_fusion(), None);
+
+    let project = |object_location: &na::Isometry3<f64>| {
+        object
+            .tags
+            .iter()
+            .map(|(tag_index, tag_location)| {
+                project_detection(
+                    family,
+                    tag_index.id,
+                    tag_location,
+                    object_location,
+                    &camera_mat,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let trace = |covariance: &PoseCovariance| covariance.finite().unwrap().trace();
+    let start = FrameTimestamp::now();
+    let at = |frame: u32| start + Duration::from_millis(33) * frame;
+    let anchor = na::Isometry3::new(na::vector![0.3, -0.2, 8.0], na::vector![0.1, -0.2, 0.05]);
+
+    // no covariance is published unless the fusion is enabled
+    let located = locator
+        .locate_objects_owned(at(0), &project(&anchor))
+        .unwrap();
+    assert!(located.covariance().is_empty());
+    locator.set_covariance_fusion(Some(COVARIANCE_FUSION_HALF_LIFE));
+
+    // the first fused covariance is the frame's own covariance
+    let single_frame = TaggedObjectLocator::calculate_covariance(
+        camera_mat,
+        object.tags.values().cloned(),
+        anchor,
+        camera.corner_variance,
+    )
+    .unwrap();
+    let mut traces = Vec::new();
+    for frame in 1..=20 {
+        let located = locator
+            .locate_objects_owned(at(frame), &project(&anchor))
+            .unwrap();
+        traces.push(trace(&located.covariance()["anchor"]));
+    }
+    assert!((traces[0] - trace(&single_frame)).abs() < 1e-3 * trace(&single_frame));
+
+    // the fused covariance of the static object shrinks with every consistent frame
+    for pair in traces[1..].windows(2) {
+        assert!(pair[1] < pair[0], "{:?}", traces);
+    }
+    let fused = *traces.last().unwrap();
+    assert!(fused < traces[0] / 5.0, "{:?}", traces);
+
+    // once the object moves, its covariance falls back to the frame's own covariance
+    let moved = na::Isometry3::new(na::vector![1.3, -0.2, 8.0], na::vector![0.1, -0.2, 0.05]);
+    let located = locator
+        .locate_objects_owned(at(21), &project(&moved))
+        .unwrap();
+    let moved_trace = trace(&located.covariance()["anchor"]);
+    let single_frame = TaggedObjectLocator::calculate_covariance(
+        camera_mat,
+        object.tags.values().cloned(),
+        moved,
+        camera.corner_variance,
+    )
+    .unwrap();
+    assert!((moved_trace - trace(&single_frame)).abs() < 1e-3 * trace(&single_frame));
+    assert!(moved_trace > fused * 5.0);
+}
+
+#[test]
+fn test_overlapping_pairs() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let family = ApriltagFamily::Tag36h11;
+    let mut screen = TaggedObject::new("screen");
+    for (id, (x, y)) in [
+        (0, (-2.0, -1.0)),
+        (1, (2.0, -1.0)),
+        (2, (2.0, 1.0)),
+        (3, (-2.0, 1.0)),
+    ] {
+        screen.tags.insert(
+            TagIndex::new(family, id),
+            TagLocation::new(1.0, na::Vector3::default(), na::vector![x, y, 0.0]),
+        );
+    }
+    // the wand is a stick along its y axis
+    let mut wand = TaggedObject::new("wand");
+    for (id, y) in [(4, 0.0), (5, -4.0)] {
+        wand.tags.insert(
+            TagIndex::new(family, id),
+            TagLocation::new(0.5, na::Vector3::default(), na::vector![0.0, y, 0.0]),
+        );
+    }
+    let anchor = TaggedObject::new_simple("anchor", family, 6, 1.0);
+    let mut locator = TaggedObjectLocator::new(camera);
+    for object in [&screen, &wand, &anchor] {
+        locator.add(object).unwrap();
+    }
+    let object_map = locator.get_object_map();
+
+    let mut located = LocatedObjects::new();
+    assert!(located.overlapping_pairs(&object_map).is_empty());
+    located
+        .name_map
+        .insert("screen", na::Isometry3::translation(0.0, 0.0, 10.0));
+    located
+        .name_map
+        .insert("anchor", na::Isometry3::translation(20.0, 0.0, 10.0));
+    // the wand points away from the camera, passing beside the screen
+    let pointing = na::vector![std::f64::consts::FRAC_PI_2, 0.0, 0.0];
+    located.name_map.insert(
+        "wand",
+        na::Isometry3::new(na::vector![0.0, 2.0, 10.0], pointing),
+    );
+    assert!(located.overlapping_pairs(&object_map).is_empty());
+
+    // the wand passes through the screen
+    located.name_map.insert(
+        "wand",
+        na::Isometry3::new(na::vector![0.0, 1.0, 10.0], pointing),
+    );
+    assert_eq!(
+        located.overlapping_pairs(&object_map),
+        vec![("screen", "wand")]
+    );
+
+    // objects without tags in the map never overlap
+    let mut partial_map = object_map.clone();
+    partial_map.remove("wand");
+    assert!(located.overlapping_pairs(&partial_map).is_empty());
 }
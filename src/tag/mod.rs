@@ -1,13 +1,15 @@
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Condvar, Mutex, RwLock};
-use std::thread;
-use std::time::SystemTime;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
 
+use opencv::core;
+#[cfg(feature = "visualize")]
+use opencv::highgui;
 use opencv::imgproc;
 use opencv::prelude::*;
-#[cfg(feature = "visualize")]
-use opencv::{core, highgui};
 
+use crate::camera::{CameraProperty, LatestFrameReceiver, PauseControl};
+use crate::metrics::{METRICS_LOG_INTERVAL, Metrics};
 use crate::tag::apriltag::ImageU8View;
 
 extern crate nalgebra as na;
@@ -24,150 +26,633 @@ pub mod locator;
 /// Defines the errors related to object tagging and tag recognitions
 pub mod error;
 
-pub fn locator_thread_main<'a>(
+/// Draws the detected tags and located objects onto camera frames
+pub mod draw;
+
+/// The interval at which the locator thread logs the registered tags that have never been detected.
+pub const NEVER_SEEN_TAGS_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The relative error of the measured distance above which the self test flags the setup as miscalibrated.
+pub const SELF_TEST_TOLERANCE: f64 = 0.05;
+
+/// The interval at which the self-test thread logs its report.
+pub const SELF_TEST_LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The key in the OpenCV window that pauses or resumes the camera.
+#[cfg(feature = "visualize")]
+pub const PAUSE_KEY: i32 = b' ' as i32;
+
+/// The key in the OpenCV window that captures a single frame while the camera is paused.
+#[cfg(feature = "visualize")]
+pub const STEP_KEY: i32 = b'n' as i32;
+
+/// The interval (in milliseconds) at which the OpenCV window's keys are polled while the camera is paused.
+#[cfg(feature = "visualize")]
+const PAUSED_KEY_POLL_MS: i32 = 50;
+
+/// Optional preprocessing applied to the grayscale image before the apriltag detection. See
+/// `detect_in_roi`.
+///
+/// The default configuration does nothing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Preprocessing {
+    /// The sigma (in pixels) of the gaussian blur applied to the image. A slight blur helps quad detection
+    /// on noisy low-light footage.
+    pub blur_sigma: Option<f64>,
+    /// Whether to stretch the image's intensity range to the full 8-bit range. Helps low-contrast footage.
+    pub stretch_contrast: bool,
+    /// The factor by which the image is downscaled before the other preprocessing and the detection, e.g.
+    /// 0.5 to detect on an image of half the width and height. The detected corners are scaled back to the
+    /// full resolution, so the camera matrix stays valid.
+    ///
+    /// Unlike the detector's `quad_decimate`, which only decimates the image for the quad detection, this
+    /// also shrinks the blur and the contrast stretching, at the cost of missing small tags. Not downscaled
+    /// if unset.
+    pub detect_scale: Option<f64>,
+}
+
+impl Preprocessing {
+    /// Apply the blur and the contrast stretching to `image` in place. The image is not downscaled, which is
+    /// done by `detect_in_roi`.
+    pub fn apply<T>(&self, image: &mut ImageU8View<T>) {
+        if let Some(sigma) = self.blur_sigma
+            && sigma > 0.0
+        {
+            // the same kernel size as the apriltag library uses for `quad_sigma`
+            let mut kernel_size = (4.0 * sigma) as i32;
+            if kernel_size % 2 == 0 {
+                kernel_size += 1;
+            }
+            image.gaussian_blur(sigma, kernel_size);
+        }
+        if self.stretch_contrast {
+            image.stretch_contrast();
+        }
+    }
+}
+
+/// Convert a camera frame to an 8-bit grayscale image, which the apriltag detector requires.
+///
+/// Returns `false` without touching `gray` if the frame is already an 8-bit single-channel image, in which
+/// case the frame itself should be passed to the detector. 16-bit single-channel frames are scaled down to
+/// 8 bits, and 3- or 4-channel frames are treated as BGR or BGRA images.
+pub fn convert_to_gray(frame: &Mat, gray: &mut Mat) -> Result<bool, Box<dyn std::error::Error>> {
+    match frame.typ() {
+        core::CV_8UC1 => return Ok(false),
+        core::CV_16UC1 => frame.convert_to(gray, core::CV_8U, 1.0 / 256.0, 0.0)?,
+        core::CV_8UC3 | core::CV_8UC4 => imgproc::cvt_color(
+            frame,
+            gray,
+            if frame.channels() == 3 {
+                imgproc::COLOR_BGR2GRAY
+            } else {
+                imgproc::COLOR_BGRA2GRAY
+            },
+            0,
+            core::AlgorithmHint::ALGO_HINT_ACCURATE,
+        )?,
+        typ => {
+            return Err(format!(
+                "Unsupported camera frame type {}!",
+                core::type_to_string(typ)?
+            )
+            .into());
+        }
+    }
+    Ok(true)
+}
+
+/// Preprocess and run `detector` on the region `roi` of `image`, or on the whole image if `roi` is `None`.
+///
+/// The region is clamped to the image's bounds, and the detections are moved back into the coordinates of
+/// the whole image. The region is copied out of the image before it is preprocessed, so that pixels outside
+/// of it cost nothing, and the image itself is only preprocessed in place without a region. If
+/// `preprocessing` has a detection scale, the region is downscaled before it is preprocessed, and the
+/// detections are scaled back as well.
+pub fn detect_in_roi<T>(
+    detector: &apriltag::ApriltagDetector,
+    image: &mut ImageU8View<T>,
+    roi: Option<core::Rect>,
+    preprocessing: &Preprocessing,
+) -> Vec<apriltag::ApriltagDetection> {
+    let Some(roi) = roi else {
+        return detect_scaled(detector, image, preprocessing);
+    };
+    let (width, height) = (image.inner_ref().width, image.inner_ref().height);
+    let x0 = roi.x.clamp(0, width);
+    let y0 = roi.y.clamp(0, height);
+    let x1 = roi.x.saturating_add(roi.width).clamp(x0, width);
+    let y1 = roi.y.saturating_add(roi.height).clamp(y0, height);
+    if x1 == x0 || y1 == y0 {
+        return Vec::new();
+    }
+    let mut cropped = image.crop(x0, y0, x1 - x0, y1 - y0);
+    let mut detections = detect_scaled(
+        detector,
+        &mut ImageU8View::from(&mut cropped),
+        preprocessing,
+    );
+    for detection in &mut detections {
+        detection.translate(na::vector![x0 as f64, y0 as f64]);
+    }
+    detections
+}
+
+/// Preprocess and run `detector` on `image`, downscaled by the detection scale of `preprocessing` if it is
+/// set, and scale the detections back into the coordinates of `image`.
+///
+/// The blur's sigma is scaled along with the image, so that it blurs the same features. If the image cannot
+/// be downscaled, it is detected at the full resolution instead.
+fn detect_scaled<T>(
+    detector: &apriltag::ApriltagDetector,
+    image: &mut ImageU8View<T>,
+    preprocessing: &Preprocessing,
+) -> Vec<apriltag::ApriltagDetection> {
+    let scale = preprocessing
+        .detect_scale
+        .filter(|scale| *scale > 0.0 && *scale != 1.0);
+    let scaled = scale.map(|scale| (scale, image.resized(scale)));
+    let (scale, mut scaled) = match scaled {
+        Some((scale, Ok(scaled))) => (scale, scaled),
+        Some((_, Err(e))) => {
+            log::warn!(
+                "Failed to downscale the image for detection, detecting at the full resolution: {}",
+                e
+            );
+            preprocessing.apply(image);
+            return detector.detect(image.inner_mut());
+        }
+        None => {
+            preprocessing.apply(image);
+            return detector.detect(image.inner_mut());
+        }
+    };
+    let mut scaled_image = ImageU8View::from(&mut scaled);
+    Preprocessing {
+        blur_sigma: preprocessing.blur_sigma.map(|sigma| sigma * scale),
+        ..preprocessing.clone()
+    }
+    .apply(&mut scaled_image);
+    let mut detections = detector.detect(scaled_image.inner_mut());
+    for detection in &mut detections {
+        detection.scale(1.0 / scale);
+    }
+    detections
+}
+
+/// Main function of the locator thread, which processes the newest frame received from `frames` until the
+/// camera thread exits.
+///
+/// The detector's tag families are kept in sync with the families used by the objects registered in
+/// `object_locator`. The families are taken from `family_set`, with up to `bits_corrected` bit errors
+/// corrected in each tag. If `roi` is given, tags are only detected inside of it.
+///
+/// Each processed frame is recorded in `metrics`, a snapshot of which is logged every
+/// `METRICS_LOG_INTERVAL`.
+///
+/// With the `visualize` feature, `PAUSE_KEY` and `STEP_KEY` in the OpenCV window control `pause`, and any
+/// other key stops the thread.
+pub fn locator_thread_main<'a, 'f>(
     termination_signal: Arc<AtomicBool>,
-    shared_frame: Arc<RwLock<(Mat, SystemTime)>>,
-    detector: apriltag::ApriltagDetector,
+    frames: LatestFrameReceiver,
+    #[cfg_attr(not(feature = "visualize"), allow(unused_variables))] pause: Arc<PauseControl>,
+    metrics: Arc<Metrics>,
+    mut detector: apriltag::ApriltagDetector<'f>,
+    family_set: &'f apriltag::ApriltagFamilySet,
+    bits_corrected: i32,
+    preprocessing: Preprocessing,
+    roi: Option<core::Rect>,
     mut object_locator: locator::TaggedObjectLocator<'a>,
     located_objects: Arc<(Mutex<locator::LocatedObjects<'a>>, Condvar)>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     #[cfg(feature = "visualize")]
     let object_map = object_locator.get_object_map();
     #[cfg(feature = "visualize")]
-    let camera_mat = object_locator.camera().camera_mat_na()?;
+    let camera = object_locator.camera().clone();
 
     #[cfg(feature = "visualize")]
     highgui::named_window("window", highgui::WINDOW_KEEPRATIO)?;
+    #[cfg(feature = "visualize")]
+    log::info!(
+        "Press space in the camera window to pause or resume, and N to step a frame while paused"
+    );
 
-    let mut last_recorded_timestamp = SystemTime::UNIX_EPOCH;
+    let mut last_never_seen_log = Instant::now();
+    let mut last_metrics_log = Instant::now();
     while !termination_signal.load(Ordering::Relaxed) {
-        let mut shared_frame_mat = loop {
-            // park the thread and wait for the camera thread to unpark it
-            thread::park();
-            // when unparked, read the camera frame
-            let shared_frame_read = shared_frame.read().unwrap();
-            if shared_frame_read.1 != last_recorded_timestamp {
-                // check the timestamp to prevent false unparking
-                last_recorded_timestamp = shared_frame_read.1;
-                break shared_frame_read.0.clone();
-            }
+        // wait for the camera thread's next frame, until the camera thread exits
+        let Some((mut frame, timestamp)) = frames.recv() else {
+            break;
         };
         // to ensure that the timestamp accurately reflects the time at which
         // the objects are located.
         let mut gray = Mat::default();
-        imgproc::cvt_color(
-            &shared_frame_mat,
-            &mut gray,
-            imgproc::COLOR_BGR2GRAY,
-            0,
-            opencv::core::AlgorithmHint::ALGO_HINT_ACCURATE,
-        )?;
-        let mut image = ImageU8View::from(&mut gray);
-        let detections = detector.detect(image.inner_mut());
+        let mut image = if convert_to_gray(&frame, &mut gray)? {
+            ImageU8View::from(&mut gray)
+        } else {
+            // the frame is already an 8-bit grayscale image
+            ImageU8View::from(&mut frame)
+        };
+        if let Some(families) = object_locator.poll_required_families() {
+            log::info!(
+                "Detecting tag families: {}",
+                families
+                    .iter()
+                    .map(|family| family.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            detector.update_families(family_set, &families, bits_corrected);
+        }
+        let detections = detect_in_roi(&detector, &mut image, roi, &preprocessing);
+
+        let solve_start = Instant::now();
+        object_locator.locate_objects(timestamp, detections.as_slice(), located_objects.clone())?;
+        let solve_time = solve_start.elapsed();
+        let tracked_objects = located_objects.0.lock().unwrap().name_map().len();
+        metrics.frame_processed(detections.len(), solve_time, tracked_objects);
+        if last_metrics_log.elapsed() >= METRICS_LOG_INTERVAL {
+            log::info!("Metrics: {}", metrics.snapshot());
+            last_metrics_log = Instant::now();
+        }
 
-        object_locator.locate_objects(
-            last_recorded_timestamp,
-            detections.as_slice(),
-            located_objects.clone(),
-        )?;
+        if last_never_seen_log.elapsed() >= NEVER_SEEN_TAGS_LOG_INTERVAL {
+            let never_seen_tags = object_locator.never_seen_tags();
+            if !never_seen_tags.is_empty() {
+                log::warn!(
+                    "The following tags have never been detected: {}",
+                    never_seen_tags
+                        .iter()
+                        .map(|tag_index| tag_index.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+            last_never_seen_log = Instant::now();
+        }
 
         #[cfg(feature = "visualize")]
         {
-            use crate::tag::locator::TAG_CORNERS;
-
-            // draw the detected apriltag on the frame
-            for detection in &detections {
-                for i in 0..4 {
-                    let start_pt = detection.corners()[i];
-                    let end_pt = detection.corners()[(i + 1) % 4];
-                    imgproc::line(
-                        &mut shared_frame_mat,
-                        core::Point::new(start_pt.x.round() as i32, start_pt.y.round() as i32),
-                        core::Point::new(end_pt.x.round() as i32, end_pt.y.round() as i32),
-                        core::Scalar::new(
-                            if i != 0 { 255. } else { 0. },
-                            if i != 1 { 255. } else { 0. },
-                            if i != 2 { 255. } else { 0. },
-                            0.,
-                        ),
-                        2,
-                        imgproc::LINE_8,
-                        0,
-                    )?;
-                }
-            }
-            // draw each tag's reprojection on the image
             let lock = located_objects.0.lock().unwrap();
-            for (name, loc) in lock.name_map() {
-                if let Some(object) = object_map.get(*name) {
-                    let color = crate::visualize::utils::generate_random_color(name);
-                    // plot the reprojection of all tags
-                    for (_, tag_loc) in object {
-                        let corners = TAG_CORNERS
-                            .iter()
-                            .map(|point| {
-                                let point1 = tag_loc.0.transform_point(point);
-                                let point2 = loc.transform_point(&point1);
-                                let projected = camera_mat * point2;
-                                projected.xy() / projected.z
-                            })
-                            .collect::<Vec<_>>();
-                        for i in 0..4 {
-                            imgproc::line(
-                                &mut shared_frame_mat,
-                                core::Point::new(corners[i].x as i32, corners[i].y as i32),
-                                core::Point::new(corners[i + 1].x as i32, corners[i + 1].y as i32),
-                                core::Scalar::new(
-                                    color.2 as f64,
-                                    color.1 as f64,
-                                    color.0 as f64, // in the order of BGR
-                                    0.0,
-                                ),
-                                2,
-                                imgproc::LINE_8,
-                                0,
-                            )?;
-                        }
-                        // plot the x, y, and z axes of each tag
-                        for i in 0..3 {
-                            use crate::visualize::utils::{AXES, AXES_COLORS};
-
-                            let axis_origin = camera_mat
-                                * loc.transform_point(
-                                    &tag_loc.0.transform_point(&na::Point3::origin()),
-                                );
-                            let axis_origin = axis_origin.xy() / axis_origin.z;
-                            let axis_end = camera_mat
-                                * loc.transform_point(&tag_loc.0.transform_point(&AXES[i]));
-                            let axis_end = axis_end.xy() / axis_end.z;
-                            let color = AXES_COLORS[i];
-                            imgproc::line(
-                                &mut shared_frame_mat,
-                                core::Point::new(axis_origin.x as i32, axis_origin.y as i32),
-                                core::Point::new(axis_end.x as i32, axis_end.y as i32),
-                                core::Scalar::new(
-                                    color.2 as f64,
-                                    color.1 as f64,
-                                    color.0 as f64,
-                                    0.0,
-                                ), // in the order of BGR
-                                2,
-                                imgproc::LINE_8,
-                                0,
-                            )?;
-                        }
-                    }
-                }
-            }
+            draw::draw_detections(&mut frame, &detections, &lock, &object_map, &camera)?;
             drop(lock);
             // show image
-            highgui::imshow("window", &shared_frame_mat)?;
-            // wait for exit key
-            let key = highgui::wait_key(1)?;
-            if key > 0 && key != 255 {
+            highgui::imshow("window", &frame)?;
+            // handle the keys. While paused, keep polling them until a frame is stepped
+            let mut key = highgui::wait_key(1)?;
+            let exit = loop {
+                if key == PAUSE_KEY {
+                    if pause.toggle() {
+                        log::info!("Paused the camera");
+                    } else {
+                        log::info!("Resumed the camera");
+                    }
+                } else if key == STEP_KEY {
+                    if pause.step() {
+                        break false;
+                    }
+                    log::info!("Paused the camera");
+                } else if key > 0 && key != 255 {
+                    // exit key
+                    break true;
+                }
+                if !pause.is_paused() || termination_signal.load(Ordering::Relaxed) {
+                    break false;
+                }
+                key = highgui::wait_key(PAUSED_KEY_POLL_MS)?;
+            };
+            if exit {
                 break;
             }
         }
     }
     Ok(())
 }
+
+/// Main function of the detect-only thread.
+///
+/// Runs the apriltag detector on each camera frame and logs every detection, without locating any objects.
+/// Useful for checking tag visibility and tag IDs.
+pub fn detect_only_thread_main(
+    termination_signal: Arc<AtomicBool>,
+    frames: LatestFrameReceiver,
+    detector: apriltag::ApriltagDetector,
+    preprocessing: Preprocessing,
+) -> Result<(), Box<dyn std::error::Error>> {
+    while !termination_signal.load(Ordering::Relaxed) {
+        let Some((mut frame, _)) = frames.recv() else {
+            break;
+        };
+        let mut gray = Mat::default();
+        let mut image = if convert_to_gray(&frame, &mut gray)? {
+            ImageU8View::from(&mut gray)
+        } else {
+            // the frame is already an 8-bit grayscale image
+            ImageU8View::from(&mut frame)
+        };
+        let detections = detect_in_roi(&detector, &mut image, None, &preprocessing);
+
+        log::info!("Detected {} tags", detections.len());
+        for detection in &detections {
+            let family = detection
+                .family()
+                .map(|family| format!("{:?}", family))
+                .unwrap_or_else(|_| "unknown".to_string());
+            let corners = detection
+                .corners()
+                .map(|corner| format!("({:.1}, {:.1})", corner.x, corner.y));
+            log::info!(
+                "  {} #{}: decision margin {:.2}, corners [{}]",
+                family,
+                detection.id(),
+                detection.decision_margin(),
+                corners.join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The comparison between a reference tag's located pose and its measured distance from the camera, made
+/// by `self_test_thread_main`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SelfTestReport {
+    /// The tag's located distance along the camera's optical axis, i.e. the z component of its translation.
+    pub measured_distance: f64,
+    /// The tag's distance along the camera's optical axis measured by hand.
+    pub expected_distance: f64,
+    /// The angle between the tag's normal and the camera's optical axis. Unit: radians.
+    pub tilt: f64,
+}
+
+impl SelfTestReport {
+    pub fn new(location: &na::Isometry3<f64>, expected_distance: f64) -> Self {
+        Self {
+            measured_distance: location.translation.z,
+            expected_distance,
+            tilt: (location.rotation * na::Vector3::z()).angle(&na::Vector3::z()),
+        }
+    }
+
+    /// The error of the measured distance relative to the expected distance. Positive if the tag is
+    /// located farther than it is.
+    pub fn relative_error(&self) -> f64 {
+        (self.measured_distance - self.expected_distance) / self.expected_distance
+    }
+
+    /// Whether the relative error is within `SELF_TEST_TOLERANCE`.
+    pub fn passed(&self) -> bool {
+        self.relative_error().abs() <= SELF_TEST_TOLERANCE
+    }
+
+    /// The tag size that would explain the error, if the camera's intrinsics are correct.
+    ///
+    /// The located distance is proportional to the tag size, so a tag size off by some ratio scales the
+    /// distance by the same ratio.
+    pub fn corrected_tag_size(&self, tag_size: f64) -> f64 {
+        tag_size * self.expected_distance / self.measured_distance
+    }
+
+    /// The field of view (in radians) that would explain the error, if the tag size is correct.
+    ///
+    /// The located distance is proportional to the focal length, which is inversely proportional to the
+    /// tangent of half of the field of view.
+    pub fn corrected_fov(&self, fov: f64) -> f64 {
+        2.0 * f64::atan(f64::tan(fov * 0.5) * self.measured_distance / self.expected_distance)
+    }
+}
+
+impl std::fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "distance {:.1} (expected {:.1}, {:+.1}% error), tilt {:.1} degrees",
+            self.measured_distance,
+            self.expected_distance,
+            self.relative_error() * 100.0,
+            self.tilt.to_degrees()
+        )
+    }
+}
+
+/// Main function of the self-test thread, which locates the single-tag object `tag_object` (see
+/// `TaggedObject::new_simple`) held at `expected_distance` from the camera in each frame, and periodically
+/// logs how far off its located distance is.
+///
+/// When the error exceeds `SELF_TEST_TOLERANCE`, the tag size and the field of view that would explain the
+/// error are logged as well, since either of them is the likely culprit.
+pub fn self_test_thread_main(
+    termination_signal: Arc<AtomicBool>,
+    frames: LatestFrameReceiver,
+    detector: apriltag::ApriltagDetector,
+    preprocessing: Preprocessing,
+    camera: CameraProperty,
+    tag_object: &tagged_object::TaggedObject,
+    expected_distance: f64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tag_size = tag_object
+        .tags
+        .values()
+        .next()
+        .ok_or("The self-test object has no tag!")?
+        .half_extents
+        .x
+        * 2.0;
+    let fov = camera.computed_fov();
+    let mut object_locator = locator::TaggedObjectLocator::new(camera);
+    object_locator.add(tag_object)?;
+    log::info!(
+        "Hold the tag of \"{}\" facing the camera, {} away from it along the optical axis",
+        tag_object.name,
+        expected_distance
+    );
+
+    let mut last_log = Instant::now();
+    while !termination_signal.load(Ordering::Relaxed) {
+        let Some((mut frame, timestamp)) = frames.recv() else {
+            break;
+        };
+        let mut gray = Mat::default();
+        let mut image = if convert_to_gray(&frame, &mut gray)? {
+            ImageU8View::from(&mut gray)
+        } else {
+            // the frame is already an 8-bit grayscale image
+            ImageU8View::from(&mut frame)
+        };
+        let detections = detect_in_roi(&detector, &mut image, None, &preprocessing);
+        let located = object_locator.locate_objects_owned(timestamp, &detections)?;
+
+        if last_log.elapsed() < SELF_TEST_LOG_INTERVAL {
+            continue;
+        }
+        last_log = Instant::now();
+        let Some(location) = located.name_map().get(tag_object.name.as_str()) else {
+            log::info!("The tag is not detected");
+            continue;
+        };
+        let report = SelfTestReport::new(location, expected_distance);
+        if report.passed() {
+            log::info!("Passed: {}", report);
+        } else {
+            log::warn!("Failed: {}", report);
+            log::warn!(
+                "Either the tag size is {:.1} instead of {:.1} (measure the black border of the tag, not the \
+                 white margin around it), or the camera's horizontal FOV is {:.1} instead of {:.1} degrees",
+                report.corrected_tag_size(tag_size),
+                tag_size,
+                report.corrected_fov(fov.0).to_degrees(),
+                fov.0.to_degrees()
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tag::apriltag::ImageU8;
+
+    #[test]
+    fn test_grayscale_frame() {
+        // an 8-bit single-channel frame is passed to the view directly
+        let mut frame =
+            Mat::new_rows_cols_with_default(4, 6, core::CV_8UC1, core::Scalar::all(7.0)).unwrap();
+        let mut gray = Mat::default();
+        assert!(!convert_to_gray(&frame, &mut gray).unwrap());
+        assert!(gray.empty());
+        let image = ImageU8View::from(&mut frame);
+        assert_eq!(image.inner_ref().width, 6);
+        assert_eq!(image.inner_ref().height, 4);
+        assert_eq!(image.inner_ref().stride, 6);
+        let buf = unsafe { std::slice::from_raw_parts(image.inner_ref().buf, 24) };
+        assert!(buf.iter().all(|&value| value == 7));
+
+        // a 16-bit frame is scaled down to 8 bits
+        let frame = Mat::new_rows_cols_with_default(4, 6, core::CV_16UC1, core::Scalar::all(512.0))
+            .unwrap();
+        assert!(convert_to_gray(&frame, &mut gray).unwrap());
+        assert_eq!(gray.typ(), core::CV_8UC1);
+        assert_eq!(*gray.at_2d::<u8>(0, 0).unwrap(), 2);
+
+        // a BGR frame is converted to grayscale
+        let frame =
+            Mat::new_rows_cols_with_default(4, 6, core::CV_8UC3, core::Scalar::all(100.0)).unwrap();
+        assert!(convert_to_gray(&frame, &mut gray).unwrap());
+        assert_eq!(gray.typ(), core::CV_8UC1);
+        assert_eq!(*gray.at_2d::<u8>(3, 5).unwrap(), 100);
+    }
+
+    #[test]
+    fn test_preprocessing() {
+        let mut frame =
+            Mat::new_rows_cols_with_default(4, 6, core::CV_8UC1, core::Scalar::all(50.0)).unwrap();
+        *frame.at_2d_mut::<u8>(1, 2).unwrap() = 100;
+        let frame_before = frame.clone();
+
+        // the default preprocessing does nothing
+        Preprocessing::default().apply(&mut ImageU8View::from(&mut frame));
+        assert_eq!(
+            frame.data_bytes().unwrap(),
+            frame_before.data_bytes().unwrap()
+        );
+
+        let preprocessing = Preprocessing {
+            blur_sigma: None,
+            stretch_contrast: true,
+            detect_scale: None,
+        };
+        preprocessing.apply(&mut ImageU8View::from(&mut frame));
+        assert_eq!(*frame.at_2d::<u8>(1, 2).unwrap(), 255);
+        assert_eq!(*frame.at_2d::<u8>(0, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_image_to_mat() {
+        // the image's stride is padded beyond its width
+        let mut image = ImageU8::new(10, 3);
+        let (width, stride) = (image.inner_ref().width, image.inner_ref().stride);
+        assert!(stride > width);
+        for y in 0..3 {
+            for x in 0..10 {
+                unsafe {
+                    *image.inner_mut().buf.add((y * stride + x) as usize) = (y * 10 + x) as u8;
+                }
+            }
+        }
+        let mat = image.to_mat().unwrap();
+        assert_eq!(mat.typ(), core::CV_8UC1);
+        assert_eq!((mat.rows(), mat.cols()), (3, 10));
+        assert_eq!(*mat.at_2d::<u8>(0, 9).unwrap(), 9);
+        assert_eq!(*mat.at_2d::<u8>(2, 3).unwrap(), 23);
+
+        let mut frame = mat.clone();
+        let view_mat = ImageU8View::from(&mut frame).to_mat().unwrap();
+        assert_eq!(view_mat.data_bytes().unwrap(), mat.data_bytes().unwrap());
+    }
+
+    #[test]
+    fn test_crop() {
+        let mut frame =
+            Mat::new_rows_cols_with_default(5, 7, core::CV_8UC1, core::Scalar::all(0.0)).unwrap();
+        for y in 0..5 {
+            for x in 0..7 {
+                *frame.at_2d_mut::<u8>(y, x).unwrap() = (y * 10 + x) as u8;
+            }
+        }
+        let image = ImageU8View::from(&mut frame);
+        // a region flush against the bottom-right corner, whose last row ends at the end of the buffer
+        let cropped = image.crop(4, 2, 3, 3);
+        assert_eq!(cropped.inner_ref().width, 3);
+        assert_eq!(cropped.inner_ref().height, 3);
+        let mat = cropped.to_mat().unwrap();
+        assert_eq!(*mat.at_2d::<u8>(0, 0).unwrap(), 24);
+        assert_eq!(*mat.at_2d::<u8>(2, 2).unwrap(), 46);
+        assert_eq!(*mat.at_2d::<u8>(1, 0).unwrap(), 34);
+    }
+
+    #[test]
+    fn test_self_test_report() {
+        let location = na::Isometry3::new(na::vector![0.1, -0.2, 1.1], na::vector![0.0, 0.0, 0.0]);
+        let report = SelfTestReport::new(&location, 1.0);
+        assert!((report.relative_error() - 0.1).abs() < 1e-12);
+        assert!(!report.passed());
+        assert!(report.tilt.abs() < 1e-12);
+        assert!((report.corrected_tag_size(60.0) - 60.0 / 1.1).abs() < 1e-9);
+
+        // a camera with the corrected FOV has a focal length that locates the tag at the expected distance
+        let fov = f64::to_radians(60.0);
+        let camera = CameraProperty::new((1920, 1080), (Some(fov), None), None).unwrap();
+        let corrected =
+            CameraProperty::new((1920, 1080), (Some(report.corrected_fov(fov)), None), None)
+                .unwrap();
+        let focal = |camera: &CameraProperty| camera.camera_mat_na().unwrap()[(0, 0)];
+        assert!((focal(&camera) / focal(&corrected) - 1.1).abs() < 1e-9);
+
+        let location = na::Isometry3::new(
+            na::vector![0.0, 0.0, 1.02],
+            na::vector![f64::to_radians(30.0), 0.0, 0.0],
+        );
+        let report = SelfTestReport::new(&location, 1.0);
+        assert!(report.passed());
+        assert!((report.tilt.to_degrees() - 30.0).abs() < 1e-9);
+        assert_eq!(
+            report.to_string(),
+            "distance 1.0 (expected 1.0, +2.0% error), tilt 30.0 degrees"
+        );
+    }
+
+    #[test]
+    fn test_family_names() {
+        for family in apriltag::ApriltagFamily::ALL {
+            assert_eq!(
+                apriltag::ApriltagFamily::try_from(family.name()).unwrap(),
+                family
+            );
+        }
+        let error = apriltag::ApriltagFamily::try_from("tag36h12").unwrap_err();
+        let message = error.to_string();
+        assert!(message.contains("\"tag36h12\""));
+        assert!(message.contains("tag16h5, tag25h9, tag36h10, tag36h11, tagCircle21h7"));
+    }
+}
@@ -1,9 +1,14 @@
 use std::fmt::Display;
+use std::fs::{self, File};
 use std::ops::RangeInclusive;
+use std::path::Path;
 use std::{collections::HashMap, hash::Hash};
 
 extern crate nalgebra as na;
 
+use serde::Deserialize;
+use serde_path_to_error::Segment;
+
 use crate::tag::apriltag;
 use crate::tag::error::*;
 
@@ -49,23 +54,28 @@ impl From<(apriltag::ApriltagFamily, i32)> for TagIndex {
 /// Records the transformation and scaling relative to the camera's origin.
 ///
 /// For a point [x y z] in the tag's reference frame (e.g. [-1 -1 0] = upleft corner), after
-/// applying the transformation, it will be transformed to the camera's reference frame. Note
-/// that the `size` variable represents the side length of the tag in real life, it is divided
-/// by 2 when initialized.
+/// applying the transformation, it will be transformed to the camera's reference frame. The point
+/// is first scaled by `half_extents` on the x and y axes, then moved by `isometry`. Note that the
+/// size variables passed to the constructors represent the side lengths of the tag in real life,
+/// which are divided by 2 when initialized.
+///
+/// Rectangular tags are supported by giving different half extents on the x and y axes.
 ///
 /// You can choose the unit of measurement arbitrarily, but please ensure that all your units
 /// are consistent. For example, if you are using milimeter as the unit of length here, please
 /// use milimeter everywhere, or the result would be inaccurate.
 #[derive(Debug, Clone)]
-pub struct TagLocation(pub na::SimilarityMatrix3<f64>);
+pub struct TagLocation {
+    /// Rotation and translation of the tag's center.
+    pub isometry: na::IsometryMatrix3<f64>,
+
+    /// Half of the tag's side lengths on its local x and y axes.
+    pub half_extents: na::Vector2<f64>,
+}
 
 impl TagLocation {
     pub fn new_size(size: f64) -> Self {
-        Self(na::SimilarityMatrix3::new(
-            na::Vector3::default(),
-            na::Vector3::default(),
-            size * 0.5,
-        ))
+        Self::new(size, na::Vector3::default(), na::Vector3::default())
     }
 
     /// Create a tag location from scaling factor, rotation vector, and translation vector.
@@ -73,15 +83,357 @@ impl TagLocation {
     /// The final transformation is equivalent to first scaling, then rotating, finally
     /// translating the tag by the given amount.
     pub fn new(size: f64, rv: na::Vector3<f64>, tv: na::Vector3<f64>) -> Self {
-        Self(na::SimilarityMatrix3::new(tv, rv, size * 0.5))
+        Self::new_rect((size, size), rv, tv)
     }
 
     pub fn new_from_matrix(size: f64, rm: na::Matrix3<f64>, tv: na::Vector3<f64>) -> Self {
-        Self(na::SimilarityMatrix3::from_parts(
-            tv.into(),
-            na::Rotation3::from_matrix_unchecked(rm),
-            size * 0.5,
-        ))
+        Self::new_rect_from_matrix((size, size), rm, tv)
+    }
+
+    /// Create a rectangular tag location from its side lengths on x and y axes, rotation vector,
+    /// and translation vector.
+    pub fn new_rect(size: (f64, f64), rv: na::Vector3<f64>, tv: na::Vector3<f64>) -> Self {
+        Self {
+            isometry: na::IsometryMatrix3::new(tv, rv),
+            half_extents: na::Vector2::new(size.0 * 0.5, size.1 * 0.5),
+        }
+    }
+
+    pub fn new_rect_from_matrix(
+        size: (f64, f64),
+        rm: na::Matrix3<f64>,
+        tv: na::Vector3<f64>,
+    ) -> Self {
+        Self {
+            isometry: na::IsometryMatrix3::from_parts(
+                tv.into(),
+                na::Rotation3::from_matrix_unchecked(rm),
+            ),
+            half_extents: na::Vector2::new(size.0 * 0.5, size.1 * 0.5),
+        }
+    }
+
+    /// Whether the tag has the same side length on both of its axes.
+    pub fn is_square(&self) -> bool {
+        self.half_extents.x == self.half_extents.y
+    }
+
+    /// Transform a point from the tag's reference frame to its parent's reference frame.
+    ///
+    /// The x and y coordinates are scaled by the half extents on each axis. The z coordinate is
+    /// scaled by the smaller one of the two half extents.
+    pub fn transform_point(&self, point: &na::Point3<f64>) -> na::Point3<f64> {
+        let scaled = na::Point3::new(
+            point.x * self.half_extents.x,
+            point.y * self.half_extents.y,
+            point.z * self.half_extents.x.min(self.half_extents.y),
+        );
+        self.isometry.transform_point(&scaled)
+    }
+}
+
+/// The minimum length of the cross product of two box axes for it to be checked as a separating axis in
+/// `BoundingBox::intersects`. Shorter cross products come from parallel axes, which are already checked.
+const SEPARATING_AXIS_TOLERANCE: f64 = 1e-9;
+
+/// An axis-aligned box in an object's reference frame, e.g. the box enclosing all of the object's tags.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min: na::Point3<f64>,
+    pub max: na::Point3<f64>,
+}
+
+impl BoundingBox {
+    /// The smallest box enclosing the corners of all `tags`, or `None` if there is no tag.
+    ///
+    /// The box is flat if all tags lie on the same plane, e.g. the tags on a screen.
+    pub fn from_tags<'t, I: IntoIterator<Item = &'t TagLocation>>(tags: I) -> Option<Self> {
+        tags.into_iter()
+            .flat_map(|tag| {
+                [(-1.0, 1.0), (1.0, 1.0), (1.0, -1.0), (-1.0, -1.0)]
+                    .map(|(x, y)| tag.transform_point(&na::Point3::new(x, y, 0.0)))
+            })
+            .map(|corner| Self {
+                min: corner,
+                max: corner,
+            })
+            .reduce(|a, b| Self {
+                min: a.min.inf(&b.min),
+                max: a.max.sup(&b.max),
+            })
+    }
+
+    pub fn center(&self) -> na::Point3<f64> {
+        na::center(&self.min, &self.max)
+    }
+
+    /// Half of the box's side lengths on each axis.
+    pub fn half_extents(&self) -> na::Vector3<f64> {
+        (self.max - self.min) * 0.5
+    }
+
+    /// Check whether this box placed at `pose` intersects (or touches) `other` placed at `other_pose`.
+    ///
+    /// The boxes are oriented boxes once placed, so they are checked with the separating axis theorem: they
+    /// are disjoint exactly when their projections onto one of the 15 candidate axes (each box's 3 axes,
+    /// and the cross products of every pair of them) do not overlap.
+    pub fn intersects(
+        &self,
+        pose: &na::Isometry3<f64>,
+        other: &BoundingBox,
+        other_pose: &na::Isometry3<f64>,
+    ) -> bool {
+        let axes = pose.rotation.to_rotation_matrix().into_inner();
+        let other_axes = other_pose.rotation.to_rotation_matrix().into_inner();
+        let offset =
+            other_pose.transform_point(&other.center()) - pose.transform_point(&self.center());
+        let mut candidates = Vec::with_capacity(15);
+        for i in 0..3 {
+            candidates.push(axes.column(i).into_owned());
+            candidates.push(other_axes.column(i).into_owned());
+            for j in 0..3 {
+                let cross = axes.column(i).cross(&other_axes.column(j));
+                if cross.norm() > SEPARATING_AXIS_TOLERANCE {
+                    candidates.push(cross.normalize());
+                }
+            }
+        }
+        // the radius of a box's projection onto `axis`
+        let radius =
+            |axes: &na::Matrix3<f64>, half_extents: &na::Vector3<f64>, axis: &na::Vector3<f64>| {
+                (0..3)
+                    .map(|i| half_extents[i] * axes.column(i).dot(axis).abs())
+                    .sum::<f64>()
+            };
+        candidates.iter().all(|axis| {
+            offset.dot(axis).abs()
+                <= radius(&axes, &self.half_extents(), axis)
+                    + radius(&other_axes, &other.half_extents(), axis)
+        })
+    }
+}
+
+/// The maximum difference allowed between each entry of $R^T R$ and the identity matrix, for a matrix
+/// $R$ to be considered orthonormal.
+pub const ORTHONORMAL_TOLERANCE: f64 = 1e-3;
+
+/// The maximum difference allowed between each entry of $R^T R$ and the identity matrix, for a matrix
+/// $R$ to be corrected into its nearest rotation matrix in lenient mode.
+pub const LENIENT_ORTHONORMAL_TOLERANCE: f64 = 0.1;
+
+/// Check whether the matrix's columns are orthogonal unit vectors, within `ORTHONORMAL_TOLERANCE`.
+fn is_orthonormal(mat: &na::Matrix3<f64>) -> bool {
+    orthonormal_error(mat) <= ORTHONORMAL_TOLERANCE
+}
+
+fn orthonormal_error(mat: &na::Matrix3<f64>) -> f64 {
+    (mat.transpose() * mat - na::Matrix3::identity()).amax()
+}
+
+/// Find the rotation matrix closest to `mat` in Frobenius norm.
+fn nearest_rotation(mat: &na::Matrix3<f64>) -> na::Matrix3<f64> {
+    let svd = mat.svd(true, true);
+    let mut u = svd.u.unwrap();
+    let v_t = svd.v_t.unwrap();
+    if (u * v_t).determinant() < 0.0 {
+        // flip the axis with the smallest singular value to make the result a proper rotation
+        let (min_index, _) = svd.singular_values.argmin();
+        let mut column = u.column_mut(min_index);
+        column *= -1.0;
+    }
+    u * v_t
+}
+
+/// Defines how rotation matrices that are not exactly proper rotations are handled when loading
+/// tagobj files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RotationMatrixMode {
+    /// Reject any rotation matrix that is not orthonormal (within `ORTHONORMAL_TOLERANCE`) or does not
+    /// have a determinant of +1.
+    #[default]
+    Strict,
+
+    /// Replace slightly skewed rotation matrices (within `LENIENT_ORTHONORMAL_TOLERANCE`) with their
+    /// nearest rotation matrix. Matrices with a negative determinant are still rejected.
+    Lenient,
+}
+
+impl RotationMatrixMode {
+    /// Check the rotation matrix according to the mode, returning the (possibly corrected) rotation
+    /// matrix, or the reason why it is rejected.
+    fn check(&self, rm: na::Matrix3<f64>) -> Result<na::Matrix3<f64>, String> {
+        if rm.determinant() <= 0.0 {
+            return Err(format!(
+                "rotation matrix {} has a non-positive determinant, so it is not a proper rotation",
+                rm
+            ));
+        }
+        let error = orthonormal_error(&rm);
+        if error <= ORTHONORMAL_TOLERANCE {
+            return Ok(rm);
+        }
+        match self {
+            Self::Lenient if error <= LENIENT_ORTHONORMAL_TOLERANCE => Ok(nearest_rotation(&rm)),
+            _ => Err(format!(
+                "rotation matrix {} is not orthonormal (max deviation of R^T R from identity is {})",
+                rm, error
+            )),
+        }
+    }
+}
+
+/// Defines how invalid tag entries (e.g. a missing field, or a field of the wrong type) are handled when
+/// loading tagobj files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ValidationMode {
+    /// Skip invalid tag entries, logging a warning with the JSON path of the invalid field.
+    #[default]
+    Lenient,
+
+    /// Fail the loading with an `InvalidFieldError` at the first invalid tag entry.
+    Strict,
+}
+
+/// The header of a tagobj file, read before the rest of the file since the format depends on the version.
+#[derive(Deserialize)]
+struct TagObjHeader {
+    version: i64,
+}
+
+/// A version 1 or 2 tagobj file.
+#[derive(Deserialize)]
+struct TagObjFile {
+    /// The tag entries, keyed by their ID references. Each entry is deserialized on its own, so that an
+    /// invalid entry can be skipped in lenient mode.
+    tags: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A tag entry in a version 1 or 2 tagobj file.
+#[derive(Deserialize)]
+struct TagEntry {
+    /// The tag's family. Only used in version 2 files, when the tag is not in the ID mapping.
+    family: Option<String>,
+    /// The tag's ID. Only used in version 2 files, when the tag is not in the ID mapping.
+    id: Option<i32>,
+    size: TagSize,
+    tv: [f64; 3],
+    /// Rotation matrix. Exactly one of `rm`, `rv`, and `rq` must be given.
+    rm: Option<RotationMatrix>,
+    /// Rotation vector.
+    rv: Option<[f64; 3]>,
+    /// Unit quaternion in the order of `[i, j, k, w]`.
+    rq: Option<[f64; 4]>,
+}
+
+/// A tag's size, which is either a single number for square tags, or `[x, y]` for rectangular tags.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TagSize {
+    Square(f64),
+    Rect([f64; 2]),
+}
+
+/// A rotation matrix, given by its columns.
+#[derive(Deserialize)]
+struct RotationMatrix {
+    x: [f64; 3],
+    y: [f64; 3],
+    z: [f64; 3],
+}
+
+/// The rotation of a tag entry.
+enum TagRotation {
+    Matrix(na::Matrix3<f64>),
+    Vector(na::Vector3<f64>),
+    Quaternion(na::UnitQuaternion<f64>),
+}
+
+/// Deserialize the JSON value at `path`. On failure, the error is located at the invalid field inside of
+/// the value.
+fn deserialize_at<'de, T: Deserialize<'de>>(
+    value: &'de serde_json::Value,
+    path: &str,
+) -> Result<T, InvalidFieldError> {
+    serde_path_to_error::deserialize(value).map_err(|error| {
+        let mut field_path = path.to_string();
+        for segment in error.path().iter() {
+            match segment {
+                Segment::Seq { index } => field_path += &format!("[{}]", index),
+                Segment::Map { key } | Segment::Enum { variant: key } => {
+                    field_path += &format!(".{}", key)
+                }
+                Segment::Unknown => field_path += ".?",
+            }
+        }
+        InvalidFieldError::new(field_path, error.into_inner().to_string())
+    })
+}
+
+impl TagEntry {
+    /// Convert the entry at `path` into the tag's index, size, rotation, and translation vector.
+    ///
+    /// `mapped_index` is the tag's index in the ID mapping. If `None`, the index is taken from the entry's
+    /// `family` and `id` fields.
+    fn convert(
+        self,
+        path: &str,
+        mapped_index: Option<TagIndex>,
+    ) -> Result<(TagIndex, (f64, f64), TagRotation, na::Vector3<f64>), InvalidFieldError> {
+        let index = match mapped_index {
+            Some(index) => index,
+            None => {
+                let family = self.family.ok_or_else(|| {
+                    InvalidFieldError::new(
+                        path,
+                        "missing field `family`, which is required for tags not in the ID mapping",
+                    )
+                })?;
+                let family = apriltag::ApriltagFamily::try_from(family.as_str()).map_err(|_| {
+                    InvalidFieldError::new(
+                        format!("{}.family", path),
+                        format!("unsupported tag family \"{}\"", family),
+                    )
+                })?;
+                let id = self.id.ok_or_else(|| {
+                    InvalidFieldError::new(
+                        path,
+                        "missing field `id`, which is required for tags not in the ID mapping",
+                    )
+                })?;
+                TagIndex::new(family, id)
+            }
+        };
+        let size = match self.size {
+            TagSize::Square(size) => (size, size),
+            TagSize::Rect([x, y]) => (x, y),
+        };
+        if !(size.0 > 0.0 && size.1 > 0.0) {
+            return Err(InvalidFieldError::new(
+                format!("{}.size", path),
+                format!("the size must be positive, but found {:?}", size),
+            ));
+        }
+        let rotation = match (self.rm, self.rv, self.rq) {
+            (Some(rm), None, None) => TagRotation::Matrix(na::Matrix3::from_columns(&[
+                rm.x.into(),
+                rm.y.into(),
+                rm.z.into(),
+            ])),
+            (None, Some(rv), None) => TagRotation::Vector(rv.into()),
+            (None, None, Some([i, j, k, w])) => TagRotation::Quaternion(
+                na::UnitQuaternion::try_new(na::Quaternion::new(w, i, j, k), f64::EPSILON)
+                    .ok_or_else(|| {
+                        InvalidFieldError::new(format!("{}.rq", path), "the quaternion is zero")
+                    })?,
+            ),
+            _ => {
+                return Err(InvalidFieldError::new(
+                    path,
+                    "exactly one of the fields `rm`, `rv`, and `rq` must be given",
+                ));
+            }
+        };
+        Ok((index, size, rotation, self.tv.into()))
     }
 }
 
@@ -115,132 +467,130 @@ impl TaggedObject {
         }
     }
 
+    /// The smallest box enclosing all of the object's tags in its reference frame, or `None` if the object
+    /// has no tag. See `BoundingBox::from_tags`.
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        BoundingBox::from_tags(self.tags.values())
+    }
+
     /// Create a TaggedObject from a tagobj file.
     ///
     /// `tagobj` is the loaded tagobj file (in JSON format). `id_mapping` defines the specific tag family
-    /// and tag ID for each template tag in the tagobj file, as the tagobj format doesn't specify each tag's
-    /// specific information in it.
+    /// and tag ID for each template tag in the tagobj file, as the version 1 tagobj format doesn't specify
+    /// each tag's specific information in it.
+    ///
+    /// Version 2 tagobj files embed each tag's family and ID in the `family` and `id` fields of each tag
+    /// entry, so `id_mapping` can be left empty. When a tag's reference is present in `id_mapping`, the
+    /// mapping takes precedence over the embedded tag index.
+    ///
+    /// Rotation matrices in the tagobj file are checked in strict mode. See `RotationMatrixMode`.
     pub fn new_from_json<S: Into<String> + Clone>(
         name: S,
         tagobj: &serde_json::Value,
         id_mapping: &HashMap<String, TagIndex>,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        const SUPPORTED_VERSIONS: RangeInclusive<i64> = 1..=1;
+        Self::new_from_json_with_mode(name, tagobj, id_mapping, RotationMatrixMode::Strict)
+    }
+
+    /// Create a TaggedObject from a tagobj file, checking the rotation matrices with the given mode.
+    ///
+    /// Invalid tag entries are skipped with a warning. See `ValidationMode`.
+    pub fn new_from_json_with_mode<S: Into<String> + Clone>(
+        name: S,
+        tagobj: &serde_json::Value,
+        id_mapping: &HashMap<String, TagIndex>,
+        rotation_matrix_mode: RotationMatrixMode,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::new_from_json_with_modes(
+            name,
+            tagobj,
+            id_mapping,
+            rotation_matrix_mode,
+            ValidationMode::Lenient,
+        )
+    }
+
+    /// Create a TaggedObject from a tagobj file, checking the rotation matrices and the tag entries with the
+    /// given modes.
+    pub fn new_from_json_with_modes<S: Into<String> + Clone>(
+        name: S,
+        tagobj: &serde_json::Value,
+        id_mapping: &HashMap<String, TagIndex>,
+        rotation_matrix_mode: RotationMatrixMode,
+        validation_mode: ValidationMode,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        const SUPPORTED_VERSIONS: RangeInclusive<i64> = 1..=2;
 
         log::info!(
             "Loading tagged object \"{}\" from JSON...",
             name.clone().into()
         );
-        let tagobj_object = tagobj.as_object().ok_or(InvalidFormatError::new(
-            &tagobj,
-            "tagobj file must be an object!",
-        ))?;
-
-        let version = tagobj_object
-            .get("version")
-            .ok_or(InvalidFormatError::new(
-                &tagobj,
-                "tagobj file must have a \'version\' field!",
-            ))?
-            .as_i64()
-            .ok_or(InvalidFormatError::new(
-                &tagobj,
-                "tagobj version must be an integer!",
-            ))?;
+        let version = TagObjHeader::deserialize(tagobj)
+            .map_err(|error| InvalidFormatError::new(tagobj, error.to_string()))?
+            .version;
         match version {
-            1 => {
-                // Version 1 TagObj file
-                let tags_json = tagobj_object
-                    .get("tags")
-                    .ok_or(InvalidFormatError::new(
-                        &tagobj,
-                        "version 1 tagobj file must have a \'tags\' field!",
-                    ))?
-                    .as_object()
-                    .ok_or(InvalidFormatError::new(
-                        &tagobj,
-                        "version 1 tagobj file's \'tags\' field must be an array!",
-                    ))?;
-
-                let tags = tags_json.iter()
-                    .filter_map(|(id_ref, json_value)| {
-                        // filter out all invalid entries
-                        let id = *id_mapping.get(id_ref)
-                            .or_else(|| {
-                                log::info!("ID reference \"{}\" in object \"{}\" does not exist in tag ID mapping.", id_ref, name.clone().into());
-                                None
-                            })?;
-                        let json_value = json_value.as_object()
-                            .or_else(|| {
-                                log::warn!("Invalid format encountered in ID reference \"{}\" in object \"{}\". Skipping.", id_ref, name.clone().into());
-                                log::warn!("Entry must be an object type!");
-                                None
-                            })?;
-                        let size = json_value.get("size")
-                            .or_else(|| {
-                                log::warn!("ID reference \"{}\" in object \"{}\" does not have a \"size\" field. Skipping.", id_ref, name.clone().into());
-                                None
-                            })?
-                            .as_f64()
-                            .or_else(|| {
-                                log::warn!("The \"size\" field is not a valid floating point number in ID reference \"{}\" in object\"{}\". Skipping.", id_ref, name.clone().into());
-                                None
+            1 | 2 => {
+                // Version 1 and 2 TagObj files. Version 2 files have the tag index embedded in each tag.
+                let tags_json = TagObjFile::deserialize(tagobj)
+                    .map_err(|error| InvalidFormatError::new(tagobj, error.to_string()))?
+                    .tags;
+
+                let mut tags = HashMap::new();
+                for (id_ref, json_value) in &tags_json {
+                    let mapped_index = id_mapping.get(id_ref).copied();
+                    if mapped_index.is_none() && version < 2 {
+                        log::info!(
+                            "ID reference \"{}\" in object \"{}\" does not exist in tag ID mapping.",
+                            id_ref,
+                            name.clone().into()
+                        );
+                        continue;
+                    }
+                    let path = format!("$.tags[{}]", serde_json::Value::from(id_ref.as_str()));
+                    let (index, size, rotation, tv) =
+                        match deserialize_at::<TagEntry>(json_value, &path)
+                            .and_then(|entry| entry.convert(&path, mapped_index))
+                        {
+                            Ok(entry) => entry,
+                            Err(error) if validation_mode == ValidationMode::Strict => {
+                                return Err(error.into());
+                            }
+                            Err(error) => {
+                                log::warn!(
+                                    "{} in object \"{}\". Skipping ID reference \"{}\".",
+                                    error,
+                                    name.clone().into(),
+                                    id_ref
+                                );
+                                continue;
+                            }
+                        };
+                    let location = match rotation {
+                        TagRotation::Matrix(rm) => {
+                            let checked = rotation_matrix_mode.check(rm).map_err(|reason| {
+                                InvalidFormatError::new(
+                                    &json_value["rm"],
+                                    format!("In ID reference \"{}\": {}", id_ref, reason),
+                                )
                             })?;
-                        // get translation vector
-                        let mut tv = json_value.get("tv")
-                            .or_else(|| {
-                                log::warn!("ID reference \"{}\" in object \"{}\" does not have a \"tv\" field. Skipping.", id_ref, name.clone().into());
-                                None
-                            })?
-                            .as_array()
-                            .or_else(|| {
-                                log::warn!("Field \"tv\" in ID reference \"{}\" in object \"{}\" is not an array. Skipping.", id_ref, name.clone().into());
-                                None
-                            })?
-                            .iter()
-                            .map(|v| v.as_f64().unwrap());
-                        let tv = na::Vector3::new(tv.next()?, tv.next()?, tv.next()?);
-                        match (json_value.get("rm"), json_value.get("rv")) {
-                            (Some(_), Some(_)) => {
-                                // Both rotation matrix and rotation vector are defined
-                                // Use rotation matrix
-                                log::warn!("Both rotation matrix and rotation vector are defined for ID reference \"{}\" in object \"{}\".", id_ref, name.clone().into());
-                                log::warn!("Please choose either one to use as the rotation component.");
-                                None
-                            },
-                            (Some(rm), None) => {
-                                let rm = rm.as_object()?;
-                                let mut rx = rm.get("x")?
-                                    .as_array()?
-                                    .iter().map(|v| v.as_f64().unwrap());
-                                let mut ry = rm.get("y")?
-                                    .as_array()?
-                                    .iter().map(|v| v.as_f64().unwrap());
-                                let mut rz = rm.get("z")?
-                                    .as_array()?
-                                    .iter().map(|v| v.as_f64().unwrap());
-                                let rm = na::Matrix3::new(
-                                    rx.next()?, ry.next()?, rz.next()?,
-                                    rx.next()?, ry.next()?, rz.next()?,
-                                    rx.next()?, ry.next()?, rz.next()?,
+                            if checked != rm {
+                                log::warn!(
+                                    "The rotation matrix in ID reference \"{}\" in object \"{}\" is not orthonormal. Replaced with its nearest rotation matrix.",
+                                    id_ref,
+                                    name.clone().into()
                                 );
-                                Some((id, TagLocation::new_from_matrix(size, rm, tv)))
-                            },
-                            (None, Some(rv)) => {
-                                let mut rv = rv.as_array()?
-                                    .iter()
-                                    .map(|v| v.as_f64().unwrap());
-                                let rv = na::Vector3::new(rv.next()?, rv.next()?, rv.next()?);
-                                Some((id, TagLocation::new(size, rv, tv)))
-                            },
-                            (None, None) => {
-                                log::warn!("Neither rotation matrix or rotation vector is defined for ID reference \"{}\" in object \"{}\".", id_ref, name.clone().into());
-                                log::warn!("Skipping ID reference {}.", id_ref);
-                                None
                             }
+                            TagLocation::new_rect_from_matrix(size, checked, tv)
                         }
-                    })
-                    .collect::<HashMap<_, _>>();
+                        TagRotation::Vector(rv) => TagLocation::new_rect(size, rv, tv),
+                        TagRotation::Quaternion(rq) => TagLocation::new_rect_from_matrix(
+                            size,
+                            rq.to_rotation_matrix().into_inner(),
+                            tv,
+                        ),
+                    };
+                    tags.insert(index, location);
+                }
 
                 Ok(Self {
                     name: name.into(),
@@ -251,3 +601,503 @@ impl TaggedObject {
         }
     }
 }
+
+/// Load all version 2 tagobj files (files with the `.tagobj` extension) in a directory.
+///
+/// Each object is named after its file name without the extension. Version 1 files are skipped, since
+/// they require a tag ID mapping. If tags conflict across the loaded objects, all conflicts are reported
+/// in a single `ConflictingTagsError`.
+pub fn load_tagobj_dir<P: AsRef<Path>>(
+    dir: P,
+) -> Result<Vec<TaggedObject>, Box<dyn std::error::Error>> {
+    load_tagobj_dir_with_mode(dir, ValidationMode::Lenient)
+}
+
+/// Load all version 2 tagobj files in a directory like `load_tagobj_dir`, handling invalid tag entries with
+/// the given mode.
+pub fn load_tagobj_dir_with_mode<P: AsRef<Path>>(
+    dir: P,
+    validation_mode: ValidationMode,
+) -> Result<Vec<TaggedObject>, Box<dyn std::error::Error>> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "tagobj"));
+    paths.sort();
+
+    let mut objects = Vec::with_capacity(paths.len());
+    for path in paths {
+        let tagobj_json: serde_json::Value = serde_json::from_reader(File::open(&path)?)?;
+        if tagobj_json.get("version").and_then(|v| v.as_i64()) == Some(1) {
+            log::warn!(
+                "Skipping version 1 tagobj file {}, since it requires a tag ID mapping.",
+                path.display()
+            );
+            continue;
+        }
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        objects.push(TaggedObject::new_from_json_with_modes(
+            name,
+            &tagobj_json,
+            &HashMap::new(),
+            RotationMatrixMode::Strict,
+            validation_mode,
+        )?);
+        log::info!("Successfully loaded tagobj file {}", path.display());
+    }
+
+    // check for conflicting tags across objects
+    let mut tag_owners = HashMap::<TagIndex, &str>::new();
+    let mut conflicts = Vec::new();
+    for object in &objects {
+        for tag_index in object.tags.keys() {
+            if let Some(owner) = tag_owners.insert(*tag_index, &object.name) {
+                conflicts.push(ConflictingTagError::new(
+                    *tag_index,
+                    owner.to_string(),
+                    object.name.clone(),
+                ));
+            }
+        }
+    }
+    if !conflicts.is_empty() {
+        return Err(ConflictingTagsError::new(conflicts).into());
+    }
+    Ok(objects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_single_tag(tag_json: serde_json::Value) -> TaggedObject {
+        let tagobj = serde_json::json!({
+            "version": 1,
+            "tags": { "0": tag_json },
+        });
+        let id_mapping = HashMap::from([(
+            "0".to_string(),
+            TagIndex::new(apriltag::ApriltagFamily::Tag36h11, 0),
+        )]);
+        TaggedObject::new_from_json("object", &tagobj, &id_mapping).unwrap()
+    }
+
+    #[test]
+    fn test_load_tag_size() {
+        let object = load_single_tag(serde_json::json!({
+            "size": 2.0, "rv": [0.0, 0.0, 0.0], "tv": [0.0, 0.0, 0.0],
+        }));
+        let tag = object.tags.values().next().unwrap();
+        assert_eq!(tag.half_extents, na::Vector2::new(1.0, 1.0));
+
+        let object = load_single_tag(serde_json::json!({
+            "size": [2.0, 1.0], "rv": [0.0, 0.0, 0.0], "tv": [0.0, 0.0, 0.0],
+        }));
+        let tag = object.tags.values().next().unwrap();
+        assert_eq!(tag.half_extents, na::Vector2::new(1.0, 0.5));
+    }
+
+    #[test]
+    fn test_skip_non_positive_tag_size() {
+        for size in [
+            serde_json::json!(0.0),
+            serde_json::json!(-1.0),
+            serde_json::json!([1.0, 0.0]),
+            serde_json::json!([-1.0, 1.0]),
+        ] {
+            let object = load_single_tag(serde_json::json!({
+                "size": size, "rv": [0.0, 0.0, 0.0], "tv": [0.0, 0.0, 0.0],
+            }));
+            assert!(
+                object.tags.is_empty(),
+                "Tag with size {} is not skipped",
+                size
+            );
+        }
+    }
+
+    fn load_single_rotation_matrix(
+        rm: na::Matrix3<f64>,
+        mode: RotationMatrixMode,
+    ) -> Result<TaggedObject, Box<dyn std::error::Error>> {
+        let tagobj = serde_json::json!({
+            "version": 1,
+            "tags": {
+                "0": {
+                    "size": 1.0,
+                    "rm": {
+                        "x": [rm[(0, 0)], rm[(1, 0)], rm[(2, 0)]],
+                        "y": [rm[(0, 1)], rm[(1, 1)], rm[(2, 1)]],
+                        "z": [rm[(0, 2)], rm[(1, 2)], rm[(2, 2)]],
+                    },
+                    "tv": [0.0, 0.0, 0.0],
+                },
+            },
+        });
+        let id_mapping = HashMap::from([(
+            "0".to_string(),
+            TagIndex::new(apriltag::ApriltagFamily::Tag36h11, 0),
+        )]);
+        TaggedObject::new_from_json_with_mode("object", &tagobj, &id_mapping, mode)
+    }
+
+    #[test]
+    fn test_load_rotation_matrix() {
+        let rotation = na::Rotation3::from_scaled_axis(na::vector![0.3, -0.2, 0.1]);
+        for mode in [RotationMatrixMode::Strict, RotationMatrixMode::Lenient] {
+            let object = load_single_rotation_matrix(*rotation.matrix(), mode).unwrap();
+            let tag = object.tags.values().next().unwrap();
+            assert!((tag.isometry.rotation.matrix() - rotation.matrix()).amax() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_load_slightly_skewed_rotation_matrix() {
+        let rotation = na::Rotation3::from_scaled_axis(na::vector![0.3, -0.2, 0.1]);
+        let mut skewed = *rotation.matrix();
+        skewed[(0, 1)] += 0.01;
+        assert!(load_single_rotation_matrix(skewed, RotationMatrixMode::Strict).is_err());
+
+        let object = load_single_rotation_matrix(skewed, RotationMatrixMode::Lenient).unwrap();
+        let corrected = *object
+            .tags
+            .values()
+            .next()
+            .unwrap()
+            .isometry
+            .rotation
+            .matrix();
+        assert!(is_orthonormal(&corrected));
+        assert!((corrected.determinant() - 1.0).abs() < 1e-9);
+        assert!((corrected - rotation.matrix()).amax() < 0.01);
+    }
+
+    #[test]
+    fn test_load_grossly_skewed_rotation_matrix() {
+        let sheared = na::Matrix3::new(1.0, 0.8, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0);
+        let reflection = na::Matrix3::new(-1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0);
+        for mode in [RotationMatrixMode::Strict, RotationMatrixMode::Lenient] {
+            assert!(load_single_rotation_matrix(sheared, mode).is_err());
+            assert!(load_single_rotation_matrix(reflection, mode).is_err());
+        }
+    }
+
+    #[test]
+    fn test_nearest_rotation() {
+        let rotation = na::Rotation3::from_scaled_axis(na::vector![-1.0, 0.5, 2.0]);
+        let nearest = nearest_rotation(&(rotation.matrix() * 1.05));
+        assert!((nearest - rotation.matrix()).amax() < 1e-9);
+    }
+
+    #[test]
+    fn test_is_orthonormal() {
+        assert!(is_orthonormal(&na::Matrix3::identity()));
+        assert!(is_orthonormal(
+            na::Rotation3::from_scaled_axis(na::vector![0.1, 0.2, 0.3]).matrix()
+        ));
+        assert!(!is_orthonormal(&na::Matrix3::new(
+            1.0, 0.5, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0
+        )));
+        assert!(!is_orthonormal(&(na::Matrix3::identity() * 2.0)));
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let mut object = TaggedObject::new("screen");
+        assert_eq!(object.bounding_box(), None);
+        object.tags.insert(
+            TagIndex::new(apriltag::ApriltagFamily::Tag36h11, 0),
+            TagLocation::new(2.0, na::Vector3::default(), na::vector![-3.0, 0.0, 0.0]),
+        );
+        object.tags.insert(
+            TagIndex::new(apriltag::ApriltagFamily::Tag36h11, 1),
+            TagLocation::new_rect(
+                (2.0, 1.0),
+                na::vector![std::f64::consts::FRAC_PI_2, 0.0, 0.0],
+                na::vector![3.0, 0.0, 1.0],
+            ),
+        );
+        let bounding_box = object.bounding_box().unwrap();
+        // the second tag is tilted onto the x-z plane
+        assert!((bounding_box.min - na::point![-4.0, -1.0, 0.0]).norm() < 1e-12);
+        assert!((bounding_box.max - na::point![4.0, 1.0, 1.5]).norm() < 1e-12);
+        assert!((bounding_box.center() - na::point![0.0, 0.0, 0.75]).norm() < 1e-12);
+        assert!((bounding_box.half_extents() - na::vector![4.0, 1.0, 0.75]).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_bounding_box_intersects() {
+        let unit = BoundingBox {
+            min: na::point![-1.0, -1.0, -1.0],
+            max: na::point![1.0, 1.0, 1.0],
+        };
+        let flat = BoundingBox {
+            min: na::point![-2.0, -1.0, 0.0],
+            max: na::point![2.0, 1.0, 0.0],
+        };
+        let identity = na::Isometry3::identity();
+        let at = |x: f64, y: f64, z: f64, rotation: na::Vector3<f64>| {
+            na::Isometry3::new(na::vector![x, y, z], rotation)
+        };
+
+        assert!(unit.intersects(&identity, &unit, &at(1.5, 0.0, 0.0, na::Vector3::zeros())));
+        assert!(!unit.intersects(&identity, &unit, &at(2.5, 0.0, 0.0, na::Vector3::zeros())));
+        // touching boxes intersect
+        assert!(unit.intersects(&identity, &unit, &at(2.0, 0.0, 0.0, na::Vector3::zeros())));
+        // a box rotated by 45 degrees reaches further along the x axis
+        let edge = na::vector![0.0, 0.0, std::f64::consts::FRAC_PI_4];
+        let diagonal = 1.0 + std::f64::consts::SQRT_2;
+        assert!(!unit.intersects(&identity, &unit, &at(diagonal + 0.1, 0.0, 0.0, edge)));
+        assert!(unit.intersects(&identity, &unit, &at(diagonal - 0.1, 0.0, 0.0, edge)));
+        // a flat box passing through a box, and one in front of it
+        let upright = na::vector![std::f64::consts::FRAC_PI_2, 0.0, 0.0];
+        assert!(flat.intersects(&at(0.0, 0.0, 0.5, upright), &unit, &identity));
+        assert!(!flat.intersects(&at(0.0, 0.0, 1.5, na::Vector3::zeros()), &unit, &identity));
+        // the check is symmetric
+        assert!(!unit.intersects(&identity, &flat, &at(0.0, 0.0, 1.5, na::Vector3::zeros())));
+    }
+
+    #[test]
+    fn test_load_version_2() {
+        let tagobj = serde_json::json!({
+            "version": 2,
+            "tags": {
+                "front": { "family": "tag25h9", "id": 3, "size": 1.0, "rv": [0.0, 0.0, 0.0], "tv": [0.0, 0.0, 0.0] },
+                "back": { "family": "tag36h11", "id": 4, "size": 1.0, "rv": [0.0, 0.0, 0.0], "tv": [0.0, 0.0, 1.0] },
+                "invalid": { "family": "tag00h0", "id": 5, "size": 1.0, "rv": [0.0, 0.0, 0.0], "tv": [0.0, 0.0, 2.0] },
+            },
+        });
+        let object = TaggedObject::new_from_json("object", &tagobj, &HashMap::new()).unwrap();
+        assert_eq!(object.tags.len(), 2);
+        assert!(
+            object
+                .tags
+                .contains_key(&TagIndex::new(apriltag::ApriltagFamily::Tag25h9, 3))
+        );
+        assert!(
+            object
+                .tags
+                .contains_key(&TagIndex::new(apriltag::ApriltagFamily::Tag36h11, 4))
+        );
+
+        // the ID mapping takes precedence over the embedded tag index
+        let id_mapping = HashMap::from([(
+            "front".to_string(),
+            TagIndex::new(apriltag::ApriltagFamily::Tag16h5, 0),
+        )]);
+        let object = TaggedObject::new_from_json("object", &tagobj, &id_mapping).unwrap();
+        assert!(
+            object
+                .tags
+                .contains_key(&TagIndex::new(apriltag::ApriltagFamily::Tag16h5, 0))
+        );
+        assert!(
+            !object
+                .tags
+                .contains_key(&TagIndex::new(apriltag::ApriltagFamily::Tag25h9, 3))
+        );
+    }
+
+    #[test]
+    fn test_load_tagobj_dir() {
+        let dir = std::env::temp_dir().join(format!("tagobj-dir-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let write_tagobj = |file_name: &str, version: i64, id: i32| {
+            let tagobj = serde_json::json!({
+                "version": version,
+                "tags": {
+                    "0": { "family": "tag36h11", "id": id, "size": 1.0, "rv": [0.0, 0.0, 0.0], "tv": [0.0, 0.0, 0.0] },
+                },
+            });
+            fs::write(dir.join(file_name), tagobj.to_string()).unwrap();
+        };
+        write_tagobj("a.tagobj", 2, 0);
+        write_tagobj("b.tagobj", 2, 1);
+        write_tagobj("c.tagobj", 1, 0); // version 1 files are skipped
+        write_tagobj("d.json", 2, 0); // files without the tagobj extension are skipped
+
+        let objects = load_tagobj_dir(&dir).unwrap();
+        assert_eq!(
+            objects
+                .iter()
+                .map(|object| object.name.as_str())
+                .collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+
+        // conflicting tags across files are reported together
+        write_tagobj("e.tagobj", 2, 0);
+        write_tagobj("f.tagobj", 2, 1);
+        let error = load_tagobj_dir(&dir).unwrap_err();
+        let error = error.downcast_ref::<ConflictingTagsError>().unwrap();
+        assert_eq!(error.conflicts().len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Load a version 2 tagobj file with a single tag entry "tag" in strict mode, returning the JSON path
+    /// of the invalid field and the reason.
+    fn invalid_field(tag_json: serde_json::Value) -> (String, String) {
+        let tagobj = serde_json::json!({ "version": 2, "tags": { "tag": tag_json } });
+        let error = TaggedObject::new_from_json_with_modes(
+            "object",
+            &tagobj,
+            &HashMap::new(),
+            RotationMatrixMode::Strict,
+            ValidationMode::Strict,
+        )
+        .unwrap_err();
+        let error = error
+            .downcast_ref::<InvalidFieldError>()
+            .unwrap_or_else(|| panic!("Unexpected error: {}", error));
+        (error.path().to_string(), error.reason().to_string())
+    }
+
+    #[test]
+    fn test_strict_validation() {
+        let valid = serde_json::json!({
+            "family": "tag36h11", "id": 0, "size": 1.0, "rv": [0.0, 0.0, 0.0], "tv": [0.0, 0.0, 0.0],
+        });
+        let tagobj = serde_json::json!({ "version": 2, "tags": { "tag": valid } });
+        let object = TaggedObject::new_from_json_with_modes(
+            "object",
+            &tagobj,
+            &HashMap::new(),
+            RotationMatrixMode::Strict,
+            ValidationMode::Strict,
+        )
+        .unwrap();
+        assert_eq!(object.tags.len(), 1);
+
+        // replace or remove a single field of the valid entry
+        let with = |key: &str, value: Option<serde_json::Value>| {
+            let mut tag_json = valid.clone();
+            match value {
+                Some(value) => tag_json[key] = value,
+                None => {
+                    tag_json.as_object_mut().unwrap().remove(key);
+                }
+            }
+            tag_json
+        };
+        let rm =
+            serde_json::json!({ "x": [1.0, 0.0, 0.0], "y": [0.0, 1.0, 0.0], "z": [0.0, 0.0, 1.0] });
+        let mut rm_typo = rm.clone();
+        rm_typo["y"][1] = serde_json::json!("1.0");
+        let mut rm_missing_column = rm.clone();
+        rm_missing_column.as_object_mut().unwrap().remove("z");
+        let without_rv = with("rv", None);
+        let with_rm = |rm: serde_json::Value| {
+            let mut tag_json = without_rv.clone();
+            tag_json["rm"] = rm;
+            tag_json
+        };
+        // each case is the entry, the path of the invalid field, and a part of the reason. Missing fields
+        // are located at the object that should contain them
+        let entry = r#"$.tags["tag"]"#;
+        for (tag_json, path, reason) in [
+            (
+                serde_json::json!("tag"),
+                entry.to_string(),
+                "expected struct TagEntry",
+            ),
+            (with("family", None), entry.to_string(), "`family`"),
+            (
+                with("family", Some(serde_json::json!("tag00h0"))),
+                format!("{}.family", entry),
+                "tag00h0",
+            ),
+            (with("id", None), entry.to_string(), "`id`"),
+            (
+                with("id", Some(serde_json::json!(1.5))),
+                format!("{}.id", entry),
+                "i32",
+            ),
+            (
+                with("id", Some(serde_json::json!(1i64 << 40))),
+                format!("{}.id", entry),
+                "i32",
+            ),
+            (with("size", None), entry.to_string(), "`size`"),
+            (
+                with("size", Some(serde_json::json!(-1.0))),
+                format!("{}.size", entry),
+                "positive",
+            ),
+            (
+                with("size", Some(serde_json::json!([1.0, 2.0, 3.0]))),
+                format!("{}.size", entry),
+                "TagSize",
+            ),
+            (with("tv", None), entry.to_string(), "`tv`"),
+            (
+                with("tv", Some(serde_json::json!([0.0, 0.0]))),
+                format!("{}.tv", entry),
+                "length 2",
+            ),
+            (
+                with("tv", Some(serde_json::json!([0.0, null, 0.0]))),
+                format!("{}.tv[1]", entry),
+                "null",
+            ),
+            (
+                with("rv", Some(serde_json::json!("0 0 0"))),
+                format!("{}.rv", entry),
+                "string",
+            ),
+            (without_rv.clone(), entry.to_string(), "exactly one"),
+            (
+                with("rm", Some(rm.clone())),
+                entry.to_string(),
+                "exactly one",
+            ),
+            (
+                with_rm(serde_json::json!(1.0)),
+                format!("{}.rm", entry),
+                "expected struct RotationMatrix",
+            ),
+            (with_rm(rm_typo), format!("{}.rm.y[1]", entry), "string"),
+            (with_rm(rm_missing_column), format!("{}.rm", entry), "`z`"),
+            (
+                with("rq", Some(serde_json::json!([0.0, 0.0, 0.0, 0.0]))),
+                entry.to_string(),
+                "exactly one",
+            ),
+        ] {
+            let (actual_path, actual_reason) = invalid_field(tag_json.clone());
+            assert_eq!(actual_path, path, "Entry: {}", tag_json);
+            assert!(
+                actual_reason.contains(reason),
+                "Entry: {}, reason: {}",
+                tag_json,
+                actual_reason
+            );
+        }
+        let mut zero_quaternion = without_rv.clone();
+        zero_quaternion["rq"] = serde_json::json!([0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(invalid_field(zero_quaternion).0, format!("{}.rq", entry));
+
+        // the same entries are skipped in lenient mode
+        let tagobj = serde_json::json!({ "version": 2, "tags": { "tag": with("tv", None) } });
+        let object = TaggedObject::new_from_json("object", &tagobj, &HashMap::new()).unwrap();
+        assert!(object.tags.is_empty());
+    }
+
+    #[test]
+    fn test_load_rotation_quaternion() {
+        let rotation = na::UnitQuaternion::from_scaled_axis(na::vector![0.3, -0.2, 0.1]);
+        let object = load_single_tag(serde_json::json!({
+            "size": 1.0,
+            "rq": [rotation.i, rotation.j, rotation.k, rotation.w],
+            "tv": [0.0, 0.0, 0.0],
+        }));
+        let tag = object.tags.values().next().unwrap();
+        assert!(
+            (tag.isometry.rotation.matrix() - rotation.to_rotation_matrix().matrix()).amax()
+                < 1e-12
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use plotters::style::RGBColor;
+
+extern crate nalgebra as na;
+
+pub const AXES: [na::Point3<f64>; 3] = [
+    na::Point3::new(1.0, 0.0, 0.0),
+    na::Point3::new(0.0, 1.0, 0.0),
+    na::Point3::new(0.0, 0.0, 1.0),
+];
+pub const AXES_COLORS: [(u8, u8, u8); 3] = [(255, 0, 0), (0, 255, 0), (0, 0, 255)];
+
+/// The maximum relative luminance of the generated colors, so that they stand out against the light
+/// background of the visualizers.
+const MAX_COLOR_LUMINANCE: f64 = 0.55;
+
+/// 64-bit FNV-1a hash. Unlike `DefaultHasher`, its output is stable across Rust versions and builds.
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Generate a color for an object from its name.
+///
+/// The same name always yields the same color, in all visualizers and across builds. The hue is taken
+/// from the FNV-1a hash of the name, and the color is darkened if necessary to keep enough contrast
+/// against a light background.
+pub(crate) fn generate_random_color(name: &str) -> RGBColor {
+    let hue = (fnv1a_hash(name.as_bytes()) % 360) as f64 / 60.0;
+    let (saturation, value) = (0.85, 0.8);
+    // convert from HSV to RGB
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let m = value - chroma;
+    let (r, g, b) = match hue as usize {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let (mut r, mut g, mut b) = (r + m, g + m, b + m);
+    let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    if luminance > MAX_COLOR_LUMINANCE {
+        let scale = MAX_COLOR_LUMINANCE / luminance;
+        (r, g, b) = (r * scale, g * scale, b * scale);
+    }
+    let to_u8 = |channel: f64| (channel * 255.0).round() as u8;
+    RGBColor(to_u8(r), to_u8(g), to_u8(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_random_color() {
+        assert_eq!(generate_random_color("wand"), RGBColor(101, 164, 25));
+        assert_eq!(generate_random_color("fractal tag"), RGBColor(48, 31, 204));
+        for name in ["wand", "handheld screen", "fractal tag", "", "a"] {
+            let RGBColor(r, g, b) = generate_random_color(name);
+            let luminance = 0.2126 * r as f64 + 0.7152 * g as f64 + 0.0722 * b as f64;
+            assert!(luminance <= 255.0 * MAX_COLOR_LUMINANCE + 1.0);
+        }
+    }
+}
@@ -1,22 +1,30 @@
 extern crate nalgebra as na;
 
+/// Colors used when drawing the located objects
+pub mod color;
+
+/// Conversions between rotation representations, following the same conventions everywhere
+pub mod rotation;
+
+/// The right Jacobian $J_r(\mathbf{\omega})$ of the rotation vector $\mathbf{\omega}$, such that
+/// $\exp([\mathbf{\omega} + d\mathbf{\omega}]_\times) \approx \exp([\mathbf{\omega}]_\times)
+/// \exp([J_r(\mathbf{\omega}) d\mathbf{\omega}]_\times)$.
+pub fn right_jacobian(omega: &na::Vector3<f64>) -> na::Matrix3<f64> {
+    let theta = omega.norm();
+    if theta < 1e-10 {
+        return na::Matrix3::identity();
+    }
+    let omega_hat = omega.cross_matrix();
+    na::Matrix3::identity() - ((1.0 - theta.cos()) / (theta * theta)) * omega_hat
+        + ((theta - theta.sin()) / (theta * theta * theta)) * omega_hat * omega_hat
+}
+
 /// For a given rotation defined by rotation vector $\mathbf{\omega}$ and a vector $\mathbf{v}$,
 /// let $\mathbf{b} = \exp([\mathbf{\omega}]_\times)\mathbf{v}$. This function computes the Jacobian
 /// $\frac{\partial \mathbf{b}}{\partial \mathbf{\omega}}$.
 pub fn rotation_jacobian(r: &na::Rotation3<f64>, v: &na::Vector3<f64>) -> na::Matrix3<f64> {
-    let theta = r.angle();
-    let omega = r.scaled_axis();
-    let omega_hat = na::Matrix3::new(
-        0.0, -omega.z, omega.y, omega.z, 0.0, -omega.x, -omega.y, omega.x, 0.0,
-    );
-    let right_jacobian = if theta < 1e-10 {
-        na::Matrix3::identity()
-    } else {
-        na::Matrix3::identity() - ((1.0 - theta.cos()) / (theta * theta)) * omega_hat
-            + ((theta - theta.sin()) / (theta * theta * theta)) * omega_hat * omega_hat
-    };
     let v_hat = na::Matrix3::new(0.0, -v.z, v.y, v.z, 0.0, -v.x, -v.y, v.x, 0.0);
-    -r.matrix() * v_hat * right_jacobian
+    -r.matrix() * v_hat * right_jacobian(&r.scaled_axis())
 }
 
 #[cfg(test)]
@@ -0,0 +1,210 @@
+//! Conversions between the representations of rotations and rigid transforms used by the packets, the
+//! logs, and the visualizers, so that all of them follow the same conventions.
+//!
+//! Euler angles are `[roll, pitch, yaw]` in radians, applied as extrinsic rotations about the x, y, and z
+//! axes in this order, i.e. `R = Rz(yaw) * Ry(pitch) * Rx(roll)`. Quaternions are stored as `(i, j, k, w)`
+//! wherever they are flattened.
+
+extern crate nalgebra as na;
+
+/// How close the pitch's sine has to be to ±1 for `quat_to_euler` to treat the rotation as gimbal-locked.
+pub const GIMBAL_LOCK_TOLERANCE: f64 = 1e-12;
+
+/// Convert a rotation into Euler angles `[roll, pitch, yaw]`, with the roll and the yaw in `[-π, π]` and the
+/// pitch in `[-π/2, π/2]`.
+///
+/// At a pitch of ±π/2 (gimbal lock), only the difference (or sum) of the roll and the yaw is determined, so
+/// the roll is set to 0 and the whole rotation about the vertical axis is put into the yaw. Unlike
+/// `na::UnitQuaternion::euler_angles`, rotations within `GIMBAL_LOCK_TOLERANCE` of gimbal lock are treated
+/// the same way, instead of dividing rounding errors by each other.
+pub fn quat_to_euler(rotation: &na::UnitQuaternion<f64>) -> na::Vector3<f64> {
+    let m = rotation.to_rotation_matrix().into_inner();
+    let sin_pitch = -m[(2, 0)];
+    if sin_pitch >= 1.0 - GIMBAL_LOCK_TOLERANCE {
+        // R = Rz(yaw - roll) * Ry(π/2)
+        na::vector![
+            0.0,
+            std::f64::consts::FRAC_PI_2,
+            f64::atan2(-m[(0, 1)], m[(1, 1)])
+        ]
+    } else if sin_pitch <= -1.0 + GIMBAL_LOCK_TOLERANCE {
+        // R = Rz(yaw + roll) * Ry(-π/2)
+        na::vector![
+            0.0,
+            -std::f64::consts::FRAC_PI_2,
+            f64::atan2(-m[(0, 1)], m[(1, 1)])
+        ]
+    } else {
+        na::vector![
+            f64::atan2(m[(2, 1)], m[(2, 2)]),
+            sin_pitch.asin(),
+            f64::atan2(m[(1, 0)], m[(0, 0)])
+        ]
+    }
+}
+
+/// Convert Euler angles `[roll, pitch, yaw]` into a rotation. This is the inverse of `quat_to_euler`.
+pub fn euler_to_quat(euler: &na::Vector3<f64>) -> na::UnitQuaternion<f64> {
+    na::UnitQuaternion::from_euler_angles(euler.x, euler.y, euler.z)
+}
+
+/// Convert a rotation matrix into a quaternion in closed form.
+///
+/// Unlike `na::UnitQuaternion::from_matrix`, which iterates from the identity and does not move away from it
+/// for rotations of exactly 180 degrees, this is exact for any rotation. Slightly non-orthogonal matrices
+/// (e.g. rounded by a client) are projected onto the nearest unit quaternion.
+pub fn matrix_to_quat(matrix: &na::Matrix3<f64>) -> na::UnitQuaternion<f64> {
+    let rotation =
+        na::UnitQuaternion::from_rotation_matrix(&na::Rotation3::from_matrix_unchecked(*matrix));
+    na::UnitQuaternion::new_normalize(rotation.into_inner())
+}
+
+/// Convert a rigid transform into the rows of its 4x4 homogeneous matrix.
+pub fn isometry_to_matrix4(isometry: &na::Isometry3<f64>) -> [[f64; 4]; 4] {
+    let matrix = isometry.to_homogeneous();
+    std::array::from_fn(|row| std::array::from_fn(|col| matrix[(row, col)]))
+}
+
+/// Convert the rows of a 4x4 homogeneous matrix into a rigid transform. This is the inverse of
+/// `isometry_to_matrix4`. The rotation part is converted with `matrix_to_quat`, and the last row is ignored.
+pub fn matrix4_to_isometry(rows: &[[f64; 4]; 4]) -> na::Isometry3<f64> {
+    let rotation = na::Matrix3::from_fn(|row, col| rows[row][col]);
+    na::Isometry3::from_parts(
+        na::Translation3::new(rows[0][3], rows[1][3], rows[2][3]),
+        matrix_to_quat(&rotation),
+    )
+}
+
+/// The angle of the rotation from `from` to `to`, in `[0, π]` radians.
+///
+/// Unlike `na::UnitQuaternion::angle_to`, which takes the arc cosine of the relative quaternion's real part,
+/// this stays accurate for tiny angles, e.g. the jitter of a static object.
+pub fn angle_between(from: &na::UnitQuaternion<f64>, to: &na::UnitQuaternion<f64>) -> f64 {
+    let relative = from.inverse() * to;
+    2.0 * f64::atan2(relative.imag().norm(), relative.scalar().abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    use super::*;
+
+    #[test]
+    fn test_euler_round_trip() {
+        for euler in [
+            na::vector![0.0, 0.0, 0.0],
+            na::vector![0.1, -0.2, 0.3],
+            na::vector![-3.0, 1.5, 3.1],
+            na::vector![3.1, 0.0, 0.0],
+            na::vector![0.5, -1.2, -3.1],
+        ] {
+            let rotation = euler_to_quat(&euler);
+            let converted = quat_to_euler(&rotation);
+            assert!(
+                (converted - euler).norm() < 1e-9,
+                "{:?} is converted to {:?}",
+                euler,
+                converted
+            );
+        }
+
+        // a single rotation about each axis maps to the corresponding angle
+        let roll = na::UnitQuaternion::from_axis_angle(&na::Vector3::x_axis(), 0.4);
+        assert!((quat_to_euler(&roll) - na::vector![0.4, 0.0, 0.0]).norm() < 1e-12);
+        let pitch = na::UnitQuaternion::from_axis_angle(&na::Vector3::y_axis(), 0.4);
+        assert!((quat_to_euler(&pitch) - na::vector![0.0, 0.4, 0.0]).norm() < 1e-12);
+        let yaw = na::UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), 0.4);
+        assert!((quat_to_euler(&yaw) - na::vector![0.0, 0.0, 0.4]).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_euler_gimbal_lock() {
+        for pitch in [FRAC_PI_2, -FRAC_PI_2] {
+            for (roll, yaw) in [(0.0, 0.0), (0.3, 0.0), (0.0, 0.3), (0.7, -1.1), (-2.5, 2.9)] {
+                // exactly at, and within rounding errors of gimbal lock
+                for pitch in [pitch, pitch * (1.0 - 1e-15)] {
+                    let rotation = euler_to_quat(&na::vector![roll, pitch, yaw]);
+                    let euler = quat_to_euler(&rotation);
+                    assert_eq!(euler.x, 0.0);
+                    assert_eq!(euler.y, FRAC_PI_2.copysign(pitch));
+                    assert!(euler.z.abs() <= PI);
+                    assert!(
+                        angle_between(&euler_to_quat(&euler), &rotation) < 1e-7,
+                        "{:?} is converted to {:?}",
+                        (roll, pitch, yaw),
+                        euler
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_matrix_to_quat() {
+        for scaled_axis in [
+            na::vector![0.0, 0.0, 0.0],
+            na::vector![0.3, -0.2, 0.1],
+            // rotations of exactly 180 degrees
+            na::vector![PI, 0.0, 0.0],
+            na::vector![0.0, PI, 0.0],
+            na::vector![0.0, 0.0, PI],
+        ] {
+            let expected = na::UnitQuaternion::from_scaled_axis(scaled_axis);
+            let rotation = matrix_to_quat(expected.to_rotation_matrix().matrix());
+            assert!(angle_between(&rotation, &expected) < 1e-12);
+        }
+
+        // a rounded matrix is projected to a nearby unit quaternion
+        let expected = na::UnitQuaternion::from_scaled_axis(na::vector![0.3, -0.2, 0.1]);
+        let rounded = expected
+            .to_rotation_matrix()
+            .into_inner()
+            .map(|v| (v * 1000.0).round() / 1000.0);
+        let rotation = matrix_to_quat(&rounded);
+        assert!((rotation.quaternion().norm() - 1.0).abs() < 1e-12);
+        assert!(angle_between(&rotation, &expected) < 1e-3);
+    }
+
+    #[test]
+    fn test_isometry_to_matrix4() {
+        let isometry = na::Isometry3::new(
+            na::vector![1.0, -2.0, 3.0],
+            na::vector![0.0, 0.0, FRAC_PI_2],
+        );
+        let rows = isometry_to_matrix4(&isometry);
+        // the rows are in row-major order, with the translation in the last column
+        let expected = [
+            [0.0, -1.0, 0.0, 1.0],
+            [1.0, 0.0, 0.0, -2.0],
+            [0.0, 0.0, 1.0, 3.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        for (row, expected_row) in rows.iter().zip(&expected) {
+            for (value, expected_value) in row.iter().zip(expected_row) {
+                assert!((value - expected_value).abs() < 1e-12);
+            }
+        }
+        let converted = matrix4_to_isometry(&rows);
+        assert!((converted.translation.vector - isometry.translation.vector).norm() < 1e-12);
+        assert!(angle_between(&converted.rotation, &isometry.rotation) < 1e-12);
+    }
+
+    #[test]
+    fn test_angle_between() {
+        let from = na::UnitQuaternion::from_scaled_axis(na::vector![0.3, -0.2, 0.1]);
+        for angle in [0.0, 1e-9, 1e-4, 0.5, PI - 1e-6, PI] {
+            let to = from * na::UnitQuaternion::from_axis_angle(&na::Vector3::y_axis(), angle);
+            assert!(
+                (angle_between(&from, &to) - angle).abs() < 1e-14_f64.max(angle * 1e-9),
+                "{} is measured as {}",
+                angle,
+                angle_between(&from, &to)
+            );
+            // the angle is symmetric, and does not depend on the sign of the quaternions
+            assert!((angle_between(&to, &from) - angle_between(&from, &to)).abs() < 1e-14);
+            let negated = na::UnitQuaternion::new_unchecked(-to.into_inner());
+            assert_eq!(angle_between(&from, &negated), angle_between(&from, &to));
+        }
+    }
+}
@@ -4,7 +4,7 @@ use std::sync::{Arc, Condvar, Mutex};
 use std::time::Duration;
 use std::{f64, thread};
 
-use egui::{CentralPanel, SidePanel, Visuals};
+use egui::{CentralPanel, Color32, RichText, SidePanel, TopBottomPanel, Visuals};
 use egui_plotter::{Chart, EguiBackend, MouseConfig};
 use plotters::coord::ranged3d::Cartesian3d;
 use plotters::coord::types::RangedCoordf64;
@@ -15,9 +15,9 @@ use statrs::distribution::{self, ContinuousCDF};
 extern crate nalgebra as na;
 
 use crate::camera::CameraProperty;
-use crate::tag::locator::{self, TAG_CORNERS, TaggedObjectLocator};
+use crate::tag::locator::{self, PoseCovariance, TAG_CORNERS, TaggedObjectLocator};
 use crate::tag::tagged_object::{TagIndex, TagLocation};
-use crate::visualize::utils::generate_random_color;
+use crate::utils::color::generate_random_color;
 
 /// A chart to visualize the located objects.
 ///
@@ -25,6 +25,9 @@ use crate::visualize::utils::generate_random_color;
 pub struct VisualizeChart<'a> {
     main_chart: Chart<Arc<(Mutex<locator::LocatedObjects<'a>>, Condvar)>>,
     axis_angle_chart: Chart<Arc<(Mutex<locator::LocatedObjects<'a>>, Condvar)>>,
+    /// Names of all objects that can be located, sorted alphabetically. Used by the color legend.
+    object_names: Vec<String>,
+    located_objects: Arc<(Mutex<locator::LocatedObjects<'a>>, Condvar)>,
     fps: f64,
 }
 
@@ -44,6 +47,9 @@ impl<'a> VisualizeChart<'a> {
         let cbrt_confidence_lvl = CONFIDENCE_LEVEL.cbrt();
         let ellipsoid_scale = std_normal_distr.inverse_cdf(cbrt_confidence_lvl); // these are for plotting the ellipsoid
 
+        let mut object_names = object_map.keys().cloned().collect::<Vec<_>>();
+        object_names.sort();
+
         let camera_clone = camera.clone();
         let object_map_clone = object_map.clone();
         let main_chart = Chart::new(located_objects.clone())
@@ -78,7 +84,7 @@ impl<'a> VisualizeChart<'a> {
                             chart
                                 .draw_series(LineSeries::new(
                                     TAG_CORNERS.iter().map(|point| {
-                                        let point1 = tag_loc.0.transform_point(point);
+                                        let point1 = tag_loc.transform_point(point);
                                         let point2 = loc.transform_point(&point1);
                                         (point2.x, point2.y, point2.z)
                                     }),
@@ -87,22 +93,31 @@ impl<'a> VisualizeChart<'a> {
                                 .unwrap();
                         }
                         // plot an ellipsoid representing the confidence regions of the located objects
-                        let cov_mat = TaggedObjectLocator::calculate_covariance(
-                            camera_clone.camera_mat_na().unwrap(),
-                            object.iter().map(|(_, b)| b.clone()),
-                            *loc,
-                            (2.0, 2.0), // variance of 2 pixel
-                        )
-                        .unwrap();
-                        let cov_mat = cov_mat.try_inverse().unwrap();
+                        // degenerate poses have an unbounded confidence region, so no ellipsoid is plotted
+                        let Ok(PoseCovariance::Finite(cov_mat)) =
+                            TaggedObjectLocator::calculate_covariance(
+                                camera_clone.camera_mat_na().unwrap(),
+                                object.iter().map(|(_, b)| b.clone()),
+                                *loc,
+                                camera_clone.corner_variance,
+                            )
+                        else {
+                            continue;
+                        };
+                        let Some(cov_mat) = cov_mat.try_inverse() else {
+                            continue;
+                        };
                         let a = cov_mat.fixed_view::<3, 3>(0, 0).clone_owned();
                         let b = cov_mat.fixed_view::<3, 3>(0, 3).clone_owned();
                         let bt = cov_mat.fixed_view::<3, 3>(3, 0).clone_owned();
                         let c = cov_mat.fixed_view::<3, 3>(3, 3).clone_owned();
+                        let Some(c_inverse) = c.try_inverse() else {
+                            continue;
+                        };
                         Self::plot_ellipsoid(
                             &mut chart,
                             loc.translation.vector,
-                            a - b * c.try_inverse().unwrap() * bt,
+                            a - b * c_inverse * bt,
                             ellipsoid_scale,
                             64,
                             &BLACK,
@@ -156,22 +171,30 @@ impl<'a> VisualizeChart<'a> {
                             ))
                             .unwrap();
                         // draw an ellipsoid to represent the covariance
-                        let cov_mat = TaggedObjectLocator::calculate_covariance(
-                            camera.camera_mat_na().unwrap(),
-                            object.iter().map(|(_, b)| b.clone()),
-                            *loc,
-                            (2.0, 2.0), // variance of 2 pixel
-                        )
-                        .unwrap();
-                        let cov_mat = cov_mat.try_inverse().unwrap();
+                        let Ok(PoseCovariance::Finite(cov_mat)) =
+                            TaggedObjectLocator::calculate_covariance(
+                                camera.camera_mat_na().unwrap(),
+                                object.iter().map(|(_, b)| b.clone()),
+                                *loc,
+                                camera.corner_variance,
+                            )
+                        else {
+                            continue;
+                        };
+                        let Some(cov_mat) = cov_mat.try_inverse() else {
+                            continue;
+                        };
                         let a = cov_mat.fixed_view::<3, 3>(0, 0).clone_owned();
                         let b = cov_mat.fixed_view::<3, 3>(0, 3).clone_owned();
                         let bt = cov_mat.fixed_view::<3, 3>(3, 0).clone_owned();
                         let c = cov_mat.fixed_view::<3, 3>(3, 3).clone_owned();
+                        let Some(a_inverse) = a.try_inverse() else {
+                            continue;
+                        };
                         Self::plot_ellipsoid(
                             &mut chart,
                             axis_angle,
-                            c - bt * a.try_inverse().unwrap() * b,
+                            c - bt * a_inverse * b,
                             ellipsoid_scale,
                             64,
                             &color,
@@ -183,10 +206,33 @@ impl<'a> VisualizeChart<'a> {
         Self {
             main_chart,
             axis_angle_chart,
+            object_names,
+            located_objects,
             fps,
         }
     }
 
+    /// Draw a compact legend that maps each object's name to its color. Objects that are not located in
+    /// the current frame are grayed out.
+    fn draw_legend(&self, ui: &mut egui::Ui) {
+        let located_objects_lock = self.located_objects.0.lock().unwrap();
+        ui.horizontal_wrapped(|ui| {
+            for name in &self.object_names {
+                let RGBColor(r, g, b) = generate_random_color(name);
+                let swatch = RichText::new("\u{25A0}").color(Color32::from_rgb(r, g, b));
+                let label = RichText::new(name);
+                if located_objects_lock.name_map().contains_key(name.as_str()) {
+                    ui.label(swatch);
+                    ui.label(label);
+                } else {
+                    ui.label(swatch.weak());
+                    ui.label(label.weak());
+                }
+                ui.add_space(8.0);
+            }
+        });
+    }
+
     fn plot_axes(
         chart: &mut ChartContext<
             '_,
@@ -283,6 +329,9 @@ impl<'a> eframe::App for VisualizeChart<'a> {
                 ui.separator();
                 self.axis_angle_chart.draw(ui);
             });
+        TopBottomPanel::bottom("legend_panel").show(ctx, |ui| {
+            self.draw_legend(ui);
+        });
         CentralPanel::default().show(ctx, |ui| {
             self.main_chart.draw(ui);
         });
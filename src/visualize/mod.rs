@@ -10,7 +10,6 @@ use crate::{
 use crate::{tag::locator, visualize::chart::VisualizeChart};
 
 pub mod chart;
-pub mod utils;
 
 pub fn visualize_thread_main<'a>(
     camera: CameraProperty,
@@ -18,6 +17,8 @@ pub fn visualize_thread_main<'a>(
     located_objects: Arc<(Mutex<locator::LocatedObjects<'a>>, Condvar)>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let native_options = eframe::NativeOptions::default(); // rendering on your desktop
+    // redraw at the camera's frame rate, since the located objects do not change faster than that
+    let fps = camera.fps.unwrap_or(30.0);
     eframe::run_native(
         "Object Visualizer",
         native_options,
@@ -27,7 +28,7 @@ pub fn visualize_thread_main<'a>(
                 camera,
                 object_map,
                 located_objects,
-                30.0,
+                fps,
             )))
         }),
     )?;
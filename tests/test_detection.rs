@@ -0,0 +1,505 @@
+extern crate nalgebra as na;
+
+use std::sync::{Arc, Condvar, Mutex};
+
+use opencv::{core, imgproc, prelude::*};
+
+use xDIMScreen_locator::{
+    camera::{CameraProperty, FrameTimestamp},
+    tag::{
+        Preprocessing,
+        apriltag::{
+            ApriltagDetector, ApriltagFamily, ApriltagFamilyType, ImageU8, ImageU8View,
+            apriltag_binding,
+        },
+        detect_in_roi,
+        locator::{LocatedObjects, TAG_CORNERS, TaggedObjectLocator},
+        tagged_object::{TagIndex, TagLocation, TaggedObject},
+    },
+};
+
+/// Render the tag `id` of `family`, placed at `tag_location` on an object located at `object_location`,
+/// onto `canvas`.
+///
+/// The tag's pattern is taken from the family's bit pattern, and warped onto the canvas with the
+/// homography that maps the tag's outer black border to the projection of `TAG_CORNERS`.
+fn render_tag(
+    canvas: &mut Mat,
+    family: &ApriltagFamilyType,
+    id: u32,
+    tag_location: &TagLocation,
+    object_location: &na::Isometry3<f64>,
+    camera_mat: &na::Matrix3<f64>,
+) {
+    let tag_image =
+        unsafe { ImageU8::new_from_raw(apriltag_binding::apriltag_to_image(family.c_type, id)) };
+    let tag_mat = tag_image.to_mat().unwrap();
+
+    // the outer edge of the black border in the tag image, where pixel centers are at integer coordinates
+    let (width_at_border, total_width) = unsafe {
+        (
+            (*family.c_type).width_at_border as f64,
+            (*family.c_type).total_width as f64,
+        )
+    };
+    let border_start = (total_width - width_at_border) / 2.0 - 0.5;
+    let mut src_points = core::Vector::<core::Point2f>::new();
+    let mut dst_points = core::Vector::<core::Point2f>::new();
+    for corner in TAG_CORNERS.iter().take(4) {
+        src_points.push(core::Point2f::new(
+            (border_start + (corner.x + 1.0) / 2.0 * width_at_border) as f32,
+            (border_start + (corner.y + 1.0) / 2.0 * width_at_border) as f32,
+        ));
+        let projected =
+            camera_mat * object_location.transform_point(&tag_location.transform_point(corner));
+        dst_points.push(core::Point2f::new(
+            (projected.x / projected.z) as f32,
+            (projected.y / projected.z) as f32,
+        ));
+    }
+    let homography =
+        imgproc::get_perspective_transform(&src_points, &dst_points, core::DECOMP_LU).unwrap();
+    // only the pixels covered by the tag are written, since the canvas has the same size and type as the
+    // destination, and the border mode is transparent
+    let size = canvas.size().unwrap();
+    imgproc::warp_perspective(
+        &tag_mat,
+        canvas,
+        &homography,
+        size,
+        imgproc::INTER_LINEAR,
+        core::BORDER_TRANSPARENT,
+        core::Scalar::all(255.0),
+    )
+    .unwrap();
+}
+
+/// Render the objects, run the real apriltag detector on the rendered image, and locate the objects.
+fn detect_and_locate<'a>(
+    camera: &CameraProperty,
+    locator: &mut TaggedObjectLocator<'a>,
+    objects: &[(&'a TaggedObject, na::Isometry3<f64>)],
+) -> Arc<(Mutex<LocatedObjects<'a>>, Condvar)> {
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let mut family = ApriltagFamilyType::new(ApriltagFamily::Tag36h11);
+    let mut canvas = Mat::new_rows_cols_with_default(
+        camera.resolution.1 as i32,
+        camera.resolution.0 as i32,
+        core::CV_8UC1,
+        core::Scalar::all(255.0),
+    )
+    .unwrap();
+    for (object, object_location) in objects {
+        for (tag_index, tag_location) in &object.tags {
+            render_tag(
+                &mut canvas,
+                &family,
+                tag_index.id as u32,
+                tag_location,
+                object_location,
+                &camera_mat,
+            );
+        }
+    }
+
+    let detector = ApriltagDetector::new()
+        .add_family(&mut family)
+        .quad_decimate(1.0);
+    let detections = detector.detect(ImageU8View::from(&mut canvas).inner_mut());
+    let tag_count = objects
+        .iter()
+        .map(|(object, _)| object.tags.len())
+        .sum::<usize>();
+    assert_eq!(detections.len(), tag_count);
+
+    let result = Arc::new((Mutex::new(LocatedObjects::new()), Condvar::new()));
+    locator
+        .locate_objects(FrameTimestamp::now(), &detections, result.clone())
+        .unwrap();
+    result
+}
+
+fn assert_pose_close(actual: &na::Isometry3<f64>, expected: &na::Isometry3<f64>) {
+    // the rendered image is sampled on discrete pixels, so a small error is expected
+    let translation_error = (actual.translation.vector - expected.translation.vector).norm();
+    let rotation_error = actual.rotation.angle_to(&expected.rotation);
+    assert!(
+        translation_error <= 0.05 && rotation_error <= 0.05,
+        "Pose is too far from the ground truth. Actual: {:?}, expected: {:?}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn test_detect_rendered_tag() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let object = TaggedObject::new_simple("simple", ApriltagFamily::Tag36h11, 5, 2.0);
+
+    // rotate the tag in its plane, so that a wrong corner order results in a 90 or 180 degree error
+    for spin in [0.0, 90.0, 180.0, 270.0, 30.0] {
+        let mut locator = TaggedObjectLocator::new(camera.clone());
+        locator.add(&object).unwrap();
+        let rotation = na::UnitQuaternion::from_euler_angles(0.3, -0.2, 0.0)
+            * na::UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), f64::to_radians(spin));
+        let object_location =
+            na::Isometry3::from_parts(na::Translation3::new(0.5, -0.3, 10.0), rotation);
+        let result = detect_and_locate(&camera, &mut locator, &[(&object, object_location)]);
+        let result_lock = result.0.lock().unwrap();
+        let located = result_lock
+            .name_map()
+            .get("simple")
+            .unwrap_or_else(|| panic!("The tag spun by {} degrees is not located", spin));
+        assert_pose_close(located, &object_location);
+    }
+}
+
+#[test]
+fn test_detect_rendered_object() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let mut object = TaggedObject::new("board");
+    for (id, (x, y)) in [(-1.5, -1.5), (1.5, -1.5), (-1.5, 1.5), (1.5, 1.5)]
+        .into_iter()
+        .enumerate()
+    {
+        object.tags.insert(
+            TagIndex::new(ApriltagFamily::Tag36h11, id as i32),
+            TagLocation::new(1.0, na::Vector3::default(), na::vector![x, y, 0.0]),
+        );
+    }
+    let mut locator = TaggedObjectLocator::new(camera.clone());
+    locator.add(&object).unwrap();
+
+    let object_location =
+        na::Isometry3::new(na::vector![-0.5, 0.4, 15.0], na::vector![0.2, 0.4, 0.1]);
+    let result = detect_and_locate(&camera, &mut locator, &[(&object, object_location)]);
+    let result_lock = result.0.lock().unwrap();
+    assert_pose_close(
+        result_lock.name_map().get("board").unwrap(),
+        &object_location,
+    );
+}
+
+#[test]
+fn test_detect_in_roi() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let mut family = ApriltagFamilyType::new(ApriltagFamily::Tag36h11);
+    let mut canvas =
+        Mat::new_rows_cols_with_default(1080, 1920, core::CV_8UC1, core::Scalar::all(255.0))
+            .unwrap();
+    let object_location =
+        na::Isometry3::new(na::vector![1.0, 0.5, 10.0], na::vector![0.3, -0.2, 0.1]);
+    render_tag(
+        &mut canvas,
+        &family,
+        5,
+        &TagLocation::new(2.0, na::Vector3::default(), na::Vector3::default()),
+        &object_location,
+        &camera_mat,
+    );
+
+    let detector = ApriltagDetector::new()
+        .add_family(&mut family)
+        .quad_decimate(1.0);
+    let preprocessing = Preprocessing::default();
+    let full = detect_in_roi(
+        &detector,
+        &mut ImageU8View::from(&mut canvas),
+        None,
+        &preprocessing,
+    );
+    assert_eq!(full.len(), 1);
+    // the region covers the tag, which is right of and below the image's center
+    let roi = core::Rect::new(800, 300, 800, 600);
+    let cropped = detect_in_roi(
+        &detector,
+        &mut ImageU8View::from(&mut canvas),
+        Some(roi),
+        &preprocessing,
+    );
+    assert_eq!(cropped.len(), 1);
+    assert!((cropped[0].center() - full[0].center()).norm() <= 0.05);
+    for (cropped_corner, full_corner) in cropped[0].corners().iter().zip(full[0].corners()) {
+        assert!(
+            (cropped_corner - full_corner).norm() <= 0.05,
+            "Corner {} is not moved to {}",
+            cropped_corner,
+            full_corner
+        );
+    }
+    // the homography maps the tag's center onto the detection's center
+    let center = cropped[0].homography() * na::vector![0.0, 0.0, 1.0];
+    assert!((center.xy() / center.z - full[0].center()).norm() <= 0.05);
+
+    // a region flush against the image's bottom-right corner
+    let roi = core::Rect::new(800, 300, 1120, 780);
+    let cropped = detect_in_roi(
+        &detector,
+        &mut ImageU8View::from(&mut canvas),
+        Some(roi),
+        &preprocessing,
+    );
+    assert_eq!(cropped.len(), 1);
+    assert!((cropped[0].center() - full[0].center()).norm() <= 0.05);
+
+    // a region without any tag, and a region outside of the image
+    for roi in [
+        core::Rect::new(0, 0, 500, 500),
+        core::Rect::new(2000, 0, 100, 100),
+    ] {
+        let detections = detect_in_roi(
+            &detector,
+            &mut ImageU8View::from(&mut canvas),
+            Some(roi),
+            &preprocessing,
+        );
+        assert!(detections.is_empty());
+    }
+}
+
+#[test]
+fn test_detect_scale() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let mut family = ApriltagFamilyType::new(ApriltagFamily::Tag36h11);
+    let mut canvas =
+        Mat::new_rows_cols_with_default(1080, 1920, core::CV_8UC1, core::Scalar::all(255.0))
+            .unwrap();
+    let object_location =
+        na::Isometry3::new(na::vector![1.0, 0.5, 10.0], na::vector![0.3, -0.2, 0.1]);
+    render_tag(
+        &mut canvas,
+        &family,
+        5,
+        &TagLocation::new(2.0, na::Vector3::default(), na::Vector3::default()),
+        &object_location,
+        &camera_mat,
+    );
+
+    let detector = ApriltagDetector::new()
+        .add_family(&mut family)
+        .quad_decimate(1.0);
+    let full = detect_in_roi(
+        &detector,
+        &mut ImageU8View::from(&mut canvas),
+        None,
+        &Preprocessing::default(),
+    );
+    assert_eq!(full.len(), 1);
+
+    // the corners detected on the downscaled image are scaled back to the full resolution, with and
+    // without a region
+    let preprocessing = Preprocessing {
+        detect_scale: Some(0.5),
+        ..Default::default()
+    };
+    for roi in [None, Some(core::Rect::new(800, 300, 800, 600))] {
+        let scaled = detect_in_roi(
+            &detector,
+            &mut ImageU8View::from(&mut canvas),
+            roi,
+            &preprocessing,
+        );
+        assert_eq!(scaled.len(), 1);
+        assert_eq!(scaled[0].id(), full[0].id());
+        assert!((scaled[0].center() - full[0].center()).norm() <= 1.0);
+        for (scaled_corner, full_corner) in scaled[0].corners().iter().zip(full[0].corners()) {
+            assert!(
+                (scaled_corner - full_corner).norm() <= 1.0,
+                "Corner {} is not scaled to {}",
+                scaled_corner,
+                full_corner
+            );
+        }
+        let center = scaled[0].homography() * na::vector![0.0, 0.0, 1.0];
+        assert!((center.xy() / center.z - scaled[0].center()).norm() <= 0.05);
+    }
+}
+
+#[test]
+fn test_detector_owned_families() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let object_location =
+        na::Isometry3::new(na::vector![0.5, -0.3, 10.0], na::vector![0.3, -0.2, 0.1]);
+    for family in ApriltagFamily::ALL {
+        let mut canvas =
+            Mat::new_rows_cols_with_default(1080, 1920, core::CV_8UC1, core::Scalar::all(255.0))
+                .unwrap();
+        render_tag(
+            &mut canvas,
+            &ApriltagFamilyType::new(family),
+            0,
+            &TagLocation::new(3.0, na::Vector3::default(), na::Vector3::default()),
+            &object_location,
+            &camera_mat,
+        );
+
+        let detector = ApriltagDetector::new()
+            .add_owned_family(family, 1)
+            .quad_decimate(1.0);
+        let detections = detector.detect(ImageU8View::from(&mut canvas).inner_mut());
+        // the detections outlive the detector and the families it owns
+        drop(detector);
+        assert_eq!(
+            detections.len(),
+            1,
+            "Tag of {} is not detected",
+            family.name()
+        );
+        assert_eq!(detections[0].family().unwrap(), family);
+        assert_eq!(detections[0].id(), 0);
+    }
+}
+
+#[test]
+fn test_detect_with_stats() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let mut canvas =
+        Mat::new_rows_cols_with_default(1080, 1920, core::CV_8UC1, core::Scalar::all(255.0))
+            .unwrap();
+    for (id, x) in [(0, -2.0), (1, 2.0)] {
+        render_tag(
+            &mut canvas,
+            &ApriltagFamilyType::new(ApriltagFamily::Tag36h11),
+            id,
+            &TagLocation::new(3.0, na::Vector3::default(), na::Vector3::default()),
+            &na::Isometry3::new(na::vector![x, 0.0, 10.0], na::vector![0.3, -0.2, 0.1]),
+            &camera_mat,
+        );
+    }
+
+    let detector = ApriltagDetector::new()
+        .add_owned_family(ApriltagFamily::Tag36h11, 1)
+        .quad_decimate(1.0);
+    let (detections, stats) =
+        detector.detect_with_stats(ImageU8View::from(&mut canvas).inner_mut());
+    assert_eq!(detections.len(), 2);
+    assert_eq!(stats.detections, 2);
+    assert!(stats.quads >= stats.detections);
+    assert!(!stats.stages.is_empty());
+    assert!(stats.stages.iter().all(|stage| !stage.name.is_empty()));
+    assert_eq!(
+        stats.stage(&stats.stages[0].name),
+        Some(stats.stages[0].duration)
+    );
+    assert_eq!(stats.stage("no such stage"), None);
+
+    // the stats are of the latest detection only
+    let mut blank =
+        Mat::new_rows_cols_with_default(1080, 1920, core::CV_8UC1, core::Scalar::all(255.0))
+            .unwrap();
+    let (detections, stats) = detector.detect_with_stats(ImageU8View::from(&mut blank).inner_mut());
+    assert!(detections.is_empty());
+    assert_eq!(stats.quads, 0);
+    assert_eq!(stats.detections, 0);
+}
+
+#[test]
+fn test_multithreading_parity() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    let family = ApriltagFamilyType::new(ApriltagFamily::Tag36h11);
+    let mut canvas =
+        Mat::new_rows_cols_with_default(1080, 1920, core::CV_8UC1, core::Scalar::all(255.0))
+            .unwrap();
+    for (id, x) in [(1, -2.0), (2, 0.0), (3, 2.0)] {
+        render_tag(
+            &mut canvas,
+            &family,
+            id,
+            &TagLocation::new(1.5, na::Vector3::default(), na::Vector3::default()),
+            &na::Isometry3::new(na::vector![x, 0.5, 10.0], na::vector![0.2, -0.3, 0.1]),
+            &camera_mat,
+        );
+    }
+
+    let detect = |detector: ApriltagDetector| {
+        let mut detections = detector.detect(ImageU8View::from(&mut canvas.clone()).inner_mut());
+        detections.sort_by_key(|detection| detection.id());
+        detections
+    };
+    let single = detect(ApriltagDetector::new().add_owned_family(ApriltagFamily::Tag36h11, 2));
+    let multi = detect(
+        ApriltagDetector::new_multithreading(4).add_owned_family(ApriltagFamily::Tag36h11, 2),
+    );
+    assert_eq!(single.len(), 3);
+    assert_eq!(
+        single
+            .iter()
+            .map(|detection| detection.id())
+            .collect::<Vec<_>>(),
+        multi
+            .iter()
+            .map(|detection| detection.id())
+            .collect::<Vec<_>>()
+    );
+    for (single, multi) in single.iter().zip(&multi) {
+        for (single_corner, multi_corner) in single.corners().iter().zip(multi.corners()) {
+            assert!(
+                (single_corner - multi_corner).norm() <= 1e-6,
+                "Corner {} of tag {} is different with 4 threads: {}",
+                single_corner,
+                single.id(),
+                multi_corner
+            );
+        }
+    }
+}
+
+#[test]
+fn test_detect_standard_and_custom_families() {
+    let camera =
+        CameraProperty::new((1920, 1080), (None, Some(f64::to_radians(50.0))), None).unwrap();
+    let camera_mat = camera.camera_mat_na().unwrap();
+    // these families have their data bits inside the black border, unlike the classic families
+    let tags = [
+        (ApriltagFamily::TagCustom48h12, 13, -3.0),
+        (ApriltagFamily::TagStandard41h12, 7, 0.0),
+        (ApriltagFamily::TagStandard52h13, 42, 3.0),
+    ];
+    let mut families = tags.map(|(family, _, _)| ApriltagFamilyType::new(family));
+    let mut canvas =
+        Mat::new_rows_cols_with_default(1080, 1920, core::CV_8UC1, core::Scalar::all(255.0))
+            .unwrap();
+    for ((_, id, x), family) in tags.iter().zip(&families) {
+        render_tag(
+            &mut canvas,
+            family,
+            *id,
+            &TagLocation::new(2.0, na::Vector3::default(), na::Vector3::default()),
+            &na::Isometry3::new(na::vector![*x, 0.0, 12.0], na::vector![0.1, -0.2, 0.0]),
+            &camera_mat,
+        );
+    }
+
+    let [custom48, standard41, standard52] = &mut families;
+    let detector = ApriltagDetector::new()
+        .add_family(custom48)
+        .add_family(standard41)
+        .add_family(standard52)
+        .quad_decimate(1.0);
+    let mut detections = detector
+        .detect(ImageU8View::from(&mut canvas).inner_mut())
+        .iter()
+        .map(|detection| (detection.family().unwrap(), detection.id() as u32))
+        .collect::<Vec<_>>();
+    // sorted in the order of `ApriltagFamily`, which is the order of `tags`
+    detections.sort_by_key(|(family, _)| *family as usize);
+    assert_eq!(
+        detections,
+        tags.map(|(family, id, _)| (family, id)).to_vec()
+    );
+    for (family, _, _) in tags {
+        assert_eq!(ApriltagFamily::try_from(family.name()).unwrap(), family);
+    }
+}
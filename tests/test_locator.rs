@@ -1,12 +1,11 @@
-use std::{
-    sync::{Arc, Condvar, Mutex},
-    time::SystemTime,
-};
+extern crate nalgebra as na;
+
+use std::sync::{Arc, Condvar, Mutex};
 
 use xDIMScreen_locator::{
-    camera::CameraProperty,
+    camera::{CameraProperty, FrameTimestamp},
     tag::{
-        apriltag::{ApriltagDetection, ApriltagFamily, ApriltagFamilyType, apriltag_binding},
+        apriltag::{ApriltagDetection, ApriltagFamily},
         locator::{LocatedObjects, TaggedObjectLocator},
         tagged_object::TaggedObject,
     },
@@ -22,32 +21,22 @@ fn test_locator_simple_tag() {
     locator.add(&simple_obj).unwrap();
     let locator_results = Arc::new((Mutex::new(LocatedObjects::new()), Condvar::new()));
 
-    let family_tag36h11 = ApriltagFamilyType::new(ApriltagFamily::Tag36h11);
-    let dummy_h_matd = unsafe { apriltag_binding::matd_create(2, 2) };
-    let detection_raw = unsafe {
-        libc::malloc(std::mem::size_of::<apriltag_binding::apriltag_detection>())
-            as *mut apriltag_binding::apriltag_detection
-    };
-    unsafe {
-        (*detection_raw).family = family_tag36h11.c_type;
-        (*detection_raw).id = 0;
-        (*detection_raw).hamming = 2;
-        (*detection_raw).decision_margin = 0.1;
-        (*detection_raw).H = dummy_h_matd;
-
-        // the tag is located at the center of the camera. Its side length is 20 pixels seen by the camera.
-        (*detection_raw).c = [960.0 - 0.5, 540.0 - 0.5];
-        (*detection_raw).p = [
-            [950.0 - 0.5, 550.0 - 0.5],
-            [970.0 - 0.5, 550.0 - 0.5],
-            [970.0 - 0.5, 530.0 - 0.5],
-            [950.0 - 0.5, 530.0 - 0.5], // move -0.5 pixels to make the center exactly (960, 540)
-        ];
-    }
-    let detection = unsafe { ApriltagDetection::new_from_raw(detection_raw) };
+    // the tag is located at the center of the camera. Its side length is 20 pixels seen by the camera.
+    let detection = ApriltagDetection::from_parts(
+        ApriltagFamily::Tag36h11,
+        0,
+        [
+            na::vector![950.0 - 0.5, 550.0 - 0.5],
+            na::vector![970.0 - 0.5, 550.0 - 0.5],
+            na::vector![970.0 - 0.5, 530.0 - 0.5],
+            na::vector![950.0 - 0.5, 530.0 - 0.5], // move -0.5 pixels to make the center exactly (960, 540)
+        ],
+        na::vector![960.0 - 0.5, 540.0 - 0.5],
+    )
+    .with_decision_margin(0.1);
 
     locator
-        .locate_objects(SystemTime::now(), &[detection], locator_results.clone())
+        .locate_objects(FrameTimestamp::now(), &[detection], locator_results.clone())
         .unwrap();
 
     let result_lock = locator_results.0.lock().unwrap();